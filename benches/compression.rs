@@ -0,0 +1,104 @@
+// value 压缩的基准测试，输出每种压缩方式的压缩率和编码、解码的吞吐量
+// 运行：cargo bench --bench compression
+
+use std::time::{Duration, Instant};
+
+use sql_rs::{
+    error::Result,
+    storage::{
+        codec::{Codec, Lz4Codec, NoopCodec, SnappyCodec, DEFAULT_MAX_VALUE_SIZE},
+        disk::DiskEngine,
+        engine::Engine,
+    },
+};
+
+const VALUE_SIZE: usize = 4096;
+const ROUNDS: usize = 2000;
+
+// 接近表中文本数据的 value，重复较多
+fn text_value() -> Vec<u8> {
+    let mut data = Vec::with_capacity(VALUE_SIZE);
+    let mut i = 0;
+    while data.len() < VALUE_SIZE {
+        data.extend_from_slice(format!("{{\"id\":{},\"name\":\"user-{}\",\"active\":true}}", i, i % 37).as_bytes());
+        i += 1;
+    }
+    data.truncate(VALUE_SIZE);
+    data
+}
+
+// 伪随机数据，基本无法压缩
+fn random_value() -> Vec<u8> {
+    let mut state = 0x2545_f491_4f6c_dd1du64;
+    (0..VALUE_SIZE)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+fn throughput(elapsed: Duration) -> f64 {
+    (VALUE_SIZE * ROUNDS) as f64 / elapsed.as_secs_f64() / 1024.0 / 1024.0
+}
+
+fn bench_codec(name: &str, codec: &dyn Codec, data: &[u8]) -> Result<()> {
+    let start = Instant::now();
+    let mut encoded = Vec::new();
+    for _ in 0..ROUNDS {
+        encoded = std::hint::black_box(codec.encode(std::hint::black_box(data)));
+    }
+    let encode_time = start.elapsed();
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let decoded = codec.decode(std::hint::black_box(&encoded), DEFAULT_MAX_VALUE_SIZE)?;
+        std::hint::black_box(decoded);
+    }
+    let decode_time = start.elapsed();
+
+    println!(
+        "{:<8} ratio {:>6.2}  encode {:>9.1} MB/s  decode {:>9.1} MB/s",
+        name,
+        data.len() as f64 / encoded.len() as f64,
+        throughput(encode_time),
+        throughput(decode_time)
+    );
+    Ok(())
+}
+
+// 用 DiskEngine 写入相同的数据，比较文件大小
+fn bench_disk(name: &str, codec: Box<dyn Codec>, data: &[u8]) -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("sqldb-log");
+    let mut eng = DiskEngine::new(path.clone())?;
+    eng.set_codec(codec);
+    let start = Instant::now();
+    for i in 0..ROUNDS {
+        eng.set(format!("key{:06}", i).as_bytes(), data.to_vec())?;
+    }
+    eng.flush()?;
+    let elapsed = start.elapsed();
+    println!(
+        "{:<8} file {:>9} bytes  write {:>9.1} MB/s",
+        name,
+        std::fs::metadata(&path)?.len(),
+        throughput(elapsed)
+    );
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    for (kind, data) in [("text", text_value()), ("random", random_value())] {
+        println!("== {} values, {} bytes x {} ==", kind, VALUE_SIZE, ROUNDS);
+        bench_codec("none", &NoopCodec, &data)?;
+        bench_codec("lz4", &Lz4Codec, &data)?;
+        bench_codec("snappy", &SnappyCodec, &data)?;
+        bench_disk("none", Box::new(NoopCodec), &data)?;
+        bench_disk("lz4", Box::new(Lz4Codec), &data)?;
+        bench_disk("snappy", Box::new(SnappyCodec), &data)?;
+    }
+    Ok(())
+}
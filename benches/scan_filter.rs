@@ -0,0 +1,62 @@
+// 过滤条件下推的基准测试，比较扫描之后再过滤和在 scan_table 中过滤的耗时
+// 运行：cargo bench --bench scan_filter
+
+use std::time::{Duration, Instant};
+
+use sql_rs::{
+    error::Result,
+    sql::{
+        engine::{kv::KVEngine, Engine, Transaction},
+        parser::{ast::Statement, Parser},
+        types::Value,
+    },
+    storage::memory::MemoryEngine,
+};
+
+const ROWS: usize = 50_000;
+const ROUNDS: u32 = 20;
+
+fn main() -> Result<()> {
+    let kvengine = KVEngine::new(MemoryEngine::new())?;
+    let mut s = kvengine.session()?;
+    s.execute("create table t (id int primary key, v int, name string);")?;
+    let values = (0..ROWS).map(|i| format!("({}, {}, 'row{}')", i, i % 100, i)).collect::<Vec<_>>();
+    s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+    let filter = match Parser::new("select * from t where v = 7;").parse()? {
+        Statement::Select { where_clause: Some(filter), .. } => filter,
+        _ => unreachable!(),
+    };
+    let columns = vec!["id".to_string(), "v".to_string(), "name".to_string()];
+
+    let txn = kvengine.begin()?;
+    let mut without = Duration::ZERO;
+    let mut with = Duration::ZERO;
+    for _ in 0..ROUNDS {
+        // 读出所有的行之后再计算过滤条件
+        let start = Instant::now();
+        let mut before = Vec::new();
+        for row in txn.scan_table("t".to_string(), None)? {
+            let row = row?;
+            if filter.evaluate(&columns, &row)? == Value::Boolean(true) {
+                before.push(row);
+            }
+        }
+        without += start.elapsed();
+
+        // 条件下推给 scan_table，反序列化之后立即过滤
+        let start = Instant::now();
+        let after = txn.scan_table("t".to_string(), Some(&filter))?.collect::<Result<Vec<_>>>()?;
+        with += start.elapsed();
+        assert_eq!(std::hint::black_box(before), std::hint::black_box(after));
+    }
+    txn.commit()?;
+
+    println!(
+        "filtered scan of {} rows: {:?} without pushdown, {:?} with pushdown",
+        ROWS,
+        without / ROUNDS,
+        with / ROUNDS
+    );
+    Ok(())
+}
@@ -0,0 +1,18 @@
+// SQL 解析器的模糊测试，任意输入都只能返回解析结果或者 Error::Parse，不能 panic 或者栈溢出
+// 运行（需要 nightly 工具链和 cargo-fuzz）：
+//   cargo install cargo-fuzz
+//   cargo +nightly fuzz run fuzz_parser
+// 发现的崩溃输入保存在 fuzz/artifacts/fuzz_parser 中，可以用 cargo +nightly fuzz run fuzz_parser <文件> 复现
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sql_rs::{error::Error, sql::parser::Parser};
+
+// fuzz_target! 生成 libFuzzer 调用的 #[no_mangle] 入口 LLVMFuzzerTestOneInput
+fuzz_target!(|data: &[u8]| {
+    let input = String::from_utf8_lossy(data);
+    match Parser::new(&input).parse() {
+        Ok(_) | Err(Error::Parse(_)) => {}
+        Err(err) => panic!("unexpected error {:?} for input {:?}", err, input),
+    }
+});
@@ -1,15 +1,84 @@
-use std::sync::PoisonError;
+use std::{fmt::Display, sync::PoisonError};
 
 use bincode::ErrorKind;
 
+use crate::sql::types::DataType;
+
 
 
 pub type Result<T> = std::result::Result<T,Error>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Error {
+    // SQL 解析错误
     Parse(String),
-    Internel(String),
+    // 表不存在
+    TableNotFound(String),
+    // 表已经存在
+    TableExists(String),
+    // 列不存在
+    ColumnNotFound { table: String, column: String },
+    // 列的类型和值的类型不匹配
+    TypeMismatch { column: String, expected: DataType, got: DataType },
+    // 表结构相关的错误，例如表已存在、缺少默认值等
+    Schema(String),
+    // 写入的值违反列的约束，例如非空、最大长度
+    ConstraintViolation(String),
+    // 文件读写错误
+    Io(String),
+    // 序列化、反序列化错误
+    Serialization(String),
+    // 事务冲突，并发的事务写入了同一个 key，重新执行事务通常可以成功
+    TransactionConflict,
+    // 非预期的内部错误
+    Internal(String),
+    // 语句执行被取消
+    Cancelled,
+    // 语句执行超时
+    Timeout,
+    // 在只读模式打开的数据库或者只读事务中写入
+    ReadOnly(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Parse(err) => write!(f, "parse error {}", err),
+            Error::TableNotFound(table) => write!(f, "table {} does not exist", table),
+            Error::TableExists(table) => write!(f, "table {} already exists", table),
+            Error::ColumnNotFound { table, column } => {
+                write!(f, "column {} does not exist in table {}", column, table)
+            }
+            Error::TypeMismatch { column, expected, got } => write!(
+                f,
+                "type mismatch for column {}, expected {}, got {}",
+                column, expected, got
+            ),
+            Error::Schema(err) => write!(f, "schema error {}", err),
+            Error::ConstraintViolation(err) => write!(f, "constraint violation {}", err),
+            Error::Io(err) => write!(f, "io error {}", err),
+            Error::Serialization(err) => write!(f, "serialization error {}", err),
+            Error::TransactionConflict => write!(f, "transaction conflict, try transaction"),
+            Error::Internal(err) => write!(f, "internal error {}", err),
+            Error::Cancelled => write!(f, "statement cancelled"),
+            Error::Timeout => write!(f, "statement timed out"),
+            Error::ReadOnly(err) => write!(f, "read-only error {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl serde::ser::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Serialization(msg.to_string())
+    }
+}
+
+impl serde::de::Error for Error {
+    fn custom<T: Display>(msg: T) -> Self {
+        Error::Serialization(msg.to_string())
+    }
 }
 
 impl From<std::num::ParseIntError> for Error {
@@ -26,18 +95,30 @@ impl From<std::num::ParseFloatError> for Error {
 
 impl<T> From<PoisonError<T>> for Error {
     fn from(value: PoisonError<T>) -> Self {
-        Error::Internel(value.to_string())
+        Error::Internal(value.to_string())
     }
 }
 
 impl From<Box<ErrorKind>> for Error {
     fn from(value: Box<ErrorKind>) -> Self {
-        Error::Internel(value.to_string())
+        Error::Serialization(value.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(value: serde_json::Error) -> Self {
+        Error::Serialization(value.to_string())
+    }
+}
+
+impl From<std::array::TryFromSliceError> for Error {
+    fn from(value: std::array::TryFromSliceError) -> Self {
+        Error::Serialization(value.to_string())
     }
 }
 
 impl From<std::io::Error> for Error {
     fn from(value: std::io::Error) -> Self {
-        Error::Internel(value.to_string())
+        Error::Io(value.to_string())
     }
-}
\ No newline at end of file
+}
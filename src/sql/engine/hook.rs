@@ -0,0 +1,64 @@
+use std::{io::Write, sync::Mutex, time::Duration};
+
+use crate::{error::Result, sql::executor::ResultSet};
+
+// 语句执行结束之后的回调，通过 Session::set_hook 注册，用于记录日志、统计耗时等
+// 成功和失败的语句都会调用，失败时事务已经回滚；elapsed 包含解析、规划、执行和提交的时间
+// 回调中的 panic 会被 Session 捕获并忽略，不影响语句的执行结果
+pub trait QueryHook {
+    fn on_statement(&self, sql: &str, outcome: &Result<ResultSet>, elapsed: Duration);
+}
+
+// 将执行时间不少于 threshold 的语句写入 writer，每条语句一行
+// [slow query] 12.3ms ok: select * from t;
+// 写入失败时直接忽略
+pub struct SlowQueryLogger<W: Write> {
+    threshold: Duration,
+    writer: Mutex<W>,
+}
+
+impl<W: Write> SlowQueryLogger<W> {
+    pub fn new(threshold: Duration, writer: W) -> Self {
+        Self { threshold, writer: Mutex::new(writer) }
+    }
+}
+
+impl<W: Write> QueryHook for SlowQueryLogger<W> {
+    fn on_statement(&self, sql: &str, outcome: &Result<ResultSet>, elapsed: Duration) {
+        if elapsed < self.threshold {
+            return;
+        }
+        let status = match outcome {
+            Ok(_) => "ok".to_string(),
+            Err(err) => format!("error {}", err),
+        };
+        if let Ok(mut writer) = self.writer.lock() {
+            let _ = writeln!(writer, "[slow query] {:.1}ms {}: {}", elapsed.as_secs_f64() * 1000.0, status, sql);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::{error::Error, sql::executor::ResultSet};
+
+    use super::{QueryHook, SlowQueryLogger};
+
+    #[test]
+    fn test_slow_query_logger() {
+        let logger = SlowQueryLogger::new(Duration::from_millis(10), Vec::new());
+        let ok = Ok(ResultSet::Insert { count: 1, keys: vec![] });
+        logger.on_statement("insert into t values (1);", &ok, Duration::from_millis(9));
+        logger.on_statement("insert into t values (2);", &ok, Duration::from_millis(12));
+        logger.on_statement("select * from u;", &Err(Error::TableNotFound("u".to_string())), Duration::from_millis(10));
+
+        let output = String::from_utf8(logger.writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "[slow query] 12.0ms ok: insert into t values (2);\n\
+             [slow query] 10.0ms error table u does not exist: select * from u;\n"
+        );
+    }
+}
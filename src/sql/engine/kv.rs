@@ -1,140 +1,695 @@
+use std::{collections::HashSet, ops::Bound, time::{Duration, SystemTime, UNIX_EPOCH}};
+
 use serde::{Deserialize, Serialize};
 
-use crate::{error::{Error, Result}, sql::{schema::Table, types::{Row, Value}}, storage::{self, engine::Engine as StorageEngein}};
+use crate::{error::{Error, Result}, sql::{executor::filter_rows, parser::ast::Expression, schema::{Index, Table, TableStats, View}, types::{format_primary_key, PrimaryKey, Row, Rows, Value}}, storage::{self, codec, engine::{Engine as StorageEngine, EngineStats}, keycode::{deserialize_key, serialize_key}, mvcc::{IsolationLevel, MvccKey, StoredValue, Version}}};
 
-use super::{Engine, Transaction};
+use super::{storage_format::{self, decode_row, decode_table, decode_view, encode_row, encode_table, encode_view, FORMAT_VERSION}, DebugKey, Engine, Transaction};
 
 // kv Engine 定义，是对存储引擎的 MVCC 的封装
-pub struct KVEngine<E : StorageEngein>{
+pub struct KVEngine<E : StorageEngine>{
     pub kv : storage::mvcc::Mvcc<E>,
 }
 
-impl<E: StorageEngein> KVEngine<E>  {
-    pub fn new(engine: E) -> Self{
-        Self{
-            kv: storage::mvcc::Mvcc::new(engine)
+impl<E: StorageEngine> KVEngine<E>  {
+    // 打开时会回滚存储中残留的未完成事务
+    pub fn new(engine: E) -> Result<Self>{
+        Ok(Self{
+            kv: storage::mvcc::Mvcc::new(engine)?
+        })
+    }
+
+    // 只读模式，用于查询另一个进程正在写入的数据库，例如 DiskEngine::open_read_only 打开的引擎
+    // 只能执行查询，写入返回 Error::ReadOnly
+    pub fn new_read_only(engine: E) -> Self {
+        Self { kv: storage::mvcc::Mvcc::new_read_only(engine) }
+    }
+
+    // 读取时单个 value 允许的最大字节数，默认为 codec::DEFAULT_MAX_VALUE_SIZE，写入不受限制
+    // 只影响之后创建的会话，同一个存储的其他 KVEngine 使用各自的设置
+    pub fn set_max_value_size(&mut self, limit: u64) {
+        self.kv.set_max_value_size(limit);
+    }
+
+    // 将旧版本存储格式的表结构和行逐个版本升级到当前版本，在同一个事务中完成
+    // 没有记录版本的数据库视为版本 0，已经是当前版本时不做任何修改
+    pub fn migrate(&self) -> Result<()> {
+        let txn = self.kv.begin()?;
+        match Self::migrate_txn(&txn) {
+            Ok(()) => {
+                txn.commit()?;
+                Ok(())
+            }
+            Err(err) => {
+                txn.rollback()?;
+                Err(err)
+            }
+        }
+    }
+
+    fn migrate_txn(txn: &storage::mvcc::MvccTransaction<E>) -> Result<()> {
+        let version_key = Key::FormatVersion.encode()?;
+        let tables = txn.scan_prefix(KeyPrefix::Table.encode()?)?;
+        let stored = txn.get(version_key.clone())?.map(|v| codec::deserialize::<u8>(&v)).transpose()?;
+        let from = match stored {
+            Some(v) => v,
+            None if tables.is_empty() => FORMAT_VERSION,
+            None => 0,
+        };
+        if from > FORMAT_VERSION {
+            return Err(Error::Serialization(format!("database uses unsupported format version {}", from)));
+        }
+
+        let upgrade = |step: fn(u8, &[u8]) -> Result<Vec<u8>>, mut data: Vec<u8>| -> Result<Vec<u8>> {
+            for version in from..FORMAT_VERSION {
+                data = step(version, &data)?;
+            }
+            Ok(data)
+        };
+        if from < FORMAT_VERSION {
+            for result in tables {
+                // 表结构升级之后才能读取表名，再升级表中所有的行
+                let value = upgrade(storage_format::upgrade_table, result.value)?;
+                let table = decode_table(&value, txn.max_value_size())?;
+                txn.set(result.key, value)?;
+                for row in txn.scan_prefix(KeyPrefix::Row(table.name).encode()?)? {
+                    txn.set(row.key, upgrade(storage_format::upgrade_row, row.value)?)?;
+                }
+            }
         }
+        if stored != Some(FORMAT_VERSION) {
+            txn.set(version_key, codec::serialize(&FORMAT_VERSION)?)?;
+        }
+        Ok(())
     }
 }
 
-impl<E : StorageEngein> Clone for KVEngine<E> {
+impl<E : StorageEngine> Clone for KVEngine<E> {
     fn clone(&self) -> Self {
         Self { kv: self.kv.clone() }
     }
 }
 
-impl<E : StorageEngein> Engine for KVEngine<E> {
+impl<E : StorageEngine + EngineStats + Send + 'static> Engine for KVEngine<E> {
     type Transaction = KVTransaction<E>;
 
-    fn begin(&self) -> Result<Self::Transaction> {
-        Ok(Self::Transaction::new(self.kv.begin()?))
+    fn begin_with_isolation(&self, isolation: IsolationLevel) -> Result<Self::Transaction> {
+        Ok(Self::Transaction::new(self.kv.begin_with_isolation(isolation)?))
     }
 }
 
 // KV Transaction 定义，实际是对存储引擎 MVCCTransaction 的封装
-pub struct KVTransaction<E : StorageEngein> {
+pub struct KVTransaction<E : StorageEngine> {
     txn: storage::mvcc::MvccTransaction<E>,
+    // 最后一个自动分配的自增列的值
+    last_insert_id: Option<i64>,
 }
 
-impl<E : StorageEngein> KVTransaction<E> {
+impl<E : StorageEngine> KVTransaction<E> {
     pub fn new(txn : storage::mvcc::MvccTransaction<E>) -> Self {
         Self { 
-            txn 
+            txn,
+            last_insert_id: None,
         }
     }
 }
 
-impl<E : StorageEngein> Transaction for KVTransaction<E> {
-    fn commit(&self) -> Result<()> {
+impl<E : StorageEngine> KVTransaction<E> {
+    // 给自增列为 NULL 的行分配值，显式指定的值会把计数器推进到它之后
+    // 计数器和数据在同一个事务中写入，并发分配时会在计数器的 key 上产生写冲突
+    fn fill_auto_increment(&mut self, table_name: &str, col: usize, rows: &mut [Row]) -> Result<()> {
+        let key = Key::TableSequence(table_name.to_string()).encode()?;
+        let current = match self.txn.get(key.clone())? {
+            Some(v) => codec::deserialize(&v)?,
+            None => 1,
+        };
+        let mut next: i64 = current;
+        for row in rows.iter_mut() {
+            match row.get_mut(col) {
+                Some(v @ Value::Null) => {
+                    *v = Value::Integer(next);
+                    self.last_insert_id = Some(next);
+                    next += 1;
+                }
+                Some(Value::Integer(i)) if *i >= next => next = *i + 1,
+                _ => {}
+            }
+        }
+        if next != current {
+            self.txn.set(key, codec::serialize(&next)?)?;
+        }
         Ok(())
     }
 
-    fn rollback(&self) -> Result<()> {
-        Ok(())
+    // 表中的行数和所有行编码之后的字节数，只读取 key 和 value 的长度，不解码行
+    fn row_stats(&self, table_name: &str) -> Result<(usize, u64)> {
+        self.txn
+            .scan_prefix_iter(KeyPrefix::Row(table_name.to_string()).encode()?)?
+            .try_fold((0, 0), |(count, bytes), result| Ok((count + 1, bytes + result?.value.len() as u64)))
+    }
+
+    // 索引列在表中的下标
+    fn must_index_column(&self, table: &Table, column: &str) -> Result<usize> {
+        table.column_index(column).ok_or(Error::ColumnNotFound {
+            table: table.name.clone(),
+            column: column.to_string(),
+        })
+    }
+
+    // 某一行在索引中对应的 key
+    fn index_key(&self, table_name: &str, index_name: &str, row: &Row, col: usize, pk: &[usize]) -> Result<Vec<u8>> {
+        let key = Key::Index(table_name.to_string(), index_name.to_string(), row[col].clone(), pk.iter().map(|&i| row[i].clone()).collect());
+        key.encode()
+    }
+
+    // 第一次建表时记录存储格式的版本
+    // 已经有表但没有记录版本说明是旧版本写入的数据，需要先执行 KVEngine::migrate
+    fn check_format_version(&self) -> Result<()> {
+        let key = Key::FormatVersion.encode()?;
+        match self.txn.get(key.clone())? {
+            Some(v) => match codec::deserialize::<u8>(&v)? {
+                FORMAT_VERSION => Ok(()),
+                v => Err(Error::Schema(format!("database uses storage format version {}, run migrate first", v))),
+            },
+            None if self.txn.scan_prefix(KeyPrefix::Table.encode()?)?.is_empty() => {
+                self.txn.set(key, codec::serialize(&FORMAT_VERSION)?)
+            }
+            None => Err(Error::Schema("database uses storage format version 0, run migrate first".to_string())),
+        }
+    }
+}
+
+impl<E : StorageEngine + EngineStats + 'static> KVTransaction<E> {
+    // 插入一行，经过 ttl 之后行和它的索引项都不再可见，之后由 Mvcc::purge_expired 删除
+    // 主键和未过期的行重复时返回错误，过期之后可以再次写入同一个主键
+    pub fn create_row_with_ttl(&mut self, table_name: String, row: Row, ttl: Duration) -> Result<PrimaryKey> {
+        let mut keys = self.write_rows(table_name, vec![row], Some(ttl))?;
+        Ok(keys.remove(0))
     }
 
-    fn create_row(&mut self, table_name: String, row: Row) -> Result<()> {
+    // 写入多行，ttl 不为 None 时行和它的索引项经过 ttl 之后一起过期
+    // 主键和已有的行或者同一批中的其他行重复时返回 Error::ConstraintViolation，不写入任何一行
+    fn write_rows(&mut self, table_name: String, mut rows: Vec<Row>, ttl: Option<Duration>) -> Result<Vec<PrimaryKey>> {
         let table = self.must_get_table(table_name.clone())?;
-        // 检查类型有效性
-        for (i,col) in table.columns.iter().enumerate() {
-            match row[i].datatype() {
-                None if col.nullable => {},
-                None => return Err(Error::Internel(format!("column {} cannot be null",col.name))),
-                Some(dt) => {
-                    if dt != col.datatype {
-                        return Err(Error::Internel(format!("column {} type mismatched",col.name)));
-                    }
-                },
+        if let Some(col) = table.auto_increment() {
+            self.fill_auto_increment(&table_name, col, &mut rows)?;
+        }
+        // 先校验所有的行，再一次性写入
+        let pk = &table.primary_key;
+        let indexes = table
+            .indexes
+            .iter()
+            .map(|index| Ok((index.name.clone(), self.must_index_column(&table, &index.column)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let mut keys = Vec::with_capacity(rows.len());
+        let mut items = Vec::with_capacity(rows.len() * (indexes.len() + 1));
+        let mut seen = HashSet::new();
+        for row in rows {
+            let row = table.coerce_row(row)?;
+            table.validate_row(&row)?;
+            let key = table.primary_key_of(&row);
+            let id = Key::Row(table_name.clone(), key.clone()).encode()?;
+            if !seen.insert(id.clone()) || self.txn.get(id.clone())?.is_some() {
+                return Err(duplicate_key(&key, &table_name));
+            }
+            for (name, col) in &indexes {
+                items.push((self.index_key(&table_name, name, &row, *col, pk)?, Vec::new()));
             }
+            items.push((id, encode_row(&row)?));
+            keys.push(key);
         }
+        match ttl {
+            Some(ttl) => self.txn.set_batch_with_ttl(items, ttl)?,
+            None => self.txn.set_batch(items)?,
+        }
+        Ok(keys)
+    }
+}
+
+impl<E : StorageEngine + EngineStats + 'static> Transaction for KVTransaction<E> {
+    fn commit(&self) -> Result<Version> {
+        self.txn.commit()
+    }
+
+    fn rollback(&self) -> Result<()> {
+        self.txn.rollback()
+    }
+
+    fn version(&self) -> Version {
+        self.txn.version()
+    }
 
-        // 存放数据
-        // 暂时以第一列作为主键
-        let id = Key::Row(table_name, row[0].clone());
-        let value = bincode::serialize(&row)?;
-        self.txn.set(bincode::serialize(&id)?, value)?;
+    fn create_row(&mut self, table_name: String, row: Row) -> Result<PrimaryKey> {
+        let mut keys = self.create_rows(table_name, vec![row])?;
+        Ok(keys.remove(0))
+    }
+
+    fn create_rows(&mut self, table_name: String, rows: Vec<Row>) -> Result<Vec<PrimaryKey>> {
+        self.write_rows(table_name, rows, None)
+    }
+
+    fn update_row(&mut self, table_name: String, primary_key: PrimaryKey, new_row: Row) -> Result<()> {
+        let table = self.must_get_table(table_name.clone())?;
+        let mut new_row = table.coerce_row(new_row)?;
+        table.validate_row(&new_row)?;
+        let pk = &table.primary_key;
+        let old_key = Key::Row(table_name.clone(), primary_key.clone()).encode()?;
+        let old = match self.txn.get(old_key.clone())? {
+            Some(v) => at_key(&old_key, decode_row(&v, self.txn.max_value_size()))?,
+            None => return Err(Error::Schema(format!("row {} does not exist in table {}", format_primary_key(&primary_key), table_name))),
+        };
+        // 修改主键时，新的主键不能已经被其他行使用
+        let new_pk = table.primary_key_of(&new_row);
+        let new_key = Key::Row(table_name.clone(), new_pk.clone()).encode()?;
+        if new_key != old_key && self.txn.get(new_key.clone())?.is_some() {
+            return Err(duplicate_key(&new_pk, &table_name));
+        }
+        // 自增列改为更大的值时推进计数器，避免之后分配的值和它冲突
+        if let Some(col) = table.auto_increment() {
+            self.fill_auto_increment(&table_name, col, std::slice::from_mut(&mut new_row))?;
+        }
 
+        // 先写入新的行和索引项，再删除旧的，都在同一个事务中完成
+        // 重写的行不再过期，没有变化的索引项同样重写，否则会保留旧的过期时间，和行不一致
+        let mut items = Vec::with_capacity(table.indexes.len() + 1);
+        let mut stale = Vec::new();
+        for index in &table.indexes {
+            let col = self.must_index_column(&table, &index.column)?;
+            let old_index = self.index_key(&table_name, &index.name, &old, col, pk)?;
+            let new_index = self.index_key(&table_name, &index.name, &new_row, col, pk)?;
+            if old_index != new_index {
+                stale.push(old_index);
+            }
+            items.push((new_index, Vec::new()));
+        }
+        items.push((new_key.clone(), encode_row(&new_row)?));
+        if new_key != old_key {
+            stale.push(old_key);
+        }
+        self.txn.set_batch(items)?;
+        for key in stale {
+            self.txn.delete(key)?;
+        }
         Ok(())
     }
 
-    fn scan_table(&self, table_name: String) -> Result<Vec<Row>> {
-        let perfix = KeyPerfix::Row(table_name.clone());
-        let results = self.txn.scan_prefix(bincode::serialize(&perfix)?)?;
+    fn scan_table(&self, table_name: String, filter: Option<&Expression>) -> Result<Rows> {
+        let prefix = KeyPrefix::Row(table_name.clone());
+        // 遍历到某一行时才从存储中读取并反序列化
+        let results = self.txn.scan_prefix_iter(prefix.encode()?)?;
+        let limit = self.txn.max_value_size();
+        let rows = results.map(move |result| result.and_then(|result| at_key(&result.key, decode_row(&result.value, limit))));
+        let Some(filter) = filter.cloned() else {
+            return Ok(Box::new(rows));
+        };
+        // 反序列化之后立即计算过滤条件，不满足的行不会返回给上层
+        let columns = self
+            .must_get_table(table_name)?
+            .columns
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>();
+        Ok(filter_rows(filter, columns, Box::new(rows)))
+    }
 
-        let mut rows = Vec::new();
-        for result in results {
-            let row: Row = bincode::deserialize(&result.value)?;
-            rows.push(row);
+    fn scan_table_range(&self, table_name: String, start: Option<PrimaryKey>, end: Option<PrimaryKey>) -> Result<Rows> {
+        // 没有指定边界时使用表中所有行的前缀作为边界
+        // 边界可以只包含复合主键的前几列，结束边界包含所有以它开头的主键
+        let prefix = KeyPrefix::Row(table_name.clone()).encode()?;
+        let start = match start {
+            Some(v) => Bound::Included(Key::Row(table_name.clone(), v).encode()?),
+            None => Bound::Included(prefix.clone()),
+        };
+        let end = match end {
+            Some(v) => {
+                let mut end = Key::Row(table_name, v).encode()?;
+                end.push(0xff);
+                Bound::Included(end)
+            }
+            None => prefix_end(prefix),
+        };
+        let results = self.txn.scan_iter((start, end))?;
+        let limit = self.txn.max_value_size();
+        Ok(Box::new(results.map(move |result| result.and_then(|result| at_key(&result.key, decode_row(&result.value, limit))))))
+    }
+
+    fn scan_table_page(&self, table_name: String, start_after: Option<PrimaryKey>, limit: usize) -> Result<(Vec<Row>, Option<PrimaryKey>)> {
+        if limit == 0 {
+            return Err(Error::Internal("page limit must be greater than 0".to_string()));
         }
-        Ok(rows)
+        let table = self.must_get_table(table_name.clone())?;
+        let prefix = KeyPrefix::Row(table_name.clone()).encode()?;
+        let start = match start_after {
+            Some(v) => Bound::Excluded(Key::Row(table_name, v).encode()?),
+            None => Bound::Included(prefix.clone()),
+        };
+        // 多读取一行，用来判断之后是否还有数据
+        let results = self.txn.scan_iter((start, prefix_end(prefix)))?;
+        let mut rows = results
+            .take(limit + 1)
+            .map(|result| result.and_then(|result| at_key(&result.key, decode_row(&result.value, self.txn.max_value_size()))))
+            .collect::<Result<Vec<_>>>()?;
+        let next = if rows.len() > limit {
+            rows.truncate(limit);
+            rows.last().map(|row| table.primary_key_of(row))
+        } else {
+            None
+        };
+        Ok((rows, next))
     }
 
     // 创建表，此处去调用底层存储引擎的接口
     fn create_table(&mut self, table: Table) -> Result<()> {
-        // 判断表是否已经存在
+        // 判断表是否已经存在，表和视图不能同名
+        // 和视图同名时不是 TableExists，IF NOT EXISTS 同样会报错
         if self.get_table(table.name.clone())?.is_some() {
-            return Err(Error::Internel(format!("table {} already exists",table.name)));
+            return Err(Error::TableExists(table.name));
         }
-        // 判断表的有效性
-        if table.columns.is_empty() {
-            return Err(Error::Internel(format!("table {} has no columns",table.name)));
+        if self.get_view(table.name.clone())?.is_some() {
+            return Err(Error::Schema(format!("view {} already exists", table.name)));
         }
+        // 判断表的有效性
+        table.validate()?;
+        self.check_format_version()?;
         // 将表名序列化作为键，将整张表序列化作为值
         let key = Key::Table(table.name.clone());
-        let value = bincode::serialize(&table)?;
-        self.txn.set(bincode::serialize(&key)?, value)?;
+        let value = encode_table(&table)?;
+        self.txn.set(key.encode()?, value)?;
         Ok(())
     }
 
+    fn create_index(&mut self, index_name: String, table_name: String, column_name: String) -> Result<()> {
+        let name_key = Key::IndexName(index_name.clone()).encode()?;
+        if self.txn.get(name_key.clone())?.is_some() {
+            return Err(Error::Schema(format!("index {} already exists", index_name)));
+        }
+        let mut table = self.must_get_table(table_name.clone())?;
+        let col = self.must_index_column(&table, &column_name)?;
+
+        // 为表中已有的数据建立索引
+        let mut items = Vec::new();
+        for row in self.scan_table(table_name.clone(), None)? {
+            let row = row?;
+            items.push((self.index_key(&table_name, &index_name, &row, col, &table.primary_key)?, Vec::new()));
+        }
+        table.indexes.push(Index { name: index_name, column: column_name });
+        items.push((Key::Table(table_name.clone()).encode()?, encode_table(&table)?));
+        items.push((name_key, codec::serialize(&table_name)?));
+        self.txn.set_batch(items)
+    }
+
+    fn drop_index(&mut self, index_name: String) -> Result<()> {
+        let name_key = Key::IndexName(index_name.clone()).encode()?;
+        let table_name: String = match self.txn.get(name_key.clone())? {
+            Some(v) => codec::deserialize(&v)?,
+            None => return Err(Error::Schema(format!("index {} does not exist", index_name))),
+        };
+        let mut table = self.must_get_table(table_name.clone())?;
+        let pos = table
+            .indexes
+            .iter()
+            .position(|i| i.name == index_name)
+            .ok_or(Error::Internal(format!("index {} missing from table {}", index_name, table_name)))?;
+        let index = table.indexes.remove(pos);
+        let col = self.must_index_column(&table, &index.column)?;
+
+        // 索引项和表中的行一一对应，按行删除即可
+        for row in self.scan_table(table_name.clone(), None)? {
+            let row = row?;
+            self.txn.delete(self.index_key(&table_name, &index_name, &row, col, &table.primary_key)?)?;
+        }
+        self.txn.set(Key::Table(table_name).encode()?, encode_table(&table)?)?;
+        self.txn.delete(name_key)
+    }
+
+    fn truncate_table(&mut self, table_name: String) -> Result<usize> {
+        let table = self.must_get_table(table_name.clone())?;
+        let pk = &table.primary_key;
+        let indexes = table
+            .indexes
+            .iter()
+            .map(|index| Ok((index.name.clone(), self.must_index_column(&table, &index.column)?)))
+            .collect::<Result<Vec<_>>>()?;
+        // 直接按前缀删除所有的行，只有存在索引时才需要解码出行来找到索引项
+        // 删除时写入的版本只影响已经读过的 key，不影响之后的批
+        let mut count = 0;
+        for result in self.txn.scan_prefix_iter(KeyPrefix::Row(table_name.clone()).encode()?)? {
+            let result = result?;
+            count += 1;
+            if !indexes.is_empty() {
+                let row = at_key(&result.key, decode_row(&result.value, self.txn.max_value_size()))?;
+                for (name, col) in &indexes {
+                    self.txn.delete(self.index_key(&table_name, name, &row, *col, pk)?)?;
+                }
+            }
+            self.txn.delete(result.key)?;
+        }
+        self.txn.delete(Key::TableSequence(table_name).encode()?)?;
+        Ok(count)
+    }
+
+    fn drop_table(&mut self, table_name: String) -> Result<()> {
+        let table = self.must_get_table(table_name.clone())?;
+        self.truncate_table(table_name.clone())?;
+        for index in &table.indexes {
+            self.txn.delete(Key::IndexName(index.name.clone()).encode()?)?;
+        }
+        self.txn.delete(Key::TableStats(table_name.clone()).encode()?)?;
+        self.txn.delete(Key::Table(table_name).encode()?)
+    }
+
+    fn scan_index(&self, table_name: String, index_name: String, value: &Value) -> Result<Vec<Row>> {
+        let prefix = KeyPrefix::Index(table_name.clone(), index_name, value.clone());
+        let mut rows = Vec::new();
+        for result in self.txn.scan_prefix_iter(prefix.encode()?)? {
+            let result = result?;
+            // 主键是索引项 key 的最后一部分
+            let Key::Index(.., pk) = deserialize_key(&result.key)? else {
+                return Err(Error::Internal(format!("unexpected key {} in index", describe_key(&result.key))));
+            };
+            let key = Key::Row(table_name.clone(), pk).encode()?;
+            if let Some(row) = self.txn.get(key.clone())? {
+                rows.push(at_key(&key, decode_row(&row, self.txn.max_value_size()))?);
+            }
+        }
+        Ok(rows)
+    }
+
     fn get_table(&self, table_name: String) -> Result<Option<Table>> {
-        let key = Key::Table(table_name);
-        Ok(self.txn.get(bincode::serialize(&key)?)?
-                .map(|v| bincode::deserialize(&v))
-                .transpose()?)
+        let key = Key::Table(table_name).encode()?;
+        self.txn.get(key.clone())?
+                .map(|v| at_key(&key, decode_table(&v, self.txn.max_value_size())))
+                .transpose()
+    }
+
+    fn count_rows(&self, table_name: String) -> Result<usize> {
+        self.must_get_table(table_name.clone())?;
+        Ok(self.row_stats(&table_name)?.0)
+    }
+
+    fn analyze_table(&mut self, table_name: String) -> Result<TableStats> {
+        self.must_get_table(table_name.clone())?;
+        let (row_count, size_bytes) = self.row_stats(&table_name)?;
+        let analyzed_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| Error::Internal(err.to_string()))?
+            .as_millis() as i64;
+        let stats = TableStats { row_count, size_bytes, analyzed_at };
+        self.txn.set(Key::TableStats(table_name).encode()?, codec::serialize(&stats)?)?;
+        Ok(stats)
+    }
+
+    fn get_table_stats(&self, table_name: String) -> Result<Option<TableStats>> {
+        self.txn
+            .get(Key::TableStats(table_name).encode()?)?
+            .map(|v| codec::deserialize(&v))
+            .transpose()
+    }
+
+    fn list_tables(&self) -> Result<Vec<Table>> {
+        // 表名编码之后的顺序和字符串的顺序一致，扫描的结果已经按表名排序
+        self.txn
+            .scan_prefix(KeyPrefix::Table.encode()?)?
+            .into_iter()
+            .map(|result| at_key(&result.key, decode_table(&result.value, self.txn.max_value_size())))
+            .collect()
+    }
+
+    fn create_view(&mut self, view: View) -> Result<()> {
+        if self.get_table(view.name.clone())?.is_some() {
+            return Err(Error::TableExists(view.name));
+        }
+        let key = Key::View(view.name.clone()).encode()?;
+        if self.txn.get(key.clone())?.is_some() {
+            return Err(Error::Schema(format!("view {} already exists", view.name)));
+        }
+        self.check_format_version()?;
+        self.txn.set(key, encode_view(&view)?)
+    }
+
+    fn drop_view(&mut self, name: String) -> Result<()> {
+        let key = Key::View(name.clone()).encode()?;
+        if self.txn.get(key.clone())?.is_none() {
+            return Err(Error::Schema(format!("view {} does not exist", name)));
+        }
+        self.txn.delete(key)
+    }
+
+    fn get_view(&self, name: String) -> Result<Option<View>> {
+        let key = Key::View(name).encode()?;
+        self.txn.get(key.clone())?.map(|v| at_key(&key, decode_view(&v, self.txn.max_value_size()))).transpose()
+    }
+
+    fn list_views(&self) -> Result<Vec<View>> {
+        self.txn
+            .scan_prefix(KeyPrefix::View.encode()?)?
+            .into_iter()
+            .map(|result| at_key(&result.key, decode_view(&result.value, self.txn.max_value_size())))
+            .collect()
+    }
+
+    fn engine_status(&self) -> Result<Vec<(String, Value)>> {
+        let storage = self.txn.engine_stats()?;
+        let mvcc = self.txn.stats()?;
+        Ok(vec![
+            ("entry_count".to_string(), Value::Integer(storage.entry_count as i64)),
+            ("disk_size_bytes".to_string(), Value::Integer(storage.disk_size_bytes as i64)),
+            ("live_data_bytes".to_string(), Value::Integer(storage.live_data_bytes as i64)),
+            ("live_entry_ratio".to_string(), Value::Float(storage.live_entry_ratio)),
+            ("active_transactions".to_string(), Value::Integer(mvcc.active_transactions as i64)),
+            ("total_versions".to_string(), Value::Integer(mvcc.total_versions as i64)),
+        ])
+    }
+
+    fn debug_keys(&self) -> Result<Vec<DebugKey>> {
+        self.txn
+            .raw_scan_prefix(&[])?
+            .into_iter()
+            .map(|(raw_key, value)| {
+                let (key, tombstone, expires_at) = match MvccKey::decode(raw_key.clone())? {
+                    MvccKey::Version(key, version) => {
+                        // 数据的 data 为 None 表示删除，设置了 TTL 时带有过期时间
+                        let stored = at_key(&key, StoredValue::decode(&value, self.txn.max_value_size()))?;
+                        (format!("Version({}, {})", describe_key(&key), version), Some(stored.data.is_none()), stored.expires_at)
+                    }
+                    MvccKey::TxnWrite(version, key) => (format!("TxnWrite({}, {})", version, describe_key(&key)), None, None),
+                    key => (format!("{:?}", key), None, None),
+                };
+                Ok(DebugKey { key, raw_key, value_size: value.len(), tombstone, expires_at })
+            })
+            .collect()
+    }
+
+    fn last_insert_id(&self) -> Option<i64> {
+        self.last_insert_id
+    }
+}
+
+fn duplicate_key(key: &[Value], table_name: &str) -> Error {
+    Error::ConstraintViolation(format!("duplicate primary key {} in table {}", format_primary_key(key), table_name))
+}
+
+// 解码 value 失败时在错误中带上对应的 key，便于定位损坏的数据
+fn at_key<T>(key: &[u8], result: Result<T>) -> Result<T> {
+    result.map_err(|err| match err {
+        Error::Serialization(msg) => Error::Serialization(format!("{} at key {}", msg, describe_key(key))),
+        err => err,
+    })
+}
+
+// 按 Key 解码 MVCC 中保存的原始 key，无法解码时输出转义之后的字节
+fn describe_key(key: &[u8]) -> String {
+    match deserialize_key::<Key>(key) {
+        Ok(key) => format!("{:?}", key),
+        Err(_) => format!("{:?}", key.escape_ascii().to_string()),
     }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 enum Key {
     Table(String),
-    Row(String,Value),
+    // 行：表名、主键，复合主键按列的顺序编码，单列主键的编码和单个值相同
+    Row(String, PrimaryKey),
+    // 表中自增列的下一个值
+    TableSequence(String),
+    // 索引名到表名的映射
+    IndexName(String),
+    // 索引项：表名、索引名、列值、主键，值为空
+    // 旧版本写入的索引项的值为主键，读取时只使用 key 中的主键
+    Index(String, String, Value, PrimaryKey),
+    // 数据库中行和表结构的存储格式版本
+    FormatVersion,
+    // 表最近一次 ANALYZE 的统计信息
+    TableStats(String),
+    // 视图名到视图定义的映射
+    View(String),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-enum KeyPerfix {
+impl Key {
+    // 使用保持顺序的编码，同一张表的行按主键的顺序存放，可以按主键范围扫描
+    fn encode(&self) -> Result<Vec<u8>> {
+        serialize_key(self)
+    }
+}
+
+// 扫描时使用的 key 前缀，编码由对应的 Key 得到，不需要和 Key 的变体顺序保持一致
+#[derive(Debug)]
+enum KeyPrefix {
     Table,
     Row(String),
+    Index(String, String, Value),
+    View,
+}
+
+impl KeyPrefix {
+    fn encode(&self) -> Result<Vec<u8>> {
+        match self {
+            // 只保留开头表示变体的一个字节
+            KeyPrefix::Table => Ok(Key::Table(String::new()).encode()?[..1].to_vec()),
+            KeyPrefix::View => Ok(Key::View(String::new()).encode()?[..1].to_vec()),
+            // 主键在 key 的最后，编码时没有长度和结束符，空的主键编码之后正好是前缀
+            KeyPrefix::Row(table) => Key::Row(table.clone(), Vec::new()).encode(),
+            KeyPrefix::Index(table, index, value) => Key::Index(table.clone(), index.clone(), value.clone(), Vec::new()).encode(),
+        }
+    }
+}
+
+// 编码后的前缀以 [0, 0] 结尾，最后一个字节加一即为所有以它开头的 key 的上界
+fn prefix_end(mut prefix: Vec<u8>) -> Bound<Vec<u8>> {
+    *prefix.last_mut().unwrap() += 1;
+    Bound::Excluded(prefix)
 }
 
 
+#[cfg(test)]
 mod tests {
-    use crate::{error::Result, sql::engine::Engine, storage::memory::MemoryEngine};
+    use std::{
+        ops::RangeBounds,
+        sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex},
+        time::{Duration, Instant},
+    };
 
-    use super::KVEngine;
+    use crate::{
+        error::{Error, Result},
+        sql::{
+            engine::{hook::QueryHook, storage_format::{encode_row, v1, v2, v3, FORMAT_VERSION}, Engine, Session, Transaction},
+            executor::{filter_rows, ResultSet},
+            parser::{ast::Statement, Parser},
+            types::{DataType, Row, Rows, Value},
+        },
+        storage::{
+            engine::{Engine as StorageEngine, EngineStats},
+            disk::DiskEngine,
+            memory::{MemoryEngine, MemoryEngineIterator},
+            mvcc::{ManualClock, Mvcc},
+        },
+    };
+
+    use super::{Key, KeyPrefix, KVEngine, KVTransaction};
 
     #[test]
     fn test_create_table() -> Result<()> {
-        let kvengine = KVEngine::new(MemoryEngine::new());
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
         let mut s = kvengine.session()?;
 
         s.execute("create table t1 (a int, b text default 'vv', c integer default 100);")?;
@@ -146,4 +701,2674 @@ mod tests {
 
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_column_default() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+
+        // 默认值的类型在建表时校验
+        assert_eq!(
+            s.execute("create table t1 (a int, b text default 100);").err(),
+            Some(Error::TypeMismatch { column: "b".to_string(), expected: DataType::String, got: DataType::Integer })
+        );
+        assert!(s.execute("create table t1 (a int, b int default a);").is_err());
+        assert!(s.execute("select * from t1;").is_err());
+
+        // 默认值表达式在插入时计算
+        s.execute("create table t1 (a int, b boolean default (1 < 2), c float default 1.5, d text);")?;
+        s.execute("insert into t1 values (1);")?;
+        s.execute("insert into t1 (d, a) values ('x', 2);")?;
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), Value::Boolean(true), Value::Float(1.5), Value::Null],
+                    vec![Value::Integer(2), Value::Boolean(true), Value::Float(1.5), Value::String("x".to_string())],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_tables() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let txn = kvengine.begin()?;
+        assert!(txn.list_tables()?.is_empty());
+        txn.commit()?;
+
+        s.execute("create table orders (id int primary key);")?;
+        s.execute("create table accounts (id int primary key);")?;
+        s.execute("create table items (id int primary key);")?;
+
+        let txn = kvengine.begin()?;
+        let names = txn.list_tables()?.into_iter().map(|t| t.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["accounts", "items", "orders"]);
+        assert!(txn.table_exists("items")?);
+        assert!(!txn.table_exists("item")?);
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_default_keyword() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int primary key auto_increment, b text default 'vv', c int null, d int not null);")?;
+
+        // 显式的值和 DEFAULT 可以混用，自增列的默认值为 NULL，写入时分配
+        s.execute("insert into t1 values (default, 'x', default, 1), (default, default, 3, 2);")?;
+        s.execute("insert into t1 (d, b) values (3, default);")?;
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), Value::String("x".to_string()), Value::Null, Value::Integer(1)],
+                    vec![Value::Integer(2), Value::String("vv".to_string()), Value::Integer(3), Value::Integer(2)],
+                    vec![Value::Integer(3), Value::String("vv".to_string()), Value::Null, Value::Integer(3)],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 没有默认值的列不能使用 DEFAULT
+        assert_eq!(
+            s.execute("insert into t1 (d) values (default);").err(),
+            Some(Error::Schema("No default value for column d!".to_string()))
+        );
+        assert!(s.execute("insert into t1 values (default, default, default, default, default);").is_err());
+        // DEFAULT 只能单独作为 VALUES 中的值
+        assert!(s.execute("insert into t1 (d) values (default + 1);").is_err());
+        assert!(s.execute("select * from t1 where d = default;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_invalid_columns() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b int default 10);")?;
+
+        // 不存在的列不能被静默忽略
+        assert_eq!(
+            s.execute("insert into t1(a, typo_col) values (1, 2);").err(),
+            Some(Error::ColumnNotFound {
+                table: "t1".to_string(),
+                column: "typo_col".to_string()
+            })
+        );
+        assert_eq!(
+            s.execute("insert into t1(a, x, y) values (1, 2, 3);").err(),
+            Some(Error::ColumnNotFound {
+                table: "t1".to_string(),
+                column: "x, y".to_string()
+            })
+        );
+        // 重复的列
+        assert!(s.execute("insert into t1(a, a) values (1, 2);").is_err());
+        // 值的个数多于指定的列
+        assert!(s.execute("insert into t1(a) values (1, 2);").is_err());
+        assert!(s.execute("insert into t1 values (1, 2, 3);").is_err());
+        // 多行插入时，后面的行校验失败，前面的行也不能写入
+        assert!(s.execute("insert into t1(a) values (1), (2, 3);").is_err());
+
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => assert!(rows.is_empty()),
+            _ => unreachable!(),
+        }
+
+        s.execute("insert into t1(b, a) values (2, 1);")?;
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(1), Value::Integer(2)]])
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_to_writer() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text, c float, d bool);")?;
+        s.execute("insert into t1 values (1, 'a', 1.5, true), (2, null, 2.0, false);")?;
+
+        let mut buf = Vec::new();
+        let count = s.execute_to_writer("select * from t1;", &mut buf)?;
+        assert_eq!(count, 2);
+
+        let output = String::from_utf8(buf).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        // 按照列定义的顺序输出
+        assert_eq!(lines[0], r#"{"a":1,"b":"a","c":1.5,"d":true}"#);
+
+        let rows = lines
+            .iter()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(rows[1]["a"], serde_json::json!(2));
+        assert_eq!(rows[1]["b"], serde_json::Value::Null);
+        assert_eq!(rows[1]["c"], serde_json::json!(2.0));
+        assert_eq!(rows[1]["d"], serde_json::json!(false));
+
+        // 非查询语句不能流式输出
+        assert!(s.execute_to_writer("insert into t1 values (3, 'c', 3.0, true);", &mut Vec::new()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_rows_conflict() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b int);")?;
+
+        let mut tx1 = kvengine.begin()?;
+        let mut tx2 = kvengine.begin()?;
+        tx1.create_row("t1".to_string(), vec![Value::Integer(3), Value::Integer(30)])?;
+
+        // 批量写入中的任意一行冲突，整批都不会写入
+        let rows = (1..=3).map(|i| vec![Value::Integer(i), Value::Integer(i * 10)]).collect();
+        assert_eq!(tx2.create_rows("t1".to_string(), rows), Err(Error::TransactionConflict));
+        tx2.rollback()?;
+        tx1.commit()?;
+
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::Integer(3), Value::Integer(30)]])
+            }
+            _ => unreachable!(),
+        }
+
+        // 批量写入中的任意一行校验失败，同样都不会写入
+        let mut tx3 = kvengine.begin()?;
+        let rows = vec![
+            vec![Value::Integer(4), Value::Integer(40)],
+            vec![Value::Integer(5), Value::String("x".to_string())],
+        ];
+        assert_eq!(
+            tx3.create_rows("t1".to_string(), rows),
+            Err(Error::TypeMismatch {
+                column: "b".to_string(),
+                expected: DataType::Integer,
+                got: DataType::String
+            })
+        );
+        tx3.commit()?;
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_row_non_finite_float() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b float);")?;
+
+        let mut txn = kvengine.begin()?;
+        for f in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                txn.create_row("t1".to_string(), vec![Value::Integer(1), Value::Float(f)]),
+                Err(Error::ConstraintViolation(_))
+            ));
+        }
+        txn.create_row("t1".to_string(), vec![Value::Integer(1), Value::Float(1.5)])?;
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_natural_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int, name text);")?;
+        s.execute("create table orders (oid int, id int null, amount float);")?;
+        s.execute("insert into users values (1, 'a'), (2, 'b'), (3, 'c');")?;
+        s.execute("insert into orders values (10, 1, 1.5), (11, 1, 2.5), (12, 3, 3.5), (13, null, 4.5);")?;
+
+        match s.execute("select * from users natural join orders;")?.result {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["id", "name", "oid", "amount"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::Integer(1), Value::String("a".to_string()), Value::Integer(10), Value::Float(1.5)],
+                        vec![Value::Integer(1), Value::String("a".to_string()), Value::Integer(11), Value::Float(2.5)],
+                        vec![Value::Integer(3), Value::String("c".to_string()), Value::Integer(12), Value::Float(3.5)],
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+
+        // 没有同名列时报错
+        s.execute("create table t (x int);")?;
+        assert!(matches!(
+            s.execute("select * from users natural join t;"),
+            Err(Error::Schema(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_inner_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name text);")?;
+        s.execute("create table orders (id int primary key, user_id int null, amount int);")?;
+        s.execute("insert into users values (1, 'a'), (2, 'b'), (3, 'c');")?;
+        s.execute("insert into orders values (10, 1, 15), (11, 1, 25), (12, 3, 35), (13, null, 45);")?;
+        let scan = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+                _ => unreachable!(),
+            }
+        };
+        let int = |v: i64| Value::Integer(v);
+        let name = |v: &str| Value::String(v.to_string());
+
+        // 一对多，同名的 id 列加上表名，NULL 不和任何值相等
+        let (columns, rows) = scan(&mut s, "select * from users join orders on users.id = user_id;")?;
+        assert_eq!(columns, vec!["users.id", "name", "orders.id", "user_id", "amount"]);
+        assert_eq!(
+            rows,
+            vec![
+                vec![int(1), name("a"), int(10), int(1), int(15)],
+                vec![int(1), name("a"), int(11), int(1), int(25)],
+                vec![int(3), name("c"), int(12), int(3), int(35)],
+            ]
+        );
+
+        // 多对一，没有表名的同名列解析到左侧，WHERE 可以引用 Join 输出的列
+        let (columns, rows) =
+            scan(&mut s, "select * from orders inner join users on user_id = users.id where id > 10 and name = 'a';")?;
+        assert_eq!(columns, vec!["orders.id", "user_id", "amount", "users.id", "name"]);
+        assert_eq!(rows, vec![vec![int(11), int(1), int(25), int(1), name("a")]]);
+
+        // 没有条件时是笛卡尔积
+        let (columns, rows) = scan(&mut s, "select * from users cross join orders;")?;
+        assert_eq!(columns, vec!["users.id", "name", "orders.id", "user_id", "amount"]);
+        assert_eq!(rows.len(), 12);
+        let (_, rows) = scan(&mut s, "select * from users join orders on true;")?;
+        assert_eq!(rows.len(), 12);
+
+        // 条件中引用不存在的列
+        assert!(s.execute("select * from users join orders on users.id = x;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_prefix() -> Result<()> {
+        let row = |table: &str| Key::Row(table.to_string(), vec![Value::Integer(1), Value::String("a".to_string())]);
+        let prefix = KeyPrefix::Row("t".to_string()).encode()?;
+        assert!(row("t").encode()?.starts_with(&prefix));
+        assert!(!row("t2").encode()?.starts_with(&prefix));
+        assert!(!Key::TableSequence("t".to_string()).encode()?.starts_with(&prefix));
+
+        let prefix = KeyPrefix::Index("t".to_string(), "i".to_string(), Value::Integer(3)).encode()?;
+        let index = |value: i64| Key::Index("t".to_string(), "i".to_string(), Value::Integer(value), vec![Value::Integer(1)]);
+        assert!(index(3).encode()?.starts_with(&prefix));
+        assert!(!index(4).encode()?.starts_with(&prefix));
+
+        // 表和视图的前缀只匹配对应的变体
+        let prefix = KeyPrefix::Table.encode()?;
+        assert!(Key::Table("t".to_string()).encode()?.starts_with(&prefix));
+        assert!(!Key::TableStats("t".to_string()).encode()?.starts_with(&prefix));
+        let prefix = KeyPrefix::View.encode()?;
+        assert!(Key::View("v".to_string()).encode()?.starts_with(&prefix));
+        assert!(!Key::Table("v".to_string()).encode()?.starts_with(&prefix));
+        Ok(())
+    }
+
+    #[test]
+    fn test_composite_primary_key() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (a int, b int, v text, primary key (a, b));")?;
+        s.execute("create index idx_v on t (v);")?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Vec<Value>>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let row = |a: i64, b: i64, v: &str| vec![Value::Integer(a), Value::Integer(b), Value::String(v.to_string())];
+
+        // 返回的主键包含所有的主键列
+        let result = s.execute("insert into t values (1, 2, 'y'), (2, 1, 'z'), (1, 1, 'x');")?;
+        match &result.result {
+            ResultSet::Insert { keys, .. } => assert_eq!(keys[0], vec![Value::Integer(1), Value::Integer(2)]),
+            _ => unreachable!(),
+        }
+        assert!(result.to_string().starts_with("3 rows inserted (keys (1, 2),(2, 1),(1, 1))"));
+
+        // 行按主键的顺序存放，第一列相同的行按第二列排序
+        assert_eq!(select(&mut s, "select * from t;")?, vec![row(1, 1, "x"), row(1, 2, "y"), row(2, 1, "z")]);
+        assert_eq!(select(&mut s, "select * from t where a = 1;")?, vec![row(1, 1, "x"), row(1, 2, "y")]);
+        assert_eq!(select(&mut s, "select * from t where b = 2 and a = 1;")?, vec![row(1, 2, "y")]);
+        assert_eq!(select(&mut s, "select * from t where v = 'z';")?, vec![row(2, 1, "z")]);
+
+        // 整个复合主键重复时冲突，同一批写入中的重复同样冲突
+        assert_eq!(
+            s.execute("insert into t values (1, 1, 'w');").err(),
+            Some(Error::ConstraintViolation("duplicate primary key (1, 1) in table t".to_string()))
+        );
+        assert_eq!(
+            s.execute("insert into t values (3, 1, 'a'), (3, 1, 'b');").err(),
+            Some(Error::ConstraintViolation("duplicate primary key (3, 1) in table t".to_string()))
+        );
+        // 主键的任何一列都不能为空
+        assert_eq!(
+            s.execute("insert into t values (3, null, 'a');").err(),
+            Some(Error::ConstraintViolation("primary key column b cannot be null".to_string()))
+        );
+        assert_eq!(select(&mut s, "select * from t;")?.len(), 3);
+
+        // 单列主键的表同样不允许重复，失败的语句不写入任何一行，也不留下索引项
+        s.execute("create table s (id int primary key, v text);")?;
+        s.execute("create index idx_s_v on s (v);")?;
+        s.execute("insert into s values (1, 'a');")?;
+        assert_eq!(
+            s.execute("insert into s values (1, 'b');").err(),
+            Some(Error::ConstraintViolation("duplicate primary key 1 in table s".to_string()))
+        );
+        assert_eq!(
+            s.execute("insert into s values (2, 'x'), (2, 'y');").err(),
+            Some(Error::ConstraintViolation("duplicate primary key 2 in table s".to_string()))
+        );
+        let pair = |id: i64, v: &str| vec![Value::Integer(id), Value::String(v.to_string())];
+        assert_eq!(select(&mut s, "select * from s;")?, vec![pair(1, "a")]);
+        let txn = kvengine.begin()?;
+        for v in ["b", "x", "y"] {
+            assert!(txn.scan_index("s".to_string(), "idx_s_v".to_string(), &Value::String(v.to_string()))?.is_empty());
+        }
+        txn.commit()?;
+
+        // 只指定第一列时按前缀扫描，指定所有列时只读取一行
+        let txn = kvengine.begin()?;
+        let ids = |rows: Rows| -> Result<Vec<Row>> { rows.collect() };
+        assert_eq!(
+            ids(txn.scan_table_range("t".to_string(), Some(vec![Value::Integer(1)]), Some(vec![Value::Integer(1)]))?)?,
+            vec![row(1, 1, "x"), row(1, 2, "y")]
+        );
+        assert_eq!(
+            ids(txn.scan_table_range("t".to_string(), Some(vec![Value::Integer(1), Value::Integer(2)]), None)?)?,
+            vec![row(1, 2, "y"), row(2, 1, "z")]
+        );
+        let (page, next) = txn.scan_table_page("t".to_string(), None, 2)?;
+        assert_eq!(page.len(), 2);
+        assert_eq!(next, Some(vec![Value::Integer(1), Value::Integer(2)]));
+        assert_eq!(txn.scan_table_page("t".to_string(), next, 2)?, (vec![row(2, 1, "z")], None));
+        txn.commit()?;
+
+        // 主键中的列必须存在且不能重复，不能和列上的 PRIMARY KEY 冲突
+        assert_eq!(
+            s.execute("create table t2 (a int, b int, primary key (a, c));").err(),
+            Some(Error::ColumnNotFound { table: "t2".to_string(), column: "c".to_string() })
+        );
+        assert!(matches!(s.execute("create table t2 (a int, b int, primary key (a, a));"), Err(Error::Schema(_))));
+        assert!(matches!(
+            s.execute("create table t2 (a int primary key, b int, primary key (b));"),
+            Err(Error::Schema(_))
+        ));
+        assert!(matches!(
+            s.execute("create table t2 (a int auto_increment, b int, primary key (a, b));"),
+            Err(Error::Schema(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_alias() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name text);")?;
+        s.execute("create table orders (id int primary key, user_id int, amount int);")?;
+        s.execute("insert into users values (1, 'a'), (2, 'b');")?;
+        s.execute("insert into orders values (10, 1, 15), (11, 2, 25), (12, 1, 35);")?;
+        let scan = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+                _ => unreachable!(),
+            }
+        };
+        let int = |v: i64| Value::Integer(v);
+        let name = |v: &str| Value::String(v.to_string());
+
+        // 单表的别名
+        let (columns, rows) = scan(&mut s, "select u.name, u.id as uid from users as u where u.id = 2;")?;
+        assert_eq!(columns, vec!["u.name", "uid"]);
+        assert_eq!(rows, vec![vec![name("b"), int(2)]]);
+        let (columns, rows) = scan(&mut s, "select u.* from users u order by u.id desc;")?;
+        assert_eq!(columns, vec!["id", "name"]);
+        assert_eq!(rows, vec![vec![int(2), name("b")], vec![int(1), name("a")]]);
+        match s.execute("explain select * from users u where u.id = 1;")?.result {
+            ResultSet::Explain { plan } => assert_eq!(plan, "Scan: users AS u (filter: u.id = 1)"),
+            _ => unreachable!(),
+        }
+
+        // Join 时同名的列加上别名，表名.* 只展开对应的表
+        let (columns, rows) =
+            scan(&mut s, "select o.*, u.name from users u join orders o on u.id = o.user_id where o.amount > 20;")?;
+        assert_eq!(columns, vec!["id", "user_id", "amount", "u.name"]);
+        assert_eq!(rows, vec![vec![int(11), int(2), int(25), name("b")], vec![int(12), int(1), int(35), name("a")]]);
+        let (columns, rows) = scan(&mut s, "select u.*, o.id from users u join orders o on u.id = o.user_id where o.id = 10;")?;
+        assert_eq!(columns, vec!["id", "name", "o.id"]);
+        assert_eq!(rows, vec![vec![int(1), name("a"), int(10)]]);
+        let (columns, _) = scan(&mut s, "select * from users u cross join orders o;")?;
+        assert_eq!(columns, vec!["u.id", "name", "o.id", "user_id", "amount"]);
+
+        // 有别名之后不能再通过表名引用，引用的表或列不存在时报错
+        for sql in [
+            "select users.name from users u;",
+            "select users.* from users u;",
+            "select * from users u join orders o on users.id = o.user_id;",
+            "select x.* from users;",
+            "select u.amount from users u join orders o on u.id = o.user_id;",
+        ] {
+            assert!(matches!(s.execute(sql), Err(Error::Schema(_))), "{}", sql);
+        }
+        // 同一张表连接自身时需要用别名区分
+        let (columns, rows) = scan(&mut s, "select a.id, b.name from users a join users b on a.id != b.id where a.id = 1;")?;
+        assert_eq!(columns, vec!["a.id", "b.name"]);
+        assert_eq!(rows, vec![vec![int(1), name("b")]]);
+        assert!(matches!(s.execute("select * from users join users on true;"), Err(Error::Schema(_))));
+        assert!(matches!(s.execute("select * from users u join orders u on true;"), Err(Error::Schema(_))));
+        assert!(matches!(s.execute("select * from users u where u.* = 1;"), Err(Error::Parse(_))));
+        assert!(matches!(s.execute("select u.id from users u order by u.*;"), Err(Error::Parse(_))));
+        assert!(matches!(s.execute("select u.* is null from users u;"), Err(Error::Parse(_))));
+        assert!(matches!(s.execute("select u.* as x from users u;"), Err(Error::Parse(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_hash_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table a (id int primary key, k int null);")?;
+        s.execute("create table b (id int primary key, k float null);")?;
+        s.execute("insert into a values (1, 1), (2, 1), (3, 2), (4, null), (5, 3);")?;
+        s.execute("insert into b values (10, 1.0), (11, 1.0), (12, null), (13, 2.5);")?;
+        let rows = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Vec<Value>>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let explain = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<String> {
+            match s.execute(&format!("explain {}", sql))?.result {
+                ResultSet::Explain { plan } => Ok(plan),
+                _ => unreachable!(),
+            }
+        };
+
+        // 两边的 key 都有重复，NULL 不和任何值相等，整数和数值相等的浮点数相等
+        let expect = vec![
+            vec![Value::Integer(1), Value::Integer(1), Value::Integer(10), Value::Float(1.0)],
+            vec![Value::Integer(1), Value::Integer(1), Value::Integer(11), Value::Float(1.0)],
+            vec![Value::Integer(2), Value::Integer(1), Value::Integer(10), Value::Float(1.0)],
+            vec![Value::Integer(2), Value::Integer(1), Value::Integer(11), Value::Float(1.0)],
+        ];
+        let sql = "select * from a join b on a.k = b.k;";
+        assert!(explain(&mut s, sql)?.starts_with("HashJoin: on a.k = b.k"));
+        let mut joined = rows(&mut s, sql)?;
+        joined.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(joined, expect);
+
+        // 条件两边的顺序不影响结果，和逐对比较的结果一致
+        let sql = "select * from a join b on b.k = a.k and true;";
+        assert!(explain(&mut s, sql)?.starts_with("NestedLoopJoin"));
+        assert_eq!(rows(&mut s, sql)?, expect);
+        let mut joined = rows(&mut s, "select * from a join b on b.k = a.k;")?;
+        joined.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(joined, expect);
+
+        // 两列都在同一边时不能使用 HashJoin
+        assert!(explain(&mut s, "select * from a join b on a.id = k;")?.starts_with("NestedLoopJoin"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_show_engine_status() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b int);")?;
+        s.execute("insert into t1 values (1, 1), (2, 2);")?;
+
+        match s.execute("show engine status;")?.result {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["name", "value"]);
+                let get = |name: &str| {
+                    rows.iter()
+                        .find(|r| r[0] == Value::String(name.to_string()))
+                        .map(|r| r[1].clone())
+                };
+                // 存储格式的版本、一张表加上两行数据
+                assert_eq!(get("total_versions"), Some(Value::Integer(4)));
+                // 只有执行当前语句的事务是活跃的
+                assert_eq!(get("active_transactions"), Some(Value::Integer(1)));
+                assert_eq!(get("live_entry_ratio"), Some(Value::Float(1.0)));
+                // 内存引擎中所有的数据都是有效的
+                assert_eq!(get("live_data_bytes"), get("disk_size_bytes"));
+            }
+            _ => unreachable!(),
+        }
+
+        // 插入之后 key 的数量和数据的大小都会增加
+        let status = |s: &mut Session<KVEngine<MemoryEngine>>, name: &str| -> Result<i64> {
+            match s.execute("show engine status;")?.result {
+                ResultSet::Scan { rows, .. } => match rows.iter().find(|r| r[0] == Value::String(name.to_string())) {
+                    Some(row) => match row[1] {
+                        Value::Integer(v) => Ok(v),
+                        _ => unreachable!(),
+                    },
+                    None => unreachable!(),
+                },
+                _ => unreachable!(),
+            }
+        };
+        let (entries, bytes) = (status(&mut s, "entry_count")?, status(&mut s, "live_data_bytes")?);
+        s.execute("insert into t1 values (3, 3);")?;
+        // 新的一行只增加一个版本，事务的其他记录在提交时已经删除
+        assert_eq!(status(&mut s, "entry_count")?, entries + 1);
+        assert!(status(&mut s, "live_data_bytes")? > bytes);
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_keys() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key);")?;
+        s.execute("insert into t values (1);")?;
+
+        let debug = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => {
+                    assert_eq!(columns, vec!["key", "raw_key", "value_size", "tombstone", "expires_at"]);
+                    Ok(rows)
+                }
+                _ => unreachable!(),
+            }
+        };
+        let find = |rows: &[Row], key: &str| rows.iter().find(|r| r[0] == Value::String(key.to_string())).cloned();
+
+        // 另一个会话中未提交的事务同样可以看到
+        let mut other = kvengine.session()?;
+        other.execute("begin;")?;
+        let rows = debug(&mut s, "debug keys;")?;
+        let next_version = find(&rows, "NextVersion").unwrap();
+        assert_eq!(next_version[1], Value::String("00".to_string()));
+        assert_eq!(next_version[3], Value::Null);
+        let active = rows.iter().filter(|r| matches!(&r[0], Value::String(k) if k.starts_with("TxnActive("))).count();
+        assert_eq!(active, 2);
+        let row = find(&rows, "Version(Row(\"t\", [Integer(1)]), 2)").unwrap();
+        assert_eq!(row[3], Value::Boolean(false));
+        assert_eq!(row[4], Value::Null);
+        other.execute("rollback;")?;
+
+        // 删除之后旧的版本仍然保留，新的版本是删除标记
+        s.execute("truncate table t;")?;
+        let rows = debug(&mut s, "debug keys 'Version(Row';")?;
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][3], Value::Boolean(false));
+        assert_eq!(rows[1][3], Value::Boolean(true));
+        assert_eq!(debug(&mut s, "debug keys 'TxnActive';")?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_where() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int, a int, b int null);")?;
+        s.execute("insert into t1 values (1, 5, 3), (2, 2, 7), (3, 4, 4), (4, 9, null);")?;
+
+        let ids = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|r| r[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 比较同一行中的两列，b 为 NULL 的行不满足条件
+        assert_eq!(ids(&mut s, "select * from t1 where a > b;")?, vec![Value::Integer(1)]);
+        assert_eq!(
+            ids(&mut s, "select * from t1 where a >= b;")?,
+            vec![Value::Integer(1), Value::Integer(3)]
+        );
+        assert_eq!(
+            ids(&mut s, "select * from t1 where a != b or id = 4;")?,
+            vec![Value::Integer(1), Value::Integer(2), Value::Integer(4)]
+        );
+        assert_eq!(
+            ids(&mut s, "select * from t1 where not (a < b) and a < 5;")?,
+            vec![Value::Integer(3)]
+        );
+
+        // 常量条件在执行前被折叠
+        assert_eq!(ids(&mut s, "select * from t1 where 1 > 2 or id = 2;")?, vec![Value::Integer(2)]);
+
+        // 很长的 OR 条件列表
+        let chain = (0..1000).map(|i| format!("id = {}", i * 3)).collect::<Vec<_>>().join(" or ");
+        assert_eq!(
+            ids(&mut s, &format!("select * from t1 where {};", chain))?,
+            vec![Value::Integer(3)]
+        );
+        assert_eq!(ids(&mut s, "select * from t1 where 1 < 2 and 1 > 2;")?, vec![]);
+        assert!(s.execute("select * from t1 where 1 > 'x';").is_err());
+
+        // NULL = NULL 的结果是 NULL，只有 IS NULL 能选出 b 为 NULL 的行
+        assert_eq!(ids(&mut s, "select * from t1 where b is null;")?, vec![Value::Integer(4)]);
+        assert_eq!(ids(&mut s, "select * from t1 where b = null;")?, vec![]);
+        assert_eq!(ids(&mut s, "select * from t1 where not (b = null);")?, vec![]);
+        assert_eq!(
+            ids(&mut s, "select * from t1 where b is not null and a > 3;")?,
+            vec![Value::Integer(1), Value::Integer(3)]
+        );
+
+        // BETWEEN 和 IN，常量的 IN 列表同样会被折叠
+        assert_eq!(
+            ids(&mut s, "select * from t1 where a between 4 and 5 and b in (3, 4);")?,
+            vec![Value::Integer(1), Value::Integer(3)]
+        );
+        assert_eq!(
+            ids(&mut s, "select * from t1 where b not between 3 and 4 or id not in (1, 2, 3);")?,
+            vec![Value::Integer(2), Value::Integer(4)]
+        );
+        assert_eq!(ids(&mut s, "select * from t1 where b not in (3, null);")?, vec![]);
+        assert_eq!(ids(&mut s, "select * from t1 where 2 in (1, 2) and id in ();")?, vec![]);
+        assert!(s.execute("select * from t1 where a in (1, 'x');").is_err());
+
+        assert!(s.execute("select * from t1 where c > 1;").is_err());
+        assert!(s.execute("select * from t1 where a;").is_err());
+        assert!(s.execute("select * from t1 where a > 'x';").is_err());
+
+        // 连接之后的过滤
+        s.execute("create table t2 (id int, c int);")?;
+        s.execute("insert into t2 values (1, 10), (2, 20);")?;
+        assert_eq!(
+            ids(&mut s, "select * from t1 natural join t2 where c > a;")?,
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+        assert_eq!(
+            ids(&mut s, "select * from t1 natural join t2 where c > 15;")?,
+            vec![Value::Integer(2)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_insert_keys() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (a int, b text);")?;
+
+        let keys = match s.execute("insert into t1(b, a) values ('x', 3), ('y', 1), ('z', 2);")?.result {
+            ResultSet::Insert { count, keys } => {
+                assert_eq!(count, 3);
+                keys.concat()
+            }
+            _ => unreachable!(),
+        };
+        assert_eq!(keys, vec![Value::Integer(3), Value::Integer(1), Value::Integer(2)]);
+
+        // 返回的主键和查询出来的主键一致
+        match s.execute("select * from t1;")?.result {
+            ResultSet::Scan { rows, .. } => {
+                let mut selected = rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+                let mut keys = keys;
+                selected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                keys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                assert_eq!(selected, keys);
+            }
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_select_like() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t1 (id int, name text null);")?;
+        s.execute("insert into t1 values (1, 'abc'), (2, 'xyz'), (3, '50%'), (4, null);")?;
+
+        let ids = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|r| r[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(ids(&mut s, "select * from t1 where name like 'a%';")?, vec![Value::Integer(1)]);
+        assert_eq!(ids(&mut s, "select * from t1 where name like '%\\%';")?, vec![Value::Integer(3)]);
+        // NULL 既不满足 LIKE 也不满足 NOT LIKE
+        assert_eq!(
+            ids(&mut s, "select * from t1 where name not like 'a%';")?,
+            vec![Value::Integer(2), Value::Integer(3)]
+        );
+        assert!(s.execute("select * from t1 where id like '1';").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_increment() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (name varchar, id int primary key auto_increment);")?;
+
+        let keys = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Insert { keys, .. } => Ok(keys.concat()),
+                _ => unreachable!(),
+            }
+        };
+        // 省略或者为 NULL 时自动分配
+        assert_eq!(
+            keys(&mut s, "insert into t(name) values ('a'), ('b');")?,
+            vec![Value::Integer(1), Value::Integer(2)]
+        );
+        assert_eq!(keys(&mut s, "insert into t values ('c', null);")?, vec![Value::Integer(3)]);
+        // 显式指定的值会推进计数器
+        assert_eq!(
+            keys(&mut s, "insert into t values ('d', 10), ('e', null), ('f', 5);")?,
+            vec![Value::Integer(10), Value::Integer(11), Value::Integer(5)]
+        );
+        assert_eq!(keys(&mut s, "insert into t(name) values ('g');")?, vec![Value::Integer(12)]);
+        assert_eq!(s.last_insert_id(), Some(12));
+        // 没有分配自增值的语句不改变 last_insert_id
+        keys(&mut s, "insert into t values ('g2', 100);")?;
+        assert_eq!(s.last_insert_id(), Some(12));
+
+        // 回滚之后计数器同样回滚
+        let mut tx = kvengine.begin()?;
+        assert_eq!(tx.create_row("t".to_string(), vec![Value::String("h".to_string()), Value::Null])?, vec![Value::Integer(101)]);
+        assert_eq!(tx.last_insert_id(), Some(101));
+        tx.rollback()?;
+        assert_eq!(keys(&mut s, "insert into t(name) values ('i');")?, vec![Value::Integer(101)]);
+
+        // 并发分配时后写入计数器的事务冲突
+        let mut tx1 = kvengine.begin()?;
+        let mut tx2 = kvengine.begin()?;
+        tx1.create_row("t".to_string(), vec![Value::String("j".to_string()), Value::Null])?;
+        assert_eq!(
+            tx2.create_row("t".to_string(), vec![Value::String("k".to_string()), Value::Null]),
+            Err(Error::TransactionConflict)
+        );
+        tx2.rollback()?;
+        tx1.commit()?;
+
+        match s.execute("select * from t where name = 'j';")?.result {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows, vec![vec![Value::String("j".to_string()), Value::Integer(102)]])
+            }
+            _ => unreachable!(),
+        }
+
+        // SERIAL 等同于 INTEGER AUTO_INCREMENT
+        s.execute("create table t3 (id serial primary key, name varchar);")?;
+        assert_eq!(keys(&mut s, "insert into t3(name) values ('a'), ('b');")?, vec![Value::Integer(1), Value::Integer(2)]);
+        assert_eq!(s.last_insert_id(), Some(2));
+
+        // 自增列必须是整数类型的主键
+        assert!(matches!(s.execute("create table t2 (id int auto_increment);"), Err(Error::Schema(_))));
+        assert!(matches!(
+            s.execute("create table t2 (id text primary key auto_increment);"),
+            Err(Error::Schema(_))
+        ));
+        assert!(matches!(
+            s.execute("create table t2 (a int primary key, b int primary key);"),
+            Err(Error::Schema(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_index() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, name varchar, score int);")?;
+        s.execute("insert into t values (1, 'a', 10), (2, 'b', 20), (3, 'a', 30);")?;
+
+        // 建索引时回填已有的数据
+        s.execute("create index idx_name on t(name);")?;
+        assert!(s.execute("create index idx_name on t(score);").is_err());
+        assert!(s.execute("create index idx_x on t(typo);").is_err());
+        assert!(s.execute("create index idx_x on missing(name);").is_err());
+
+        let tx = kvengine.begin()?;
+        let table = tx.must_get_table("t".to_string())?;
+        assert_eq!(table.indexes.len(), 1);
+        let lookup = |value: &str| -> Result<Vec<Value>> {
+            let mut ids = tx
+                .scan_index("t".to_string(), "idx_name".to_string(), &Value::String(value.to_string()))?
+                .into_iter()
+                .map(|row| row[0].clone())
+                .collect::<Vec<_>>();
+            ids.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            Ok(ids)
+        };
+        assert_eq!(lookup("a")?, vec![Value::Integer(1), Value::Integer(3)]);
+        assert_eq!(lookup("c")?, vec![]);
+        tx.commit()?;
+
+        // 插入和更新时维护索引
+        s.execute("insert into t values (4, 'c', 40);")?;
+        let mut tx = kvengine.begin()?;
+        tx.update_row("t".to_string(), vec![Value::Integer(1)], vec![Value::Integer(1), Value::String("c".to_string()), Value::Integer(11)])?;
+        let ids = |tx: &super::KVTransaction<MemoryEngine>, value: &str| -> Result<usize> {
+            Ok(tx.scan_index("t".to_string(), "idx_name".to_string(), &Value::String(value.to_string()))?.len())
+        };
+        assert_eq!(ids(&tx, "a")?, 1);
+        assert_eq!(ids(&tx, "c")?, 2);
+        tx.commit()?;
+
+        // 查询走索引时结果和全表扫描一致，其余条件依然生效
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|r| r[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(select(&mut s, "select * from t where name = 'c' and score > 20;")?, vec![Value::Integer(4)]);
+        assert_eq!(select(&mut s, "select * from t where 'a' = name;")?, vec![Value::Integer(3)]);
+
+        s.execute("drop index idx_name;")?;
+        assert!(s.execute("drop index idx_name;").is_err());
+        let tx = kvengine.begin()?;
+        assert!(tx.must_get_table("t".to_string())?.indexes.is_empty());
+        assert_eq!(ids(&tx, "c")?, 0);
+        tx.commit()?;
+        assert_eq!(select(&mut s, "select * from t where name = 'c';")?.len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_version() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let v1 = s.execute("create table t (a int primary key);")?.version;
+        let v2 = s.execute("insert into t values (1);")?.version;
+        let v3 = s.execute("select * from t;")?.version;
+        assert!(v1 < v2 && v2 < v3);
+
+        let tx = kvengine.begin()?;
+        assert!(tx.commit()? > v3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_column_comment() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key comment 'user id', name varchar(20) comment 'the user name', age int default 18);")?;
+
+        let table = kvengine.begin()?.must_get_table("users".to_string())?;
+        let comments = table.columns.iter().map(|c| c.comment.as_deref()).collect::<Vec<_>>();
+        assert_eq!(comments, vec![Some("user id"), Some("the user name"), None]);
+
+        match s.execute("describe users;")?.result {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["column", "type", "nullable", "default", "primary_key", "comment"]);
+                let (st, b) = (|v: &str| Value::String(v.to_string()), Value::Boolean);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![st("id"), st("INTEGER"), b(false), Value::Null, b(true), st("user id")],
+                        vec![st("name"), st("STRING(20)"), b(true), st("NULL"), b(false), st("the user name")],
+                        vec![st("age"), st("INTEGER"), b(true), st("18"), b(false), Value::Null],
+                    ]
+                );
+            },
+            _ => unreachable!(),
+        }
+        assert!(matches!(s.execute("describe missing;"), Err(Error::TableNotFound(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_varchar_max_len() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, name varchar(5), note varchar);")?;
+
+        // 按字符数计算长度
+        s.execute("insert into t values (1, 'abcde', 'x');")?;
+        s.execute("insert into t values (2, '你好世界啊', null);")?;
+        s.execute("insert into t values (3, null, null);")?;
+        assert_eq!(
+            s.execute("insert into t values (4, 'abcdef', 'x');").err(),
+            Some(Error::ConstraintViolation("value for column name exceeds maximum length 5".to_string()))
+        );
+        // 多行插入时任意一行超长，整条语句都不写入
+        assert!(s.execute("insert into t values (5, 'a', 'x'), (6, 'abcdefg', 'x');").is_err());
+
+        // 未指定长度时不限制
+        let long = "x".repeat(10_000);
+        s.execute(&format!("insert into t values (7, 'a', '{}');", long))?;
+
+        match s.execute("select * from t;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 4),
+            _ => unreachable!(),
+        }
+
+        // 默认值同样受长度限制
+        assert!(matches!(
+            s.execute("create table t2 (a varchar(2) default 'abc');"),
+            Err(Error::ConstraintViolation(_))
+        ));
+
+        // 非空约束
+        s.execute("create table t3 (id int primary key, name varchar not null);")?;
+        assert_eq!(
+            s.execute("insert into t3 values (1, null);").err(),
+            Some(Error::ConstraintViolation("column name cannot be null".to_string()))
+        );
+        Ok(())
+    }
+
+    // 开启 slow 之后每次读取都会等待一段时间
+    struct SlowEngine {
+        inner: MemoryEngine,
+        slow: Arc<AtomicBool>,
+    }
+
+    impl SlowEngine {
+        fn wait(&self) {
+            if self.slow.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        }
+    }
+
+    impl StorageEngine for SlowEngine {
+        type EngineIterator<'a> = MemoryEngineIterator<'a>;
+
+        fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+            self.inner.set(key, value)
+        }
+
+        fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+            self.wait();
+            self.inner.get(key)
+        }
+
+        fn delete(&mut self, key: &[u8]) -> Result<()> {
+            self.inner.delete(key)
+        }
+
+        fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+            self.wait();
+            self.inner.scan(range)
+        }
+    }
+
+    impl EngineStats for SlowEngine {
+        fn entry_count(&self) -> Result<usize> {
+            self.inner.entry_count()
+        }
+
+        fn disk_size_bytes(&self) -> Result<u64> {
+            self.inner.disk_size_bytes()
+        }
+
+        fn live_data_bytes(&self) -> Result<u64> {
+            self.inner.live_data_bytes()
+        }
+
+        fn live_entry_ratio(&self) -> Result<f64> {
+            self.inner.live_entry_ratio()
+        }
+    }
+
+    #[test]
+    fn test_statement_timeout() -> Result<()> {
+        let slow = Arc::new(AtomicBool::new(false));
+        let kvengine = KVEngine::new(SlowEngine { inner: MemoryEngine::new(), slow: slow.clone() })?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key);")?;
+        let values = (0..500).map(|i| format!("({})", i)).collect::<Vec<_>>();
+        let insert = format!("insert into t values {};", values.join(", "));
+        let count = |s: &mut Session<KVEngine<SlowEngine>>| -> Result<usize> {
+            match s.execute("select * from t;")?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 超时之后中断执行，已经写入的部分被回滚
+        slow.store(true, Ordering::Relaxed);
+        assert_eq!(s.execute_with_timeout(&insert, Duration::from_millis(10)).err(), Some(Error::Timeout));
+        slow.store(false, Ordering::Relaxed);
+        assert_eq!(count(&mut s)?, 0);
+
+        // 在其他线程中取消
+        slow.store(true, Ordering::Relaxed);
+        let cancelled = s.cancel_flag();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(10));
+            cancelled.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(s.execute(&insert).err(), Some(Error::Cancelled));
+        handle.join().unwrap();
+        slow.store(false, Ordering::Relaxed);
+        assert_eq!(count(&mut s)?, 0);
+
+        // 取消标记在下一条语句开始时重置，没有超时的语句正常执行
+        s.execute_with_timeout(&insert, Duration::from_secs(60))?;
+        assert_eq!(count(&mut s)?, 500);
+        Ok(())
+    }
+
+    // 对比过滤条件下推前后扫描的耗时，下推之后不满足条件的行不会返回给上层
+    #[test]
+    fn test_scan_table_filter() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int, name string);")?;
+        let values = (0..5000).map(|i| format!("({}, {}, 'row{}')", i, i % 100, i)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+        let txn = kvengine.begin()?;
+        let filter = Parser::new("select * from t where v = 7;").parse()?;
+        let filter = match filter {
+            Statement::Select { where_clause: Some(filter), .. } => filter,
+            _ => unreachable!(),
+        };
+        let columns = vec!["id".to_string(), "v".to_string(), "name".to_string()];
+
+        // 下推到 scan_table 的过滤条件和扫描之后再过滤的结果相同，耗时的比较见 benches/scan_filter.rs
+        let before = filter_rows(filter.clone(), columns, txn.scan_table("t".to_string(), None)?).collect::<Result<Vec<_>>>()?;
+        let after = txn.scan_table("t".to_string(), Some(&filter))?.collect::<Result<Vec<_>>>()?;
+        assert_eq!(before, after);
+        assert_eq!(after.len(), 50);
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_table_pk_order() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|row| row[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 行 key 使用保持顺序的编码，扫描的结果按主键的值排序，而不是插入的顺序
+        // 负数的情况见 test_scan_table_range
+        s.execute("create table t (id int primary key);")?;
+        s.execute("insert into t values (20), (1), (10), (2);")?;
+        assert_eq!(
+            select(&mut s, "select * from t;")?,
+            [1, 2, 10, 20].into_iter().map(Value::Integer).collect::<Vec<_>>()
+        );
+
+        s.execute("create table f (id float primary key);")?;
+        s.execute("insert into f values (2.25), (10.0), (0.5);")?;
+        assert_eq!(
+            select(&mut s, "select * from f;")?,
+            [0.5, 2.25, 10.0].into_iter().map(Value::Float).collect::<Vec<_>>()
+        );
+
+        s.execute("create table s (id string primary key);")?;
+        s.execute("insert into s values ('ba'), ('b'), ('ab'), ('a');")?;
+        assert_eq!(
+            select(&mut s, "select * from s;")?,
+            ["a", "ab", "b", "ba"].into_iter().map(|v| Value::String(v.to_string())).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_table_range() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+        // 包含负数，编码后的顺序需要和数值的顺序一致
+        let mut txn = kvengine.begin()?;
+        let rows = (-50..50).map(|i| vec![Value::Integer(i), Value::Integer(i * 2)]).collect();
+        txn.create_rows("t".to_string(), rows)?;
+        txn.commit()?;
+
+        let txn = kvengine.begin()?;
+        let ids = |rows: Rows| -> Result<Vec<Value>> { rows.map(|row| Ok(row?[0].clone())).collect() };
+        let range = |start: i64, end: i64| (start..=end).map(Value::Integer).collect::<Vec<_>>();
+        assert_eq!(
+            ids(txn.scan_table_range("t".to_string(), Some(vec![Value::Integer(-5)]), Some(vec![Value::Integer(20)]))?)?,
+            range(-5, 20)
+        );
+        assert_eq!(ids(txn.scan_table_range("t".to_string(), None, Some(vec![Value::Integer(-45)]))?)?, range(-50, -45));
+        assert_eq!(ids(txn.scan_table_range("t".to_string(), Some(vec![Value::Integer(45)]), None)?)?, range(45, 49));
+        assert_eq!(ids(txn.scan_table_range("t".to_string(), None, None)?)?, range(-50, 49));
+        assert_eq!(ids(txn.scan_table_range("t".to_string(), Some(vec![Value::Integer(100)]), None)?)?, vec![]);
+        txn.commit()?;
+
+        // 主键上的范围条件按范围扫描，开区间由过滤条件保证
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|row| row[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(select(&mut s, "select * from t where id >= 10 and id <= 20;")?, range(10, 20));
+        assert_eq!(select(&mut s, "select * from t where id > 10 and 20 > id and v != 30;")?, {
+            let mut ids = range(11, 19);
+            ids.retain(|id| id != &Value::Integer(15));
+            ids
+        });
+        assert_eq!(select(&mut s, "select * from t where id < 0 and id > 48;")?, vec![]);
+        assert_eq!(select(&mut s, "select * from t where id = 7;")?, range(7, 7));
+        Ok(())
+    }
+
+    #[test]
+    fn test_order_by() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, a int null, b text);")?;
+        s.execute("insert into t values (1, 2, 'x'), (2, null, 'y'), (3, 1, 'y'), (4, 2, 'w'), (5, 1, 'x');")?;
+        let ids = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|row| row[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+        let int = |ids: &[i64]| ids.iter().map(|i| Value::Integer(*i)).collect::<Vec<_>>();
+
+        // NULL 在升序时排在最前面，降序时排在最后面，相等的行保持扫描的顺序
+        assert_eq!(ids(&mut s, "select * from t order by a;")?, int(&[2, 3, 5, 1, 4]));
+        assert_eq!(ids(&mut s, "select * from t order by a desc;")?, int(&[1, 4, 3, 5, 2]));
+        assert_eq!(ids(&mut s, "select * from t order by a desc, b asc;")?, int(&[4, 1, 5, 3, 2]));
+        assert_eq!(ids(&mut s, "select * from t where b != 'w' order by b desc, id desc;")?, int(&[3, 2, 5, 1]));
+        assert!(s.execute("select * from t order by c;").is_err());
+
+        match s.execute("explain select * from t where a > 1 order by b desc;")?.result {
+            ResultSet::Explain { plan } => assert_eq!(plan, "Sort: b DESC\n-> Scan: t (filter: a > 1)"),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_group_by() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, g text null, v int null);")?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+                _ => unreachable!(),
+            }
+        };
+
+        // 表为空且没有 GROUP BY 时仍然输出一行
+        let (columns, rows) = select(&mut s, "select count(*), count(v), sum(v), avg(v), min(v), max(v) from t;")?;
+        assert_eq!(columns, vec!["COUNT(*)", "COUNT(v)", "SUM(v)", "AVG(v)", "MIN(v)", "MAX(v)"]);
+        assert_eq!(
+            rows,
+            vec![vec![Value::Integer(0), Value::Integer(0), Value::Null, Value::Null, Value::Null, Value::Null]]
+        );
+        assert!(select(&mut s, "select g, count(*) from t group by g;")?.1.is_empty());
+
+        s.execute(
+            "insert into t values (1, 'a', 1), (2, null, 5), (3, 'b', null), (4, 'a', 3), (5, null, null), (6, 'b', 4);",
+        )?;
+        // NULL 属于同一组，聚合函数跳过 NULL
+        let (columns, rows) =
+            select(&mut s, "select g, count(*) as n, count(v), sum(v), avg(v), min(v), max(v) from t group by g order by g;")?;
+        assert_eq!(columns, vec!["g", "n", "COUNT(v)", "SUM(v)", "AVG(v)", "MIN(v)", "MAX(v)"]);
+        let s_ = |s: &str| Value::String(s.to_string());
+        let i = Value::Integer;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Null, i(2), i(1), i(5), Value::Float(5.0), i(5), i(5)],
+                vec![s_("a"), i(2), i(2), i(4), Value::Float(2.0), i(1), i(3)],
+                vec![s_("b"), i(2), i(1), i(4), Value::Float(4.0), i(4), i(4)],
+            ]
+        );
+
+        // HAVING 可以使用没有出现在 select 中的聚合函数，ORDER BY 可以使用别名
+        let (_, rows) = select(&mut s, "select g, sum(v) as total from t group by g having count(v) > 0 order by total desc;")?;
+        assert_eq!(rows, vec![vec![Value::Null, i(5)], vec![s_("a"), i(4)], vec![s_("b"), i(4)]]);
+        let (_, rows) = select(&mut s, "select count(*) from t where v > 1;")?;
+        assert_eq!(rows, vec![vec![i(3)]]);
+
+        assert!(s.execute("select id, count(*) from t group by g;").is_err());
+        assert!(s.execute("select * from t group by g;").is_err());
+        assert!(s.execute("select g from t where count(*) > 1 group by g;").is_err());
+        assert!(s.execute("select sum(count(v)) from t;").is_err());
+        // 对文本列求和或者求平均值时报告类型不匹配
+        assert_eq!(
+            s.execute("select sum(g) from t;").err(),
+            Some(Error::TypeMismatch { column: "g".to_string(), expected: DataType::Float, got: DataType::String })
+        );
+        assert_eq!(
+            s.execute("select avg(g) from t;").err(),
+            Some(Error::TypeMismatch { column: "g".to_string(), expected: DataType::Float, got: DataType::String })
+        );
+
+        // 分组之后聚合函数改为引用 GroupBy 输出的列，列名需要加上引号
+        match s.execute("explain select g, count(*) as n from t group by g having count(*) > 1;")?.result {
+            ResultSet::Explain { plan } => assert_eq!(
+                plan,
+                "Compute: g, \"COUNT(*)\" AS n\n-> GroupBy: keys [g], aggregates [COUNT(*)] (having: \"COUNT(*)\" > 1)\n  -> Scan: t"
+            ),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_numeric_aggregates() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, amount float null, qty int null, name text null, note text null);")?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { mut rows, .. } => Ok(rows.remove(0)),
+                _ => unreachable!(),
+            }
+        };
+        let f = Value::Float;
+        let i = Value::Integer;
+        let s_ = |s: &str| Value::String(s.to_string());
+
+        // 没有行时除了 COUNT 都返回 NULL
+        assert_eq!(
+            select(&mut s, "select sum(amount), avg(amount), min(amount), max(amount), count(amount) from t;")?,
+            vec![Value::Null, Value::Null, Value::Null, Value::Null, i(0)]
+        );
+
+        s.execute(
+            "insert into t values (1, 2.5, 2, 'pear', null), (2, null, null, null, null), (3, 1.0, 5, 'apple', null), (4, 4.5, 1, 'fig', null);",
+        )?;
+        // 浮点数列的结果为浮点数，整数列的 SUM 为整数，AVG 总是浮点数，NULL 不参与计算
+        assert_eq!(
+            select(&mut s, "select sum(amount), avg(amount), min(amount), max(amount) from t;")?,
+            vec![f(8.0), f(8.0 / 3.0), f(1.0), f(4.5)]
+        );
+        assert_eq!(
+            select(&mut s, "select sum(qty), avg(qty), min(qty), max(qty), count(qty) from t;")?,
+            vec![i(8), f(8.0 / 3.0), i(1), i(5), i(3)]
+        );
+        // MIN 和 MAX 按 Value 的顺序比较字符串
+        assert_eq!(select(&mut s, "select min(name), max(name) from t;")?, vec![s_("apple"), s_("pear")]);
+        // 全部为 NULL 的列同样返回 NULL
+        assert_eq!(
+            select(&mut s, "select sum(note), avg(note), min(note), max(note), count(note) from t;")?,
+            vec![Value::Null, Value::Null, Value::Null, Value::Null, i(0)]
+        );
+        assert_eq!(select(&mut s, "select sum(amount), sum(qty) from t where id > 2;")?, vec![f(5.5), i(6)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_distinct() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table items (id int primary key, category text null, price int);")?;
+        s.execute(
+            "insert into items values (1, 'b', 10), (2, 'a', 20), (3, 'b', 10), (4, null, 5), (5, 'a', 30), (6, null, 5);",
+        )?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Vec<Value>>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let text = |s: &str| Value::String(s.to_string());
+
+        // 按第一次出现的顺序输出，NULL 只保留一个
+        assert_eq!(
+            select(&mut s, "select distinct category from items;")?,
+            vec![vec![text("b")], vec![text("a")], vec![Value::Null]]
+        );
+        // 按输出的整行去重
+        assert_eq!(
+            select(&mut s, "select distinct category, price from items order by price desc;")?,
+            vec![
+                vec![text("a"), Value::Integer(30)],
+                vec![text("a"), Value::Integer(20)],
+                vec![text("b"), Value::Integer(10)],
+                vec![Value::Null, Value::Integer(5)],
+            ]
+        );
+        assert_eq!(select(&mut s, "select distinct * from items;")?.len(), 6);
+        assert_eq!(select(&mut s, "select category from items;")?.len(), 6);
+
+        match s.execute("explain select distinct category from items;")?.result {
+            ResultSet::Explain { plan } => assert_eq!(plan, "Distinct\n-> Compute: category\n  -> Scan: items"),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_operations() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table a (id int primary key, v int null);")?;
+        s.execute("insert into a values (1, 1), (2, 1), (3, 2), (4, 3), (5, null), (6, null);")?;
+        s.execute("create table b (id int primary key, v int null, w int null);")?;
+        s.execute("insert into b values (1, 1, 0), (2, 2, 0), (3, 2, 0), (4, null, 0), (5, 4, 0);")?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Value>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.into_iter().map(|row| row[0].clone()).collect()),
+                _ => unreachable!(),
+            }
+        };
+        let (i, null) = (Value::Integer, Value::Null);
+
+        assert_eq!(
+            select(&mut s, "select v from a union all select v from b;")?,
+            vec![i(1), i(1), i(2), i(3), null.clone(), null.clone(), i(1), i(2), i(2), null.clone(), i(4)]
+        );
+        // 去重时 NULL 和 NULL 相同，按第一次出现的顺序输出
+        assert_eq!(select(&mut s, "select v from a union select v from b;")?, vec![i(1), i(2), i(3), null.clone(), i(4)]);
+        assert_eq!(select(&mut s, "select v from a union distinct select v from b;")?, vec![i(1), i(2), i(3), null.clone(), i(4)]);
+        assert_eq!(select(&mut s, "select v from a intersect select v from b;")?, vec![i(1), i(2), null.clone()]);
+        // 1 在左边出现两次、右边出现一次，2 在左边出现一次、右边出现两次
+        assert_eq!(select(&mut s, "select v from a intersect all select v from b;")?, vec![i(1), i(2), null.clone()]);
+        assert_eq!(select(&mut s, "select v from a except select v from b;")?, vec![i(3)]);
+        assert_eq!(select(&mut s, "select v from a except all select v from b;")?, vec![i(1), i(3), null.clone()]);
+
+        // INTERSECT 优先于 UNION，相同优先级从左往右
+        assert_eq!(
+            select(&mut s, "select v from a union select v from b intersect select v from b where v = 4;")?,
+            vec![i(1), i(2), i(3), null.clone(), i(4)]
+        );
+        assert_eq!(select(&mut s, "select v from a except select v from b union select w from b;")?, vec![i(3), i(0)]);
+
+        // 列名和左边一致，两边的列数必须相同
+        match s.execute("select v as x from a union select w from b;")?.result {
+            ResultSet::Scan { columns, .. } => assert_eq!(columns, vec!["x"]),
+            _ => unreachable!(),
+        }
+        assert!(matches!(s.execute("select v from a union select v, w from b;"), Err(Error::Schema(_))));
+
+        match s.execute("explain select v from a except all select v from b;")?.result {
+            ResultSet::Explain { plan } => {
+                assert_eq!(plan, "SetOperation: EXCEPT ALL\n-> Compute: v\n  -> Scan: a\n-> Compute: v\n  -> Scan: b")
+            },
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_quoted_names() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute(r#"create table "order" ("select" int primary key, "my col" string, "Total" int);"#)?;
+        s.execute(r#"insert into "order" ("select", "my col", "Total") values (1, 'a', 10), (2, 'b', 20);"#)?;
+        s.execute(r#"insert into "order" values (3, 'c', 30);"#)?;
+        // 表名和列名按原样保存，引号内区分大小写
+        assert_eq!(kvengine.begin()?.get_table("order".to_string())?.map(|t| t.columns.len()), Some(3));
+        assert!(s.execute(r#"select total from "order";"#).is_err());
+        assert!(s.execute(r#"select * from "ORDER";"#).is_err());
+
+        match s.execute(r#"select "my col", "Total" from "order" where "select" >= 2 order by "select" desc;"#)?.result {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["my col", "Total"]);
+                assert_eq!(
+                    rows,
+                    vec![
+                        vec![Value::String("c".to_string()), Value::Integer(30)],
+                        vec![Value::String("b".to_string()), Value::Integer(20)],
+                    ]
+                );
+            },
+            _ => unreachable!(),
+        }
+        match s.execute(r#"select "order"."select" from "order" where "my col" = 'a';"#)?.result {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["order.select"]);
+                assert_eq!(rows, vec![vec![Value::Integer(1)]]);
+            },
+            _ => unreachable!(),
+        }
+
+        // EXPLAIN 中需要引号的名字重新加上引号
+        match s.execute(r#"explain select "my col" as "Name" from "order" where "Total" > 10;"#)?.result {
+            ResultSet::Explain { plan } => {
+                assert_eq!(plan, "Compute: \"my col\" AS \"Name\"\n-> Scan: \"order\" (filter: \"Total\" > 10)")
+            },
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_view() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name string, active bool);")?;
+        s.execute("insert into users values (1, 'a', true), (2, 'b', false), (3, 'c', true);")?;
+        s.execute("create table orders (id int primary key, user_id int);")?;
+        s.execute("insert into orders values (1, 1), (2, 3), (3, 3), (4, 2);")?;
+        assert!(matches!(
+            s.execute("create view active_users as select id, name from users where active = true;")?.result,
+            ResultSet::CreateView { view_name } if view_name == "active_users"
+        ));
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Vec<Value>>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let (i, st) = (Value::Integer, |v: &str| Value::String(v.to_string()));
+
+        // 视图和表一样可以过滤、排序、连接，看到的是查询时的数据
+        s.execute("insert into users values (4, 'd', true);")?;
+        assert_eq!(
+            select(&mut s, "select name from active_users where id > 1 order by id desc;")?,
+            vec![vec![st("d")], vec![st("c")]]
+        );
+        assert_eq!(
+            select(&mut s, "select name, count(*) from active_users join orders on id = user_id group by name;")?,
+            vec![vec![st("a"), i(1)], vec![st("c"), i(2)]]
+        );
+        // 视图之上可以再建立视图，EXPLAIN 输出的是展开之后的执行计划
+        s.execute("create view a_users as select * from active_users where name = 'a';")?;
+        assert_eq!(select(&mut s, "select * from a_users;")?, vec![vec![i(1), st("a")]]);
+        match s.execute("explain select * from a_users;")?.result {
+            ResultSet::Explain { plan } => {
+                assert_eq!(plan, "Filter: name = 'a'\n-> Compute: id, name\n  -> Scan: users (filter: active = TRUE)")
+            },
+            _ => unreachable!(),
+        }
+        assert_eq!(select(&mut s, "show views;")?, vec![vec![st("a_users")], vec![st("active_users")]]);
+
+        // 视图是只读的，名字不能和表或者其他视图相同，定义中的查询在建立时校验
+        for sql in [
+            "insert into active_users values (5, 'e');",
+            "truncate table active_users;",
+            "create index idx on active_users (name);",
+            "analyze active_users;",
+        ] {
+            assert_eq!(s.execute(sql).err(), Some(Error::Internal("views are not updatable".to_string())));
+        }
+        assert_eq!(s.execute("create view users as select * from orders;").err(), Some(Error::TableExists("users".to_string())));
+        assert_eq!(
+            s.execute("create table a_users (id int primary key);").err(),
+            Some(Error::Schema("view a_users already exists".to_string()))
+        );
+        assert!(matches!(s.execute("create view a_users as select * from orders;"), Err(Error::Schema(_))));
+        assert!(matches!(s.execute("create view v as select id from missing;"), Err(Error::TableNotFound(_))));
+        assert!(matches!(s.execute("create view v as select id from users union select id, user_id from orders;"), Err(Error::Schema(_))));
+
+        // 视图的定义保存在存储中，删除之后不能再查询
+        let mut s2 = kvengine.session()?;
+        assert_eq!(select(&mut s2, "select id from active_users;")?, vec![vec![i(1)], vec![i(3)], vec![i(4)]]);
+        assert!(matches!(s2.execute("drop view a_users;")?.result, ResultSet::DropView { .. }));
+        assert!(s2.execute("select * from a_users;").is_err());
+        assert!(matches!(s2.execute("drop view a_users;"), Err(Error::Schema(_))));
+        assert_eq!(select(&mut s2, "show views;")?, vec![vec![st("active_users")]]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_with() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+        s.execute("insert into t values (1, 3), (2, 1), (3, 4), (4, 2);")?;
+        s.execute("create table e (id int primary key, src int, dst int);")?;
+        s.execute("insert into e values (1, 1, 2), (2, 2, 3), (3, 3, 1), (4, 4, 1);")?;
+        s.execute("create table c (id int primary key, nxt int null);")?;
+        s.execute("insert into c values (1, 3), (2, null), (3, 4), (4, 2);")?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<(Vec<String>, Vec<Vec<Value>>)> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+                _ => unreachable!(),
+            }
+        };
+        let int = |ids: &[i64]| ids.iter().map(|i| vec![Value::Integer(*i)]).collect::<Vec<_>>();
+
+        // 之后的 CTE 可以引用之前的 CTE，CTE 的名字优先于同名的表
+        let (columns, rows) = select(
+            &mut s,
+            "with big as (select id, v from t where v > 1), e as (select id from big where v < 4) select * from e order by id;",
+        )?;
+        assert_eq!(columns, vec!["id"]);
+        assert_eq!(rows, int(&[1, 4]));
+        let (_, rows) = select(&mut s, "with a as (select id as x from t) select v from a join t on x = id where v > 2 order by v;")?;
+        assert_eq!(rows, int(&[3, 4]));
+
+        // 递归的 CTE，每一轮只用上一轮新产生的行执行递归的部分
+        let (columns, rows) = select(
+            &mut s,
+            "with recursive r as (select id as n from c where id = 1 union all select nxt from r join c on n = id where nxt is not null) \
+             select * from r;",
+        )?;
+        assert_eq!(columns, vec!["n"]);
+        assert_eq!(rows, int(&[1, 3, 4, 2]));
+        // 图中有环，UNION 去掉已经出现过的行之后可以结束
+        let (_, rows) = select(
+            &mut s,
+            "with recursive reach as (select dst as node from e where src = 1 union select dst from reach join e on node = src) \
+             select * from reach order by node;",
+        )?;
+        assert_eq!(rows, int(&[1, 2, 3]));
+        // 没有 RECURSIVE 时 UNION 只合并两个查询的结果
+        let (_, rows) = select(&mut s, "with u as (select v from t where v > 2 union select v from t where v > 3) select * from u;")?;
+        assert_eq!(rows, int(&[3, 4]));
+
+        assert!(s.execute("with a as (select id from t), a as (select id from t) select * from a;").is_err());
+        assert!(s.execute("with recursive r as (select id from t union select id, v from r) select * from r;").is_err());
+        assert!(s.execute("with a as (select id from t) select * from b;").is_err());
+
+        match s.execute("explain with a as (select id from t where v > 2) select * from a;")?.result {
+            ResultSet::Explain { plan } => assert_eq!(plan, "InMemoryScan: a (rows: 2)"),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_external_sort() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int, name text);")?;
+        // 数据量约为阈值的 10 倍，v 有重复，相等时按 id 排序
+        let threshold = 4096;
+        let mut txn = kvengine.begin()?;
+        let rows = (0..1000)
+            .map(|i: i64| vec![Value::Integer(i), Value::Integer((i * 7919) % 250), Value::String(format!("name-{}", i))])
+            .collect();
+        txn.create_rows("t".to_string(), rows)?;
+        txn.commit()?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>| -> Result<Vec<Vec<Value>>> {
+            match s.execute("select * from t order by v desc, id;")?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        let expected = select(&mut s)?;
+        assert!(expected.windows(2).all(|w| w[0][1] > w[1][1] || (w[0][1] == w[1][1] && w[0][0] < w[1][0])));
+
+        let dir = tempfile::tempdir()?;
+        s.set_external_sort(dir.path().to_path_buf(), threshold);
+        assert_eq!(select(&mut s)?, expected);
+
+        // 读取结果的过程中临时文件存在，结果被丢弃之后全部删除
+        let files = || -> Result<usize> { Ok(std::fs::read_dir(dir.path())?.count()) };
+        match s.execute_stream("select * from t order by v desc, id;")?.result {
+            ResultSet::Stream { mut rows, .. } => {
+                assert!(files()? >= 10);
+                assert_eq!(rows.next().transpose()?, Some(expected[0].clone()));
+            }
+            _ => unreachable!(),
+        }
+        assert_eq!(files()?, 0);
+
+        // 没有超过阈值时不写文件
+        s.set_external_sort(dir.path().to_path_buf(), 1 << 20);
+        assert_eq!(select(&mut s)?, expected);
+        assert_eq!(files()?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_float_round_trip() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, f float);")?;
+        s.execute("insert into t values (1, 0.1), (2, 1e-3), (3, .5), (4, 1.7976931348623157e308), (5, 1e10), (6, 2.5E-300);")?;
+        let floats = match s.execute("select * from t;")?.result {
+            ResultSet::Scan { rows, .. } => rows.into_iter().map(|row| row[1].clone()).collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        assert_eq!(
+            floats,
+            [0.1, 1e-3, 0.5, f64::MAX, 1e10, 2.5e-300].into_iter().map(Value::Float).collect::<Vec<_>>()
+        );
+
+        // 输出的文本重新插入之后得到相同的值
+        let text = floats.iter().map(|f| f.to_string()).collect::<Vec<_>>();
+        assert_eq!(text, vec!["0.1", "0.001", "0.5", "1.7976931348623157e308", "10000000000.0", "2.5e-300"]);
+        for (i, f) in text.iter().enumerate() {
+            s.execute(&format!("insert into t values ({}, {});", i + 10, f))?;
+        }
+        match s.execute("select * from t where id >= 10;")?.result {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows.into_iter().map(|row| row[1].clone()).collect::<Vec<_>>(), floats)
+            }
+            _ => unreachable!(),
+        }
+        assert!(s.execute("insert into t values (20, 1e309);").is_err());
+
+        // 整数写入浮点数列时转换为浮点数
+        s.execute("insert into t values (21, 7);")?;
+        match s.execute("select * from t where id = 21;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][1], Value::Float(7.0)),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_table_page() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (v int, id int primary key);")?;
+        let values = (0..25).map(|i| format!("({}, {})", i * 2, i)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+        // 每一页在新的事务中读取，下一页从上一页最后的主键之后开始
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let txn = kvengine.begin()?;
+            let (rows, next) = txn.scan_table_page("t".to_string(), cursor, 10)?;
+            txn.commit()?;
+            pages.push(rows.iter().map(|row| row[1].clone()).collect::<Vec<_>>());
+            match next {
+                Some(next) => cursor = Some(next),
+                None => break,
+            }
+        }
+        let ids = |r: std::ops::Range<i64>| r.map(Value::Integer).collect::<Vec<_>>();
+        assert_eq!(pages, vec![ids(0..10), ids(10..20), ids(20..25)]);
+
+        let txn = kvengine.begin()?;
+        // 行数正好是一页时没有下一页
+        assert_eq!(txn.scan_table_page("t".to_string(), Some(vec![Value::Integer(14)]), 10)?.1, None);
+        assert_eq!(txn.scan_table_page("t".to_string(), Some(vec![Value::Integer(24)]), 10)?, (vec![], None));
+        assert!(txn.scan_table_page("t".to_string(), None, 0).is_err());
+        assert!(txn.scan_table_page("x".to_string(), None, 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_explicit_transaction_isolation() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+        s.execute("insert into t values (1, 1);")?;
+        let count = |s: &mut Session<KVEngine<MemoryEngine>>| -> Result<usize> {
+            match s.execute("select * from t;")?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 和 mvcc 中 unrepeatable_read 的场景相同：事务开启之后其他事务提交了新的行
+        let mut rc = kvengine.session()?;
+        let mut snapshot = kvengine.session()?;
+        rc.execute("begin isolation level read committed;")?;
+        snapshot.execute("begin;")?;
+        assert_eq!((count(&mut rc)?, count(&mut snapshot)?), (1, 1));
+        s.execute("insert into t values (2, 2);")?;
+        // READ COMMITTED 读到其他事务的提交，快照隔离仍然读到开启时的数据
+        assert_eq!((count(&mut rc)?, count(&mut snapshot)?), (2, 1));
+        rc.execute("commit;")?;
+        snapshot.execute("commit;")?;
+        assert!(!snapshot.in_transaction());
+
+        // 显式事务中的修改在提交之前对其他 session 不可见，ROLLBACK 之后丢弃
+        s.execute("begin;")?;
+        assert!(s.execute("begin;").is_err());
+        s.execute("insert into t values (3, 3);")?;
+        assert_eq!((count(&mut s)?, count(&mut rc)?), (3, 2));
+        assert!(matches!(s.execute("rollback;")?.result, ResultSet::Rollback { .. }));
+        assert_eq!(count(&mut s)?, 2);
+        assert!(s.execute("commit;").is_err());
+
+        // 事务中的语句出错时整个事务回滚
+        s.execute("begin;")?;
+        s.execute("insert into t values (3, 3);")?;
+        assert!(s.execute("insert into t values (5, 'x');").is_err());
+        assert!(!s.in_transaction());
+        assert_eq!(count(&mut s)?, 2);
+
+        // 丢弃 Session 时回滚未提交的事务
+        s.execute("begin;")?;
+        s.execute("insert into t values (4, 4);")?;
+        drop(s);
+        assert_eq!(count(&mut rc)?, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cast() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v float);")?;
+        s.execute("insert into t values (cast('1' as int), cast('2.5' as float)), (2, cast(7 as float));")?;
+        match s.execute("select * from t where cast(v as int) = 2;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(1), Value::Float(2.5)]]),
+            _ => unreachable!(),
+        }
+
+        // 转换失败时在计算时报错，常量折叠不会提前报错
+        assert_eq!(
+            s.execute("insert into t values (cast('abc' as int), 1.0);").err(),
+            Some(Error::TypeMismatch { column: String::new(), expected: DataType::Integer, got: DataType::String })
+        );
+        assert!(s.execute("select * from t where cast('abc' as int) = 1;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_stats() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v varchar);")?;
+        let values = (0..10).map(|i| format!("({}, 'v{}')", i, i)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+        let txn = kvengine.begin()?;
+        assert_eq!(txn.count_rows("t".to_string())?, 10);
+        assert!(txn.count_rows("x".to_string()).is_err());
+        assert_eq!(txn.get_table_stats("t".to_string())?, None);
+        txn.commit()?;
+
+        // 未提交的删除在事务内可见，其他事务看到的仍然是之前的行数
+        let mut txn = kvengine.begin()?;
+        let other = kvengine.begin()?;
+        txn.truncate_table("t".to_string())?;
+        txn.create_row("t".to_string(), vec![Value::Integer(1), Value::String("a".to_string())])?;
+        assert_eq!(txn.count_rows("t".to_string())?, 1);
+        assert_eq!(txn.analyze_table("t".to_string())?.row_count, 1);
+        assert_eq!(other.count_rows("t".to_string())?, 10);
+        assert_eq!(other.get_table_stats("t".to_string())?, None);
+        txn.rollback()?;
+        other.commit()?;
+
+        // ANALYZE 保存统计信息，字节数为每一行编码之后的长度之和
+        match s.execute("analyze t;")?.result {
+            ResultSet::Scan { columns, rows } => {
+                assert_eq!(columns, vec!["table", "rows", "bytes", "analyzed_at"]);
+                assert_eq!(rows[0][..2], [Value::String("t".to_string()), Value::Integer(10)]);
+            }
+            _ => unreachable!(),
+        }
+        let txn = kvengine.begin()?;
+        let stats = txn.get_table_stats("t".to_string())?.unwrap();
+        let bytes = txn.scan_table("t".to_string(), None)?.map(|row| Ok(encode_row(&row?)?.len() as u64)).sum::<Result<u64>>()?;
+        assert_eq!((stats.row_count, stats.size_bytes), (10, bytes));
+        assert!(stats.analyzed_at > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_stream() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        // 非查询语句的结果和 execute 相同
+        assert!(matches!(
+            s.execute_stream("create table t (id int primary key, v int);")?.result,
+            ResultSet::CreateTable { .. }
+        ));
+        let values = (0..1000).map(|i| format!("({}, {})", i, i % 10)).collect::<Vec<_>>();
+        assert!(matches!(
+            s.execute_stream(&format!("insert into t values {};", values.join(", ")))?.result,
+            ResultSet::Insert { count: 1000, .. }
+        ));
+
+        match s.execute_stream("select * from t where v = 3;")?.result {
+            ResultSet::Stream { columns, rows } => {
+                assert_eq!(columns, vec!["id".to_string(), "v".to_string()]);
+                let rows = rows.collect::<Result<Vec<_>>>()?;
+                assert_eq!(rows.len(), 100);
+                assert!(rows.iter().all(|row| row[1] == Value::Integer(3)));
+            },
+            _ => unreachable!(),
+        }
+
+        // 语句返回时还没有计算过滤条件，错误在遍历时返回
+        match s.execute_stream("select * from t where v;")?.result {
+            ResultSet::Stream { mut rows, .. } => assert!(matches!(rows.next(), Some(Err(Error::Internal(_))))),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover_uncommitted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        {
+            let kvengine = KVEngine::new(DiskEngine::new(path.clone())?)?;
+            let mut s = kvengine.session()?;
+            s.execute("create table t (id int primary key, name text);")?;
+            s.execute("create index idx_name on t (name);")?;
+            s.execute("insert into t values (1, 'a');")?;
+
+            // 进程在事务提交前退出，行和索引都已经写入存储
+            let mut txn = kvengine.begin()?;
+            txn.create_row("t".to_string(), vec![Value::Integer(2), Value::String("b".to_string())])?;
+        }
+
+        let kvengine = KVEngine::new(DiskEngine::new(path)?)?;
+        let mut s = kvengine.session()?;
+        let count = |s: &mut Session<KVEngine<DiskEngine>>, sql: &str| -> Result<usize> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(count(&mut s, "select * from t;")?, 1);
+        assert_eq!(count(&mut s, "select * from t where name = 'b';")?, 0);
+
+        // 未提交事务写过的主键可以重新写入，不会产生写冲突
+        s.execute("insert into t values (2, 'b');")?;
+        assert_eq!(count(&mut s, "select * from t where name = 'b';")?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_row_ttl() -> Result<()> {
+        let clock = ManualClock::new(1_000);
+        let kvengine = KVEngine { kv: Mvcc::with_clock(MemoryEngine::new(), Arc::new(clock.clone()))? };
+        let mut s = kvengine.session()?;
+        let count = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<usize> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+        s.execute("create table t (id int primary key, name text);")?;
+        s.execute("create index idx_name on t (name);")?;
+        s.execute("insert into t values (1, 'a');")?;
+        let mut txn = kvengine.begin()?;
+        txn.create_row_with_ttl("t".to_string(), vec![Value::Integer(2), Value::String("b".to_string())], Duration::from_secs(60))?;
+        txn.commit()?;
+        assert_eq!(count(&mut s, "select * from t;")?, 2);
+        assert_eq!(count(&mut s, "select * from t where name = 'b';")?, 1);
+        // DEBUG KEYS 可以解码带有过期时间的版本
+        match s.execute("debug keys 'Version(Row(\"t\", [Integer(2)])';")?.result {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows.len(), 1);
+                assert_eq!(rows[0][3], Value::Boolean(false));
+                assert_eq!(rows[0][4], Value::Integer(61_000));
+            }
+            _ => unreachable!(),
+        }
+
+        // 过期之后行和索引项都不可见，主键可以重新使用
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(count(&mut s, "select * from t;")?, 1);
+        assert_eq!(count(&mut s, "select * from t where name = 'b';")?, 0);
+        assert_eq!(kvengine.kv.purge_expired()?, 2);
+        s.execute("insert into t values (2, 'c');")?;
+        assert_eq!(count(&mut s, "select * from t where name = 'c';")?, 1);
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(count(&mut s, "select * from t;")?, 2);
+        assert_eq!(kvengine.kv.purge_expired()?, 0);
+
+        // 更新带有 TTL 的行之后行和索引项都不再过期，全表扫描和索引查询的结果一致
+        s.execute("create table t2 (id int primary key, name text, n int);")?;
+        s.execute("create index idx_t2_name on t2 (name);")?;
+        let mut txn = kvengine.begin()?;
+        let row = |n: i64| vec![Value::Integer(1), Value::String("b".to_string()), Value::Integer(n)];
+        txn.create_row_with_ttl("t2".to_string(), row(1), Duration::from_secs(60))?;
+        txn.update_row("t2".to_string(), vec![Value::Integer(1)], row(5))?;
+        txn.commit()?;
+        clock.advance(Duration::from_secs(60));
+        let rows = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<Row>> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(rows(&mut s, "select * from t2;")?, vec![row(5)]);
+        assert_eq!(rows(&mut s, "select * from t2 where name = 'b';")?, vec![row(5)]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_only_session() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let count = |s: &mut Session<KVEngine<DiskEngine>>, sql: &str| -> Result<usize> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+        let kvengine = KVEngine::new(DiskEngine::new(path.clone())?)?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, name text);")?;
+        s.execute("insert into t values (1, 'a'), (2, 'b');")?;
+        // 写进程中还没有提交的事务
+        let mut txn = kvengine.begin()?;
+        txn.create_row("t".to_string(), vec![Value::Integer(3), Value::String("c".to_string())])?;
+
+        // 只读模式和写进程同时打开，看不到未提交的数据，也不会回滚它
+        let reader = KVEngine::new_read_only(DiskEngine::open_read_only(path.clone())?);
+        let mut r = reader.session()?;
+        assert_eq!(count(&mut r, "select * from t;")?, 2);
+        r.execute("begin;")?;
+        assert_eq!(count(&mut r, "select * from t where id = 2;")?, 1);
+        r.execute("commit;")?;
+        assert!(matches!(r.execute("insert into t values (4, 'd');"), Err(Error::ReadOnly(_))));
+        assert!(matches!(r.execute("create table t2 (id int primary key);"), Err(Error::ReadOnly(_))));
+
+        // 写进程不受影响，之后提交的数据在重新以只读模式打开之后可见
+        txn.commit()?;
+        s.execute("insert into t values (4, 'd');")?;
+        assert_eq!(count(&mut s, "select * from t;")?, 4);
+        assert_eq!(count(&mut r, "select * from t;")?, 2);
+        let reader = KVEngine::new_read_only(DiskEngine::open_read_only(path)?);
+        assert_eq!(count(&mut reader.session()?, "select * from t;")?, 4);
+        Ok(())
+    }
+
+    // 构造旧版本的数据库：表结构使用版本 1 的定义编码，版本 2、3 使用对应版本的定义编码
+    // 版本 0 没有版本号前缀和版本记录
+    fn legacy_engine(version: u8) -> Result<KVEngine<MemoryEngine>> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name string default 'x');")?;
+        s.execute("insert into users values (1, 'a'), (2, 'b');")?;
+
+        let column = |name: &str, datatype, default| v1::Column {
+            name: name.to_string(),
+            datatype,
+            nullable: name != "id",
+            default,
+            primary_key: name == "id",
+            auto_increment: false,
+            max_len: None,
+        };
+        let table = v1::Table {
+            name: "users".to_string(),
+            columns: vec![
+                column("id", DataType::Integer, None),
+                column("name", DataType::String, Some(Value::String("x".to_string()))),
+            ],
+            indexes: Vec::new(),
+        };
+        let with_version = |mut data: Vec<u8>| {
+            if version > 0 {
+                data.insert(0, version);
+            }
+            data
+        };
+
+        let table = match version {
+            2 => bincode::serialize(&v2::Table::from(table))?,
+            3 => bincode::serialize(&v3::Table::from(v2::Table::from(table)))?,
+            _ => bincode::serialize(&table)?,
+        };
+
+        let txn = kvengine.kv.begin()?;
+        txn.set(Key::Table("users".to_string()).encode()?, with_version(table))?;
+        for row in txn.scan_prefix(KeyPrefix::Row("users".to_string()).encode()?)? {
+            txn.set(row.key, with_version(row.value[1..].to_vec()))?;
+        }
+        match version {
+            0 => txn.delete(Key::FormatVersion.encode()?)?,
+            v => txn.set(Key::FormatVersion.encode()?, bincode::serialize(&v)?)?,
+        }
+        txn.commit()?;
+        Ok(kvengine)
+    }
+
+    #[test]
+    fn test_storage_format_migrate() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name string);")?;
+        s.execute("insert into users values (1, 'a'), (2, 'b');")?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>| -> Result<usize> {
+            match s.execute("select * from users;")?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+
+        // 手动构造未知版本的行
+        let row_key = Key::Row("users".to_string(), vec![Value::Integer(1)]).encode()?;
+        let txn = kvengine.kv.begin()?;
+        let original = txn.get(row_key.clone())?.unwrap();
+        let mut row = original.clone();
+        row[0] = 5;
+        txn.set(row_key.clone(), row)?;
+        txn.commit()?;
+        assert_eq!(
+            select(&mut s).err(),
+            Some(Error::Serialization(
+                r#"row encoded with unsupported format version 5 at key Row("users", [Integer(1)])"#.to_string()
+            ))
+        );
+        let txn = kvengine.kv.begin()?;
+        txn.set(row_key.clone(), original)?;
+        txn.commit()?;
+        assert!(select(&mut s).is_ok());
+
+        // 版本 0 到 3 的数据库都需要迁移之后才能读写，迁移后旧的默认值转换为常量表达式
+        for version in [0, 1, 2, 3] {
+            let kvengine = legacy_engine(version)?;
+            let mut s = kvengine.session()?;
+            assert!(select(&mut s).is_err());
+            assert_eq!(
+                s.execute("create table t (id int primary key);").err(),
+                Some(Error::Schema(format!("database uses storage format version {}, run migrate first", version)))
+            );
+
+            // 迁移之后可以正常读写，再次迁移不做任何修改
+            kvengine.migrate()?;
+            assert_eq!(select(&mut s)?, 2);
+            kvengine.migrate()?;
+            assert_eq!(select(&mut s)?, 2);
+            s.execute("create table t (id int primary key);")?;
+            s.execute("insert into users (id) values (3);")?;
+            match s.execute("select * from users where id = 3;")?.result {
+                ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(3), Value::String("x".to_string())]]),
+                _ => unreachable!(),
+            }
+        }
+
+        // 空的数据库迁移之后直接是当前版本
+        let empty = KVEngine::new(MemoryEngine::new())?;
+        empty.migrate()?;
+        empty.session()?.execute("create table t (id int primary key);")?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted_values() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table users (id int primary key, name string);")?;
+        s.execute("create table other (id int primary key);")?;
+        s.execute("insert into users values (1, 'a'), (2, 'b');")?;
+        // 版本号之后的长度前缀声明了 1TB 的数据，解码时应该直接返回错误，而不是尝试分配内存
+        let bomb = || {
+            let mut data = vec![FORMAT_VERSION];
+            data.extend_from_slice(&(1u64 << 40).to_le_bytes());
+            data
+        };
+        let plant = |key: Key| -> Result<()> {
+            let txn = kvengine.kv.begin()?;
+            txn.set(key.encode()?, bomb())?;
+            txn.commit()?;
+            Ok(())
+        };
+        let expect_error = |result: Result<_>, key: &str| match result {
+            Err(Error::Serialization(msg)) => assert!(msg.contains(key), "{}", msg),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("corrupted value was decoded"),
+        };
+
+        plant(Key::Row("users".to_string(), vec![Value::Integer(2)]))?;
+        expect_error(s.execute("select * from users;"), r#"Row("users", [Integer(2)])"#);
+
+        plant(Key::Table("users".to_string()))?;
+        expect_error(s.execute("select * from users;"), r#"size limit of 16777216 bytes at key Table("users")"#);
+
+        // 其他表不受影响
+        s.execute("insert into other values (1);")?;
+        match s.execute("select * from other;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_value_size() -> Result<()> {
+        let mut kvengine = KVEngine::new(MemoryEngine::new())?;
+        kvengine.set_max_value_size(1024);
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, name string);")?;
+        s.execute("insert into t values (1, 'a');")?;
+        // 写入不受上限影响，读取超过上限的行时返回错误
+        s.execute(&format!("insert into t values (2, '{}');", "x".repeat(4096)))?;
+        match s.execute("select * from t;") {
+            Err(Error::Serialization(msg)) => assert!(msg.contains("size limit of 1024 bytes"), "{}", msg),
+            Err(err) => panic!("unexpected error {:?}", err),
+            Ok(_) => panic!("value over the limit was decoded"),
+        }
+
+        // 同一个存储的其他引擎按自己的上限读取
+        let mut wide = kvengine.clone();
+        wide.set_max_value_size(8192);
+        match wide.session()?.execute("select * from t;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 2),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_case_insensitive_identifiers() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<(Vec<String>, Vec<Row>)> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+                _ => unreachable!(),
+            }
+        };
+
+        // 没有引号的标识符不区分大小写
+        s.execute("CREATE TABLE Users (Id INT PRIMARY KEY, UserName STRING);")?;
+        s.execute("INSERT INTO USERS (ID, username) VALUES (1, 'Alice');")?;
+        let (columns, rows) = select(&mut s, "select * from users where USERNAME = 'Alice';")?;
+        assert_eq!(columns, vec!["id".to_string(), "username".to_string()]);
+        assert_eq!(rows, vec![vec![Value::Integer(1), Value::String("Alice".to_string())]]);
+        assert!(s.execute("create table USERS (id int primary key);").is_err());
+
+        // 有引号的标识符保留大小写，可以使用关键字
+        s.execute(r#"create table "Mixed" ("select" int primary key, "Value" string);"#)?;
+        s.execute(r#"insert into "Mixed" values (1, 'a');"#)?;
+        let (columns, rows) = select(&mut s, r#"select * from "Mixed" where "select" = 1;"#)?;
+        assert_eq!(columns, vec!["select".to_string(), "Value".to_string()]);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(s.execute("select * from mixed;").err(), Some(Error::TableNotFound("mixed".to_string())));
+        assert!(s.execute(r#"select * from "Mixed" where value = 'a';"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_timestamp() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, ts timestamp, note timestamp default '2000-01-01T00:00:00Z');")?;
+
+        // 字符串按照 ISO-8601 格式转换为时间戳
+        s.execute("insert into t values (1, '2024-01-02T03:04:05Z');")?;
+        s.execute("insert into t values (2, TIMESTAMP '2023-06-01T00:00:00.5Z', null);")?;
+        match s.execute("select * from t where ts > TIMESTAMP '2024-01-01T00:00:00Z';")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![vec![Value::Integer(1), Value::Timestamp(1704164645000), Value::Timestamp(946684800000)]]
+            ),
+            _ => unreachable!(),
+        }
+        match s.execute("select * from t where id = 2;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows[0][1].to_string(), "2023-06-01T00:00:00.500Z"),
+            _ => unreachable!(),
+        }
+
+        // 格式错误的时间戳
+        assert!(matches!(s.execute("insert into t values (3, '2024-01-02 03:04:05');"), Err(Error::Parse(_))));
+        assert!(matches!(s.execute("select * from t where ts > TIMESTAMP '2024';"), Err(Error::Parse(_))));
+        assert!(matches!(s.execute("insert into t values (3, 5);"), Err(Error::TypeMismatch { .. })));
+        Ok(())
+    }
+
+    // 记录每次回调的语句、是否成功以及耗时
+    #[derive(Clone, Default)]
+    struct RecordingHook(Arc<Mutex<Vec<(String, bool, Duration)>>>);
+
+    impl QueryHook for RecordingHook {
+        fn on_statement(&self, sql: &str, outcome: &Result<ResultSet>, elapsed: Duration) {
+            self.0.lock().unwrap().push((sql.to_string(), outcome.is_ok(), elapsed));
+        }
+    }
+
+    struct PanickingHook;
+
+    impl QueryHook for PanickingHook {
+        fn on_statement(&self, _: &str, _: &Result<ResultSet>, _: Duration) {
+            panic!("hook failed");
+        }
+    }
+
+    #[test]
+    fn test_query_hook() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let hook = RecordingHook::default();
+        s.set_hook(Box::new(hook.clone()));
+
+        let start = Instant::now();
+        let result = s.execute("create table t (id int primary key);")?;
+        s.execute("insert into t values (1);")?;
+        assert!(s.execute("insert into t values ('a');").is_err());
+        assert!(s.execute("select * from").is_err());
+        // 显式事务中的语句失败时整个事务回滚，同样会调用回调
+        s.execute("begin;")?;
+        assert!(s.execute("insert into missing values (1);").is_err());
+        let stmt = s.prepare("select * from t where id = ?;")?;
+        s.execute_prepared(&stmt, &[Value::Integer(1)])?;
+        let total = start.elapsed();
+
+        let records = hook.0.lock().unwrap().clone();
+        let summary = records.iter().map(|(sql, ok, _)| (sql.as_str(), *ok)).collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("create table t (id int primary key);", true),
+                ("insert into t values (1);", true),
+                ("insert into t values ('a');", false),
+                ("select * from", false),
+                ("begin;", true),
+                ("insert into missing values (1);", false),
+                ("select * from t where id = ?;", true),
+            ]
+        );
+        assert_eq!(records[0].2, result.elapsed);
+        assert!(records.iter().all(|(_, _, elapsed)| *elapsed > Duration::ZERO && *elapsed <= total));
+        assert!(!s.in_transaction());
+
+        // 回调中的 panic 不影响执行结果
+        s.set_hook(Box::new(PanickingHook));
+        assert!(matches!(s.execute("select * from t;")?.result, ResultSet::Scan { rows, .. } if rows.len() == 1));
+        assert!(s.execute("select * from missing;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_prepared_statement() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, name string);")?;
+
+        let insert = s.prepare("insert into t values (?, ?);")?;
+        assert_eq!(insert.parameters(), 2);
+        s.execute_prepared(&insert, &[Value::Integer(1), Value::String("a".to_string())])?;
+        // 参数不会作为 SQL 解析
+        s.execute_prepared(&insert, &[Value::Integer(2), Value::String("b'); drop index i; --".to_string())])?;
+
+        let select = s.prepare("select * from t where id = ? or name = ?;")?;
+        match s.execute_prepared(&select, &[Value::Integer(2), Value::String("a".to_string())])?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(
+                rows,
+                vec![
+                    vec![Value::Integer(1), Value::String("a".to_string())],
+                    vec![Value::Integer(2), Value::String("b'); drop index i; --".to_string())],
+                ]
+            ),
+            _ => unreachable!(),
+        }
+
+        // 参数个数不匹配，或者未经过 prepare 直接执行
+        assert_eq!(
+            s.execute_prepared(&insert, &[Value::Integer(3)]).err(),
+            Some(Error::Parse("statement expects 2 parameters, got 1".to_string()))
+        );
+        assert_eq!(
+            s.execute("insert into t values (3, ?);").err(),
+            Some(Error::Parse("parameter 1 is not bound".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_execute_with_retry() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+
+        // 另一个未提交的事务写入了同一个 key，重试次数用完之后返回写冲突
+        let mut blocker = kvengine.begin()?;
+        blocker.create_row("t".to_string(), vec![Value::Integer(1), Value::Integer(0)])?;
+        assert_eq!(
+            s.execute_with_retry("insert into t values (1, 1);", 2, Duration::from_millis(1)).err(),
+            Some(Error::TransactionConflict)
+        );
+        // 提交之后主键重复，不是冲突，不会重试
+        blocker.commit()?;
+        assert!(matches!(
+            s.execute_with_retry("insert into t values (1, 1);", 2, Duration::from_millis(1)),
+            Err(Error::ConstraintViolation(_))
+        ));
+
+        // 两个线程并发写入同一个表的自增序列，冲突的语句重试之后都能成功
+        s.execute("create table c (id int primary key auto_increment, v int);")?;
+        let handles = (0..2)
+            .map(|n| {
+                let mut s = kvengine.session()?;
+                Ok(std::thread::spawn(move || -> Result<()> {
+                    for _ in 0..50 {
+                        s.execute_with_retry(
+                            &format!("insert into c (v) values ({});", n),
+                            1000,
+                            Duration::from_micros(100),
+                        )?;
+                    }
+                    Ok(())
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        match s.execute("select * from c;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 100),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_with_txn_retry() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+        s.execute("insert into t values (1, 0);")?;
+        let row = |v: i64| vec![Value::Integer(1), Value::Integer(v)];
+
+        // 另一个事务先修改了同一行但还没有提交，第一次执行时写冲突，
+        // 它提交之后在新的事务中重试，读到的是它写入的值
+        let mut winner = Some(kvengine.begin()?);
+        winner.as_mut().unwrap().update_row("t".to_string(), vec![Value::Integer(1)], row(10))?;
+        let mut attempts = 0;
+        let v = kvengine.with_txn(3, Duration::from_millis(1), |txn| {
+            attempts += 1;
+            let Value::Integer(v) = txn.scan_table("t".to_string(), None)?.next().unwrap()?[1] else { unreachable!() };
+            let result = txn.update_row("t".to_string(), vec![Value::Integer(1)], row(v + 1));
+            if let Some(winner) = winner.take() {
+                winner.commit()?;
+            }
+            result.map(|_| v + 1)
+        })?;
+        assert_eq!((v, attempts), (11, 2));
+
+        // 不重试时返回写冲突，修改被回滚；其他错误不会重试
+        let blocker = kvengine.begin()?;
+        blocker.txn.set(Key::Row("t".to_string(), vec![Value::Integer(1)]).encode()?, Vec::new())?;
+        assert_eq!(
+            kvengine.with_txn(0, Duration::from_millis(1), |txn| txn.update_row("t".to_string(), vec![Value::Integer(1)], row(0))),
+            Err(Error::TransactionConflict)
+        );
+        blocker.rollback()?;
+        let mut attempts = 0;
+        assert!(kvengine
+            .with_txn(3, Duration::from_millis(1), |txn| {
+                attempts += 1;
+                txn.update_row("missing".to_string(), vec![Value::Integer(1)], row(0))
+            })
+            .is_err());
+        assert_eq!(attempts, 1);
+        match s.execute("select * from t;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![row(11)]),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_table() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key auto_increment, name string);")?;
+        s.execute("create index idx_name on t (name);")?;
+        s.execute("insert into t (name) values ('a'), ('b'), ('a');")?;
+
+        match s.execute("truncate table t;")?.result {
+            ResultSet::Delete { count } => assert_eq!(count, 3),
+            _ => unreachable!(),
+        }
+
+        // 表结构保留，行和索引项都被删除，自增列重新从 1 开始
+        let txn = kvengine.begin()?;
+        let table = txn.must_get_table("t".to_string())?;
+        assert_eq!(table.indexes.len(), 1);
+        assert_eq!(txn.scan_table("t".to_string(), None)?.count(), 0);
+        assert!(txn.scan_index("t".to_string(), "idx_name".to_string(), &Value::String("a".to_string()))?.is_empty());
+        txn.commit()?;
+        match s.execute("insert into t (name) values ('c');")?.result {
+            ResultSet::Insert { keys, .. } => assert_eq!(keys, vec![vec![Value::Integer(1)]]),
+            _ => unreachable!(),
+        }
+
+        assert_eq!(s.execute("truncate table missing;").err(), Some(Error::TableNotFound("missing".to_string())));
+        Ok(())
+    }
+
+    #[test]
+    fn test_update_row() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key auto_increment, name varchar(3) not null);")?;
+        s.execute("create index idx_name on t (name);")?;
+        s.execute("insert into t (name) values ('a'), ('b');")?;
+        let string = |v: &str| Value::String(v.to_string());
+        let rows = |txn: &KVTransaction<MemoryEngine>| -> Result<Vec<Row>> {
+            txn.scan_table("t".to_string(), None)?.collect()
+        };
+
+        // 主键不变时原地更新，索引项随之修改
+        let mut txn = kvengine.begin()?;
+        txn.update_row("t".to_string(), vec![Value::Integer(1)], vec![Value::Integer(1), string("c")])?;
+        assert_eq!(rows(&txn)?, vec![vec![Value::Integer(1), string("c")], vec![Value::Integer(2), string("b")]]);
+        assert!(txn.scan_index("t".to_string(), "idx_name".to_string(), &string("a"))?.is_empty());
+        assert_eq!(txn.scan_index("t".to_string(), "idx_name".to_string(), &string("c"))?.len(), 1);
+
+        // 修改主键时旧的行被删除，自增的计数器推进到新的主键之后
+        txn.update_row("t".to_string(), vec![Value::Integer(2)], vec![Value::Integer(10), string("b")])?;
+        assert_eq!(rows(&txn)?, vec![vec![Value::Integer(1), string("c")], vec![Value::Integer(10), string("b")]]);
+        assert_eq!(
+            txn.scan_index("t".to_string(), "idx_name".to_string(), &string("b"))?,
+            vec![vec![Value::Integer(10), string("b")]]
+        );
+        assert_eq!(txn.create_row("t".to_string(), vec![Value::Null, string("d")])?, vec![Value::Integer(11)]);
+
+        // 违反约束时原来的行保持不变
+        assert_eq!(
+            txn.update_row("t".to_string(), vec![Value::Integer(1)], vec![Value::Integer(10), string("x")]),
+            Err(Error::ConstraintViolation("duplicate primary key 10 in table t".to_string()))
+        );
+        assert!(matches!(
+            txn.update_row("t".to_string(), vec![Value::Integer(1)], vec![Value::Integer(1), Value::Null]),
+            Err(Error::ConstraintViolation(_))
+        ));
+        assert!(matches!(
+            txn.update_row("t".to_string(), vec![Value::Integer(1)], vec![Value::Integer(1), string("long")]),
+            Err(Error::ConstraintViolation(_))
+        ));
+        assert!(matches!(
+            txn.update_row("t".to_string(), vec![Value::Integer(1)], vec![Value::Integer(1), Value::Integer(1)]),
+            Err(Error::TypeMismatch { .. })
+        ));
+        assert!(txn.update_row("t".to_string(), vec![Value::Integer(5)], vec![Value::Integer(5), string("e")]).is_err());
+        assert_eq!(rows(&txn)?.len(), 3);
+        assert_eq!(txn.scan_index("t".to_string(), "idx_name".to_string(), &string("c"))?.len(), 1);
+        txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_increment_concurrent() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        kvengine.session()?.execute("create table t (id serial primary key, n int);")?;
+
+        // 多个线程并发分配，冲突时重试，每个值只会分配一次
+        let handles = (0..4)
+            .map(|n| {
+                let mut s = kvengine.session()?;
+                Ok(std::thread::spawn(move || -> Result<Vec<Value>> {
+                    let mut keys = Vec::new();
+                    for _ in 0..20 {
+                        match s.execute_with_retry(&format!("insert into t(n) values ({});", n), 1000, Duration::from_micros(100))?.result {
+                            ResultSet::Insert { keys: k, .. } => keys.extend(k.concat()),
+                            _ => unreachable!(),
+                        }
+                    }
+                    Ok(keys)
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut keys = Vec::new();
+        for handle in handles {
+            keys.extend(handle.join().unwrap()?);
+        }
+        keys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(keys, (1..=80).map(Value::Integer).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_or_replace_table() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let select = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<(Vec<String>, Vec<Row>)> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+                _ => unreachable!(),
+            }
+        };
+        s.execute("create table t (id int primary key auto_increment, name text);")?;
+        s.execute("create index idx_name on t(name);")?;
+        s.execute("insert into t (name) values ('a'), ('b');")?;
+        s.execute("analyze t;")?;
+
+        // 原来的行、索引和统计信息都被删除，新的表结构生效
+        match s.execute("create or replace table t (id int primary key auto_increment, v int, w text);")?.result {
+            ResultSet::CreateTable { created, .. } => assert!(created),
+            _ => unreachable!(),
+        }
+        assert_eq!(select(&mut s, "select * from t;")?, (vec!["id".to_string(), "v".to_string(), "w".to_string()], vec![]));
+        let txn = kvengine.begin()?;
+        assert!(txn.must_get_table("t".to_string())?.indexes.is_empty());
+        assert_eq!(txn.get_table_stats("t".to_string())?, None);
+        txn.rollback()?;
+        s.execute("create index idx_name on t(w);")?;
+        s.execute("insert into t (v, w) values (10, 'x');")?;
+        assert_eq!(
+            select(&mut s, "select * from t where w = 'x';")?.1,
+            vec![vec![Value::Integer(1), Value::Integer(10), Value::String("x".to_string())]]
+        );
+
+        // 新的表结构无效时整个语句回滚，原来的表保持不变
+        assert!(s.execute("create or replace table t (a int primary key, b int primary key);").is_err());
+        assert_eq!(select(&mut s, "select * from t;")?.1.len(), 1);
+
+        // 表不存在时直接创建，和视图同名时报错
+        s.execute("create or replace table t2 (id int primary key);")?;
+        s.execute("insert into t2 values (1);")?;
+        s.execute("create view v as select * from t2;")?;
+        assert_eq!(
+            s.execute("create or replace table v (id int primary key);").err(),
+            Some(Error::Schema("view v already exists".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_table_if_not_exists() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        let created = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<bool> {
+            match s.execute(sql)?.result {
+                ResultSet::CreateTable { created, .. } => Ok(created),
+                _ => unreachable!(),
+            }
+        };
+        assert!(created(&mut s, "create table if not exists t (id int primary key);")?);
+        s.execute("insert into t values (1), (2);")?;
+        // 已经存在时跳过，表结构和已有的数据保持不变
+        assert!(!created(&mut s, "create table if not exists t (id int primary key, v text);")?);
+        assert_eq!(kvengine.begin()?.must_get_table("t".to_string())?.columns.len(), 1);
+        match s.execute("select * from t;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows, vec![vec![Value::Integer(1)], vec![Value::Integer(2)]]),
+            _ => unreachable!(),
+        }
+        // 和视图同名时不能跳过
+        s.execute("create view v as select * from t;")?;
+        assert_eq!(
+            s.execute("create table if not exists v (id int primary key);").err(),
+            Some(Error::Schema("view v already exists".to_string()))
+        );
+        assert_eq!(s.execute("create table t (id int primary key);").err(), Some(Error::TableExists("t".to_string())));
+        assert!(s.execute("create table if exists t2 (id int primary key);").is_err());
+
+        // 同一个事务中重复创建
+        s.execute("begin;")?;
+        assert!(created(&mut s, "create table t2 (id int primary key);")?);
+        assert!(!created(&mut s, "create table if not exists t2 (id int primary key);")?);
+        assert_eq!(s.execute("create table t2 (id int primary key);").err(), Some(Error::TableExists("t2".to_string())));
+
+        // 两个事务同时创建同一张表，后写入的事务写冲突，先创建的事务提交之后开始的事务看到表已经存在
+        let mut s1 = kvengine.session()?;
+        let mut s2 = kvengine.session()?;
+        s1.execute("begin;")?;
+        s2.execute("begin;")?;
+        assert!(created(&mut s1, "create table if not exists t3 (id int primary key);")?);
+        assert_eq!(s2.execute("create table if not exists t3 (id int primary key);").err(), Some(Error::TransactionConflict));
+        s1.execute("commit;")?;
+        assert!(!created(&mut s2, "create table if not exists t3 (id int primary key);")?);
+
+        // 多个线程同时创建，只有一个成功
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(4));
+        let handles = (0..4)
+            .map(|_| {
+                let mut s = kvengine.session()?;
+                let barrier = barrier.clone();
+                Ok(std::thread::spawn(move || {
+                    barrier.wait();
+                    s.execute("create table race (id int primary key);").map(|_| ())
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let results = handles.into_iter().map(|h| h.join().unwrap()).collect::<Vec<_>>();
+        assert_eq!(results.iter().filter(|r| r.is_ok()).count(), 1);
+        for result in results {
+            assert!(matches!(result, Ok(()) | Err(Error::TransactionConflict) | Err(Error::TableExists(_))), "{:?}", result);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_sessions() -> Result<()> {
+        // 引擎和 Session 都可以在线程之间传递
+        fn assert_send<T: Send>() {}
+        assert_send::<KVEngine<MemoryEngine>>();
+        assert_send::<Session<KVEngine<MemoryEngine>>>();
+        assert_send::<Session<KVEngine<DiskEngine>>>();
+
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        kvengine.session()?.execute("create table t (id int primary key, n int, v text);")?;
+
+        // 每个线程使用自己的 Session 写入不同的行，互相之间不会冲突
+        let handles = (0..4)
+            .map(|n| {
+                let mut s = kvengine.session()?;
+                Ok(std::thread::spawn(move || -> Result<()> {
+                    for i in 0..25 {
+                        s.execute(&format!("insert into t values ({}, {}, 'v{}');", n * 100 + i, n, i))?;
+                    }
+                    Ok(())
+                }))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+        match kvengine.session()?.execute("select * from t;")?.result {
+            ResultSet::Scan { rows, .. } => {
+                assert_eq!(rows.len(), 100);
+                for row in rows {
+                    let (Value::Integer(id), Value::Integer(n)) = (&row[0], &row[1]) else { unreachable!() };
+                    assert_eq!(*n, id / 100);
+                    assert_eq!(row[2], Value::String(format!("v{}", id % 100)));
+                }
+            }
+            _ => unreachable!(),
+        }
+
+        // 其他线程中的 Session 写入未提交事务已经写过的 key，返回写冲突，提交之后再写入时主键重复
+        let mut blocker = kvengine.begin()?;
+        blocker.create_row("t".to_string(), vec![Value::Integer(1000), Value::Integer(9), Value::Null])?;
+        let mut s = kvengine.session()?;
+        let (mut s, result) = std::thread::spawn(move || {
+            let result = s.execute("insert into t values (1000, 1, null);").map(|_| ());
+            (s, result)
+        })
+        .join()
+        .unwrap();
+        assert_eq!(result, Err(Error::TransactionConflict));
+        blocker.commit()?;
+        let result = std::thread::spawn(move || s.execute("insert into t values (1000, 1, null);").map(|_| ())).join().unwrap();
+        assert!(matches!(result, Err(Error::ConstraintViolation(_))));
+        Ok(())
+    }
+}
@@ -1,19 +1,61 @@
 
-use crate::error::{Error, Result};
+use std::{collections::hash_map::RandomState, hash::{BuildHasher, Hasher}, io::Write, panic::{self, AssertUnwindSafe}, path::PathBuf, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::{Duration, Instant}};
 
-use super::{executor::ResultSet, parser::Parser, plan::Plan, schema::Table, types::Row};
+use crate::{error::{Error, Result}, storage::mvcc::{IsolationLevel, Version}};
 
-mod kv;
+use hook::QueryHook;
 
-pub trait Engine : Clone {
-    type Transaction: Transaction;
+use super::{executor::{self, ExecutionContext, ExecutionResult, ResultSet}, parser::{ast::{Expression, Statement}, Parser}, plan::Plan, schema::{Table, TableStats, View}, types::{PrimaryKey, Row, Rows, Value}};
 
-    // 开启事务
-    fn begin(&self) -> Result<Self::Transaction>;
+pub mod hook;
+pub mod kv;
+pub mod storage_format;
+
+// 引擎在多个客户端线程之间共享，每个线程克隆一份并创建自己的 Session
+// 克隆出来的引擎共享同一份底层存储，并发事务之间的冲突以 Error::TransactionConflict 返回
+pub trait Engine : Clone + Send + Sync {
+    type Transaction: Transaction + 'static;
+
+    // 开启事务，默认使用快照隔离
+    fn begin(&self) -> Result<Self::Transaction> {
+        self.begin_with_isolation(IsolationLevel::Snapshot)
+    }
+
+    // 以指定的隔离级别开启事务
+    fn begin_with_isolation(&self, isolation: IsolationLevel) -> Result<Self::Transaction>;
+
+    // 在新的事务中执行 f，成功时提交并返回结果，出错时回滚
+    // 遇到写冲突时开启新的事务重新执行，重试的次数和等待时间与 Session::execute_with_retry 相同
+    // f 可能被执行多次，除了事务中的读写之外不应该有其他副作用
+    fn with_txn<T>(
+        &self,
+        max_retries: usize,
+        backoff: Duration,
+        mut f: impl FnMut(&mut Self::Transaction) -> Result<T>,
+    ) -> Result<T> {
+        retry_on_conflict(max_retries, backoff, || {
+            let mut txn = self.begin()?;
+            match f(&mut txn) {
+                Ok(value) => {
+                    txn.commit()?;
+                    Ok(value)
+                }
+                Err(err) => {
+                    txn.rollback()?;
+                    Err(err)
+                }
+            }
+        })
+    }
 
     fn session(&self) -> Result<Session<Self>> {
         Ok(Session{
             engine: self.clone(),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            last_insert_id: None,
+            external_sort: None,
+            txn: None,
+            hook: None,
         })
     }
 }
@@ -22,59 +64,390 @@ pub trait Engine : Clone {
 // 抽象的事务信息，包含了 DML，DDL 操作
 // 底层可以接入普通的 KV 引擎，也可以接入分布式存储引擎
 pub trait Transaction {
-    // 提交事物
-    fn commit(&self) -> Result<()>;
+    // 提交事物，返回提交时的版本号
+    fn commit(&self) -> Result<Version>;
 
     // 回滚事物
     fn rollback(&self) -> Result<()>;
 
-    // 创建行
-    fn create_row(&mut self, table_name: String, row: Row) -> Result<()>;
+    // 事务的版本号，提交之后即为提交时的版本号
+    fn version(&self) -> Version;
+
+    // 创建行，返回这一行的主键
+    fn create_row(&mut self, table_name: String, row: Row) -> Result<PrimaryKey>;
+
+    // 批量创建行，所有行校验通过之后才写入，按顺序返回每一行的主键
+    fn create_rows(&mut self, table_name: String, rows: Vec<Row>) -> Result<Vec<PrimaryKey>> {
+        rows.into_iter()
+            .map(|row| self.create_row(table_name.clone(), row))
+            .collect()
+    }
 
-    // 扫描表
-    fn scan_table(&self, table_name: String) -> Result<Vec<Row>>;
+    // 更新主键为 primary_key 的行，new_row 中的主键可以和原来不同，但不能和其他行冲突
+    // 新的行同样要通过表定义的校验，写入失败时原来的行保持不变
+    fn update_row(&mut self, table_name: String, primary_key: PrimaryKey, new_row: Row) -> Result<()>;
+
+    // 扫描表，返回的迭代器在遍历时才读取每一行，行按主键从小到大的顺序返回
+    // 传入过滤条件时只返回满足条件的行，None 表示不过滤
+    fn scan_table(&self, table_name: String, filter: Option<&Expression>) -> Result<Rows>;
+
+    // 按主键范围扫描表，start 和 end 都包含在范围内，None 表示这一侧没有边界
+    // 复合主键的边界可以只包含前几列，end 包含所有以它开头的主键
+    fn scan_table_range(&self, table_name: String, start: Option<PrimaryKey>, end: Option<PrimaryKey>) -> Result<Rows>;
+
+    // 分页扫描表，返回主键大于 start_after 的至多 limit 行，以及读取下一页时传入的主键
+    // 没有更多的行时下一页的主键为 None，每一页可以在不同的事务中读取
+    fn scan_table_page(&self, table_name: String, start_after: Option<PrimaryKey>, limit: usize) -> Result<(Vec<Row>, Option<PrimaryKey>)>;
 
     // DDL相关操作
     fn create_table(&mut self, table: Table) -> Result<()>;
 
+    // 在表的某一列上创建索引，并为已有的数据建立索引
+    fn create_index(&mut self, index_name: String, table_name: String, column_name: String) -> Result<()>;
+
+    // 删除索引以及索引中的所有数据
+    fn drop_index(&mut self, index_name: String) -> Result<()>;
+
+    // 删除表中所有的行以及对应的索引项，并重置自增列，保留表结构，返回删除的行数
+    fn truncate_table(&mut self, table_name: String) -> Result<usize>;
+
+    // 删除表结构、表中所有的行、索引和统计信息
+    fn drop_table(&mut self, table_name: String) -> Result<()>;
+
+    // 通过索引查找列值等于 value 的行
+    fn scan_index(&self, table_name: String, index_name: String, value: &Value) -> Result<Vec<Row>>;
+
     // 获取表信息
     fn get_table(&self, table_name: String) -> Result<Option<Table>>;
 
+    // 获取所有的表，按表名排序
+    fn list_tables(&self) -> Result<Vec<Table>>;
+
+    // 创建视图，名字不能和已有的表或视图相同
+    fn create_view(&mut self, view: View) -> Result<()>;
+
+    // 删除视图，不影响视图引用的表
+    fn drop_view(&mut self, name: String) -> Result<()>;
+
+    // 获取视图的定义
+    fn get_view(&self, name: String) -> Result<Option<View>>;
+
+    // 获取所有的视图，按名字排序
+    fn list_views(&self) -> Result<Vec<View>>;
+
+    // 表中的行数，只统计 key 的个数，不解码行
+    fn count_rows(&self, table_name: String) -> Result<usize>;
+
+    // 统计表的行数和大小，保存为表的统计信息并返回
+    fn analyze_table(&mut self, table_name: String) -> Result<TableStats>;
+
+    // 最近一次 ANALYZE 保存的统计信息，没有统计过时为 None
+    fn get_table_stats(&self, table_name: String) -> Result<Option<TableStats>>;
+
+    // 判断表是否存在
+    fn table_exists(&self, name: &str) -> Result<bool> {
+        Ok(self.get_table(name.to_string())?.is_some())
+    }
+
+    // 存储引擎的状态信息，以 (名称, 值) 的形式返回
+    fn engine_status(&self) -> Result<Vec<(String, Value)>>;
+
+    // 存储引擎中所有的 key，按编码后的顺序返回，不做可见性判断，用于 DEBUG KEYS
+    fn debug_keys(&self) -> Result<Vec<DebugKey>>;
+
+    // 事务中最后一个自动分配的自增列的值，没有分配过时为 None
+    fn last_insert_id(&self) -> Option<i64>;
+
     // 必须拿到表名
     fn must_get_table(&self, table_name: String) -> Result<Table> {
-        self.get_table(table_name.clone())?.ok_or(Error::Internel(
-            format!("table {} dose not exist!", table_name)
-        ))
+        self.get_table(table_name.clone())?.ok_or(Error::TableNotFound(table_name))
+    }
+}
+
+// 写冲突重试时两次重试之间的最长等待时间
+const MAX_RETRY_BACKOFF: Duration = Duration::from_millis(100);
+
+// 在 [delay / 2, delay] 之间随机选择等待时间，随机数取自标准库中随机初始化的哈希种子
+fn jitter(delay: Duration) -> Duration {
+    let half = delay / 2;
+    let nanos = half.as_nanos() as u64;
+    if nanos == 0 {
+        return delay;
+    }
+    half + Duration::from_nanos(RandomState::new().build_hasher().finish() % (nanos + 1))
+}
+
+// 遇到写冲突时重新执行 f，最多重试 max_retries 次，重试次数用完后返回最后一次的错误
+// 每次重试前等待一段时间，从 backoff 开始每次翻倍，不超过 MAX_RETRY_BACKOFF，并加上随机抖动避免冲突的操作同时重试
+fn retry_on_conflict<T>(max_retries: usize, backoff: Duration, mut f: impl FnMut() -> Result<T>) -> Result<T> {
+    let mut delay = backoff;
+    let mut retries = 0;
+    loop {
+        match f() {
+            Err(Error::TransactionConflict) if retries < max_retries => {
+                retries += 1;
+                std::thread::sleep(jitter(delay));
+                delay = (delay * 2).min(MAX_RETRY_BACKOFF);
+            }
+            result => return result,
+        }
+    }
+}
+
+// DEBUG KEYS 输出的存储引擎中的一个 key
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugKey {
+    // 解码之后的 key，例如 Version(Row("t", [Integer(1)]), 7)
+    pub key: String,
+    pub raw_key: Vec<u8>,
+    pub value_size: usize,
+    // 是否为删除标记，只有数据的版本有这个属性，其他的 key 为 None
+    pub tombstone: Option<bool>,
+    // 数据版本的过期时间（UNIX 毫秒），没有设置 TTL 时为 None
+    pub expires_at: Option<u64>,
+}
+
+// 预处理语句，由 Session::prepare 创建
+pub struct PreparedStatement {
+    // 原始的 SQL 文本，执行时传给 QueryHook
+    sql: String,
+    stmt: Statement,
+    // 占位符的个数
+    parameters: usize,
+}
+
+impl PreparedStatement {
+    pub fn parameters(&self) -> usize {
+        self.parameters
     }
 }
 
 // 客户端 session 定义
+// Session 可以移动到其他线程中使用，但同一时间只被一个线程使用，多个客户端各自持有一个 Session
 pub struct Session<E: Engine> {
     engine: E,
+    // 取消正在执行的语句，每条语句开始执行时重置
+    cancelled: Arc<AtomicBool>,
+    // 最近一次成功执行的语句中自动分配的自增列的值
+    last_insert_id: Option<i64>,
+    // 设置之后 ORDER BY 使用外部排序，值为临时文件的目录和缓存的字节数上限
+    external_sort: Option<(PathBuf, usize)>,
+    // BEGIN 开启的显式事务，为 None 时每条语句在单独的隐式事务中执行
+    txn: Option<E::Transaction>,
+    // 每条语句执行结束之后调用
+    hook: Option<Box<dyn QueryHook + Send>>,
 }
 
 impl<E: Engine> Session<E> {
     
-    // 执行客户端 sql 语句
-    pub fn execute(&mut self, sql: &str) -> Result<ResultSet> {
-        match Parser::new(sql).parse()? {
-            stmt => {
-                // 开启一个事务
-                let mut txn = self.engine.begin()?;
-
-                match Plan::build(stmt).execute(&mut txn) {
-                    Ok(result) => {
-                        // 执行成功，提交事务
-                        txn.commit()?;
-                        Ok(result)
-                    },
-                    Err(err) => {
-                        // 执行失败，回滚事务
-                        txn.rollback()?;
-                        Err(err)
-                    }
+    // 执行客户端 sql 语句，返回执行结果以及耗时
+    pub fn execute(&mut self, sql: &str) -> Result<ExecutionResult> {
+        self.execute_until(sql, None)
+    }
+
+    // 执行时间超过 timeout 时中断执行并回滚，返回 Error::Timeout
+    pub fn execute_with_timeout(&mut self, sql: &str, timeout: Duration) -> Result<ExecutionResult> {
+        self.execute_until(sql, Some(Instant::now() + timeout))
+    }
+
+    // 遇到写冲突时回滚并重新执行语句，最多重试 max_retries 次，重试次数用完后返回最后一次的错误
+    // 隐式事务中重新执行整条语句是安全的；显式事务出错时已经整体回滚，只重新执行这一条语句会丢失之前的修改，因此不会重试
+    pub fn execute_with_retry(&mut self, sql: &str, max_retries: usize, backoff: Duration) -> Result<ExecutionResult> {
+        if self.txn.is_some() {
+            return self.execute(sql);
+        }
+        retry_on_conflict(max_retries, backoff, || self.execute(sql))
+    }
+
+    // 是否处于 BEGIN 开启的显式事务中
+    pub fn in_transaction(&self) -> bool {
+        self.txn.is_some()
+    }
+
+    // 最近一个自动分配的自增列的值，没有分配自增值的语句不会改变它
+    pub fn last_insert_id(&self) -> Option<i64> {
+        self.last_insert_id
+    }
+
+    // ORDER BY 缓存的行超过 threshold 字节时，排序之后写入 spill_path 下的临时文件，最后归并输出
+    // 临时文件在结果读取完或者被丢弃时删除，阈值可以使用 DEFAULT_SORT_THRESHOLD
+    pub fn set_external_sort(&mut self, spill_path: PathBuf, threshold: usize) {
+        self.external_sort = Some((spill_path, threshold));
+    }
+
+    // 注册语句执行结束之后的回调，替换之前注册的回调
+    // execute、execute_stream、execute_prepared 等返回 ExecutionResult 的方法都会调用，execute_to_writer 不会
+    pub fn set_hook(&mut self, hook: Box<dyn QueryHook + Send>) {
+        self.hook = Some(hook);
+    }
+
+    // 返回取消标记，在其他线程中将其置为 true 可以中断正在执行的语句，语句返回 Error::Cancelled
+    pub fn cancel_flag(&self) -> Arc<AtomicBool> {
+        self.cancelled.clone()
+    }
+
+    // 执行查询语句，结果为 ResultSet::Stream，调用方逐行读取，不会把所有的行都放到内存中
+    // 返回之前事务已经提交，读取行的过程中出现的错误（例如过滤条件的类型错误）在遍历时返回
+    pub fn execute_stream(&mut self, sql: &str) -> Result<ExecutionResult> {
+        self.run(sql, None, Plan::execute_stream)
+    }
+
+    // 解析带有 ? 占位符的语句，之后可以通过 execute_prepared 绑定不同的参数多次执行
+    pub fn prepare(&self, sql: &str) -> Result<PreparedStatement> {
+        let mut parser = Parser::new(sql);
+        let stmt = parser.parse()?;
+        Ok(PreparedStatement { sql: sql.to_string(), stmt, parameters: parser.parameters() })
+    }
+
+    // 按顺序将 params 绑定到占位符上并执行，参数的个数必须和占位符的个数一致
+    // 参数直接作为常量代入，不会作为 SQL 文本解析
+    pub fn execute_prepared(&mut self, stmt: &PreparedStatement, params: &[Value]) -> Result<ExecutionResult> {
+        if params.len() != stmt.parameters {
+            return Err(Error::Parse(format!(
+                "statement expects {} parameters, got {}",
+                stmt.parameters,
+                params.len()
+            )));
+        }
+        let start = Instant::now();
+        let result = stmt.stmt.clone().bind(params).and_then(|s| self.run_statement(s, start, None, Plan::execute));
+        self.notify(&stmt.sql, result, start)
+    }
+
+    fn execute_until(&mut self, sql: &str, deadline: Option<Instant>) -> Result<ExecutionResult> {
+        self.run(sql, deadline, Plan::execute)
+    }
+
+    fn run(
+        &mut self,
+        sql: &str,
+        deadline: Option<Instant>,
+        execute: fn(Plan, &mut ExecutionContext<E::Transaction>) -> Result<ResultSet>,
+    ) -> Result<ExecutionResult> {
+        let start = Instant::now();
+        let result = Parser::new(sql).parse().and_then(|stmt| self.run_statement(stmt, start, deadline, execute));
+        self.notify(sql, result, start)
+    }
+
+    // 调用注册的回调，回调中的 panic 被捕获之后忽略，返回原来的执行结果
+    fn notify(&self, sql: &str, result: Result<ExecutionResult>, start: Instant) -> Result<ExecutionResult> {
+        let Some(hook) = &self.hook else {
+            return result;
+        };
+        let (outcome, version, elapsed) = match result {
+            Ok(ExecutionResult { result, version, elapsed }) => (Ok(result), version, elapsed),
+            Err(err) => (Err(err), 0, start.elapsed()),
+        };
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| hook.on_statement(sql, &outcome, elapsed)));
+        outcome.map(|result| ExecutionResult { result, version, elapsed })
+    }
+
+    fn run_statement(
+        &mut self,
+        stmt: Statement,
+        start: Instant,
+        deadline: Option<Instant>,
+        execute: fn(Plan, &mut ExecutionContext<E::Transaction>) -> Result<ResultSet>,
+    ) -> Result<ExecutionResult> {
+        let stmt = match stmt {
+            Statement::Begin { isolation } => return self.begin(isolation, start),
+            Statement::Commit => return self.end_transaction(true, start),
+            Statement::Rollback => return self.end_transaction(false, start),
+            stmt => stmt,
+        };
+        let explicit = self.txn.is_some();
+        let mut ctx = self.context(deadline)?;
+        let result = self.plan(stmt, &mut ctx).and_then(|plan| execute(plan, &mut ctx));
+        let (result, version) = self.finish(ctx, explicit, result)?;
+        Ok(ExecutionResult { result, version, elapsed: start.elapsed() })
+    }
+
+    // 开启显式事务，已经在事务中时报错
+    fn begin(&mut self, isolation: IsolationLevel, start: Instant) -> Result<ExecutionResult> {
+        if let Some(txn) = &self.txn {
+            return Err(Error::Internal(format!("transaction {} is already in progress", txn.version())));
+        }
+        let txn = self.engine.begin_with_isolation(isolation)?;
+        let version = txn.version();
+        self.txn = Some(txn);
+        Ok(ExecutionResult { result: ResultSet::Begin { version, isolation }, version, elapsed: start.elapsed() })
+    }
+
+    // 提交或回滚显式事务，不在事务中时报错
+    fn end_transaction(&mut self, commit: bool, start: Instant) -> Result<ExecutionResult> {
+        let txn = self.txn.take().ok_or_else(|| Error::Internal("no transaction in progress".to_string()))?;
+        let (result, version) = if commit {
+            let version = txn.commit()?;
+            (ResultSet::Commit { version }, version)
+        } else {
+            txn.rollback()?;
+            (ResultSet::Rollback { version: txn.version() }, txn.version())
+        };
+        Ok(ExecutionResult { result, version, elapsed: start.elapsed() })
+    }
+
+    // 语句执行结束之后，隐式事务成功时提交，显式事务保留到 COMMIT 或 ROLLBACK
+    // 执行失败时回滚事务，显式事务中的语句失败同样会回滚整个事务
+    fn finish<T>(&mut self, ctx: ExecutionContext<E::Transaction>, explicit: bool, result: Result<T>) -> Result<(T, Version)> {
+        match result {
+            Ok(value) => {
+                if let Some(id) = ctx.txn.last_insert_id() {
+                    self.last_insert_id = Some(id);
                 }
+                let version = if explicit {
+                    let version = ctx.txn.version();
+                    self.txn = Some(ctx.txn);
+                    version
+                } else {
+                    ctx.txn.commit()?
+                };
+                Ok((value, version))
+            },
+            Err(err) => {
+                ctx.txn.rollback()?;
+                Err(err)
             }
         }
     }
-}
\ No newline at end of file
+
+    // 生成并优化执行计划，语句中的 WITH 在这里执行
+    fn plan(&self, stmt: Statement, ctx: &mut ExecutionContext<E::Transaction>) -> Result<Plan> {
+        let plan = Plan::build_in_context(stmt, ctx)?.optimize();
+        Ok(match &self.external_sort {
+            Some((spill_path, threshold)) => plan.with_external_sort(spill_path.clone(), *threshold),
+            None => plan,
+        })
+    }
+
+    // 创建执行语句的上下文，不在显式事务中时开启一个新的事务
+    fn context(&mut self, deadline: Option<Instant>) -> Result<ExecutionContext<E::Transaction>> {
+        self.cancelled.store(false, Ordering::Relaxed);
+        let txn = match self.txn.take() {
+            Some(txn) => txn,
+            None => self.engine.begin()?,
+        };
+        let ctx = ExecutionContext::new(txn).with_cancel_flag(self.cancelled.clone());
+        Ok(match deadline {
+            Some(deadline) => ctx.with_deadline(deadline),
+            None => ctx,
+        })
+    }
+
+    // 执行查询语句，并将结果以 JSON Lines 的格式写入 writer，返回写入的行数
+    pub fn execute_to_writer<W: Write>(&mut self, sql: &str, writer: &mut W) -> Result<usize> {
+        let stmt = Parser::new(sql).parse()?;
+        let explicit = self.txn.is_some();
+        let mut ctx = self.context(None)?;
+        let result = self.plan(stmt, &mut ctx).and_then(|plan| executor::write_json_lines(plan.0, &mut ctx, writer));
+        Ok(self.finish(ctx, explicit, result)?.0)
+    }
+}
+
+// 丢弃 Session 时回滚未提交的显式事务
+impl<E: Engine> Drop for Session<E> {
+    fn drop(&mut self) {
+        if let Some(txn) = self.txn.take() {
+            let _ = txn.rollback();
+        }
+    }
+}
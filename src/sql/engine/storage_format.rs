@@ -0,0 +1,286 @@
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{error::{Error, Result}, sql::{schema::{Table, View}, types::Row}, storage::codec};
+
+// 行和表结构在存储中的编码格式，第一个字节为格式的版本号，之后是 bincode 编码
+// 修改 Value、Table 等结构的序列化方式时需要增加版本号，并在 upgrade_row、upgrade_table 中处理上一个版本的数据
+// 版本 0：没有版本号，直接使用 bincode 编码
+// 版本 1：一个字节的版本号 + bincode 编码
+// 版本 2：列的默认值保存为表达式，而不是建表时计算出的值，行的编码不变
+// 版本 3：列增加注释，行的编码不变
+// 版本 4：表结构增加主键列的下标，行的编码不变
+// 解码时的 limit 为允许的最大字节数，和存储引擎的设置一致，见 MvccTransaction::max_value_size
+pub const FORMAT_VERSION: u8 = 4;
+
+pub fn encode_row(row: &Row) -> Result<Vec<u8>> {
+    encode(row)
+}
+
+pub fn decode_row(data: &[u8], limit: u64) -> Result<Row> {
+    decode("row", data, limit)
+}
+
+pub fn encode_table(table: &Table) -> Result<Vec<u8>> {
+    encode(table)
+}
+
+pub fn decode_table(data: &[u8], limit: u64) -> Result<Table> {
+    decode("table", data, limit)
+}
+
+// 视图在版本 2 之后才出现，升级版本时如果修改了语法树的序列化方式，同样需要处理视图
+pub fn encode_view(view: &View) -> Result<Vec<u8>> {
+    encode(view)
+}
+
+pub fn decode_view(data: &[u8], limit: u64) -> Result<View> {
+    decode("view", data, limit)
+}
+
+// 将 version 版本编码的行转换为 version + 1 版本，由 KVEngine::migrate 逐个版本调用
+pub fn upgrade_row(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+    match version {
+        0 => Ok(add_version(1, data)),
+        1..=3 => Ok(add_version(version + 1, &data[1..])),
+        v => Err(unsupported_upgrade(v)),
+    }
+}
+
+// 将 version 版本编码的表结构转换为 version + 1 版本
+pub fn upgrade_table(version: u8, data: &[u8]) -> Result<Vec<u8>> {
+    match version {
+        0 => Ok(add_version(1, data)),
+        1 => {
+            let table: v1::Table = codec::deserialize(&data[1..])?;
+            Ok(add_version(2, &codec::serialize(&v2::Table::from(table))?))
+        }
+        2 => {
+            let table: v2::Table = codec::deserialize(&data[1..])?;
+            Ok(add_version(3, &codec::serialize(&v3::Table::from(table))?))
+        }
+        3 => {
+            let table: v3::Table = codec::deserialize(&data[1..])?;
+            encode_table(&table.into())
+        }
+        v => Err(unsupported_upgrade(v)),
+    }
+}
+
+fn add_version(version: u8, data: &[u8]) -> Vec<u8> {
+    let mut upgraded = Vec::with_capacity(data.len() + 1);
+    upgraded.push(version);
+    upgraded.extend_from_slice(data);
+    upgraded
+}
+
+fn unsupported_upgrade(version: u8) -> Error {
+    Error::Serialization(format!("cannot upgrade from unsupported format version {}", version))
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let mut data = vec![FORMAT_VERSION];
+    codec::serialize_into(&mut data, value)?;
+    Ok(data)
+}
+
+fn decode<T: DeserializeOwned>(kind: &str, data: &[u8], limit: u64) -> Result<T> {
+    match data.split_first() {
+        Some((&FORMAT_VERSION, rest)) => codec::deserialize_with_limit(rest, limit),
+        Some((version, _)) => Err(Error::Serialization(format!(
+            "{} encoded with unsupported format version {}",
+            kind, version
+        ))),
+        None => Err(Error::Serialization(format!("{} has no format version", kind))),
+    }
+}
+
+// 版本 1 的表结构，只用于读取旧的数据
+pub(crate) mod v1 {
+    use serde::{Deserialize, Serialize};
+
+    use crate::sql::{parser::ast::Consts, schema::Index, types::{DataType, Value}};
+
+    use super::v2;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Table {
+        pub name: String,
+        pub columns: Vec<Column>,
+        pub indexes: Vec<Index>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Column {
+        pub name: String,
+        pub datatype: DataType,
+        pub nullable: bool,
+        // 建表时计算出的默认值
+        pub default: Option<Value>,
+        pub primary_key: bool,
+        pub auto_increment: bool,
+        pub max_len: Option<usize>,
+    }
+
+    impl From<Table> for v2::Table {
+        fn from(table: Table) -> Self {
+            let columns = table
+                .columns
+                .into_iter()
+                .map(|c| v2::Column {
+                    name: c.name,
+                    datatype: c.datatype,
+                    nullable: c.nullable,
+                    default: c.default.map(|v| Consts::from(v).into()),
+                    primary_key: c.primary_key,
+                    auto_increment: c.auto_increment,
+                    max_len: c.max_len,
+                })
+                .collect();
+            Self { name: table.name, columns, indexes: table.indexes }
+        }
+    }
+}
+
+// 版本 2 的表结构，列没有注释
+pub(crate) mod v2 {
+    use serde::{Deserialize, Serialize};
+
+    use crate::sql::{parser::ast::Expression, schema::{self, Index}, types::DataType};
+
+    use super::v3;
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Table {
+        pub name: String,
+        pub columns: Vec<Column>,
+        pub indexes: Vec<Index>,
+    }
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Column {
+        pub name: String,
+        pub datatype: DataType,
+        pub nullable: bool,
+        pub default: Option<Expression>,
+        pub primary_key: bool,
+        pub auto_increment: bool,
+        pub max_len: Option<usize>,
+    }
+
+    impl From<Table> for v3::Table {
+        fn from(table: Table) -> Self {
+            let columns = table
+                .columns
+                .into_iter()
+                .map(|c| schema::Column {
+                    name: c.name,
+                    datatype: c.datatype,
+                    nullable: c.nullable,
+                    default: c.default,
+                    primary_key: c.primary_key,
+                    auto_increment: c.auto_increment,
+                    max_len: c.max_len,
+                    comment: None,
+                })
+                .collect();
+            Self { name: table.name, columns, indexes: table.indexes }
+        }
+    }
+}
+
+// 版本 3 的表结构，主键只记录在列上，最多只有一列
+pub(crate) mod v3 {
+    use serde::{Deserialize, Serialize};
+
+    use crate::sql::schema::{self, Column, Index};
+
+    #[derive(Debug, Serialize, Deserialize)]
+    pub struct Table {
+        pub name: String,
+        pub columns: Vec<Column>,
+        pub indexes: Vec<Index>,
+    }
+
+    impl From<Table> for schema::Table {
+        fn from(table: Table) -> Self {
+            // 没有声明主键时以第一列作为主键
+            let primary_key = vec![table.columns.iter().position(|c| c.primary_key).unwrap_or(0)];
+            Self { name: table.name, columns: table.columns, indexes: table.indexes, primary_key }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::{Error, Result},
+        sql::{parser::ast::Consts, schema::Index, types::{DataType, Value}},
+        storage::codec::DEFAULT_MAX_VALUE_SIZE,
+    };
+
+    use super::{decode_row, decode_table, encode_row, upgrade_row, upgrade_table, v1, v2, v3, FORMAT_VERSION};
+
+    #[test]
+    fn test_row_format_version() -> Result<()> {
+        let row = vec![Value::Integer(1), Value::String("a".to_string()), Value::Null];
+        let data = encode_row(&row)?;
+        assert_eq!(data[0], FORMAT_VERSION);
+        assert_eq!(decode_row(&data, DEFAULT_MAX_VALUE_SIZE)?, row);
+
+        // 未知的版本号
+        let mut unknown = bincode::serialize(&row)?;
+        unknown.insert(0, 5);
+        assert_eq!(
+            decode_row(&unknown, DEFAULT_MAX_VALUE_SIZE),
+            Err(Error::Serialization("row encoded with unsupported format version 5".to_string()))
+        );
+        assert!(decode_row(&[], DEFAULT_MAX_VALUE_SIZE).is_err());
+
+        // 版本 0 没有版本号前缀，逐个版本升级之后可以正常读取
+        let legacy = bincode::serialize(&row)?;
+        let upgraded = (0..FORMAT_VERSION).try_fold(legacy, |data, version| upgrade_row(version, &data))?;
+        assert_eq!(decode_row(&upgraded, DEFAULT_MAX_VALUE_SIZE)?, row);
+        assert!(upgrade_row(FORMAT_VERSION, &data).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_format_upgrade() -> Result<()> {
+        let legacy = v1::Table {
+            name: "t".to_string(),
+            columns: vec![v1::Column {
+                name: "a".to_string(),
+                datatype: DataType::Integer,
+                nullable: true,
+                default: Some(Value::Integer(3)),
+                primary_key: true,
+                auto_increment: false,
+                max_len: None,
+            }],
+            indexes: vec![Index { name: "i".to_string(), column: "a".to_string() }],
+        };
+        let mut data = bincode::serialize(&legacy)?;
+        data.insert(0, 1);
+        assert!(decode_table(&data, DEFAULT_MAX_VALUE_SIZE).is_err());
+
+        // 默认值转换为常量表达式
+        let upgraded = upgrade_table(1, &data)?;
+        assert_eq!(upgraded[0], 2);
+        let v2: v2::Table = bincode::deserialize(&upgraded[1..])?;
+        assert_eq!(v2.columns[0].default, Some(Consts::Integer(3).into()));
+
+        // 版本 3 的列增加了注释，升级之后为空
+        let upgraded = upgrade_table(2, &upgraded)?;
+        assert_eq!(upgraded[0], 3);
+        let v3: v3::Table = bincode::deserialize(&upgraded[1..])?;
+        assert_eq!(v3.columns[0].comment, None);
+
+        // 版本 4 的表结构记录主键列的下标
+        let table = decode_table(&upgrade_table(3, &upgraded)?, DEFAULT_MAX_VALUE_SIZE)?;
+        assert_eq!(table.columns[0].default, Some(Consts::Integer(3).into()));
+        assert_eq!(table.primary_key, vec![0]);
+        assert_eq!(table.indexes, legacy.indexes);
+        assert!(upgrade_table(FORMAT_VERSION, &data).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::{cmp::Ordering, collections::{hash_map::Entry, HashMap}};
+
+use crate::{
+    error::{Error, Result},
+    sql::{engine::Transaction, parser::ast::{Aggregate, Expression}, types::{DataType, Value}},
+};
+
+use super::{query::filter_rows, ExecutionContext, Executor, ResultSet};
+
+// 读取所有的行，按 group_by 的值放入哈希表中分组，每组输出一行
+// 输出的行依次为分组的值和每个聚合函数的结果，分组按第一次出现的顺序输出
+// 没有 GROUP BY 时所有的行属于同一组，即使没有输入的行也会输出一行
+pub struct GroupBy<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    group_by: Vec<Expression>,
+    aggregates: Vec<Expression>,
+    having: Option<Expression>,
+}
+
+impl<T: Transaction> GroupBy<T> {
+    pub fn new(
+        source: Box<dyn Executor<T>>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<Expression>,
+        having: Option<Expression>,
+    ) -> Box<Self> {
+        Box::new(Self { source, group_by, aggregates, having })
+    }
+}
+
+impl<T: Transaction> Executor<T> for GroupBy<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, rows) = self.source.execute(ctx)?.into_stream("group by")?;
+        let functions = self
+            .aggregates
+            .iter()
+            .map(|e| match e {
+                Expression::Aggregate(func, arg) => Ok((*func, arg.as_deref())),
+                e => Err(Error::Internal(format!("{} is not an aggregate function", e))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let accumulators = || functions.iter().map(|(func, _)| Accumulator::new(*func)).collect::<Vec<_>>();
+
+        let mut groups: Vec<(Vec<Value>, Vec<Accumulator>)> = Vec::new();
+        let mut index: HashMap<Vec<Value>, usize> = HashMap::new();
+        for row in rows {
+            let row = row?;
+            let keys = self.group_by.iter().map(|e| e.evaluate(&columns, &row)).collect::<Result<Vec<_>>>()?;
+            let i = match index.entry(keys.iter().cloned().map(Value::group_key).collect()) {
+                Entry::Occupied(entry) => *entry.get(),
+                Entry::Vacant(entry) => {
+                    groups.push((keys, accumulators()));
+                    *entry.insert(groups.len() - 1)
+                }
+            };
+            for (acc, (_, arg)) in groups[i].1.iter_mut().zip(&functions) {
+                // COUNT(*) 统计所有的行，用一个非 NULL 的值代替参数
+                let value = match arg {
+                    Some(arg) => arg.evaluate(&columns, &row)?,
+                    None => Value::Boolean(true),
+                };
+                // 类型不匹配时用参数表达式作为列名
+                acc.add(value).map_err(|err| match err {
+                    Error::TypeMismatch { expected, got, .. } => {
+                        Error::TypeMismatch { column: arg.map(|a| a.to_string()).unwrap_or_default(), expected, got }
+                    }
+                    err => err,
+                })?;
+            }
+        }
+        if self.group_by.is_empty() && groups.is_empty() {
+            groups.push((Vec::new(), accumulators()));
+        }
+
+        let columns = self.group_by.iter().chain(&self.aggregates).map(|e| e.to_string()).collect::<Vec<_>>();
+        let rows = groups.into_iter().map(|(mut row, accs)| {
+            row.extend(accs.into_iter().map(Accumulator::finish));
+            Ok(row)
+        });
+        let rows = match self.having {
+            Some(having) => filter_rows(having, columns.clone(), Box::new(rows)),
+            None => Box::new(rows),
+        };
+        Ok(ResultSet::Stream { columns, rows })
+    }
+}
+
+// 聚合函数的中间状态，NULL 值不参与计算
+enum Accumulator {
+    Count(i64),
+    // 没有非 NULL 的值时为 NULL
+    Sum(Value),
+    Avg { sum: f64, count: u64 },
+    Min(Value),
+    Max(Value),
+}
+
+impl Accumulator {
+    fn new(func: Aggregate) -> Self {
+        match func {
+            Aggregate::Count => Self::Count(0),
+            Aggregate::Sum => Self::Sum(Value::Null),
+            Aggregate::Avg => Self::Avg { sum: 0.0, count: 0 },
+            Aggregate::Min => Self::Min(Value::Null),
+            Aggregate::Max => Self::Max(Value::Null),
+        }
+    }
+
+    // 参数的类型不支持时返回 Error::TypeMismatch，列名由调用方补充
+    fn add(&mut self, value: Value) -> Result<()> {
+        if value == Value::Null {
+            return Ok(());
+        }
+        match self {
+            Self::Count(count) => *count += 1,
+            // 整数相加的结果为整数，和浮点数相加时转换为浮点数
+            Self::Sum(sum) => {
+                *sum = match (&*sum, value) {
+                    (Value::Null, v @ (Value::Integer(_) | Value::Float(_))) => v,
+                    (Value::Integer(a), Value::Integer(b)) => match a.checked_add(b) {
+                        Some(v) => Value::Integer(v),
+                        None => return Err(Error::Internal("integer overflow in SUM".to_string())),
+                    },
+                    (Value::Integer(a), Value::Float(b)) => Value::Float(*a as f64 + b),
+                    (Value::Float(a), Value::Integer(b)) => Value::Float(a + b as f64),
+                    (Value::Float(a), Value::Float(b)) => Value::Float(a + b),
+                    (_, v) => return Err(numeric_expected(&v)),
+                }
+            }
+            Self::Avg { sum, count } => {
+                *sum += match value {
+                    Value::Integer(i) => i as f64,
+                    Value::Float(f) => f,
+                    v => return Err(numeric_expected(&v)),
+                };
+                *count += 1;
+            }
+            Self::Min(min) => {
+                if *min == Value::Null || compare(&value, min)? == Ordering::Less {
+                    *min = value;
+                }
+            }
+            Self::Max(max) => {
+                if *max == Value::Null || compare(&value, max)? == Ordering::Greater {
+                    *max = value;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Value {
+        match self {
+            Self::Count(count) => Value::Integer(count),
+            Self::Sum(v) | Self::Min(v) | Self::Max(v) => v,
+            Self::Avg { count: 0, .. } => Value::Null,
+            Self::Avg { sum, count } => Value::Float(sum / count as f64),
+        }
+    }
+}
+
+// SUM 和 AVG 只接受数值，非 NULL 的参数才会走到这里
+fn numeric_expected(value: &Value) -> Error {
+    Error::TypeMismatch { column: String::new(), expected: DataType::Float, got: value.datatype().unwrap() }
+}
+
+// MIN 和 MAX 比较的两个值都不是 NULL，无法比较时以已有的值的类型作为期望的类型
+fn compare(a: &Value, b: &Value) -> Result<Ordering> {
+    a.sql_cmp(b).ok_or_else(|| Error::TypeMismatch {
+        column: String::new(),
+        expected: b.datatype().unwrap(),
+        got: a.datatype().unwrap(),
+    })
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use crate::{error::Result, sql::{engine::Transaction, parser::ast::Expression, types::{Row, Value}}};
+
+use super::{query::filter_rows, ExecutionContext, Executor, ResultSet};
+
+// 嵌套循环连接，对左右两边的每一对行检查 using 中的列是否相等，再用 predicate 过滤拼接之后的行
+// predicate 为两边各一列的等值条件时，规划器会改用 HashJoin
+pub struct NestedLoopJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    using: Vec<(usize, usize)>,
+    predicate: Option<Expression>,
+    // 左右两边的表名，用于区分同名的列
+    aliases: (Option<String>, Option<String>),
+}
+
+impl<T: Transaction> NestedLoopJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+        using: Vec<(usize, usize)>,
+        predicate: Option<Expression>,
+        aliases: (Option<String>, Option<String>),
+    ) -> Box<Self> {
+        Box::new(Self { left, right, using, predicate, aliases })
+    }
+}
+
+impl<T: Transaction> Executor<T> for NestedLoopJoin<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        // 左边逐行读取，右边需要反复遍历，先全部读出，不必为左边的每一行重新扫描
+        let (lcols, lrows) = self.left.execute(ctx)?.into_stream("join")?;
+        let (rcols, rrows) = self.right.execute(ctx)?.into_stream("join")?;
+        let rrows = rrows.collect::<Result<Vec<_>>>()?;
+
+        let using = self.using;
+        let columns = join_columns(lcols, rcols, &using, (self.aliases.0.as_deref(), self.aliases.1.as_deref()));
+        let rows = lrows.flat_map(move |lrow| {
+            let lrow = match lrow {
+                Ok(lrow) => lrow,
+                Err(err) => return vec![Err(err)],
+            };
+            rrows
+                .iter()
+                // 和 SQL 的语义一致，NULL 和任何值都不相等
+                .filter(|rrow| using.iter().all(|(l, r)| lrow[*l] != Value::Null && lrow[*l] == rrow[*r]))
+                .map(|rrow| {
+                    let mut row = lrow.clone();
+                    row.extend(rrow.iter().cloned());
+                    Ok(row)
+                })
+                .collect()
+        });
+
+        // 条件在拼接之后的行上计算，右表的列位于左表的列之后
+        let rows = match self.predicate {
+            Some(predicate) => filter_rows(predicate, columns.clone(), Box::new(rows)),
+            None => Box::new(rows),
+        };
+        Ok(ResultSet::Stream { columns, rows })
+    }
+}
+
+// 等值连接，用行数较少的一边建立 key 到行的哈希表，再逐行读取另一边查找 key 相同的行
+// 输出的行仍然是左表的列在前、右表的列在后
+pub struct HashJoin<T: Transaction> {
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    left_key: Expression,
+    right_key: Expression,
+    aliases: (Option<String>, Option<String>),
+}
+
+impl<T: Transaction> HashJoin<T> {
+    pub fn new(
+        left: Box<dyn Executor<T>>,
+        right: Box<dyn Executor<T>>,
+        left_key: Expression,
+        right_key: Expression,
+        aliases: (Option<String>, Option<String>),
+    ) -> Box<Self> {
+        Box::new(Self { left, right, left_key, right_key, aliases })
+    }
+}
+
+impl<T: Transaction> Executor<T> for HashJoin<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        // 没有表的行数统计，两边都读出之后按实际的行数选择建立哈希表的一边
+        let (lcols, lrows) = self.left.execute(ctx)?.into_stream("join")?;
+        let (rcols, rrows) = self.right.execute(ctx)?.into_stream("join")?;
+        let lrows = lrows.collect::<Result<Vec<_>>>()?;
+        let rrows = rrows.collect::<Result<Vec<_>>>()?;
+
+        let build_left = lrows.len() <= rrows.len();
+        let (build, build_cols, build_key, probe, probe_cols, probe_key) = if build_left {
+            (lrows, &lcols, &self.left_key, rrows, &rcols, &self.right_key)
+        } else {
+            (rrows, &rcols, &self.right_key, lrows, &lcols, &self.left_key)
+        };
+        let mut table: HashMap<Value, Vec<Row>> = HashMap::new();
+        for row in build {
+            if let Some(key) = join_key(build_key.evaluate(build_cols, &row)?) {
+                table.entry(key).or_default().push(row);
+            }
+        }
+
+        let mut rows = Vec::new();
+        for row in probe {
+            let Some(matches) = join_key(probe_key.evaluate(probe_cols, &row)?).and_then(|k| table.get(&k)) else {
+                continue;
+            };
+            for other in matches {
+                let (l, r) = if build_left { (other, &row) } else { (&row, other) };
+                let mut joined = l.clone();
+                joined.extend(r.iter().cloned());
+                rows.push(Ok(joined));
+            }
+        }
+
+        let columns = join_columns(lcols, rcols, &[], (self.aliases.0.as_deref(), self.aliases.1.as_deref()));
+        Ok(ResultSet::Stream { columns, rows: Box::new(rows.into_iter()) })
+    }
+}
+
+// 哈希连接的 key，连接时 NULL 和 NaN 不等于任何值，没有对应的 key
+fn join_key(value: Value) -> Option<Value> {
+    match value {
+        Value::Null => None,
+        Value::Float(f) if f.is_nan() => None,
+        value => Some(value.group_key()),
+    }
+}
+
+// 连接之后的列名，两边同名的列加上各自的表名，例如 a.id、b.id
+// using 中的列两边的值相等，Natural Join 只会输出其中一列，因此保持原来的列名
+pub fn join_columns(
+    left: Vec<String>,
+    right: Vec<String>,
+    using: &[(usize, usize)],
+    aliases: (Option<&str>, Option<&str>),
+) -> Vec<String> {
+    let qualify = |columns: &[String], other: &[String], alias: Option<&str>, merged: &dyn Fn(usize) -> bool| {
+        columns
+            .iter()
+            .enumerate()
+            .map(|(i, c)| match alias {
+                Some(alias) if !merged(i) && other.contains(c) => format!("{}.{}", alias, c),
+                _ => c.clone(),
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut columns = qualify(&left, &right, aliases.0, &|i| using.iter().any(|(l, _)| *l == i));
+    columns.extend(qualify(&right, &left, aliases.1, &|i| using.iter().any(|(_, r)| *r == i)));
+    columns
+}
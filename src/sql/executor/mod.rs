@@ -1,42 +1,960 @@
-use mutation::Insert;
-use query::Scan;
-use schema::CreateTable;
+use aggregate::GroupBy;
+use analyze::{ExplainAnalyze, InstrumentedExecutor, NodeStats};
+use join::{HashJoin, NestedLoopJoin};
+use mutation::{Insert, TruncateTable};
+use query::{Compute, DebugKeys, Distinct, Explain, Filter, InMemoryScan, Projection, Scan, ShowEngineStatus};
+use schema::{AnalyzeTable, CreateIndex, CreateTable, CreateView, Describe, DropIndex, DropView, ShowViews};
+use set_operation::SetOperation;
+use sort::Sort;
 
-use crate::error::Result;
+pub(crate) use join::join_columns;
+pub(crate) use query::filter_rows;
 
-use super::{engine::Transaction, plan::Node, types::Row};
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    io::Write,
+    rc::Rc,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    time::{Duration, Instant},
+};
+
+use crate::{error::{Error, Result}, storage::mvcc::{IsolationLevel, Version}};
+
+use super::{engine::Transaction, plan::Node, types::{format_primary_key, PrimaryKey, Row, Rows, Value}};
 
 
 mod schema;
 mod mutation;
 mod query;
+mod join;
+mod sort;
+mod aggregate;
+mod set_operation;
+mod analyze;
 
 // 执行其trait
 pub trait Executor<T: Transaction> {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet>;
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet>;
+}
+
+// 扫描和插入时每处理这么多行检查一次是否需要中断
+const INTERRUPT_CHECK_ROWS: usize = 64;
+
+// 执行语句时的上下文，包含事务以及用于中断执行的取消标记和截止时间
+pub struct ExecutionContext<T: Transaction> {
+    pub txn: T,
+    interrupt: Interrupt,
+}
+
+impl<T: Transaction> ExecutionContext<T> {
+    pub fn new(txn: T) -> Self {
+        Self { txn, interrupt: Interrupt { cancelled: Arc::new(AtomicBool::new(false)), deadline: None } }
+    }
+
+    // 其他线程将 cancelled 置为 true 后，执行会在下一次检查时中断
+    pub fn with_cancel_flag(mut self, cancelled: Arc<AtomicBool>) -> Self {
+        self.interrupt.cancelled = cancelled;
+        self
+    }
+
+    // 超过截止时间后，执行会在下一次检查时中断
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.interrupt.deadline = Some(deadline);
+        self
+    }
+
+    fn interrupt(&self) -> Interrupt {
+        self.interrupt.clone()
+    }
+
+    // 已经取消或者超时时返回错误，用于执行器之外的循环，例如递归的 CTE
+    pub(crate) fn check_interrupt(&self) -> Result<()> {
+        self.interrupt.check()
+    }
+}
+
+// 判断执行是否需要中断，流式的结果在读取时同样需要检查，所以单独拿出来可以被迭代器持有
+#[derive(Clone)]
+struct Interrupt {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
 }
 
-impl<T: Transaction> dyn Executor<T> {
+impl Interrupt {
+    fn check(&self) -> Result<()> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            return Err(Error::Cancelled);
+        }
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => Err(Error::Timeout),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<T: Transaction + 'static> dyn Executor<T> {
     // 根据执行计划节点生成对应执行器
     pub fn build(node: Node) -> Box<dyn Executor<T>> {
-        match node {
-            Node::CreateTable { schema } => CreateTable::new(schema),
+        Self::build_with(node, None)
+    }
+
+    // stats 不为空时，每个执行器都会被包装成 InstrumentedExecutor，
+    // 并按照先序遍历的顺序把每个节点的统计信息放入 stats 中
+    fn build_with(node: Node, mut stats: Option<&mut Vec<Rc<NodeStats>>>) -> Box<dyn Executor<T>> {
+        let node_stats = stats.as_mut().map(|stats| {
+            let node_stats = Rc::new(NodeStats::default());
+            stats.push(node_stats.clone());
+            node_stats
+        });
+        let executor: Box<dyn Executor<T>> = match node {
+            Node::CreateTable { schema, if_not_exists, or_replace } => CreateTable::new(schema, if_not_exists, or_replace),
             Node::Insert { table_name, columns, values } => Insert::new(table_name, columns, values),
-            Node::Scan { table_name } => Scan::new(table_name),
+            Node::Scan { table_name, filter, .. } => Scan::new(table_name, filter),
+            Node::InMemoryScan { columns, rows, .. } => InMemoryScan::new(columns, rows),
+            Node::Filter { source, predicate } => {
+                Filter::new(Self::build_with(*source, stats), predicate)
+            },
+            Node::NestedLoopJoin { left, right, using, predicate } => {
+                let aliases = (left.table_alias(), right.table_alias());
+                let left = Self::build_with(*left, stats.as_deref_mut());
+                let right = Self::build_with(*right, stats);
+                NestedLoopJoin::new(left, right, using, predicate, aliases)
+            },
+            Node::HashJoin { left, right, left_key, right_key } => {
+                let aliases = (left.table_alias(), right.table_alias());
+                let left = Self::build_with(*left, stats.as_deref_mut());
+                let right = Self::build_with(*right, stats);
+                HashJoin::new(left, right, left_key, right_key, aliases)
+            },
+            Node::Projection { source, columns } => {
+                Projection::new(Self::build_with(*source, stats), columns)
+            },
+            Node::Sort { source, order_by, method } => Sort::new(Self::build_with(*source, stats), order_by, method),
+            Node::Compute { source, expressions } => Compute::new(Self::build_with(*source, stats), expressions),
+            Node::GroupBy { source, group_by, aggregates, having } => {
+                GroupBy::new(Self::build_with(*source, stats), group_by, aggregates, having)
+            },
+            Node::Distinct { source } => Distinct::new(Self::build_with(*source, stats)),
+            Node::SetOperation { op, left, right, all } => {
+                let left = Self::build_with(*left, stats.as_deref_mut());
+                let right = Self::build_with(*right, stats);
+                SetOperation::new(op, left, right, all)
+            },
+            Node::ShowEngineStatus => ShowEngineStatus::new(),
+            Node::CreateView { view } => CreateView::new(view),
+            Node::DropView { name } => DropView::new(name),
+            Node::ShowViews => ShowViews::new(),
+            Node::DebugKeys { prefix } => DebugKeys::new(prefix),
+            Node::CreateIndex { index_name, table_name, column_name } => {
+                CreateIndex::new(index_name, table_name, column_name)
+            },
+            Node::DropIndex { index_name } => DropIndex::new(index_name),
+            Node::TruncateTable { table_name } => TruncateTable::new(table_name),
+            Node::AnalyzeTable { table_name } => AnalyzeTable::new(table_name),
+            Node::Describe { table_name } => Describe::new(table_name),
+            Node::Explain { inner } => Explain::new(*inner),
+            Node::ExplainAnalyze { inner } => ExplainAnalyze::new(*inner),
+        };
+        match node_stats {
+            Some(node_stats) => InstrumentedExecutor::new(executor, node_stats),
+            None => executor,
+        }
+    }
+}
+
+// 将查询结果以 JSON Lines 的格式直接写入 writer，返回写入的行数
+// 每读取到一行就写出，不会先收集所有的行
+pub fn write_json_lines<T: Transaction + 'static, W: Write>(node: Node, ctx: &mut ExecutionContext<T>, writer: &mut W) -> Result<usize> {
+    match node {
+        Node::Scan { .. }
+        | Node::InMemoryScan { .. }
+        | Node::Filter { .. }
+        | Node::NestedLoopJoin { .. }
+        | Node::HashJoin { .. }
+        | Node::Sort { .. }
+        | Node::Projection { .. }
+        | Node::Compute { .. }
+        | Node::GroupBy { .. }
+        | Node::Distinct { .. }
+        | Node::SetOperation { .. } => {},
+        _ => return Err(Error::Internal("only select statements can be streamed".to_string())),
+    }
+    let (columns, rows) = <dyn Executor<T>>::build(node).execute(ctx)?.into_stream("json lines")?;
+    let mut count = 0;
+    for row in rows {
+        write_json_line(writer, &columns, &row?)?;
+        count += 1;
+    }
+    writer.flush()?;
+    Ok(count)
+}
+
+// 按照列的顺序写入一个 JSON 对象，并以换行结尾
+// {"a": 1, "b": "foo"}
+fn write_json_line<W: Write>(writer: &mut W, columns: &[String], row: &Row) -> Result<()> {
+    writer.write_all(b"{")?;
+    for (i, (col, value)) in columns.iter().zip(row.iter()).enumerate() {
+        if i > 0 {
+            writer.write_all(b",")?;
         }
+        serde_json::to_writer(&mut *writer, col)?;
+        writer.write_all(b":")?;
+        serde_json::to_writer(&mut *writer, &value.to_json())?;
     }
+    writer.write_all(b"}\n")?;
+    Ok(())
 }
 
 // 执行结果定义
 pub enum ResultSet {
+    // 使用 IF NOT EXISTS 并且表已经存在时 created 为 false
     CreateTable {
         table_name: String,
+        created: bool,
+    },
+    CreateIndex {
+        index_name: String,
+    },
+    DropIndex {
+        index_name: String,
+    },
+    CreateView {
+        view_name: String,
+    },
+    DropView {
+        view_name: String,
     },
     Insert {
         count: usize,
+        // 按插入顺序排列的每一行的主键
+        keys: Vec<PrimaryKey>,
+    },
+    Delete {
+        count: usize,
     },
+    // 已经全部读取到内存中的查询结果，由 Session::execute 等返回
+    // 和 Stream 分开是因为调用方需要在语句结束之后多次访问、比较或者格式化结果，
+    // 而 Stream 中的行只能读取一次，读取过程中还可能返回错误
     Scan {
         columns: Vec<String>,
         rows: Vec<Row>
+    },
+    Explain {
+        plan: String,
+    },
+    ExplainAnalyze {
+        plan_with_stats: String,
+    },
+    // 显式事务的开始和结束，version 为事务的版本号
+    Begin {
+        version: Version,
+        isolation: IsolationLevel,
+    },
+    Commit {
+        version: Version,
+    },
+    Rollback {
+        version: Version,
+    },
+    // 行在被读取时才产生的查询结果，执行器之间也以这种形式传递
+    // 表扫描按批从存储中读取（见 MvccScan），读取结果时内存中只保存一批数据
+    // Plan::execute 返回前会将其收集为 Scan，Plan::execute_stream 直接返回
+    Stream {
+        columns: Vec<String>,
+        rows: Rows,
+    },
+}
+
+impl ResultSet {
+    // 以迭代器的形式取出查询结果，供上层执行器逐行处理
+    fn into_stream(self, executor: &str) -> Result<(Vec<String>, Rows)> {
+        match self {
+            ResultSet::Scan { columns, rows } => Ok((columns, Box::new(rows.into_iter().map(Ok)))),
+            ResultSet::Stream { columns, rows } => Ok((columns, rows)),
+            _ => Err(Error::Internal(format!("unexpected result set for {}", executor))),
+        }
+    }
+
+    // 读取所有的行，得到完整的查询结果
+    pub fn collect(self) -> Result<Self> {
+        match self {
+            ResultSet::Stream { columns, rows } => Ok(ResultSet::Scan { columns, rows: rows.collect::<Result<_>>()? }),
+            rs => Ok(rs),
+        }
+    }
+
+    // 以 ASCII 表格的形式输出结果，formats 中按列名指定列的显示格式
+    // | a     | b   |
+    // |-------|-----|
+    // | 00001 | foo |
+    pub fn to_table_string(&self, formats: &HashMap<String, ColumnFormat>) -> String {
+        let (columns, rows) = match self {
+            ResultSet::CreateTable { table_name, created: true } => return format!("Table \"{}\" created.", table_name),
+            ResultSet::CreateTable { table_name, created: false } => {
+                return format!("Table \"{}\" already exists, skipped.", table_name)
+            },
+            ResultSet::CreateIndex { index_name } => return format!("Index \"{}\" created.", index_name),
+            ResultSet::DropIndex { index_name } => return format!("Index \"{}\" dropped.", index_name),
+            ResultSet::CreateView { view_name } => return format!("View \"{}\" created.", view_name),
+            ResultSet::DropView { view_name } => return format!("View \"{}\" dropped.", view_name),
+            ResultSet::Insert { count, .. } => return format!("INSERT {}", count),
+            ResultSet::Delete { count } => return format!("DELETE {}", count),
+            ResultSet::Explain { plan } => return plan.clone(),
+            ResultSet::ExplainAnalyze { plan_with_stats } => return plan_with_stats.clone(),
+            ResultSet::Begin { isolation, .. } => return format!("BEGIN {}", isolation),
+            ResultSet::Commit { .. } => return "COMMIT".to_string(),
+            ResultSet::Rollback { .. } => return "ROLLBACK".to_string(),
+            // 流式结果无法在不消费的情况下输出，只输出表头
+            ResultSet::Stream { columns, .. } => (columns, &Vec::new()),
+            ResultSet::Scan { columns, rows } => (columns, rows),
+        };
+        let default = ColumnFormat::default();
+        let cells = rows
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(col, v)| formats.get(col).unwrap_or(&default).render(v))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // 每一列的宽度为表头和所有值中最长的宽度
+        let widths = columns
+            .iter()
+            .enumerate()
+            .map(|(i, col)| {
+                cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain(std::iter::once(col.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect::<Vec<_>>();
+
+        let line = |values: &[String]| {
+            let cells = values
+                .iter()
+                .zip(widths.iter())
+                .map(|(v, w)| format!(" {}{} ", v, " ".repeat(w - v.chars().count())))
+                .collect::<Vec<_>>();
+            format!("|{}|", cells.join("|"))
+        };
+        let mut lines = vec![line(columns)];
+        let sep = widths.iter().map(|w| "-".repeat(w + 2)).collect::<Vec<_>>();
+        lines.push(format!("|{}|", sep.join("|")));
+        lines.extend(cells.iter().map(|row| line(row)));
+        lines.join("\n")
+    }
+
+    // 将查询结果转换为 CSV，第一行为表头，每行以 \n 结尾
+    // NULL 为空字段，空字符串输出为 ""，以便和 NULL 区分
+    // 浮点数使用最短的可以无损解析的形式
+    pub fn to_csv(&self) -> Result<String> {
+        let (columns, rows) = match self {
+            ResultSet::Scan { columns, rows } => (columns, rows),
+            _ => return Err(Error::Internal("not a query result".to_string())),
+        };
+        let mut csv = String::new();
+        let mut write_line = |fields: Vec<String>| {
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        };
+        write_line(columns.iter().map(|c| csv_field(c)).collect());
+        for row in rows {
+            write_line(
+                row.iter()
+                    .map(|v| match v {
+                        Value::Null => String::new(),
+                        Value::Boolean(b) => b.to_string(),
+                        Value::Integer(i) => i.to_string(),
+                        Value::Float(n) => format!("{:?}", n),
+                        Value::String(s) => csv_field(s),
+                        Value::Timestamp(_) => v.to_string(),
+                    })
+                    .collect(),
+            );
+        }
+        Ok(csv)
+    }
+
+    // 转换为 JSON，查询结果为对象的数组，每个对象为列名到值的映射，插入和删除语句为 {"affected_rows": n}
+    pub fn to_json(&self) -> Result<serde_json::Value> {
+        match self {
+            ResultSet::Scan { .. } => Ok(serde_json::Value::Array(self.to_json_array())),
+            ResultSet::Insert { count, .. } | ResultSet::Delete { count } => Ok(serde_json::json!({ "affected_rows": count })),
+            _ => Err(Error::Internal("not a query result".to_string())),
+        }
+    }
+
+    // 将查询结果转换为 JSON 对象的列表，不是查询结果时 panic
+    pub fn to_json_array(&self) -> Vec<serde_json::Value> {
+        let ResultSet::Scan { columns, rows } = self else {
+            panic!("to_json_array called on a result set that is not a query result");
+        };
+        rows.iter()
+            .map(|row| {
+                let object = columns.iter().zip(row.iter()).map(|(c, v)| (c.clone(), v.to_json())).collect();
+                serde_json::Value::Object(object)
+            })
+            .collect()
+    }
+
+    // 以 NamedRow 的形式遍历查询结果
+    pub fn named_rows(&self) -> Result<impl Iterator<Item = NamedRow<'_>>> {
+        match self {
+            ResultSet::Scan { columns, rows } => Ok(rows.iter().map(move |row| NamedRow { columns, row })),
+            _ => Err(Error::Internal("not a query result".to_string())),
+        }
+    }
+}
+
+// 查询结果中的一行，可以按列名访问值
+// 列名重复时（例如笛卡尔积的两张表有同名列）按列名访问得到第一个
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NamedRow<'a> {
+    columns: &'a [String],
+    row: &'a Row,
+}
+
+impl<'a> NamedRow<'a> {
+    pub fn get(&self, column: &str) -> Option<&'a Value> {
+        self.columns.iter().position(|c| c == column).map(|i| &self.row[i])
+    }
+
+    // 按列的顺序遍历 (列名, 值)
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a Value)> {
+        self.columns.iter().map(String::as_str).zip(self.row.iter())
+    }
+}
+
+// 字段中有逗号、引号或者换行时用双引号括起来，其中的双引号写两次
+fn csv_field(s: &str) -> String {
+    if s.is_empty() || s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// 以 ASCII 表格的形式输出，所有列都使用默认的显示格式
+impl Display for ResultSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_table_string(&HashMap::new()))
+    }
+}
+
+// 浮点数默认保留的小数位数
+pub const DEFAULT_FLOAT_PRECISION: usize = 6;
+
+// 列的显示格式，只在输出结果时使用，不影响存储的数据
+#[derive(Debug, Clone)]
+pub struct ColumnFormat {
+    // 整数不足该位数时在前面补 0
+    pub zero_pad: Option<usize>,
+    // 浮点数保留的小数位数
+    pub precision: usize,
+}
+
+impl Default for ColumnFormat {
+    fn default() -> Self {
+        Self { zero_pad: None, precision: DEFAULT_FLOAT_PRECISION }
+    }
+}
+
+impl ColumnFormat {
+    pub fn render(&self, value: &Value) -> String {
+        match (value, self.zero_pad) {
+            (Value::Integer(i), Some(width)) => format!("{:0width$}", i, width = width),
+            (Value::Float(n), _) => format!("{:.precision$}", n, precision = self.precision),
+            (v, _) => v.to_string(),
+        }
+    }
+}
+
+// Session 执行一条语句的结果，包含事务提交的版本号和执行耗时
+pub struct ExecutionResult {
+    pub result: ResultSet,
+    pub version: Version,
+    pub elapsed: Duration,
+}
+
+// 输出执行结果的摘要，例如 3 rows inserted (keys 1,2,3) in 1.2ms
+impl Display for ExecutionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = |n: usize| if n == 1 { "1 row".to_string() } else { format!("{} rows", n) };
+        match &self.result {
+            ResultSet::CreateTable { table_name, created: true } => write!(f, "table {} created", table_name)?,
+            ResultSet::CreateTable { table_name, created: false } => write!(f, "table {} already exists", table_name)?,
+            ResultSet::CreateIndex { index_name } => write!(f, "index {} created", index_name)?,
+            ResultSet::DropIndex { index_name } => write!(f, "index {} dropped", index_name)?,
+            ResultSet::CreateView { view_name } => write!(f, "view {} created", view_name)?,
+            ResultSet::DropView { view_name } => write!(f, "view {} dropped", view_name)?,
+            ResultSet::Insert { count, keys } => {
+                let keys = keys.iter().map(|k| format_primary_key(k)).collect::<Vec<_>>();
+                write!(f, "{} inserted (keys {})", rows(*count), keys.join(","))?
+            },
+            ResultSet::Delete { count } => write!(f, "{} deleted", rows(*count))?,
+            ResultSet::Scan { rows: r, .. } => write!(f, "{} returned", rows(r.len()))?,
+            ResultSet::Explain { .. } => write!(f, "plan explained")?,
+            ResultSet::ExplainAnalyze { .. } => write!(f, "plan analyzed")?,
+            ResultSet::Begin { version, isolation } => write!(f, "transaction {} started ({})", version, isolation)?,
+            ResultSet::Commit { version } => write!(f, "transaction {} committed", version)?,
+            ResultSet::Rollback { version } => write!(f, "transaction {} rolled back", version)?,
+            ResultSet::Stream { .. } => write!(f, "rows streamed")?,
+        }
+        write!(f, " in {:.1}ms", self.elapsed.as_secs_f64() * 1000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, collections::HashMap, rc::Rc, time::Duration};
+
+    use crate::{
+        error::{Error, Result},
+        sql::{
+            engine::{kv::{KVEngine, KVTransaction}, DebugKey, Engine, Session, Transaction},
+            parser::{ast::Expression, Parser},
+            plan::Plan,
+            schema::{Table, TableStats, View},
+            types::{PrimaryKey, Row, Rows, Value},
+        },
+        storage::{memory::MemoryEngine, mvcc::Version},
+    };
+
+    use super::{ColumnFormat, ExecutionContext, ExecutionResult, Executor, ResultSet};
+
+    // 记录从 scan_table 中读取了多少行
+    struct CountingTxn {
+        txn: KVTransaction<MemoryEngine>,
+        rows_read: Rc<Cell<usize>>,
     }
-}
\ No newline at end of file
+
+    impl Transaction for CountingTxn {
+        fn commit(&self) -> Result<Version> {
+            self.txn.commit()
+        }
+
+        fn rollback(&self) -> Result<()> {
+            self.txn.rollback()
+        }
+
+        fn version(&self) -> Version {
+            self.txn.version()
+        }
+
+        fn create_row(&mut self, table_name: String, row: Row) -> Result<PrimaryKey> {
+            self.txn.create_row(table_name, row)
+        }
+
+        fn update_row(&mut self, table_name: String, primary_key: PrimaryKey, new_row: Row) -> Result<()> {
+            self.txn.update_row(table_name, primary_key, new_row)
+        }
+
+        fn scan_table(&self, table_name: String, filter: Option<&Expression>) -> Result<Rows> {
+            let rows_read = self.rows_read.clone();
+            Ok(Box::new(self.txn.scan_table(table_name, filter)?.inspect(move |_| rows_read.set(rows_read.get() + 1))))
+        }
+
+        fn scan_table_range(&self, table_name: String, start: Option<PrimaryKey>, end: Option<PrimaryKey>) -> Result<Rows> {
+            let rows_read = self.rows_read.clone();
+            Ok(Box::new(
+                self.txn.scan_table_range(table_name, start, end)?.inspect(move |_| rows_read.set(rows_read.get() + 1)),
+            ))
+        }
+
+        fn scan_table_page(&self, table_name: String, start_after: Option<PrimaryKey>, limit: usize) -> Result<(Vec<Row>, Option<PrimaryKey>)> {
+            let page = self.txn.scan_table_page(table_name, start_after, limit)?;
+            self.rows_read.set(self.rows_read.get() + page.0.len());
+            Ok(page)
+        }
+
+        fn create_table(&mut self, table: Table) -> Result<()> {
+            self.txn.create_table(table)
+        }
+
+        fn create_index(&mut self, index_name: String, table_name: String, column_name: String) -> Result<()> {
+            self.txn.create_index(index_name, table_name, column_name)
+        }
+
+        fn drop_index(&mut self, index_name: String) -> Result<()> {
+            self.txn.drop_index(index_name)
+        }
+
+        fn truncate_table(&mut self, table_name: String) -> Result<usize> {
+            self.txn.truncate_table(table_name)
+        }
+
+        fn drop_table(&mut self, table_name: String) -> Result<()> {
+            self.txn.drop_table(table_name)
+        }
+
+        fn scan_index(&self, table_name: String, index_name: String, value: &Value) -> Result<Vec<Row>> {
+            self.txn.scan_index(table_name, index_name, value)
+        }
+
+        fn get_table(&self, table_name: String) -> Result<Option<Table>> {
+            self.txn.get_table(table_name)
+        }
+
+        fn count_rows(&self, table_name: String) -> Result<usize> {
+            self.txn.count_rows(table_name)
+        }
+
+        fn analyze_table(&mut self, table_name: String) -> Result<TableStats> {
+            self.txn.analyze_table(table_name)
+        }
+
+        fn get_table_stats(&self, table_name: String) -> Result<Option<TableStats>> {
+            self.txn.get_table_stats(table_name)
+        }
+
+        fn list_tables(&self) -> Result<Vec<Table>> {
+            self.txn.list_tables()
+        }
+
+        fn create_view(&mut self, view: View) -> Result<()> {
+            self.txn.create_view(view)
+        }
+
+        fn drop_view(&mut self, name: String) -> Result<()> {
+            self.txn.drop_view(name)
+        }
+
+        fn get_view(&self, name: String) -> Result<Option<View>> {
+            self.txn.get_view(name)
+        }
+
+        fn list_views(&self) -> Result<Vec<View>> {
+            self.txn.list_views()
+        }
+
+        fn engine_status(&self) -> Result<Vec<(String, Value)>> {
+            self.txn.engine_status()
+        }
+
+        fn debug_keys(&self) -> Result<Vec<DebugKey>> {
+            self.txn.debug_keys()
+        }
+
+        fn last_insert_id(&self) -> Option<i64> {
+            self.txn.last_insert_id()
+        }
+    }
+
+    #[test]
+    fn test_scan_streams_rows() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, v int);")?;
+        let values = (0..1000).map(|i| format!("({}, {})", i, i % 10)).collect::<Vec<_>>();
+        s.execute(&format!("insert into t values {};", values.join(", ")))?;
+
+        let rows_read = Rc::new(Cell::new(0));
+        let mut ctx = ExecutionContext::new(CountingTxn { txn: kvengine.begin()?, rows_read: rows_read.clone() });
+        let stream = |ctx: &mut ExecutionContext<CountingTxn>, sql: &str| -> Result<Rows> {
+            let node = Plan::build(Parser::new(sql).parse()?, &ctx.txn)?.0;
+            let (_, rows) = <dyn Executor<CountingTxn>>::build(node).execute(ctx)?.into_stream("test")?;
+            Ok(rows)
+        };
+        // 执行器返回时还没有读取任何行，只取一行时只读取一行
+        let mut rows = stream(&mut ctx, "select * from t;")?;
+        assert_eq!(rows_read.get(), 0);
+        assert!(rows.next().transpose()?.is_some());
+        assert_eq!(rows_read.get(), 1);
+
+        // 带过滤条件时读到第一行满足条件的行为止
+        rows_read.set(0);
+        let mut rows = stream(&mut ctx, "select * from t where v = 3;")?;
+        assert_eq!(rows.next().transpose()?.map(|row| row[1].clone()), Some(Value::Integer(3)));
+        assert!(rows_read.get() < 20);
+        drop(rows);
+        rows_read.set(0);
+
+        // Plan::execute 会读取所有的行，过滤条件下推到 scan_table 中，只有满足条件的行被返回
+        let stmt = Parser::new("select * from t where v = 3;").parse()?;
+        match Plan::build(stmt, &ctx.txn)?.execute(&mut ctx)? {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 100),
+            _ => unreachable!(),
+        }
+        assert_eq!(rows_read.get(), 100);
+        ctx.txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_execution_result_display() {
+        let result = ExecutionResult {
+            result: ResultSet::Insert {
+                count: 3,
+                keys: vec![vec![Value::Integer(1)], vec![Value::Integer(2)], vec![Value::Integer(3)]],
+            },
+            version: 1,
+            elapsed: Duration::from_micros(1200),
+        };
+        assert_eq!(result.to_string(), "3 rows inserted (keys 1,2,3) in 1.2ms");
+
+        let result = ExecutionResult {
+            result: ResultSet::Scan { columns: vec!["a".to_string()], rows: vec![vec![Value::Null]] },
+            version: 2,
+            elapsed: Duration::from_millis(3),
+        };
+        assert_eq!(result.to_string(), "1 row returned in 3.0ms");
+    }
+
+    #[test]
+    fn test_to_table_string_zero_pad() {
+        let rs = ResultSet::Scan {
+            columns: vec!["id".to_string(), "name".to_string(), "n".to_string()],
+            rows: vec![
+                vec![Value::Integer(42), Value::String("foo".to_string()), Value::Integer(7)],
+                vec![Value::Integer(-3), Value::Null, Value::Integer(123456)],
+                vec![Value::Null, Value::String("b".to_string()), Value::Integer(1)],
+            ],
+        };
+        let formats = HashMap::from([
+            ("id".to_string(), ColumnFormat { zero_pad: Some(5), ..Default::default() }),
+            ("n".to_string(), ColumnFormat { zero_pad: Some(5), ..Default::default() }),
+        ]);
+        assert_eq!(
+            rs.to_table_string(&formats),
+            [
+                "| id    | name | n      |",
+                "|-------|------|--------|",
+                "| 00042 | foo  | 00007  |",
+                "| -0003 | NULL | 123456 |",
+                "| NULL  | b    | 00001  |",
+            ]
+            .join("\n")
+        );
+
+        // 不指定格式时原样输出
+        assert_eq!(
+            rs.to_table_string(&HashMap::new()).lines().nth(2),
+            Some("| 42   | foo  | 7      |")
+        );
+    }
+
+    #[test]
+    fn test_result_set_display() {
+        let scan = ResultSet::Scan {
+            columns: vec!["a".to_string(), "b".to_string(), "score".to_string()],
+            rows: vec![
+                vec![Value::Integer(1), Value::String("foo".to_string()), Value::Float(1.5)],
+                vec![Value::Integer(22), Value::Null, Value::Float(-0.25)],
+            ],
+        };
+        assert_eq!(
+            scan.to_string(),
+            [
+                "| a  | b    | score     |",
+                "|----|------|-----------|",
+                "| 1  | foo  | 1.500000  |",
+                "| 22 | NULL | -0.250000 |",
+            ]
+            .join("\n")
+        );
+        // 指定浮点数的小数位数
+        let formats = HashMap::from([("score".to_string(), ColumnFormat { precision: 1, ..Default::default() })]);
+        assert_eq!(scan.to_table_string(&formats).lines().nth(3), Some("| 22 | NULL | -0.2  |"));
+        // 没有行时只输出表头
+        let empty = ResultSet::Scan { columns: vec!["a".to_string()], rows: Vec::new() };
+        assert_eq!(empty.to_string(), "| a |\n|---|");
+
+        assert_eq!(ResultSet::CreateTable { table_name: "t".to_string(), created: true }.to_string(), "Table \"t\" created.");
+        assert_eq!(ResultSet::CreateIndex { index_name: "i".to_string() }.to_string(), "Index \"i\" created.");
+        assert_eq!(ResultSet::DropIndex { index_name: "i".to_string() }.to_string(), "Index \"i\" dropped.");
+        assert_eq!(ResultSet::Insert { count: 3, keys: Vec::new() }.to_string(), "INSERT 3");
+        assert_eq!(ResultSet::Explain { plan: "Scan: t".to_string() }.to_string(), "Scan: t");
+        assert_eq!(
+            ResultSet::ExplainAnalyze { plan_with_stats: "Scan: t (actual rows: 0)".to_string() }.to_string(),
+            "Scan: t (actual rows: 0)"
+        );
+        let stream = ResultSet::Stream { columns: vec!["a".to_string()], rows: Box::new(std::iter::empty()) };
+        assert_eq!(stream.to_string(), "| a |\n|---|");
+    }
+
+    #[test]
+    fn test_to_csv() -> Result<()> {
+        let columns = vec!["id".to_string(), "name".to_string(), "score".to_string(), "ok".to_string()];
+        let rows = vec![
+            vec![Value::Integer(1), Value::String("plain".to_string()), Value::Float(0.1), Value::Boolean(true)],
+            vec![Value::Integer(-2), Value::String("a,b".to_string()), Value::Float(1e-300), Value::Boolean(false)],
+            vec![Value::Integer(3), Value::String("say \"hi\"\nbye".to_string()), Value::Float(2.0), Value::Null],
+            vec![Value::Null, Value::String(String::new()), Value::Null, Value::Null],
+        ];
+        let csv = ResultSet::Scan { columns: columns.clone(), rows: rows.clone() }.to_csv()?;
+        assert_eq!(csv.lines().next(), Some("id,name,score,ok"));
+        assert_eq!(csv.lines().nth(2), Some("-2,\"a,b\",1e-300,false"));
+
+        // 按 RFC 4180 的规则解析，再按列的类型转换回 Value
+        let mut records = Vec::new();
+        let (mut record, mut field, mut quoted, mut in_quotes) = (Vec::new(), String::new(), false, false);
+        let mut chars = csv.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if in_quotes && chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => {
+                    in_quotes = !in_quotes;
+                    quoted = true;
+                }
+                ',' | '\n' if !in_quotes => {
+                    record.push((std::mem::take(&mut field), quoted));
+                    quoted = false;
+                    if c == '\n' {
+                        records.push(std::mem::take(&mut record));
+                    }
+                }
+                c => field.push(c),
+            }
+        }
+        let parsed = records
+            .into_iter()
+            .skip(1)
+            .map(|record| {
+                record
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (field, quoted))| match (i, field.as_str()) {
+                        (_, "") if !quoted => Value::Null,
+                        (0, f) => Value::Integer(f.parse().unwrap()),
+                        (2, f) => Value::Float(f.parse().unwrap()),
+                        (3, f) => Value::Boolean(f.parse().unwrap()),
+                        (_, f) => Value::String(f.to_string()),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, rows);
+
+        assert_eq!(
+            ResultSet::Insert { count: 1, keys: Vec::new() }.to_csv(),
+            Err(Error::Internal("not a query result".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_named_rows() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table t (id int primary key, name string);")?;
+        s.execute("insert into t values (1, 'a'), (2, null);")?;
+
+        let result = s.execute("select * from t where id = 2;")?.result;
+        let rows = result.named_rows()?.collect::<Vec<_>>();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].get("id"), Some(&Value::Integer(2)));
+        assert_eq!(rows[0].get("name"), Some(&Value::Null));
+        assert_eq!(rows[0].get("missing"), None);
+        assert_eq!(
+            rows[0].iter().collect::<Vec<_>>(),
+            vec![("id", &Value::Integer(2)), ("name", &Value::Null)]
+        );
+
+        assert!(ResultSet::CreateTable { table_name: "t".to_string(), created: true }.named_rows().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_to_json() -> Result<()> {
+        let rs = ResultSet::Scan {
+            columns: vec!["n".to_string(), "b".to_string(), "i".to_string(), "f".to_string(), "s".to_string()],
+            rows: vec![
+                vec![Value::Null, Value::Boolean(true), Value::Integer(-7), Value::Float(1.5), Value::String("x".to_string())],
+                vec![Value::Null, Value::Boolean(false), Value::Integer(0), Value::Float(f64::NAN), Value::String(String::new())],
+                vec![Value::Null, Value::Null, Value::Null, Value::Float(f64::INFINITY), Value::Null],
+            ],
+        };
+        assert_eq!(
+            rs.to_json()?,
+            serde_json::json!([
+                { "n": null, "b": true, "i": -7, "f": 1.5, "s": "x" },
+                { "n": null, "b": false, "i": 0, "f": null, "s": "" },
+                { "n": null, "b": null, "i": null, "f": null, "s": null },
+            ])
+        );
+        assert_eq!(rs.to_json_array().len(), 3);
+        assert!(rs.to_json_array()[0]["i"].is_i64());
+        assert!(rs.to_json_array()[0]["f"].is_f64());
+
+        assert_eq!(
+            ResultSet::Insert { count: 2, keys: Vec::new() }.to_json()?,
+            serde_json::json!({ "affected_rows": 2 })
+        );
+        assert!(ResultSet::CreateTable { table_name: "t".to_string(), created: true }.to_json().is_err());
+        Ok(())
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_to_json_array_not_query() {
+        ResultSet::Insert { count: 1, keys: Vec::new() }.to_json_array();
+    }
+
+    #[test]
+    fn test_explain_analyze() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table a (id int primary key, x int);")?;
+        s.execute("create table b (id int primary key, y int);")?;
+        s.execute("insert into a values (1, 10), (2, 20), (3, 30), (4, 40);")?;
+        s.execute("insert into b values (1, 1), (3, 3), (5, 5);")?;
+
+        // 每一行的节点名以及实际输出的行数
+        let analyze = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<Vec<(String, usize)>> {
+            let plan = match s.execute(sql)?.result {
+                ResultSet::ExplainAnalyze { plan_with_stats } => plan_with_stats,
+                _ => unreachable!(),
+            };
+            Ok(plan
+                .lines()
+                .map(|line| {
+                    assert!(line.contains("estimated rows: N/A"));
+                    let name = line.trim_start_matches([' ', '-', '>']).split(':').next().unwrap().to_string();
+                    let rows = line.split("actual rows: ").nth(1).unwrap().split(',').next().unwrap().parse().unwrap();
+                    (name, rows)
+                })
+                .collect())
+        };
+        let count = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<usize> {
+            match s.execute(sql)?.result {
+                ResultSet::Scan { rows, .. } => Ok(rows.len()),
+                _ => unreachable!(),
+            }
+        };
+
+        // x > 10 下推到 a 的扫描中，x > y 涉及两张表，留在 Filter 中
+        let sql = "select * from a natural join b where x > 10 and x > y;";
+        let stats = analyze(&mut s, &format!("explain analyze {}", sql))?;
+        assert_eq!(
+            stats,
+            vec![
+                ("Filter".to_string(), count(&mut s, sql)?),
+                ("Projection".to_string(), count(&mut s, "select * from a natural join b where x > 10;")?),
+                ("NestedLoopJoin".to_string(), 1),
+                ("Scan".to_string(), count(&mut s, "select * from a where x > 10;")?),
+                ("Scan".to_string(), count(&mut s, "select * from b;")?),
+            ]
+        );
+        assert_eq!(stats[0].1, 1);
+
+        assert_eq!(
+            analyze(&mut s, "explain analyze select * from a where x >= 20;")?,
+            vec![("Scan".to_string(), 3)]
+        );
+        // 插入语句会被真正执行
+        assert_eq!(
+            analyze(&mut s, "explain analyze insert into b values (6, 6), (7, 7);")?,
+            vec![("Insert".to_string(), 2)]
+        );
+        assert_eq!(count(&mut s, "select * from b;")?, 5);
+        Ok(())
+    }
+}
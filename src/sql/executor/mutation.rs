@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use crate::{error::{Error, Result}, sql::{engine::Transaction, parser::ast::Expression, schema::Table, types::{Row, Value}}};
 
-use super::{Executor, ResultSet};
+use super::{ExecutionContext, Executor, ResultSet, INTERRUPT_CHECK_ROWS};
 
 // 插入数据
 pub struct Insert {
@@ -29,26 +29,75 @@ impl Insert {
 // 值有   1         2           3
 // 那么需要给 d 列进行对齐
 fn pad_row(table: &Table, row: &Row) -> Result<Row> {
+    // 给定的值不能多于表的列数
+    if row.len() > table.columns.len() {
+        return Err(Error::Schema(format!("table {} has {} columns but {} values were supplied", table.name, table.columns.len(), row.len())));
+    }
     let mut result = row.clone();
     // 跳过以指定值的部分
     for column in table.columns.iter().skip(row.len()) {
-        if let Some(default) = column.default.clone() {
+        if let Some(default) = column.default_value()? {
             result.push(default);
         } else {
-            return Err(Error::Internel(format!("No default value for column {}!",column.name)));
+            return Err(Error::Schema(format!("No default value for column {}!",column.name)));
         }
     }
     Ok(result)
 }
 
+// 计算 VALUES 中的一行，DEFAULT 替换为对应列的默认值
+fn evaluate_values(table: &Table, columns: &[String], exprs: Vec<Expression>) -> Result<Row> {
+    exprs
+        .into_iter()
+        .enumerate()
+        .map(|(i, expr)| {
+            if expr != Expression::Default {
+                return Value::from_expression(expr);
+            }
+            let column = if columns.is_empty() {
+                table.columns.get(i)
+            } else {
+                columns.get(i).and_then(|name| table.column_index(name)).map(|c| &table.columns[c])
+            };
+            match column {
+                Some(col) => col
+                    .default_value()?
+                    .ok_or_else(|| Error::Schema(format!("No default value for column {}!", col.name))),
+                // 值的个数多于列数，交给 pad_row、make_row 报错
+                None => Ok(Value::Null),
+            }
+        })
+        .collect()
+}
+
+// 校验插入时指定的列，必须都存在于表中，并且不能重复
+fn check_columns(table: &Table, columns: &[String]) -> Result<()> {
+    let mut unknown = Vec::new();
+    let mut seen = HashSet::new();
+    for col in columns {
+        if !table.columns.iter().any(|c| &c.name == col) {
+            unknown.push(col.as_str());
+        } else if !seen.insert(col) {
+            return Err(Error::Schema(format!("column {} specified more than once", col)));
+        }
+    }
+    if !unknown.is_empty() {
+        return Err(Error::ColumnNotFound {
+            table: table.name.clone(),
+            column: unknown.join(", "),
+        });
+    }
+    Ok(())
+}
+
 // 对列进行对齐
 // insert into tab(d,c) values(2,3);
 // 列有   a         b           c          d
 // 值有 default   default       2          3
-fn make_row(table: &Table, column: &Vec<String>, row: &Row) -> Result<Row> {
+fn make_row(table: &Table, column: &[String], row: &Row) -> Result<Row> {
     // 现判断指定的列和给定的值个数是否匹配
     if column.len() != row.len() {
-        return Err(Error::Internel(format!("columns and values num mismatch")));
+        return Err(Error::Schema("columns and values num mismatch".to_string()));
     }
     // 构造 hashmap 来保存制定的列和值
     let mut input = HashMap::new();
@@ -60,24 +109,47 @@ fn make_row(table: &Table, column: &Vec<String>, row: &Row) -> Result<Row> {
     for col in table.columns.iter() {
         if let Some(value) = input.get(&col.name) {
             result.push(value.clone());
-        } else if let Some(value) = col.default.clone() {
+        } else if let Some(value) = col.default_value()? {
             result.push(value);
         } else {
-            return Err(Error::Internel(format!("No value given for the column {}",col.name)));
+            return Err(Error::Schema(format!("No value given for the column {}",col.name)));
         }
     }
     Ok(result)
 }
 
 
+// 清空表
+pub struct TruncateTable {
+    table_name: String,
+}
+
+impl TruncateTable {
+    pub fn new(table_name: String) -> Box<Self> {
+        Box::new(Self { table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for TruncateTable {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let count = ctx.txn.truncate_table(self.table_name)?;
+        Ok(ResultSet::Delete { count })
+    }
+}
+
 impl<T: Transaction> Executor<T> for Insert {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
         // 插入值时现取出表信息
-        let table = txn.must_get_table(self.table_name.clone())?;
-        let mut count = 0;
+        let table = ctx.txn.must_get_table(self.table_name.clone())?;
+        check_columns(&table, &self.columns)?;
+        // 先构造出所有要插入的行，确保校验都通过后再写入
+        let mut rows = Vec::with_capacity(self.values.len());
         // 将表达式转换为值类型
-        for exprs in self.values {
-            let row = exprs.into_iter().map(|e| Value::from_expression(e)).collect::<Vec<_>>();
+        for (i, exprs) in self.values.into_iter().enumerate() {
+            if i % INTERRUPT_CHECK_ROWS == 0 {
+                ctx.interrupt().check()?;
+            }
+            let row = evaluate_values(&table, &self.columns, exprs)?;
             // 如果未指定列值
             let insert_row = if self.columns.is_empty() {
                 pad_row(&table, &row)?
@@ -85,12 +157,18 @@ impl<T: Transaction> Executor<T> for Insert {
                 // 制定了插入的列
                 make_row(&table, &self.columns, &row)?
             };
+            // 值的类型转换和校验在 create_rows 中进行
+            rows.push(insert_row);
+        }
 
-            txn.create_row(self.table_name.clone(), insert_row)?;
-            count += 1;
+        // 分批写入，每批之前检查是否需要中断，中断之后由调用方回滚已经写入的部分
+        let mut keys = Vec::with_capacity(rows.len());
+        for chunk in rows.chunks(INTERRUPT_CHECK_ROWS) {
+            ctx.interrupt().check()?;
+            keys.extend(ctx.txn.create_rows(self.table_name.clone(), chunk.to_vec())?);
         }
 
-        Ok(ResultSet::Insert { count })
+        Ok(ResultSet::Insert { count: keys.len(), keys })
 
     }
 }
\ No newline at end of file
@@ -1,24 +1,363 @@
-use crate::{error::Result, sql::engine::Transaction};
+use std::collections::HashSet;
 
-use super::{Executor, ResultSet};
+use crate::{error::Result, sql::{engine::Transaction, parser::ast::{Expression, Operation}, plan::Node, schema::Table, types::{PrimaryKey, Row, Rows, Value}}};
+
+use super::{ExecutionContext, Executor, ResultSet, INTERRUPT_CHECK_ROWS};
 
 pub struct Scan {
     table_name: String,
+    filter: Option<Expression>,
 }
 
 impl Scan {
-    pub fn new(table_name: String) -> Box<Self> {
-        Box::new(Self { table_name })
+    pub fn new(table_name: String, filter: Option<Expression>) -> Box<Self> {
+        Box::new(Self { table_name, filter })
+    }
+
+    // 扫描表中满足过滤条件的行，条件中有索引列的等值比较时走索引，有主键的范围时按范围扫描
+    // 全表扫描时把条件下推给 scan_table，在反序列化时就丢弃不满足条件的行
+    // 返回的行按需读取，不会一次性把整张表读入内存，每读取一批行检查一次是否需要中断
+    fn scan<T: Transaction>(self, ctx: &mut ExecutionContext<T>, table: &Table, columns: Vec<String>) -> Result<Rows> {
+        let rows: Rows = match self.filter.as_ref().and_then(|f| index_lookup(table, f)) {
+            Some((index_name, value)) => {
+                let rows = ctx.txn.scan_index(self.table_name.clone(), index_name, &value)?;
+                // 索引只保证了其中一个等值条件，其余条件仍然需要过滤
+                filter_rows(self.filter.unwrap(), columns, Box::new(rows.into_iter().map(Ok)))
+            },
+            // 主键上有范围条件时只扫描这一段
+            None => match self.filter.as_ref().and_then(|f| range_lookup(table, f)) {
+                Some((start, end)) => {
+                    let rows = ctx.txn.scan_table_range(self.table_name.clone(), start, end)?;
+                    filter_rows(self.filter.unwrap(), columns, rows)
+                },
+                None => ctx.txn.scan_table(self.table_name.clone(), self.filter.as_ref())?,
+            },
+        };
+        let interrupt = ctx.interrupt();
+        Ok(Box::new(rows.enumerate().map(move |(i, row)| {
+            if i % INTERRUPT_CHECK_ROWS == 0 {
+                interrupt.check()?;
+            }
+            row
+        })))
+    }
+}
+
+// 在条件中找出可以走索引的 `列 = 常量`，只看顶层的 AND
+fn index_lookup(table: &Table, filter: &Expression) -> Option<(String, Value)> {
+    match filter {
+        Expression::Operation(Operation::And(lhs, rhs)) => {
+            index_lookup(table, lhs).or_else(|| index_lookup(table, rhs))
+        }
+        Expression::Operation(Operation::Equal(lhs, rhs)) => {
+            let (field, value, _) = field_and_const(table, lhs, rhs)?;
+            let index = table.indexes.iter().find(|i| i.column == field)?;
+            Some((index.name.clone(), value))
+        }
+        _ => None,
     }
 }
 
+// 在条件中找出主键上的范围，只看顶层的 AND，返回的边界都包含在范围内
+// > 和 < 排除边界的部分由过滤条件保证，这里只需要缩小扫描的范围
+// 复合主键的前几列都有等值条件时按这几列的前缀扫描，所有列都有等值条件时只读取一行
+// 否则只使用主键第一列上的范围
+fn range_lookup(table: &Table, filter: &Expression) -> Option<(Option<PrimaryKey>, Option<PrimaryKey>)> {
+    if table.primary_key.len() > 1 {
+        let mut equalities = Vec::new();
+        collect_equalities(table, filter, &mut equalities);
+        let prefix = table
+            .primary_key
+            .iter()
+            .map_while(|&i| equalities.iter().find(|(f, _)| *f == table.columns[i].name).map(|(_, v)| v.clone()))
+            .collect::<PrimaryKey>();
+        if prefix.len() > 1 {
+            return Some((Some(prefix.clone()), Some(prefix)));
+        }
+    }
+    let mut range = (None, None);
+    collect_range(table, filter, &mut range);
+    match range {
+        (None, None) => None,
+        (start, end) => Some((start.map(|v| vec![v]), end.map(|v| vec![v]))),
+    }
+}
+
+fn collect_equalities<'a>(table: &Table, filter: &'a Expression, equalities: &mut Vec<(&'a str, Value)>) {
+    match filter {
+        Expression::Operation(Operation::And(lhs, rhs)) => {
+            collect_equalities(table, lhs, equalities);
+            collect_equalities(table, rhs, equalities);
+        }
+        Expression::Operation(Operation::Equal(lhs, rhs)) => {
+            if let Some((field, value, _)) = field_and_const(table, lhs, rhs) {
+                equalities.push((field, value));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn collect_range(table: &Table, filter: &Expression, range: &mut (Option<Value>, Option<Value>)) {
+    let Expression::Operation(op) = filter else {
+        return;
+    };
+    let (lhs, rhs) = match op {
+        Operation::And(lhs, rhs) => {
+            collect_range(table, lhs, range);
+            collect_range(table, rhs, range);
+            return;
+        }
+        Operation::Equal(lhs, rhs)
+        | Operation::GreaterThan(lhs, rhs)
+        | Operation::GreaterThanOrEqual(lhs, rhs)
+        | Operation::LessThan(lhs, rhs)
+        | Operation::LessThanOrEqual(lhs, rhs) => (lhs, rhs),
+        _ => return,
+    };
+    let Some((field, value, field_on_left)) = field_and_const(table, lhs, rhs) else {
+        return;
+    };
+    if field != table.columns[table.primary_key[0]].name {
+        return;
+    }
+    // 列在右侧时比较的方向相反，例如 10 < id 等价于 id > 10
+    let (lower, upper) = match op {
+        Operation::Equal(..) => (true, true),
+        Operation::GreaterThan(..) | Operation::GreaterThanOrEqual(..) => (field_on_left, !field_on_left),
+        _ => (!field_on_left, field_on_left),
+    };
+    // 多个条件同时限制一侧时取更窄的边界
+    if lower && range.0.as_ref().is_none_or(|v| &value > v) {
+        range.0 = Some(value.clone());
+    }
+    if upper && range.1.as_ref().is_none_or(|v| &value < v) {
+        range.1 = Some(value);
+    }
+}
+
+// 比较的一侧为列、另一侧为常量时，返回列名、常量的值以及列是否在左侧
+// 常量的类型必须和列的类型一致，否则 1 = 1.0 这类比较在索引和主键中查不到
+fn field_and_const<'a>(table: &Table, lhs: &'a Expression, rhs: &'a Expression) -> Option<(&'a str, Value, bool)> {
+    let (field, value, field_on_left) = match (lhs, rhs) {
+        (Expression::Field(f), Expression::Consts(_)) => (f, rhs, true),
+        (Expression::Consts(_), Expression::Field(f)) => (f, lhs, false),
+        _ => return None,
+    };
+    let value = value.evaluate(&[], &Vec::new()).ok()?;
+    let column = table.columns.iter().find(|c| &c.name == field)?;
+    if value.datatype()? != column.datatype {
+        return None;
+    }
+    Some((field, value, field_on_left))
+}
+
 impl<T: Transaction> Executor<T> for Scan {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
-        let table = txn.must_get_table(self.table_name.clone())?;
-        let rows = txn.scan_table(self.table_name)?;
-        Ok(ResultSet::Scan { 
-            columns: table.columns.into_iter().map(|c| c.name).collect(), 
-            rows 
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let table = ctx.txn.must_get_table(self.table_name.clone())?;
+        let columns = table.columns.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+        let rows = self.scan(ctx, &table, columns.clone())?;
+        Ok(ResultSet::Stream { columns, rows })
+    }
+}
+
+// 输出规划时已经得到的行，用于 WITH 中的 CTE
+pub struct InMemoryScan {
+    columns: Vec<String>,
+    rows: Vec<Row>,
+}
+
+impl InMemoryScan {
+    pub fn new(columns: Vec<String>, rows: Vec<Row>) -> Box<Self> {
+        Box::new(Self { columns, rows })
+    }
+}
+
+impl<T: Transaction> Executor<T> for InMemoryScan {
+    fn execute(self: Box<Self>, _ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        Ok(ResultSet::Stream { columns: self.columns, rows: Box::new(self.rows.into_iter().map(Ok)) })
+    }
+}
+
+// 按下标从输入中选取列
+pub struct Projection<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    columns: Vec<usize>,
+}
+
+impl<T: Transaction> Projection<T> {
+    pub fn new(source: Box<dyn Executor<T>>, columns: Vec<usize>) -> Box<Self> {
+        Box::new(Self { source, columns })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Projection<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, rows) = self.source.execute(ctx)?.into_stream("projection")?;
+        let indexes = self.columns;
+        Ok(ResultSet::Stream {
+            columns: indexes.iter().map(|i| columns[*i].clone()).collect(),
+            rows: Box::new(rows.map(move |row| row.map(|row| indexes.iter().map(|i| row[*i].clone()).collect()))),
+        })
+    }
+}
+
+// 在每一行上计算 SELECT 中的表达式
+pub struct Compute<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    expressions: Vec<(Expression, String)>,
+}
+
+impl<T: Transaction> Compute<T> {
+    pub fn new(source: Box<dyn Executor<T>>, expressions: Vec<(Expression, String)>) -> Box<Self> {
+        Box::new(Self { source, expressions })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Compute<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, rows) = self.source.execute(ctx)?.into_stream("compute")?;
+        let (exprs, names): (Vec<_>, Vec<_>) = self.expressions.into_iter().unzip();
+        Ok(ResultSet::Stream {
+            columns: names,
+            rows: Box::new(rows.map(move |row| {
+                let row = row?;
+                exprs.iter().map(|e| e.evaluate(&columns, &row)).collect()
+            })),
         })
     }
-}
\ No newline at end of file
+}
+
+// 输出执行计划，内部的节点不会被执行
+pub struct Explain {
+    inner: Node,
+}
+
+impl Explain {
+    pub fn new(inner: Node) -> Box<Self> {
+        Box::new(Self { inner })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Explain {
+    fn execute(self: Box<Self>, _ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        Ok(ResultSet::Explain { plan: self.inner.to_string().trim_end().to_string() })
+    }
+}
+
+// 以 (name, value) 两列的形式输出存储引擎的状态
+pub struct ShowEngineStatus;
+
+impl ShowEngineStatus {
+    pub fn new() -> Box<Self> {
+        Box::new(Self)
+    }
+}
+
+impl<T: Transaction> Executor<T> for ShowEngineStatus {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        Ok(ResultSet::Scan {
+            columns: vec!["name".to_string(), "value".to_string()],
+            rows: ctx.txn
+                .engine_status()?
+                .into_iter()
+                .map(|(name, value)| vec![Value::String(name), value])
+                .collect(),
+        })
+    }
+}
+
+// 输出存储引擎中的 key：解码之后的 key、十六进制的原始 key、value 的字节数、是否为删除标记、过期时间
+pub struct DebugKeys {
+    prefix: Option<String>,
+}
+
+impl DebugKeys {
+    pub fn new(prefix: Option<String>) -> Box<Self> {
+        Box::new(Self { prefix })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DebugKeys {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let prefix = self.prefix.unwrap_or_default();
+        Ok(ResultSet::Scan {
+            columns: vec![
+                "key".to_string(),
+                "raw_key".to_string(),
+                "value_size".to_string(),
+                "tombstone".to_string(),
+                "expires_at".to_string(),
+            ],
+            rows: ctx.txn
+                .debug_keys()?
+                .into_iter()
+                .filter(|k| k.key.starts_with(&prefix))
+                .map(|k| {
+                    vec![
+                        Value::String(k.key),
+                        Value::String(k.raw_key.iter().map(|b| format!("{:02x}", b)).collect()),
+                        Value::Integer(k.value_size as i64),
+                        k.tombstone.map_or(Value::Null, Value::Boolean),
+                        k.expires_at.map_or(Value::Null, |t| Value::Integer(t as i64)),
+                    ]
+                })
+                .collect(),
+        })
+    }
+}
+
+// 保留条件计算结果为 true 的行，结果为 NULL 的行同样被过滤掉
+pub fn filter_rows(predicate: Expression, columns: Vec<String>, rows: Rows) -> Rows {
+    Box::new(rows.filter_map(move |row| match row {
+        Ok(row) => match predicate.matches(&columns, &row) {
+            Ok(true) => Some(Ok(row)),
+            Ok(false) => None,
+            Err(err) => Some(Err(err)),
+        },
+        Err(err) => Some(Err(err)),
+    }))
+}
+
+// 过滤输入的行
+pub struct Filter<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    predicate: Expression,
+}
+
+impl<T: Transaction> Filter<T> {
+    pub fn new(source: Box<dyn Executor<T>>, predicate: Expression) -> Box<Self> {
+        Box::new(Self { source, predicate })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Filter<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, rows) = self.source.execute(ctx)?.into_stream("filter")?;
+        let rows = filter_rows(self.predicate, columns.clone(), rows);
+        Ok(ResultSet::Stream { columns, rows })
+    }
+}
+
+// 去掉重复的行，保留第一次出现的行
+pub struct Distinct<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+}
+
+impl<T: Transaction> Distinct<T> {
+    pub fn new(source: Box<dyn Executor<T>>) -> Box<Self> {
+        Box::new(Self { source })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Distinct<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, rows) = self.source.execute(ctx)?.into_stream("distinct")?;
+        let mut seen = HashSet::new();
+        let rows = rows.filter(move |row| match row {
+            Ok(row) => seen.insert(row.iter().cloned().map(Value::group_key).collect::<Vec<_>>()),
+            Err(_) => true,
+        });
+        Ok(ResultSet::Stream { columns, rows: Box::new(rows) })
+    }
+}
@@ -1,23 +1,189 @@
-use crate::{error::Result, sql::{engine::Transaction, schema::{self, Table}}};
+use crate::{error::{Error, Result}, sql::{engine::Transaction, schema::{Table, View}, types::Value}};
 
-use super::{Executor, ResultSet};
+use super::{ExecutionContext, Executor, ResultSet};
 
 // 创建表
 pub struct CreateTable {
     schema: Table,
+    if_not_exists: bool,
+    or_replace: bool,
 }
 
 
 impl CreateTable {
-    pub fn new(schema: Table) -> Box<Self> {
-        Box::new(Self{ schema })
+    pub fn new(schema: Table, if_not_exists: bool, or_replace: bool) -> Box<Self> {
+        Box::new(Self{ schema, if_not_exists, or_replace })
     }
 }
 
 impl<T: Transaction> Executor<T> for CreateTable {
-    fn execute(self: Box<Self>, txn: &mut T) -> Result<ResultSet> {
+    // 表是否存在的检查和写入在同一个事务中，并发创建同一张表时，后写入的事务会遇到写冲突
+    // OR REPLACE 时删除和创建在同一个事务中，新的表结构无效时原来的表和数据随事务回滚保留下来
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
         let table_name = self.schema.name.clone();
-        txn.create_table(self.schema)?;
-        Ok(ResultSet::CreateTable { table_name })
+        if self.or_replace && ctx.txn.get_table(table_name.clone())?.is_some() {
+            ctx.txn.drop_table(table_name.clone())?;
+        }
+        match ctx.txn.create_table(self.schema) {
+            Ok(()) => Ok(ResultSet::CreateTable { table_name, created: true }),
+            Err(Error::TableExists(_)) if self.if_not_exists => Ok(ResultSet::CreateTable { table_name, created: false }),
+            Err(err) => Err(err),
+        }
     }
-}
\ No newline at end of file
+}
+
+// 创建索引
+pub struct CreateIndex {
+    index_name: String,
+    table_name: String,
+    column_name: String,
+}
+
+impl CreateIndex {
+    pub fn new(index_name: String, table_name: String, column_name: String) -> Box<Self> {
+        Box::new(Self { index_name, table_name, column_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateIndex {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        ctx.txn.create_index(self.index_name.clone(), self.table_name, self.column_name)?;
+        Ok(ResultSet::CreateIndex { index_name: self.index_name })
+    }
+}
+
+// 删除索引
+pub struct DropIndex {
+    index_name: String,
+}
+
+impl DropIndex {
+    pub fn new(index_name: String) -> Box<Self> {
+        Box::new(Self { index_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropIndex {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        ctx.txn.drop_index(self.index_name.clone())?;
+        Ok(ResultSet::DropIndex { index_name: self.index_name })
+    }
+}
+
+// 创建视图，视图中的查询在规划时已经校验过
+pub struct CreateView {
+    view: View,
+}
+
+impl CreateView {
+    pub fn new(view: View) -> Box<Self> {
+        Box::new(Self { view })
+    }
+}
+
+impl<T: Transaction> Executor<T> for CreateView {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let view_name = self.view.name.clone();
+        ctx.txn.create_view(self.view)?;
+        Ok(ResultSet::CreateView { view_name })
+    }
+}
+
+// 删除视图
+pub struct DropView {
+    name: String,
+}
+
+impl DropView {
+    pub fn new(name: String) -> Box<Self> {
+        Box::new(Self { name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for DropView {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        ctx.txn.drop_view(self.name.clone())?;
+        Ok(ResultSet::DropView { view_name: self.name })
+    }
+}
+
+// 按名字的顺序输出所有的视图
+pub struct ShowViews;
+
+impl ShowViews {
+    pub fn new() -> Box<Self> {
+        Box::new(Self)
+    }
+}
+
+impl<T: Transaction> Executor<T> for ShowViews {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        Ok(ResultSet::Scan {
+            columns: vec!["name".to_string()],
+            rows: ctx.txn.list_views()?.into_iter().map(|view| vec![Value::String(view.name)]).collect(),
+        })
+    }
+}
+
+// 每一列输出一行：列名、类型、是否可以为空、默认值、是否为主键、注释
+pub struct Describe {
+    table_name: String,
+}
+
+impl Describe {
+    pub fn new(table_name: String) -> Box<Self> {
+        Box::new(Self { table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Describe {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let table = ctx.txn.must_get_table(self.table_name)?;
+        let columns = ["column", "type", "nullable", "default", "primary_key", "comment"];
+        let rows = table
+            .columns
+            .into_iter()
+            .map(|c| {
+                let datatype = match c.max_len {
+                    Some(len) => format!("{}({})", c.datatype, len),
+                    None => c.datatype.to_string(),
+                };
+                vec![
+                    Value::String(c.name),
+                    Value::String(datatype),
+                    Value::Boolean(c.nullable),
+                    c.default.map_or(Value::Null, |e| Value::String(e.to_string())),
+                    Value::Boolean(c.primary_key),
+                    c.comment.map_or(Value::Null, Value::String),
+                ]
+            })
+            .collect();
+        Ok(ResultSet::Scan { columns: columns.iter().map(|c| c.to_string()).collect(), rows })
+    }
+}
+
+// 统计表的行数和大小并保存，以一行的形式输出统计结果
+pub struct AnalyzeTable {
+    table_name: String,
+}
+
+impl AnalyzeTable {
+    pub fn new(table_name: String) -> Box<Self> {
+        Box::new(Self { table_name })
+    }
+}
+
+impl<T: Transaction> Executor<T> for AnalyzeTable {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let stats = ctx.txn.analyze_table(self.table_name.clone())?;
+        Ok(ResultSet::Scan {
+            columns: vec!["table".to_string(), "rows".to_string(), "bytes".to_string(), "analyzed_at".to_string()],
+            rows: vec![vec![
+                Value::String(self.table_name),
+                Value::Integer(stats.row_count as i64),
+                Value::Integer(stats.size_bytes as i64),
+                Value::Timestamp(stats.analyzed_at),
+            ]],
+        })
+    }
+}
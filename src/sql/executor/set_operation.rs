@@ -0,0 +1,66 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{error::Result, sql::{engine::Transaction, parser::ast::SetOperator, types::{Row, Rows, Value}}};
+
+use super::{ExecutionContext, Executor, ResultSet};
+
+// 集合运算，行按所有列的值比较，NULL 和 NULL 视为相同
+// UNION 按顺序输出左边和右边的行；INTERSECT、EXCEPT 先读出右边所有的行，再按顺序过滤左边的行
+pub struct SetOperation<T: Transaction> {
+    op: SetOperator,
+    left: Box<dyn Executor<T>>,
+    right: Box<dyn Executor<T>>,
+    all: bool,
+}
+
+impl<T: Transaction> SetOperation<T> {
+    pub fn new(op: SetOperator, left: Box<dyn Executor<T>>, right: Box<dyn Executor<T>>, all: bool) -> Box<Self> {
+        Box::new(Self { op, left, right, all })
+    }
+}
+
+fn row_key(row: &Row) -> Vec<Value> {
+    row.iter().cloned().map(Value::group_key).collect()
+}
+
+impl<T: Transaction> Executor<T> for SetOperation<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, left) = self.left.execute(ctx)?.into_stream("set operation")?;
+        let (_, right) = self.right.execute(ctx)?.into_stream("set operation")?;
+        let all = self.all;
+
+        // 已经输出的行，用于 DISTINCT 去重
+        let mut seen = HashSet::new();
+        let mut first = move |row: &Row| all || seen.insert(row_key(row));
+        let rows: Rows = match self.op {
+            SetOperator::Union => Box::new(left.chain(right).filter(move |row| row.as_ref().map_or(true, &mut first))),
+            SetOperator::Intersect | SetOperator::Except => {
+                // 右边每一行出现的次数，ALL 按多重集合计算：
+                // INTERSECT ALL 中一行出现 min(m, n) 次，EXCEPT ALL 中一行出现 max(m - n, 0) 次
+                let mut counts: HashMap<Vec<Value>, usize> = HashMap::new();
+                for row in right {
+                    *counts.entry(row_key(&row?)).or_default() += 1;
+                }
+                let intersect = self.op == SetOperator::Intersect;
+                let mut rows = Vec::new();
+                for row in left {
+                    let row = row?;
+                    let in_right = match counts.get_mut(&row_key(&row)) {
+                        Some(count) if *count > 0 => {
+                            if all {
+                                *count -= 1;
+                            }
+                            true
+                        },
+                        _ => false,
+                    };
+                    if in_right == intersect && first(&row) {
+                        rows.push(Ok(row));
+                    }
+                }
+                Box::new(rows.into_iter())
+            },
+        };
+        Ok(ResultSet::Stream { columns, rows })
+    }
+}
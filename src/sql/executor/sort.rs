@@ -0,0 +1,217 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::{self, File},
+    io::{BufReader, BufWriter, Seek, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::{
+    error::{Error, Result},
+    sql::{
+        engine::Transaction,
+        parser::ast::{Expression, OrderDirection},
+        plan::SortMethod,
+        types::{Row, Rows, Value},
+    },
+};
+
+use super::{ExecutionContext, Executor, ResultSet};
+
+// 排序时每一行和它的排序 key 放在一起，key 只计算一次
+type KeyedRow = (Vec<Value>, Row);
+
+// 按 order_by 对输入的行排序，相等的行保持输入时的顺序
+pub struct Sort<T: Transaction> {
+    source: Box<dyn Executor<T>>,
+    order_by: Vec<(Expression, OrderDirection)>,
+    method: SortMethod,
+}
+
+impl<T: Transaction> Sort<T> {
+    pub fn new(source: Box<dyn Executor<T>>, order_by: Vec<(Expression, OrderDirection)>, method: SortMethod) -> Box<Self> {
+        Box::new(Self { source, order_by, method })
+    }
+}
+
+impl<T: Transaction> Executor<T> for Sort<T> {
+    fn execute(self: Box<Self>, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        let (columns, rows) = self.source.execute(ctx)?.into_stream("sort")?;
+        let (exprs, directions): (Vec<_>, Vec<_>) = self.order_by.into_iter().unzip();
+        let keyed = rows.map(|row| {
+            let row = row?;
+            let keys = exprs.iter().map(|e| e.evaluate(&columns, &row)).collect::<Result<Vec<_>>>()?;
+            Ok((keys, row))
+        });
+        let rows: Rows = match self.method {
+            SortMethod::InMemory => {
+                let mut rows = keyed.collect::<Result<Vec<_>>>()?;
+                rows.sort_by(|a, b| compare_keys(&a.0, &b.0, &directions));
+                Box::new(rows.into_iter().map(|(_, row)| Ok(row)))
+            }
+            SortMethod::External { spill_path, threshold } => external_sort(keyed, directions, &spill_path, threshold)?,
+        };
+        Ok(ResultSet::Stream { columns, rows })
+    }
+}
+
+// 依次比较每个 key，NULL 小于其他的值，类型不同无法比较的值视为相等
+fn compare_keys(a: &[Value], b: &[Value], directions: &[OrderDirection]) -> Ordering {
+    for ((a, b), direction) in a.iter().zip(b).zip(directions) {
+        let ordering = match (a, b) {
+            (Value::Null, Value::Null) => Ordering::Equal,
+            (Value::Null, _) => Ordering::Less,
+            (_, Value::Null) => Ordering::Greater,
+            (a, b) => a.sql_cmp(b).unwrap_or(Ordering::Equal),
+        };
+        let ordering = match direction {
+            OrderDirection::Asc => ordering,
+            OrderDirection::Desc => ordering.reverse(),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+// 外部排序，缓存的行超过 threshold 字节时排序后写入一个临时文件，输入读完之后对所有文件做多路归并
+// 所有的行都能放入内存时不会写文件
+fn external_sort(
+    rows: impl Iterator<Item = Result<KeyedRow>>,
+    directions: Vec<OrderDirection>,
+    spill_path: &Path,
+    threshold: usize,
+) -> Result<Rows> {
+    let mut runs = Vec::new();
+    let mut buffer = Vec::new();
+    let mut size = 0;
+    for row in rows {
+        let row = row?;
+        size += bincode::serialized_size(&row)? as usize;
+        buffer.push(row);
+        if size > threshold {
+            buffer.sort_by(|a, b| compare_keys(&a.0, &b.0, &directions));
+            runs.push(SpillRun::write(spill_path, std::mem::take(&mut buffer))?);
+            size = 0;
+        }
+    }
+    buffer.sort_by(|a, b| compare_keys(&a.0, &b.0, &directions));
+    if runs.is_empty() {
+        return Ok(Box::new(buffer.into_iter().map(|(_, row)| Ok(row))));
+    }
+    if !buffer.is_empty() {
+        runs.push(SpillRun::write(spill_path, buffer)?);
+    }
+    Ok(Box::new(Merge::new(runs, directions)?))
+}
+
+// 临时文件，drop 时删除
+struct SpillFile {
+    path: PathBuf,
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// 写入临时文件的一段有序的行，按顺序读回
+struct SpillRun {
+    reader: BufReader<File>,
+    remaining: usize,
+    _file: SpillFile,
+}
+
+impl SpillRun {
+    fn write(dir: &Path, rows: Vec<KeyedRow>) -> Result<Self> {
+        let (file, path) = tempfile::Builder::new()
+            .prefix("sort-run-")
+            .tempfile_in(dir)?
+            .keep()
+            .map_err(|err| Error::Io(err.to_string()))?;
+        let guard = SpillFile { path };
+        let mut writer = BufWriter::new(file);
+        for row in &rows {
+            bincode::serialize_into(&mut writer, row)?;
+        }
+        writer.flush()?;
+        let mut file = writer.into_inner().map_err(|err| Error::Io(err.to_string()))?;
+        file.rewind()?;
+        Ok(Self { reader: BufReader::new(file), remaining: rows.len(), _file: guard })
+    }
+
+    fn next(&mut self) -> Result<Option<KeyedRow>> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        Ok(Some(bincode::deserialize_from(&mut self.reader)?))
+    }
+}
+
+// 归并时堆中的元素，每个文件同时只有一行在堆中
+struct HeapEntry {
+    row: KeyedRow,
+    run: usize,
+    directions: Rc<Vec<OrderDirection>>,
+}
+
+impl Ord for HeapEntry {
+    // BinaryHeap 是大顶堆，比较的结果取反后先弹出最小的行，相等时先弹出先写入的文件中的行
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_keys(&self.row.0, &other.row.0, &self.directions)
+            .then(self.run.cmp(&other.run))
+            .reverse()
+    }
+}
+
+impl PartialOrd for HeapEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry {}
+
+// 多路归并，每次取出所有文件当前行中最小的一行
+struct Merge {
+    runs: Vec<SpillRun>,
+    heap: BinaryHeap<HeapEntry>,
+    directions: Rc<Vec<OrderDirection>>,
+}
+
+impl Merge {
+    fn new(mut runs: Vec<SpillRun>, directions: Vec<OrderDirection>) -> Result<Self> {
+        let directions = Rc::new(directions);
+        let mut heap = BinaryHeap::with_capacity(runs.len());
+        for (run, spill) in runs.iter_mut().enumerate() {
+            if let Some(row) = spill.next()? {
+                heap.push(HeapEntry { row, run, directions: directions.clone() });
+            }
+        }
+        Ok(Self { runs, heap, directions })
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<Row>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.heap.pop()?;
+        match self.runs[entry.run].next() {
+            Ok(Some(row)) => self.heap.push(HeapEntry { row, run: entry.run, directions: self.directions.clone() }),
+            Ok(None) => {}
+            Err(err) => return Some(Err(err)),
+        }
+        Some(Ok(entry.row.1))
+    }
+}
@@ -0,0 +1,500 @@
+use std::cmp::Ordering;
+
+use crate::error::{Error, Result};
+
+use super::{parser::ast::{Consts, Expression, Operation}, schema::Table, types::{Row, Value}};
+
+// 表达式的计算，WHERE 条件、VALUES 和 DEFAULT 中的表达式都通过这里求值
+
+impl Expression {
+    // 作为过滤条件判断一行是否满足，只有结果为 TRUE 时满足，FALSE 和 NULL 都不满足
+    pub fn matches(&self, columns: &[String], row: &Row) -> Result<bool> {
+        match self.evaluate(columns, row)? {
+            Value::Boolean(b) => Ok(b),
+            Value::Null => Ok(false),
+            v => Err(Error::Internal(format!("filter condition must be boolean, got {:?}", v))),
+        }
+    }
+
+    // 表达式中引用到的所有列名
+    pub fn fields(&self) -> Vec<&str> {
+        match self {
+            Expression::Consts(_) | Expression::Parameter(_) | Expression::Default | Expression::QualifiedWildcard(_) => Vec::new(),
+            Expression::Field(name) => vec![name.as_str()],
+            Expression::Cast(expr, _) | Expression::Aggregate(_, Some(expr)) => expr.fields(),
+            Expression::Aggregate(_, None) => Vec::new(),
+            Expression::Between { expr, low, high, .. } => {
+                let mut fields = expr.fields();
+                fields.extend(low.fields());
+                fields.extend(high.fields());
+                fields
+            }
+            Expression::InList { expr, list, .. } => {
+                let mut fields = expr.fields();
+                fields.extend(list.iter().flat_map(|e| e.fields()));
+                fields
+            }
+            Expression::Operation(op) => match op {
+                Operation::Not(e) | Operation::IsNull(e) => e.fields(),
+                Operation::And(l, r)
+                | Operation::Or(l, r)
+                | Operation::Equal(l, r)
+                | Operation::NotEqual(l, r)
+                | Operation::GreaterThan(l, r)
+                | Operation::GreaterThanOrEqual(l, r)
+                | Operation::LessThan(l, r)
+                | Operation::LessThanOrEqual(l, r)
+                | Operation::Like(l, r) => {
+                    let mut fields = l.fields();
+                    fields.extend(r.fields());
+                    fields
+                }
+            },
+        }
+    }
+
+    // 在表中的一行上计算表达式的值，列引用按表结构找到对应的值
+    // 没有行数据时（例如 VALUES、DEFAULT 中的表达式）传入 None，此时不能引用列
+    pub fn evaluate_row(&self, ctx: Option<(&Table, &Row)>) -> Result<Value> {
+        match ctx {
+            Some((table, row)) => {
+                if let Some(name) = self.fields().into_iter().find(|f| table.column_index(f).is_none()) {
+                    return Err(Error::ColumnNotFound { table: table.name.clone(), column: name.to_string() });
+                }
+                let columns = table.columns.iter().map(|c| c.name.clone()).collect::<Vec<_>>();
+                self.evaluate(&columns, row)
+            }
+            None => match self.fields().first() {
+                Some(name) => Err(Error::Schema(format!("column {} cannot be referenced without a row", name))),
+                None => self.evaluate(&[], &Vec::new()),
+            },
+        }
+    }
+
+    // 在一行数据上计算表达式的值，columns 为这一行中每个值对应的列名
+    // 用于执行器中的中间结果，例如 Join 之后的行没有对应的表结构
+    pub fn evaluate(&self, columns: &[String], row: &Row) -> Result<Value> {
+        Ok(match self {
+            Expression::Consts(c) => match c {
+                Consts::Null => Value::Null,
+                Consts::Boolean(b) => Value::Boolean(*b),
+                Consts::Integer(i) => Value::Integer(*i),
+                Consts::Float(f) => Value::Float(*f),
+                Consts::String(s) => Value::String(s.clone()),
+                Consts::Timestamp(t) => Value::Timestamp(*t),
+            },
+            Expression::Field(name) => match column_position(columns, name) {
+                Some(i) => row[i].clone(),
+                None => return Err(Error::Internal(format!("unknown column {}", name))),
+            },
+            Expression::Parameter(i) => return Err(Error::Parse(format!("parameter {} is not bound", i + 1))),
+            Expression::Default => return Err(Error::Parse("DEFAULT is only allowed in INSERT VALUES".to_string())),
+            Expression::QualifiedWildcard(_) => {
+                return Err(Error::Parse(format!("{} is only allowed as an item of the SELECT list", self)))
+            }
+            Expression::Cast(expr, datatype) => expr.evaluate(columns, row)?.cast(datatype.clone())?,
+            // 聚合函数由 GroupBy 计算，规划时已经替换为对结果列的引用
+            Expression::Aggregate(..) => {
+                return Err(Error::Parse(format!("aggregate function {} is not allowed here", self)))
+            }
+            Expression::Operation(op) => match op {
+                Operation::And(l, r) => and(l.evaluate(columns, row)?, r.evaluate(columns, row)?)?,
+                Operation::Or(l, r) => or(l.evaluate(columns, row)?, r.evaluate(columns, row)?)?,
+                Operation::Not(e) => not(e.evaluate(columns, row)?)?,
+                Operation::Equal(l, r) => Self::compare(l, r, columns, row, |o| o.is_eq())?,
+                Operation::NotEqual(l, r) => Self::compare(l, r, columns, row, |o| o.is_ne())?,
+                Operation::GreaterThan(l, r) => Self::compare(l, r, columns, row, |o| o.is_gt())?,
+                Operation::GreaterThanOrEqual(l, r) => Self::compare(l, r, columns, row, |o| o.is_ge())?,
+                Operation::LessThan(l, r) => Self::compare(l, r, columns, row, |o| o.is_lt())?,
+                Operation::LessThanOrEqual(l, r) => Self::compare(l, r, columns, row, |o| o.is_le())?,
+                Operation::Like(l, r) => match (l.evaluate(columns, row)?, r.evaluate(columns, row)?) {
+                    (Value::Null, _) | (_, Value::Null) => Value::Null,
+                    (Value::String(s), Value::String(p)) => Value::Boolean(like_match(&s, &p)),
+                    (l, r) => return Err(Error::Internal(format!("can not match {:?} like {:?}", l, r))),
+                },
+                // 和 = NULL 不同，IS NULL 的结果只会是 true 或 false
+                Operation::IsNull(e) => Value::Boolean(e.evaluate(columns, row)? == Value::Null),
+            },
+            // 按 low <= expr AND expr <= high 计算，NULL 的处理和比较、AND 相同
+            Expression::Between { expr, low, high, negated } => {
+                let value = expr.evaluate(columns, row)?;
+                let ge = compare(low.evaluate(columns, row)?, value.clone(), |o| o.is_le())?;
+                let le = compare(value, high.evaluate(columns, row)?, |o| o.is_le())?;
+                let result = and(ge, le)?;
+                if *negated { not(result)? } else { result }
+            }
+            // 按 expr = list[0] OR expr = list[1] ... 计算，空列表的结果为 FALSE
+            // 列表中的每一项都会计算，类型不匹配时报错，不会因为前面已经匹配而跳过
+            Expression::InList { expr, list, negated } => {
+                let value = expr.evaluate(columns, row)?;
+                let mut result = Value::Boolean(false);
+                for item in list {
+                    result = or(result, compare(value.clone(), item.evaluate(columns, row)?, |o| o.is_eq())?)?;
+                }
+                if *negated { not(result)? } else { result }
+            }
+        })
+    }
+
+    // 将参数占位符替换为绑定的值
+    pub fn bind(self, params: &[Value]) -> Result<Expression> {
+        let bind = |e: Box<Expression>| e.bind(params).map(Box::new);
+        Ok(match self {
+            Expression::Parameter(i) => match params.get(i) {
+                Some(value) => Consts::from(value.clone()).into(),
+                None => return Err(Error::Parse(format!("parameter {} is not bound", i + 1))),
+            },
+            Expression::Operation(op) => Expression::Operation(match op {
+                Operation::And(l, r) => Operation::And(bind(l)?, bind(r)?),
+                Operation::Or(l, r) => Operation::Or(bind(l)?, bind(r)?),
+                Operation::Not(e) => Operation::Not(bind(e)?),
+                Operation::Equal(l, r) => Operation::Equal(bind(l)?, bind(r)?),
+                Operation::NotEqual(l, r) => Operation::NotEqual(bind(l)?, bind(r)?),
+                Operation::GreaterThan(l, r) => Operation::GreaterThan(bind(l)?, bind(r)?),
+                Operation::GreaterThanOrEqual(l, r) => Operation::GreaterThanOrEqual(bind(l)?, bind(r)?),
+                Operation::LessThan(l, r) => Operation::LessThan(bind(l)?, bind(r)?),
+                Operation::LessThanOrEqual(l, r) => Operation::LessThanOrEqual(bind(l)?, bind(r)?),
+                Operation::Like(l, r) => Operation::Like(bind(l)?, bind(r)?),
+                Operation::IsNull(e) => Operation::IsNull(bind(e)?),
+            }),
+            Expression::Between { expr, low, high, negated } => {
+                Expression::Between { expr: bind(expr)?, low: bind(low)?, high: bind(high)?, negated }
+            }
+            Expression::Cast(expr, datatype) => Expression::Cast(bind(expr)?, datatype),
+            Expression::Aggregate(func, arg) => Expression::Aggregate(func, arg.map(bind).transpose()?),
+            Expression::InList { expr, list, negated } => Expression::InList {
+                expr: bind(expr)?,
+                list: list.into_iter().map(|e| e.bind(params)).collect::<Result<_>>()?,
+                negated,
+            },
+            e @ (Expression::Consts(_) | Expression::Field(_) | Expression::Default | Expression::QualifiedWildcard(_)) => e,
+        })
+    }
+
+    // 自顶向下替换表达式，f 返回 Some 时用返回值替换当前的表达式，不再处理它的子表达式
+    // 返回 None 时保留当前的表达式，继续处理子表达式
+    pub fn transform<F: FnMut(&Expression) -> Result<Option<Expression>>>(self, f: &mut F) -> Result<Expression> {
+        if let Some(expr) = f(&self)? {
+            return Ok(expr);
+        }
+        fn t<F: FnMut(&Expression) -> Result<Option<Expression>>>(e: Expression, f: &mut F) -> Result<Box<Expression>> {
+            e.transform(f).map(Box::new)
+        }
+        Ok(match self {
+            Expression::Operation(op) => Expression::Operation(match op {
+                Operation::And(l, r) => Operation::And(t(*l, f)?, t(*r, f)?),
+                Operation::Or(l, r) => Operation::Or(t(*l, f)?, t(*r, f)?),
+                Operation::Not(e) => Operation::Not(t(*e, f)?),
+                Operation::Equal(l, r) => Operation::Equal(t(*l, f)?, t(*r, f)?),
+                Operation::NotEqual(l, r) => Operation::NotEqual(t(*l, f)?, t(*r, f)?),
+                Operation::GreaterThan(l, r) => Operation::GreaterThan(t(*l, f)?, t(*r, f)?),
+                Operation::GreaterThanOrEqual(l, r) => Operation::GreaterThanOrEqual(t(*l, f)?, t(*r, f)?),
+                Operation::LessThan(l, r) => Operation::LessThan(t(*l, f)?, t(*r, f)?),
+                Operation::LessThanOrEqual(l, r) => Operation::LessThanOrEqual(t(*l, f)?, t(*r, f)?),
+                Operation::Like(l, r) => Operation::Like(t(*l, f)?, t(*r, f)?),
+                Operation::IsNull(e) => Operation::IsNull(t(*e, f)?),
+            }),
+            Expression::Between { expr, low, high, negated } => {
+                Expression::Between { expr: t(*expr, f)?, low: t(*low, f)?, high: t(*high, f)?, negated }
+            }
+            Expression::InList { expr, list, negated } => Expression::InList {
+                expr: t(*expr, f)?,
+                list: list.into_iter().map(|e| e.transform(f)).collect::<Result<_>>()?,
+                negated,
+            },
+            Expression::Cast(expr, datatype) => Expression::Cast(t(*expr, f)?, datatype),
+            Expression::Aggregate(func, arg) => Expression::Aggregate(func, arg.map(|e| t(*e, f)).transpose()?),
+            e @ (Expression::Consts(_)
+            | Expression::Field(_)
+            | Expression::Parameter(_)
+            | Expression::Default
+            | Expression::QualifiedWildcard(_)) => e,
+        })
+    }
+
+    // 表达式中的第一个聚合函数
+    pub fn find_aggregate(&self) -> Option<Expression> {
+        let mut found = None;
+        let _ = self.clone().transform(&mut |e| {
+            if found.is_none() && matches!(e, Expression::Aggregate(..)) {
+                found = Some(e.clone());
+            }
+            Ok(None)
+        });
+        found
+    }
+
+    // 比较两个表达式的值，任意一边为 NULL 时结果为 NULL
+    fn compare<F: Fn(Ordering) -> bool>(l: &Expression, r: &Expression, columns: &[String], row: &Row, f: F) -> Result<Value> {
+        compare(l.evaluate(columns, row)?, r.evaluate(columns, row)?, f)
+    }
+}
+
+// 查找列名对应的下标，Join 的输出中两侧同名的列会加上表名，例如 a.id、b.id
+// 先按完整的列名查找，找不到时 a.x 按 x 查找，x 按第一个 *.x 查找，因此同名的列默认解析到左侧
+pub fn column_position(columns: &[String], name: &str) -> Option<usize> {
+    columns.iter().position(|c| c == name).or_else(|| match name.split_once('.') {
+        Some((_, column)) => columns.iter().position(|c| c == column),
+        None => columns.iter().position(|c| c.split_once('.').is_some_and(|(_, c)| c == name)),
+    })
+}
+
+// 比较两个值，任意一边为 NULL 时结果为 NULL
+fn compare<F: Fn(Ordering) -> bool>(l: Value, r: Value, f: F) -> Result<Value> {
+    if l == Value::Null || r == Value::Null {
+        return Ok(Value::Null);
+    }
+    match l.sql_cmp(&r) {
+        Some(o) => Ok(Value::Boolean(f(o))),
+        None => Err(Error::Internal(format!("can not compare {:?} and {:?}", l, r))),
+    }
+}
+
+// 三值逻辑的 AND，任意一边为 FALSE 时结果为 FALSE，否则有 NULL 时为 NULL
+fn and(l: Value, r: Value) -> Result<Value> {
+    Ok(match (l, r) {
+        (Value::Boolean(false), Value::Boolean(_) | Value::Null)
+        | (Value::Boolean(_) | Value::Null, Value::Boolean(false)) => Value::Boolean(false),
+        (Value::Boolean(true), Value::Boolean(true)) => Value::Boolean(true),
+        (Value::Boolean(_) | Value::Null, Value::Boolean(_) | Value::Null) => Value::Null,
+        (l, r) => return Err(Error::Internal(format!("can not and {:?} and {:?}", l, r))),
+    })
+}
+
+// 三值逻辑的 OR，任意一边为 TRUE 时结果为 TRUE，否则有 NULL 时为 NULL
+fn or(l: Value, r: Value) -> Result<Value> {
+    Ok(match (l, r) {
+        (Value::Boolean(true), Value::Boolean(_) | Value::Null)
+        | (Value::Boolean(_) | Value::Null, Value::Boolean(true)) => Value::Boolean(true),
+        (Value::Boolean(false), Value::Boolean(false)) => Value::Boolean(false),
+        (Value::Boolean(_) | Value::Null, Value::Boolean(_) | Value::Null) => Value::Null,
+        (l, r) => return Err(Error::Internal(format!("can not or {:?} and {:?}", l, r))),
+    })
+}
+
+fn not(v: Value) -> Result<Value> {
+    match v {
+        Value::Boolean(b) => Ok(Value::Boolean(!b)),
+        Value::Null => Ok(Value::Null),
+        v => Err(Error::Internal(format!("can not negate {:?}", v))),
+    }
+}
+
+// LIKE 模式中的元素
+enum LikeToken {
+    // % 匹配任意长度的字符串
+    Any,
+    // _ 匹配单个字符
+    One,
+    Char(char),
+}
+
+// 判断字符串是否匹配 LIKE 模式，\ 用来转义 %、_ 和 \ 本身
+fn like_match(s: &str, pattern: &str) -> bool {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '%' => LikeToken::Any,
+            '_' => LikeToken::One,
+            // 末尾单独的 \ 按普通字符处理
+            '\\' => LikeToken::Char(chars.next().unwrap_or('\\')),
+            c => LikeToken::Char(c),
+        });
+    }
+    let s = s.chars().collect::<Vec<_>>();
+
+    // 贪心匹配，遇到 % 时记录位置，后续匹配失败则回退到 % 处多吞掉一个字符
+    let (mut si, mut pi) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while si < s.len() {
+        match tokens.get(pi) {
+            Some(LikeToken::Any) => {
+                backtrack = Some((pi, si));
+                pi += 1;
+                continue;
+            }
+            Some(LikeToken::One) => {
+                si += 1;
+                pi += 1;
+                continue;
+            }
+            Some(LikeToken::Char(c)) if *c == s[si] => {
+                si += 1;
+                pi += 1;
+                continue;
+            }
+            _ => {}
+        }
+        match backtrack {
+            Some((bp, bs)) => {
+                backtrack = Some((bp, bs + 1));
+                pi = bp + 1;
+                si = bs + 1;
+            }
+            None => return false,
+        }
+    }
+    tokens[pi..].iter().all(|t| matches!(t, LikeToken::Any))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        error::{Error, Result},
+        sql::{
+            parser::{ast::{Consts, Expression, Statement}, Parser},
+            schema::{Column, Table},
+            types::{DataType, Value},
+        },
+    };
+
+    use super::like_match;
+
+    #[test]
+    fn test_like_match() {
+        // 前缀、后缀、包含
+        assert!(like_match("abc", "a%"));
+        assert!(!like_match("bac", "a%"));
+        assert!(like_match("xyz", "%z"));
+        assert!(!like_match("zyx", "%z"));
+        assert!(like_match("axb", "%x%"));
+        assert!(like_match("x", "%x%"));
+        assert!(!like_match("ab", "%x%"));
+        // 单个字符
+        assert!(like_match("abc", "a_c"));
+        assert!(!like_match("ac", "a_c"));
+        assert!(!like_match("abbc", "a_c"));
+        // 转义
+        assert!(like_match("50%", "50\\%"));
+        assert!(!like_match("500", "50\\%"));
+        assert!(like_match("a_b", "a\\_b"));
+        assert!(!like_match("axb", "a\\_b"));
+        assert!(like_match("a\\b", "a\\\\b"));
+        // 需要回溯的情况
+        assert!(like_match("aXbXyc", "%X_c"));
+        assert!(like_match("", "%"));
+        assert!(!like_match("", "_"));
+        assert!(like_match("中文", "_文"));
+    }
+
+    // 解析 WHERE 中的表达式
+    fn parse(expr: &str) -> Result<Expression> {
+        match Parser::new(&format!("select * from t where {};", expr)).parse()? {
+            Statement::Select { where_clause: Some(expr), .. } => Ok(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn table() -> Table {
+        let column = |name: &str, datatype| Column {
+            name: name.to_string(),
+            datatype,
+            nullable: true,
+            default: Some(Consts::Null.into()),
+            primary_key: name == "id",
+            auto_increment: false,
+            max_len: None,
+            comment: None,
+        };
+        Table {
+            name: "t".to_string(),
+            columns: vec![column("id", DataType::Integer), column("name", DataType::String), column("score", DataType::Float)],
+            indexes: Vec::new(),
+            primary_key: vec![0],
+        }
+    }
+
+    #[test]
+    fn test_evaluate_consts() -> Result<()> {
+        let eval = |expr: &str| parse(expr)?.evaluate_row(None);
+        assert_eq!(eval("1")?, Value::Integer(1));
+        assert_eq!(eval("1.5")?, Value::Float(1.5));
+        assert_eq!(eval("'a'")?, Value::String("a".to_string()));
+        assert_eq!(eval("true")?, Value::Boolean(true));
+        assert_eq!(eval("null")?, Value::Null);
+        assert_eq!(eval("1 < 2 and 'b' > 'a'")?, Value::Boolean(true));
+
+        // 没有行数据时不能引用列
+        assert_eq!(
+            eval("id = 1"),
+            Err(Error::Schema("column id cannot be referenced without a row".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_fields() -> Result<()> {
+        let table = table();
+        let row = vec![Value::Integer(1), Value::String("foo".to_string()), Value::Null];
+        let eval = |expr: &str| parse(expr)?.evaluate_row(Some((&table, &row)));
+        assert_eq!(eval("id")?, Value::Integer(1));
+        assert_eq!(eval("name")?, Value::String("foo".to_string()));
+        assert_eq!(eval("id = 1 and name like 'f%'")?, Value::Boolean(true));
+        assert_eq!(eval("not name = 'bar'")?, Value::Boolean(true));
+
+        // 未知的列，即使所在的分支不需要计算也报错
+        assert_eq!(
+            eval("id = 1 or missing = 2"),
+            Err(Error::ColumnNotFound { table: "t".to_string(), column: "missing".to_string() })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_null() -> Result<()> {
+        let table = table();
+        let row = vec![Value::Integer(1), Value::Null, Value::Null];
+        let eval = |expr: &str| parse(expr)?.evaluate_row(Some((&table, &row)));
+        // 比较、LIKE、NOT 遇到 NULL 时结果为 NULL
+        assert_eq!(eval("score > 1.0")?, Value::Null);
+        assert_eq!(eval("name = null")?, Value::Null);
+        assert_eq!(eval("name like '%'")?, Value::Null);
+        assert_eq!(eval("not score = 1.0")?, Value::Null);
+        // 三值逻辑
+        assert_eq!(eval("score > 1.0 and id = 2")?, Value::Boolean(false));
+        assert_eq!(eval("score > 1.0 and id = 1")?, Value::Null);
+        assert_eq!(eval("score > 1.0 or id = 1")?, Value::Boolean(true));
+        assert_eq!(eval("score > 1.0 or id = 2")?, Value::Null);
+        // IS NULL 不会返回 NULL
+        assert_eq!(eval("score is null")?, Value::Boolean(true));
+        assert_eq!(eval("id is not null")?, Value::Boolean(true));
+        // 类型不同无法比较
+        assert!(eval("id = 'a'").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_between_in() -> Result<()> {
+        let table = table();
+        let row = vec![Value::Integer(5), Value::String("b".to_string()), Value::Null];
+        let eval = |expr: &str| parse(expr)?.evaluate_row(Some((&table, &row)));
+        // BETWEEN 包含两端
+        assert_eq!(eval("id between 1 and 5")?, Value::Boolean(true));
+        assert_eq!(eval("id between 6 and 10")?, Value::Boolean(false));
+        assert_eq!(eval("id not between 6 and 10")?, Value::Boolean(true));
+        assert_eq!(eval("name between 'a' and 'c' and id = 5")?, Value::Boolean(true));
+        assert_eq!(eval("not id between 1 and 5")?, Value::Boolean(false));
+        // 按 low <= expr AND expr <= high 处理 NULL
+        assert_eq!(eval("score between 1.0 and 2.0")?, Value::Null);
+        assert_eq!(eval("id between null and 10")?, Value::Null);
+        assert_eq!(eval("id between null and 1")?, Value::Boolean(false));
+        assert_eq!(eval("id not between null and 1")?, Value::Boolean(true));
+
+        assert_eq!(eval("id in (1, 5)")?, Value::Boolean(true));
+        assert_eq!(eval("id in (1, 2)")?, Value::Boolean(false));
+        assert_eq!(eval("name not in ('a', 'c')")?, Value::Boolean(true));
+        // 空列表总是 FALSE，NULL IN (...) 为 NULL，列表中有 NULL 且没有匹配时为 NULL
+        assert_eq!(eval("id in ()")?, Value::Boolean(false));
+        assert_eq!(eval("id not in ()")?, Value::Boolean(true));
+        assert_eq!(eval("score in (1.0)")?, Value::Null);
+        assert_eq!(eval("id in (1, null)")?, Value::Null);
+        assert_eq!(eval("id in (5, null)")?, Value::Boolean(true));
+        assert_eq!(eval("id not in (1, null)")?, Value::Null);
+
+        // 列表中类型不匹配的值即使排在匹配项之后也报错
+        assert!(eval("id in (5, 'a')").is_err());
+        assert!(eval("id between 'a' and 10").is_err());
+        Ok(())
+    }
+}
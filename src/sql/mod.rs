@@ -1,5 +1,6 @@
 pub mod parser;
 pub mod types;
+pub mod expression;
 pub mod plan;
 pub mod schema;
 pub mod executor;
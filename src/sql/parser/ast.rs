@@ -1,11 +1,23 @@
-use crate::sql::types::DataType;
+use std::{borrow::Cow, fmt::Display};
 
-// 抽象语法树的定义
-#[derive(Debug,PartialEq)]
+use serde::{Deserialize, Serialize};
+
+use super::lexer::Keyword;
+
+use crate::{error::Result, sql::types::{timestamp::format_timestamp, DataType, Value}, storage::mvcc::IsolationLevel};
+
+// 抽象语法树的定义，视图的查询以语法树的形式保存，因此需要支持序列化
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
 pub enum Statement{
     CreateTable {
         name: String,
         columns: Vec<Column>,
+        // 表已经存在时不报错
+        if_not_exists: bool,
+        // 表已经存在时删除原来的表和其中的数据，再按新的定义创建
+        or_replace: bool,
+        // 列定义之后的 PRIMARY KEY (a, b, ...)，按顺序给出主键的列，为空时使用列上的 PRIMARY KEY
+        primary_key: Vec<String>,
     },
     Insert {
         table_name: String,
@@ -13,24 +25,262 @@ pub enum Statement{
         values: Vec<Vec<Expression>>,
     },
     Select {
+        // 是否去掉重复的输出行
+        distinct: bool,
+        // 输出的表达式和别名，为空时表示 SELECT *
+        select: Vec<(Expression, Option<String>)>,
+        from: FromItem,
+        where_clause: Option<Expression>,
+        group_by: Vec<Expression>,
+        having: Option<Expression>,
+        order_by: Vec<(Expression, OrderDirection)>,
+    },
+    // WITH 子句，ctes 中的查询按顺序执行，结果作为临时表，之后的 CTE 和 query 中可以按名字引用
+    // recursive 为 true 时，CTE 可以在 UNION 右侧的查询中引用自身
+    With {
+        recursive: bool,
+        ctes: Vec<(String, Box<Statement>)>,
+        query: Box<Statement>,
+    },
+    // 两个查询结果的集合运算，左右两边的列数必须相同，输出的列名和左边一致
+    // all 为 false 时去掉重复的行，为 true 时按多重集合计算
+    SetOperation {
+        op: SetOperator,
+        left: Box<Statement>,
+        right: Box<Statement>,
+        all: bool,
+    },
+    ShowEngineStatus,
+    // 保存一个查询作为视图，之后可以像表一样在 FROM 中引用
+    CreateView {
+        name: String,
+        query: Box<Statement>,
+    },
+    DropView {
+        name: String,
+    },
+    ShowViews,
+    // 列出底层存储中的 key，prefix 用于过滤解码之后的 key
+    DebugKeys {
+        prefix: Option<String>,
+    },
+    CreateIndex {
+        index_name: String,
+        table_name: String,
+        column_name: String,
+    },
+    DropIndex {
+        index_name: String,
+    },
+    // 删除表中所有的行，保留表结构
+    TruncateTable {
+        table_name: String,
+    },
+    // 统计表的行数和大小
+    AnalyzeTable {
         table_name: String,
     },
+    // 输出表中每一列的定义
+    Describe {
+        table_name: String,
+    },
+    // 开启显式事务，之后的语句都在这个事务中执行，直到 COMMIT 或 ROLLBACK
+    Begin {
+        isolation: IsolationLevel,
+    },
+    Commit,
+    Rollback,
+    // 只生成执行计划，不执行
+    Explain(Box<Statement>),
+    // 执行语句，并输出带有每个节点实际行数和耗时的执行计划
+    ExplainAnalyze(Box<Statement>),
+}
+
+impl Statement {
+    // 将语句中的参数占位符替换为绑定的值
+    pub fn bind(self, params: &[Value]) -> Result<Statement> {
+        let bind_all = |exprs: Vec<Expression>| exprs.into_iter().map(|e| e.bind(params)).collect::<Result<Vec<_>>>();
+        Ok(match self {
+            Statement::CreateTable { name, columns, if_not_exists, or_replace, primary_key } => Statement::CreateTable {
+                name,
+                if_not_exists,
+                or_replace,
+                primary_key,
+                columns: columns
+                    .into_iter()
+                    .map(|c| Ok(Column { default: c.default.map(|e| e.bind(params)).transpose()?, ..c }))
+                    .collect::<Result<_>>()?,
+            },
+            Statement::Insert { table_name, columns, values } => Statement::Insert {
+                table_name,
+                columns,
+                values: values.into_iter().map(bind_all).collect::<Result<_>>()?,
+            },
+            Statement::Select { distinct, select, from, where_clause, group_by, having, order_by } => Statement::Select {
+                distinct,
+                select: select.into_iter().map(|(e, alias)| Ok((e.bind(params)?, alias))).collect::<Result<_>>()?,
+                from,
+                where_clause: where_clause.map(|e| e.bind(params)).transpose()?,
+                group_by: bind_all(group_by)?,
+                having: having.map(|e| e.bind(params)).transpose()?,
+                order_by: order_by.into_iter().map(|(e, d)| Ok((e.bind(params)?, d))).collect::<Result<_>>()?,
+            },
+            Statement::With { recursive, ctes, query } => Statement::With {
+                recursive,
+                ctes: ctes.into_iter().map(|(name, stmt)| Ok((name, Box::new(stmt.bind(params)?)))).collect::<Result<_>>()?,
+                query: Box::new(query.bind(params)?),
+            },
+            Statement::SetOperation { op, left, right, all } => Statement::SetOperation {
+                op,
+                left: Box::new(left.bind(params)?),
+                right: Box::new(right.bind(params)?),
+                all,
+            },
+            Statement::CreateView { name, query } => Statement::CreateView { name, query: Box::new(query.bind(params)?) },
+            Statement::Explain(stmt) => Statement::Explain(Box::new(stmt.bind(params)?)),
+            Statement::ExplainAnalyze(stmt) => Statement::ExplainAnalyze(Box::new(stmt.bind(params)?)),
+            stmt @ (Statement::ShowEngineStatus | Statement::CreateIndex { .. } | Statement::DropIndex { .. }
+            | Statement::TruncateTable { .. } | Statement::AnalyzeTable { .. } | Statement::Describe { .. } | Statement::Begin { .. }
+            | Statement::Commit | Statement::Rollback | Statement::DropView { .. } | Statement::ShowViews
+            | Statement::DebugKeys { .. }) => stmt,
+        })
+    }
+}
+
+// 集合运算的类型
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum SetOperator {
+    Union,
+    Intersect,
+    Except,
+}
+
+impl Display for SetOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SetOperator::Union => write!(f, "UNION"),
+            SetOperator::Intersect => write!(f, "INTERSECT"),
+            SetOperator::Except => write!(f, "EXCEPT"),
+        }
+    }
+}
+
+// FROM 子句中的数据来源
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
+pub enum FromItem {
+    Table {
+        name: String,
+    },
+    Join {
+        left: Box<FromItem>,
+        right: Box<FromItem>,
+        join_type: JoinType,
+    },
+    // 带别名的表，查询中只能通过别名引用这张表，新增的变体放在最后，已经保存的视图的编码不变
+    AliasedTable {
+        name: String,
+        alias: String,
+    },
+}
+
+// 排序的方向
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum OrderDirection {
+    Asc,
+    Desc,
+}
+
+impl Display for OrderDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OrderDirection::Asc => "ASC",
+            OrderDirection::Desc => "DESC",
+        })
+    }
+}
+
+// 连接类型
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
+pub enum JoinType {
+    // 笛卡尔积
+    Cross,
+    // 按照两边同名的列做等值连接，同名列只输出一次
+    Natural,
+    // 只保留满足 ON 条件的行
+    Inner(Expression),
 }
 
 // 列定义
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
 pub struct Column {
     pub name: String,
     pub datatype: DataType,
     pub nullable: Option<bool>,
     pub default: Option<Expression>,
+    pub primary_key: bool,
+    pub auto_increment: bool,
+    pub max_len: Option<usize>,
+    pub comment: Option<String>,
 }
 
 
 // 表达式定义
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
 pub enum Expression {
     Consts(Consts),
+    // 列引用
+    Field(String),
+    Operation(Operation),
+    // 预处理语句的参数占位符，执行之前替换为绑定的值
+    Parameter(usize),
+    // expr [NOT] BETWEEN low AND high，等价于 low <= expr AND expr <= high
+    Between { expr: Box<Expression>, low: Box<Expression>, high: Box<Expression>, negated: bool },
+    // expr [NOT] IN (list)，等价于 expr = list[0] OR expr = list[1] ...
+    InList { expr: Box<Expression>, list: Vec<Expression>, negated: bool },
+    // INSERT 的 VALUES 中的 DEFAULT，插入时替换为列的默认值
+    Default,
+    // CAST(expr AS type)，计算时按 Value::cast 转换类型
+    Cast(Box<Expression>, DataType),
+    // 聚合函数，参数为 None 时是 COUNT(*)，只能出现在 SELECT、HAVING 和 ORDER BY 中
+    Aggregate(Aggregate, Option<Box<Expression>>),
+    // SELECT 中的 表名.*，规划时展开为这张表的所有列，不能出现在其他位置
+    QualifiedWildcard(String),
+}
+
+// 聚合函数
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    // 按函数名查找聚合函数，不区分大小写
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name.to_uppercase().as_str() {
+            "COUNT" => Aggregate::Count,
+            "SUM" => Aggregate::Sum,
+            "AVG" => Aggregate::Avg,
+            "MIN" => Aggregate::Min,
+            "MAX" => Aggregate::Max,
+            _ => return None,
+        })
+    }
+}
+
+impl Display for Aggregate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Aggregate::Count => "COUNT",
+            Aggregate::Sum => "SUM",
+            Aggregate::Avg => "AVG",
+            Aggregate::Min => "MIN",
+            Aggregate::Max => "MAX",
+        })
+    }
 }
 
 
@@ -40,13 +290,115 @@ impl From<Consts> for Expression {
     }
 }
 
+impl From<Operation> for Expression {
+    fn from(value: Operation) -> Self {
+        Self::Operation(value)
+    }
+}
+
+impl From<Value> for Consts {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::Null => Consts::Null,
+            Value::Boolean(b) => Consts::Boolean(b),
+            Value::Integer(i) => Consts::Integer(i),
+            Value::Float(f) => Consts::Float(f),
+            Value::String(s) => Consts::String(s),
+            Value::Timestamp(t) => Consts::Timestamp(t),
+        }
+    }
+}
+
+// 运算定义
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
+pub enum Operation {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Equal(Box<Expression>, Box<Expression>),
+    NotEqual(Box<Expression>, Box<Expression>),
+    GreaterThan(Box<Expression>, Box<Expression>),
+    GreaterThanOrEqual(Box<Expression>, Box<Expression>),
+    LessThan(Box<Expression>, Box<Expression>),
+    LessThanOrEqual(Box<Expression>, Box<Expression>),
+    Like(Box<Expression>, Box<Expression>),
+    IsNull(Box<Expression>),
+}
+
+
+// 以接近 SQL 的形式输出表达式，用于展示执行计划
+// 嵌套的 AND / OR 加上括号，避免优先级产生歧义
+impl Display for Expression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operand = |e: &Expression| match e {
+            Expression::Operation(Operation::And(..) | Operation::Or(..)) | Expression::Between { .. } => format!("({})", e),
+            e => e.to_string(),
+        };
+        match self {
+            Expression::Consts(c) => match c {
+                Consts::Null => write!(f, "NULL"),
+                Consts::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+                Consts::Integer(i) => write!(f, "{}", i),
+                Consts::Float(n) => write!(f, "{:?}", n),
+                Consts::String(s) => write!(f, "'{}'", s),
+                Consts::Timestamp(t) => write!(f, "TIMESTAMP '{}'", format_timestamp(*t)),
+            },
+            Expression::Field(name) => match name.split_once('.') {
+                Some((table, column)) => write!(f, "{}.{}", quote_ident(table), quote_ident(column)),
+                None => write!(f, "{}", quote_ident(name)),
+            },
+            Expression::Parameter(_) => write!(f, "?"),
+            Expression::Default => write!(f, "DEFAULT"),
+            Expression::QualifiedWildcard(table) => write!(f, "{}.*", quote_ident(table)),
+            Expression::Cast(expr, datatype) => write!(f, "CAST({} AS {})", expr, datatype),
+            Expression::Aggregate(func, Some(arg)) => write!(f, "{}({})", func, arg),
+            Expression::Aggregate(func, None) => write!(f, "{}(*)", func),
+            Expression::Between { expr, low, high, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                write!(f, "{} {}BETWEEN {} AND {}", operand(expr), not, operand(low), operand(high))
+            }
+            Expression::InList { expr, list, negated } => {
+                let not = if *negated { "NOT " } else { "" };
+                let list = list.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ");
+                write!(f, "{} {}IN ({})", operand(expr), not, list)
+            }
+            Expression::Operation(op) => match op {
+                Operation::And(l, r) => write!(f, "{} AND {}", operand(l), operand(r)),
+                Operation::Or(l, r) => write!(f, "{} OR {}", operand(l), operand(r)),
+                Operation::Not(e) => write!(f, "NOT {}", operand(e)),
+                Operation::Equal(l, r) => write!(f, "{} = {}", operand(l), operand(r)),
+                Operation::NotEqual(l, r) => write!(f, "{} != {}", operand(l), operand(r)),
+                Operation::GreaterThan(l, r) => write!(f, "{} > {}", operand(l), operand(r)),
+                Operation::GreaterThanOrEqual(l, r) => write!(f, "{} >= {}", operand(l), operand(r)),
+                Operation::LessThan(l, r) => write!(f, "{} < {}", operand(l), operand(r)),
+                Operation::LessThanOrEqual(l, r) => write!(f, "{} <= {}", operand(l), operand(r)),
+                Operation::Like(l, r) => write!(f, "{} LIKE {}", operand(l), operand(r)),
+                Operation::IsNull(e) => write!(f, "{} IS NULL", operand(e)),
+            },
+        }
+    }
+}
+
+// 不加引号时无法原样解析回来的标识符加上双引号，例如关键字、包含空格或者大写字母的名字
+// 输出的文本可以重新被解析器解析，引号内的双引号转义为两个双引号
+pub fn quote_ident(ident: &str) -> Cow<'_, str> {
+    let plain = ident.chars().next().is_some_and(|c| c.is_alphabetic())
+        && ident.chars().all(|c| c.is_alphanumeric() || c == '_')
+        && ident.to_lowercase() == ident
+        && Keyword::from_str(ident).is_none();
+    match plain {
+        true => Cow::Borrowed(ident),
+        false => Cow::Owned(format!("\"{}\"", ident.replace('"', "\"\""))),
+    }
+}
 
 // 常量定义
-#[derive(Debug,PartialEq)]
+#[derive(Debug,PartialEq,Clone,Serialize,Deserialize)]
 pub enum Consts {
     Null,
     Boolean(bool),
     Integer(i64),
     Float(f64),
     String(String),
-}
\ No newline at end of file
+    Timestamp(i64),
+}
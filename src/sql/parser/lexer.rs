@@ -20,6 +20,8 @@ pub enum Token {
     Comma,
     // 分号 ;
     Semicolon,
+    // 句点 .，用于 表名.列名
+    Period,
     // 星号 *
     Asterisk,
     // 加号 +
@@ -28,6 +30,20 @@ pub enum Token {
     Minus,
     // 斜杠 /
     Slash,
+    // 等号 =
+    Equal,
+    // 不等号 != 或 <>
+    NotEqual,
+    // 大于号 >
+    GreaterThan,
+    // 大于等于 >=
+    GreaterThanOrEqual,
+    // 小于号 <
+    LessThan,
+    // 小于等于 <=
+    LessThanOrEqual,
+    // 参数占位符 ?，按出现的顺序从 0 开始编号
+    Parameter(usize),
 }
 
 impl Display for Token {
@@ -41,10 +57,18 @@ impl Display for Token {
             Token::CloseParen => ")",
             Token::Comma => ",",
             Token::Semicolon => ";",
+            Token::Period => ".",
             Token::Asterisk => "*",
             Token::Plus => "+",
             Token::Minus => "-",
             Token::Slash => "/",
+            Token::Equal => "=",
+            Token::NotEqual => "!=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanOrEqual => ">=",
+            Token::LessThan => "<",
+            Token::LessThanOrEqual => "<=",
+            Token::Parameter(_) => "?",
         })
     }
 }
@@ -74,6 +98,61 @@ pub enum Keyword {
     Null,
     Primary,
     Key,
+    Join,
+    Inner,
+    Cross,
+    Natural,
+    Show,
+    Engine,
+    Status,
+    Where,
+    And,
+    Or,
+    Like,
+    AutoIncrement,
+    Index,
+    On,
+    Drop,
+    Truncate,
+    Is,
+    Explain,
+    Analyze,
+    Timestamp,
+    Serial,
+    Between,
+    In,
+    Order,
+    By,
+    Asc,
+    Desc,
+    Cast,
+    As,
+    Begin,
+    Commit,
+    Rollback,
+    Isolation,
+    Level,
+    Read,
+    Committed,
+    Snapshot,
+    Group,
+    Having,
+    With,
+    Recursive,
+    Union,
+    All,
+    Distinct,
+    If,
+    Exists,
+    Intersect,
+    Except,
+    View,
+    Views,
+    Comment,
+    Describe,
+    Debug,
+    Keys,
+    Replace,
 }
 
 impl Keyword {
@@ -102,6 +181,61 @@ impl Keyword {
             "NULL" => Keyword::Null,
             "PRIMARY" => Keyword::Primary,
             "KEY" => Keyword::Key,
+            "JOIN" => Keyword::Join,
+            "INNER" => Keyword::Inner,
+            "CROSS" => Keyword::Cross,
+            "NATURAL" => Keyword::Natural,
+            "SHOW" => Keyword::Show,
+            "ENGINE" => Keyword::Engine,
+            "STATUS" => Keyword::Status,
+            "WHERE" => Keyword::Where,
+            "AND" => Keyword::And,
+            "OR" => Keyword::Or,
+            "LIKE" => Keyword::Like,
+            "AUTO_INCREMENT" => Keyword::AutoIncrement,
+            "INDEX" => Keyword::Index,
+            "ON" => Keyword::On,
+            "DROP" => Keyword::Drop,
+            "TRUNCATE" => Keyword::Truncate,
+            "IS" => Keyword::Is,
+            "EXPLAIN" => Keyword::Explain,
+            "ANALYZE" => Keyword::Analyze,
+            "TIMESTAMP" => Keyword::Timestamp,
+            "SERIAL" => Keyword::Serial,
+            "BETWEEN" => Keyword::Between,
+            "IN" => Keyword::In,
+            "ORDER" => Keyword::Order,
+            "BY" => Keyword::By,
+            "ASC" => Keyword::Asc,
+            "DESC" => Keyword::Desc,
+            "CAST" => Keyword::Cast,
+            "AS" => Keyword::As,
+            "BEGIN" => Keyword::Begin,
+            "COMMIT" => Keyword::Commit,
+            "ROLLBACK" => Keyword::Rollback,
+            "ISOLATION" => Keyword::Isolation,
+            "LEVEL" => Keyword::Level,
+            "READ" => Keyword::Read,
+            "COMMITTED" => Keyword::Committed,
+            "SNAPSHOT" => Keyword::Snapshot,
+            "GROUP" => Keyword::Group,
+            "HAVING" => Keyword::Having,
+            "WITH" => Keyword::With,
+            "RECURSIVE" => Keyword::Recursive,
+            "UNION" => Keyword::Union,
+            "ALL" => Keyword::All,
+            "DISTINCT" => Keyword::Distinct,
+            "IF" => Keyword::If,
+            "EXISTS" => Keyword::Exists,
+            "INTERSECT" => Keyword::Intersect,
+            "EXCEPT" => Keyword::Except,
+            "VIEW" => Keyword::View,
+            "VIEWS" => Keyword::Views,
+            "COMMENT" => Keyword::Comment,
+            "DESCRIBE" => Keyword::Describe,
+            "DEBUG" => Keyword::Debug,
+            "KEYS" => Keyword::Keys,
+            "REPLACE" => Keyword::Replace,
             _ => return None,
         })
     }
@@ -131,6 +265,61 @@ impl Keyword {
             Keyword::Null => "NULL",
             Keyword::Primary => "PRIMARY",
             Keyword::Key => "KEY",
+            Keyword::Join => "JOIN",
+            Keyword::Inner => "INNER",
+            Keyword::Cross => "CROSS",
+            Keyword::Natural => "NATURAL",
+            Keyword::Show => "SHOW",
+            Keyword::Engine => "ENGINE",
+            Keyword::Status => "STATUS",
+            Keyword::Where => "WHERE",
+            Keyword::And => "AND",
+            Keyword::Or => "OR",
+            Keyword::Like => "LIKE",
+            Keyword::AutoIncrement => "AUTO_INCREMENT",
+            Keyword::Index => "INDEX",
+            Keyword::On => "ON",
+            Keyword::Drop => "DROP",
+            Keyword::Truncate => "TRUNCATE",
+            Keyword::Is => "IS",
+            Keyword::Explain => "EXPLAIN",
+            Keyword::Analyze => "ANALYZE",
+            Keyword::Timestamp => "TIMESTAMP",
+            Keyword::Serial => "SERIAL",
+            Keyword::Between => "BETWEEN",
+            Keyword::In => "IN",
+            Keyword::Order => "ORDER",
+            Keyword::By => "BY",
+            Keyword::Asc => "ASC",
+            Keyword::Desc => "DESC",
+            Keyword::Cast => "CAST",
+            Keyword::As => "AS",
+            Keyword::Begin => "BEGIN",
+            Keyword::Commit => "COMMIT",
+            Keyword::Rollback => "ROLLBACK",
+            Keyword::Isolation => "ISOLATION",
+            Keyword::Level => "LEVEL",
+            Keyword::Read => "READ",
+            Keyword::Committed => "COMMITTED",
+            Keyword::Snapshot => "SNAPSHOT",
+            Keyword::Group => "GROUP",
+            Keyword::Having => "HAVING",
+            Keyword::With => "WITH",
+            Keyword::Recursive => "RECURSIVE",
+            Keyword::Union => "UNION",
+            Keyword::All => "ALL",
+            Keyword::Distinct => "DISTINCT",
+            Keyword::If => "IF",
+            Keyword::Exists => "EXISTS",
+            Keyword::Intersect => "INTERSECT",
+            Keyword::Except => "EXCEPT",
+            Keyword::View => "VIEW",
+            Keyword::Views => "VIEWS",
+            Keyword::Comment => "COMMENT",
+            Keyword::Describe => "DESCRIBE",
+            Keyword::Debug => "DEBUG",
+            Keyword::Keys => "KEYS",
+            Keyword::Replace => "REPLACE",
         }
     }
 }
@@ -154,35 +343,120 @@ impl Display for Keyword {
 //    where data_type is:
 //     - BOOLEAN(BOOL): true | false
 //     - FLOAT(DOUBLE)
-//     - INTEGER(INT)
+//     - INTEGER(INT)，SERIAL 为自增的整数，等同于 INTEGER AUTO_INCREMENT
 //     - STRING(TEXT, VARCHAR)
+//     - TIMESTAMP
 //
 //    where column_constraint is:
-//    [ NOT NULL | NULL | DEFAULT expr ]
+//    [ NOT NULL | NULL | DEFAULT expr | PRIMARY KEY | AUTO_INCREMENT | COMMENT 'text' ]
 //
 // 2. Insert Into
 // -------------------------------------
 // INSERT INTO table_name
 // [ ( column_name [, ...] ) ]
-// values ( expr [, ...] );
+// values ( { expr | DEFAULT } [, ...] );
 // 3. Select * From
 // -------------------------------------
-// SELECT * FROM table_name;
+// SELECT * FROM from_item [ WHERE condition ] [ ORDER BY expr [ ASC | DESC ] [, ...] ];
+//
+//    where from_item is:
+//     - table_name
+//     - from_item CROSS JOIN table_name
+//     - from_item NATURAL JOIN table_name
+//     - from_item [ INNER ] JOIN table_name ON condition
+//
+//    where condition is an expression built from:
+//     - [ table_name. ] column_name | constant，Join 两侧同名的列需要用表名区分
+//     - expr ( = | != | <> | > | >= | < | <= ) expr
+//     - expr [ NOT ] LIKE pattern，% 匹配任意字符串，_ 匹配单个字符，\ 转义
+//     - expr [ NOT ] BETWEEN low AND high，包含两端的值
+//     - expr [ NOT ] IN ( expr [, ...] )
+//     - expr AND expr | expr OR expr | NOT expr | ( expr )
+//
+//    ORDER BY 中 NULL 排在最前面，DESC 时排在最后面
+//
+// 4. Create Index / Drop Index
+// -------------------------------------
+// CREATE INDEX index_name ON table_name ( column_name );
+// DROP INDEX index_name;
+//
+// 5. Show Engine Status / Show Views
+// -------------------------------------
+// SHOW ENGINE STATUS;
+// SHOW VIEWS;
+//
+// 6. Truncate Table
+// -------------------------------------
+// TRUNCATE TABLE table_name;
+//
+// DESCRIBE table_name; 输出表中每一列的定义
+//
+// 7. Create View / Drop View
+// -------------------------------------
+// CREATE VIEW view_name AS query;
+// DROP VIEW view_name;
+//
+//    视图只能用于查询，不能插入、清空或建立索引
+//
+// 8. Debug Keys
+// -------------------------------------
+// DEBUG KEYS [ 'prefix' ];
+//
+//    列出存储引擎中所有的 key，不做 MVCC 的可见性判断，用于排查问题
+//    指定 prefix 时只输出解码之后以 prefix 开头的 key，例如 'TxnActive'
+//
+// 预处理语句中可以使用 ? 作为参数占位符，执行时按顺序替换为绑定的值
+//
+// 标识符（表名、列名）可以使用双引号包裹，例如 "select"、"my col"，
+// 此时可以包含空格或者与关键字同名，引号内的 "" 表示一个双引号字符。
+// 未加引号的标识符保持原样，不做大小写转换。
 pub struct Lexer<'a>{
-    iter: Peekable<Chars<'a>>
+    iter: Peekable<Chars<'a>>,
+    // 下一个字符所在的行和列，均从 1 开始
+    line: usize,
+    col: usize,
+    // 已经扫描到的参数占位符个数
+    parameters: usize,
+}
+
+// token 在 SQL 文本中的位置，用于在错误信息中指出出错的地方
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Location {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Location {
+    // 文本末尾的位置
+    pub fn end_of(text: &str) -> Self {
+        match text.rsplit_once('\n') {
+            Some((before, last)) => Self { line: before.matches('\n').count() + 2, col: last.chars().count() + 1 },
+            None => Self { line: 1, col: text.chars().count() + 1 },
+        }
+    }
 }
 
-// 自定义迭代器，通过调用 scan 来扫描每个 token
+impl Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}, col {}", self.line, self.col)
+    }
+}
+
+// 自定义迭代器，通过调用 scan 来扫描每个 token，同时返回 token 开始的位置
 impl<'a> Iterator for Lexer<'a> {
-    type Item = Result<Token>;
+    type Item = Result<(Token, Location)>;
 
     fn next(&mut self) -> Option<Self::Item> {
+        // 首先清除 token 前空白字符
+        self.erase_whitespace();
+        let location = self.location();
         match self.scan() {
-            Ok(Some(token)) => Some(Ok(token)),
+            Ok(Some(token)) => Some(Ok((token, location))),
             Ok(None) => self
             .iter
             .peek()
-            .map(|c| Err(Error::Parse(format!("[Lexer] Unexpeted character {}", c)))),
+            .copied()
+            .map(|c| Err(self.error(format!("Unexpeted character {}", c)))),
             Err(err) => Some(Err(err)),
         }
     }
@@ -192,8 +466,36 @@ impl<'a> Lexer<'a> {
     // 新建一个解析器
     pub fn new(sql_text: &'a str) -> Self {
         Self { 
-            iter: sql_text.chars().peekable() 
+            iter: sql_text.chars().peekable(),
+            line: 1,
+            col: 1,
+            parameters: 0,
+        }
+    }
+
+    fn location(&self) -> Location {
+        Location { line: self.line, col: self.col }
+    }
+
+    // 带有当前位置的错误
+    fn error(&self, msg: String) -> Error {
+        Self::error_at(self.location(), msg)
+    }
+
+    fn error_at(location: Location, msg: String) -> Error {
+        Error::Parse(format!("[Lexer] {}: {}", location, msg))
+    }
+
+    // 取出下一个字符，并更新行列位置
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.iter.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.col = 1;
+        } else {
+            self.col += 1;
         }
+        Some(c)
     }
 
     // 清除空白字符，包含空格，回车等
@@ -204,7 +506,7 @@ impl<'a> Lexer<'a> {
     // 判断下一个字符是否符合条件，符合则返回
     fn next_if<F : Fn(char) -> bool>(&mut self,predict: F) -> Option<char> {
         self.iter.peek().filter(|&c| predict(*c))?;
-        self.iter.next()
+        self.next_char()
     }
 
     // 判断下一个符合条件的字符串
@@ -217,28 +519,31 @@ impl<'a> Lexer<'a> {
     }
 
     // 判断下一个是 token 则返回 token , 用于符号处理
+    // 不是 token 时不消耗字符，以便报告出错的字符
     fn next_if_token<F: Fn(char) -> Option<Token>>(&mut self,predict: F) -> Option<Token> {
-        let mut val = self.iter.peek().and_then(|&c| predict(c));
-        self.iter.next();
-        val
+        let val = self.iter.peek().and_then(|&c| predict(c))?;
+        self.next_char();
+        Some(val)
     }
 
     // 扫描拿到下一个 token
     fn scan(&mut self) -> Result<Option<Token>> {
-        // 首先清除 token 前空白字符
-        self.erase_whitespace();
-
-        match self.iter.peek() {
+        match self.iter.peek().copied() {
             Some('\'') => self.scan_string(),
+            Some('"') => self.scan_quoted_ident(),
             Some(c) if c.is_ascii_digit() => Ok(self.scan_num()),
+            // 小数点之后紧跟数字时是省略了整数部分的小数，例如 .5，否则是 表名.列名 中的句点
+            Some('.') if self.iter.clone().nth(1).is_some_and(|c| c.is_ascii_digit()) => Ok(self.scan_num()),
             Some(c) if c.is_alphabetic() => Ok(self.scan_ident()),
-            Some(_) => Ok(self.scan_symbol()),
+            Some(_) => self.scan_symbol(),
             None => Ok(None),
         }
     }
 
     // 扫描带引号字符串
     fn scan_string(&mut self) -> Result<Option<Token>> {
+        // 未结束的字符串报告开始的位置
+        let start = self.location();
         // 判断是否是但引号开头
         if self.next_if(|c| c == '\'').is_none() {
             return Ok(None);
@@ -247,24 +552,48 @@ impl<'a> Lexer<'a> {
         let mut val = String::new();
         // 循环迭代下一个字符
         loop {
-            match self.iter.next() {
+            match self.next_char() {
                 Some('\'') => break,
                 Some(c) => val.push(c),
-                None => return Err(Error::Parse(format!("[Lexer] Unexpected end of string"))),
+                None => return Err(Self::error_at(start, "Unexpected end of string".to_string())),
             }
         }
         // 判断字符非空
         if val.is_empty() {
-            return Err(Error::Parse(format!("[Lexer] Unexpected end of string")));
+            return Err(self.error("Unexpected end of string".to_string()));
         }
 
         Ok(Some(Token::String(val)))
     }
 
-    // 扫描数字
+    // 扫描双引号包裹的标识符
+    fn scan_quoted_ident(&mut self) -> Result<Option<Token>> {
+        let start = self.location();
+        if self.next_if(|c| c == '"').is_none() {
+            return Ok(None);
+        }
+
+        let mut val = String::new();
+        loop {
+            match self.next_char() {
+                // 连续两个双引号表示转义后的双引号
+                Some('"') if self.next_if(|c| c == '"').is_some() => val.push('"'),
+                Some('"') => break,
+                Some(c) => val.push(c),
+                None => return Err(Self::error_at(start, "Unexpected end of quoted identifier".to_string())),
+            }
+        }
+        if val.is_empty() {
+            return Err(self.error("Empty quoted identifier".to_string()));
+        }
+
+        Ok(Some(Token::Ident(val)))
+    }
+
+    // 扫描数字，支持 1、1.5、.5、1.、1e10、1.5E-3 这些形式
     fn scan_num(&mut self) -> Option<Token> {
-        // 先扫描一部分
-        let mut val = self.next_while(|c| c.is_ascii_digit())?;
+        // 先扫描整数部分，以小数点开头时整数部分为空
+        let mut val = self.next_while(|c| c.is_ascii_digit()).unwrap_or_default();
         // 判断是否有小数点
         if let Some(sep) = self.next_if(|c| c== '.') {
             val.push(sep);
@@ -272,32 +601,75 @@ impl<'a> Lexer<'a> {
                 val.push(c);
             }
         }
+        // 指数部分，e 之后可以有正负号，至少需要一位数字，否则 e 不属于这个数字
+        let mut ahead = self.iter.clone();
+        if ahead.next().is_some_and(|c| c == 'e' || c == 'E') {
+            if ahead.peek().is_some_and(|&c| c == '+' || c == '-') {
+                ahead.next();
+            }
+            if ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                val.push(self.next_char()?);
+                if let Some(sign) = self.next_if(|c| c == '+' || c == '-') {
+                    val.push(sign);
+                }
+                while let Some(c) = self.next_if(|c| c.is_ascii_digit()) {
+                    val.push(c);
+                }
+            }
+        }
         Some(Token::Number(val))
     }
 
-    // 扫描标识符
+    // 扫描标识符，没有引号的标识符不区分大小写，统一转换为小写
+    // 需要保留大小写时使用双引号，例如大小写不敏感之前创建的表 "Foo"
     fn scan_ident(&mut self) -> Option<Token> {
         // 需要以字符开头
         let mut val = self.next_if(|c| c.is_alphabetic())?.to_string();
         while let Some(c) = self.next_if(|c| c.is_alphanumeric() || c == '_') {
             val.push(c);
         }
-        Some(Keyword::from_str(&val).map_or( Token::Ident(val), |v| Token::Keyword(v)))
+        Some(Keyword::from_str(&val).map_or_else(|| Token::Ident(val.to_lowercase()), Token::Keyword))
     }
 
     // 扫描符号
-    fn scan_symbol(&mut self) -> Option<Token> {
-        self.next_if_token(|c| match c {
+    fn scan_symbol(&mut self) -> Result<Option<Token>> {
+        if self.next_if(|c| c == '?').is_some() {
+            self.parameters += 1;
+            return Ok(Some(Token::Parameter(self.parameters - 1)));
+        }
+        // 由两个字符组成的比较符号
+        if self.next_if(|c| c == '!').is_some() {
+            return match self.next_if(|c| c == '=') {
+                Some(_) => Ok(Some(Token::NotEqual)),
+                None => Err(self.error("Unexpeted character !".to_string())),
+            };
+        }
+        if self.next_if(|c| c == '>').is_some() {
+            return Ok(Some(match self.next_if(|c| c == '=') {
+                Some(_) => Token::GreaterThanOrEqual,
+                None => Token::GreaterThan,
+            }));
+        }
+        if self.next_if(|c| c == '<').is_some() {
+            return Ok(Some(match self.next_if(|c| c == '=' || c == '>') {
+                Some('=') => Token::LessThanOrEqual,
+                Some(_) => Token::NotEqual,
+                None => Token::LessThan,
+            }));
+        }
+        Ok(self.next_if_token(|c| match c {
+            '=' => Some(Token::Equal),
             '*' => Some(Token::Asterisk),
             '(' => Some(Token::OpenParen),
             ')' => Some(Token::CloseParen),
             ',' => Some(Token::Comma),
             ';' => Some(Token::Semicolon),
+            '.' => Some(Token::Period),
             '+' => Some(Token::Plus),
             '-' => Some(Token::Minus),
             '/' => Some(Token::Slash),
             _ => None,
-        })
+        }))
     }
 }
 
@@ -307,7 +679,7 @@ mod tests {
 
     use super::Lexer;
     use crate::{
-        error::Result,
+        error::{Error, Result},
         sql::parser::lexer::{Keyword, Token},
     };
 
@@ -322,6 +694,7 @@ mod tests {
                 ",
         )
         .peekable()
+        .map(|r| r.map(|(token, _)| token))
         .collect::<Result<Vec<_>>>()?;
 
         assert_eq!(
@@ -361,9 +734,10 @@ mod tests {
                         ",
         )
         .peekable()
+        .map(|r| r.map(|(token, _)| token))
         .collect::<Result<Vec<_>>>()?;
 
-        assert!(tokens2.len() > 0);
+        assert!(!tokens2.is_empty());
 
         Ok(())
     }
@@ -372,6 +746,7 @@ mod tests {
     fn test_lexer_insert_into() -> Result<()> {
         let tokens1 = Lexer::new("insert into tbl values (1, 2, '3', true, false, 4.55);")
             .peekable()
+            .map(|r| r.map(|(token, _)| token))
             .collect::<Result<Vec<_>>>()?;
 
         assert_eq!(
@@ -400,6 +775,7 @@ mod tests {
 
         let tokens2 = Lexer::new("INSERT INTO       tbl (id, name, age) values (100, 'db', 10);")
             .peekable()
+            .map(|r| r.map(|(token, _)| token))
             .collect::<Result<Vec<_>>>()?;
 
         assert_eq!(
@@ -429,10 +805,70 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_lexer_compare() -> Result<()> {
+        let tokens = Lexer::new("a = b != c <> d > e >= f < g <= h")
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("a".to_string()),
+                Token::Equal,
+                Token::Ident("b".to_string()),
+                Token::NotEqual,
+                Token::Ident("c".to_string()),
+                Token::NotEqual,
+                Token::Ident("d".to_string()),
+                Token::GreaterThan,
+                Token::Ident("e".to_string()),
+                Token::GreaterThanOrEqual,
+                Token::Ident("f".to_string()),
+                Token::LessThan,
+                Token::Ident("g".to_string()),
+                Token::LessThanOrEqual,
+                Token::Ident("h".to_string()),
+            ]
+        );
+
+        // 无法识别的字符报错，而不是被跳过
+        assert!(Lexer::new("a ! b").map(|r| r.map(|(token, _)| token)).collect::<Result<Vec<_>>>().is_err());
+        assert!(Lexer::new("a # b").map(|r| r.map(|(token, _)| token)).collect::<Result<Vec<_>>>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_number() -> Result<()> {
+        let tokens = Lexer::new("1 1.5 .5 1. 1e10 1.5E-3 2e+2 1e a.b")
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<Result<Vec<_>>>()?;
+        let num = |n: &str| Token::Number(n.to_string());
+        assert_eq!(
+            tokens,
+            vec![
+                num("1"),
+                num("1.5"),
+                num(".5"),
+                num("1."),
+                num("1e10"),
+                num("1.5E-3"),
+                num("2e+2"),
+                // e 之后没有数字时不属于指数
+                num("1"),
+                Token::Ident("e".to_string()),
+                Token::Ident("a".to_string()),
+                Token::Period,
+                Token::Ident("b".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_lexer_select() -> Result<()> {
         let tokens1 = Lexer::new("select * from tbl;")
             .peekable()
+            .map(|r| r.map(|(token, _)| token))
             .collect::<Result<Vec<_>>>()?;
 
         assert_eq!(
@@ -445,6 +881,76 @@ mod tests {
                 Token::Semicolon,
             ]
         );
+
+        let tokens2 = Lexer::new("select * from a natural join b;")
+            .peekable()
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tokens2,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Asterisk,
+                Token::Keyword(Keyword::From),
+                Token::Ident("a".to_string()),
+                Token::Keyword(Keyword::Natural),
+                Token::Keyword(Keyword::Join),
+                Token::Ident("b".to_string()),
+                Token::Semicolon,
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lexer_quoted_ident() -> Result<()> {
+        let tokens = Lexer::new(r#"select * from "select" "my col" "a""b";"#)
+            .peekable()
+            .map(|r| r.map(|(token, _)| token))
+            .collect::<Result<Vec<_>>>()?;
+
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Keyword(Keyword::Select),
+                Token::Asterisk,
+                Token::Keyword(Keyword::From),
+                Token::Ident("select".to_string()),
+                Token::Ident("my col".to_string()),
+                Token::Ident("a\"b".to_string()),
+                Token::Semicolon,
+            ]
+        );
+
+        // 没有引号的标识符转换为小写，有引号的保留原样
+        let tokens = Lexer::new(r#"Foo "Foo" BAR"#).map(|r| r.map(|(token, _)| token)).collect::<Result<Vec<_>>>()?;
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Ident("foo".to_string()),
+                Token::Ident("Foo".to_string()),
+                Token::Ident("bar".to_string()),
+            ]
+        );
+
+        assert!(Lexer::new("\"unterminated").map(|r| r.map(|(token, _)| token)).collect::<Result<Vec<_>>>().is_err());
+        assert!(Lexer::new("\"\"").map(|r| r.map(|(token, _)| token)).collect::<Result<Vec<_>>>().is_err());
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_lexer_location() -> Result<()> {
+        let locations = Lexer::new("select *\n  from 'a\nb' t;")
+            .map(|r| r.map(|(_, location)| (location.line, location.col)))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(locations, vec![(1, 1), (1, 8), (2, 3), (2, 8), (3, 4), (3, 5)]);
+
+        let err = Lexer::new("select\n  a # b").collect::<Result<Vec<_>>>().unwrap_err();
+        assert_eq!(err, Error::Parse("[Lexer] line 2, col 5: Unexpeted character #".to_string()));
+
+        // 未结束的字符串报告开始的位置
+        let err = Lexer::new("select\n 'abc\n\n").collect::<Result<Vec<_>>>().unwrap_err();
+        assert_eq!(err, Error::Parse("[Lexer] line 2, col 2: Unexpected end of string".to_string()));
+        Ok(())
+    }
+}
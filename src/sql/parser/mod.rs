@@ -1,35 +1,61 @@
-use std::iter::Peekable;
+use std::{fmt::Display, iter::Peekable};
 
-use ast::{Column, Expression, Statement};
-use lexer::{Lexer, Token, Keyword};
+use ast::{Column, Expression, FromItem, JoinType, Operation, OrderDirection, SetOperator, Statement};
+use lexer::{Keyword, Lexer, Location, Token};
 
-use crate::error::{Result, Error};
+use crate::{error::{Result, Error}, storage::mvcc::IsolationLevel};
 
-use super::types::DataType;
+use super::types::{timestamp::parse_timestamp, DataType};
 
 mod lexer;
 pub mod ast;
 
+// 比较运算符的优先级
+const COMPARE_PREC: u8 = 4;
+
+// 表达式和集合运算嵌套的最大深度，括号、NOT、比较运算的嵌套以及连续的集合运算都会增加深度
+// 避免构造的输入在递归下降解析、以及之后递归处理语法树时栈溢出
+// 连续的 AND、OR 构造成平衡的语法树，不计入深度，很长的条件列表可以正常解析
+const MAX_DEPTH: usize = 128;
+
 // 解析器，拿到词法分析的结果进行语法分析，最终生成抽象语法树。
 pub struct Parser<'a> {
     lexer: Peekable<Lexer<'a>>,
+    // 最近一个取出的 token 的位置，错误信息中报告这个位置
+    location: Location,
+    // 输入末尾的位置
+    end: Location,
+    // 语句中参数占位符的个数
+    parameters: usize,
+    // 当前表达式的嵌套深度
+    depth: usize,
 }
 
 impl<'a> Parser<'a> {
     pub fn new(input: &'a str) -> Self {
         Self{
-            lexer: Lexer::new(input).peekable()
+            lexer: Lexer::new(input).peekable(),
+            location: Location { line: 1, col: 1 },
+            end: Location::end_of(input),
+            parameters: 0,
+            depth: 0,
         }
     }
 
+    // 已经解析的语句中参数占位符的个数
+    pub fn parameters(&self) -> usize {
+        self.parameters
+    }
+
     // 解析
     pub fn parse(&mut self) -> Result<Statement> {
         let stmt = self.parse_statement()?;
         // 希望以分号结尾
         self.next_expect(Token::Semicolon)?;
         // 分号之后不再有内容
-        if let Some(token) = self.peek()? {
-            return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)));
+        if self.peek()?.is_some() {
+            let token = self.next()?;
+            return Err(self.error(format!("Unexpected token {}", token)));
         }
         Ok(stmt)
     }
@@ -38,10 +64,32 @@ impl<'a> Parser<'a> {
         // 查看第一个字符
         match self.peek()? {
             Some(Token::Keyword(Keyword::Create)) => self.parse_ddl(),
-            Some(Token::Keyword(Keyword::Select)) => self.parse_select(),
+            Some(Token::Keyword(Keyword::Select)) => self.parse_query(),
+            Some(Token::Keyword(Keyword::With)) => self.parse_with(),
             Some(Token::Keyword(Keyword::Insert)) => self.parse_insert(),
-            Some(t) => Err(Error::Parse(format!("[Parser] Unexpected token {}", t))),
-            None => Err(Error::Parse(format!("[Parser] Unexpected end of input"))),
+            Some(Token::Keyword(Keyword::Show)) => self.parse_show(),
+            Some(Token::Keyword(Keyword::Drop)) => self.parse_drop(),
+            Some(Token::Keyword(Keyword::Truncate)) => self.parse_truncate(),
+            Some(Token::Keyword(Keyword::Explain)) => self.parse_explain(),
+            Some(Token::Keyword(Keyword::Analyze)) => self.parse_analyze(),
+            Some(Token::Keyword(Keyword::Describe)) => {
+                self.next()?;
+                Ok(Statement::Describe { table_name: self.next_ident()? })
+            },
+            Some(Token::Keyword(Keyword::Debug)) => self.parse_debug(),
+            Some(Token::Keyword(Keyword::Begin)) => self.parse_begin(),
+            Some(Token::Keyword(Keyword::Commit)) => {
+                self.next()?;
+                Ok(Statement::Commit)
+            },
+            Some(Token::Keyword(Keyword::Rollback)) => {
+                self.next()?;
+                Ok(Statement::Rollback)
+            },
+            _ => {
+                let token = self.next()?;
+                Err(self.error(format!("Unexpected token {}", token)))
+            }
         }
     }
 
@@ -51,20 +99,308 @@ impl<'a> Parser<'a> {
             // 期望是 Create 关键字
             Token::Keyword(Keyword::Create) => match self.next()? {
                 // Create 关键字之后应该是 Table 关键字
-                Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(),
-                token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+                Token::Keyword(Keyword::Table) => self.parse_ddl_create_table(false),
+                Token::Keyword(Keyword::Or) => {
+                    self.next_expect(Token::Keyword(Keyword::Replace))?;
+                    self.next_expect(Token::Keyword(Keyword::Table))?;
+                    self.parse_ddl_create_table(true)
+                }
+                Token::Keyword(Keyword::Index) => self.parse_ddl_create_index(),
+                Token::Keyword(Keyword::View) => self.parse_ddl_create_view(),
+                token => Err(self.error(format!("Unexpected token {}", token))),
             },
-            token => Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
+            token => Err(self.error(format!("Unexpected token {}", token))),
         }
     }
 
-    // 解析 Select 语句
+    // 解析 Explain [Analyze] 语句，不支持嵌套的 Explain
+    fn parse_explain(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Explain))?;
+        let analyze = self.next_if_token(Token::Keyword(Keyword::Analyze)).is_some();
+        if let Some(Token::Keyword(Keyword::Explain)) = self.peek()? {
+            return Err(self.error("Cannot explain an explain statement"));
+        }
+        let stmt = Box::new(self.parse_statement()?);
+        Ok(if analyze { Statement::ExplainAnalyze(stmt) } else { Statement::Explain(stmt) })
+    }
+
+    // 解析 Begin 语句，没有指定隔离级别时使用快照隔离
+    // BEGIN [ISOLATION LEVEL {READ COMMITTED | SNAPSHOT}]
+    fn parse_begin(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Begin))?;
+        let mut isolation = IsolationLevel::Snapshot;
+        if self.next_if_token(Token::Keyword(Keyword::Isolation)).is_some() {
+            self.next_expect(Token::Keyword(Keyword::Level))?;
+            isolation = match self.next()? {
+                Token::Keyword(Keyword::Read) => {
+                    self.next_expect(Token::Keyword(Keyword::Committed))?;
+                    IsolationLevel::ReadCommitted
+                }
+                Token::Keyword(Keyword::Snapshot) => IsolationLevel::Snapshot,
+                token => return Err(self.error(format!("Unexpected isolation level {}", token))),
+            };
+        }
+        Ok(Statement::Begin { isolation })
+    }
+
+    // 解析 Drop 语句
+    fn parse_drop(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Drop))?;
+        match self.next()? {
+            Token::Keyword(Keyword::Index) => Ok(Statement::DropIndex { index_name: self.next_ident()? }),
+            Token::Keyword(Keyword::View) => Ok(Statement::DropView { name: self.next_ident()? }),
+            token => Err(self.error(format!("Unexpected token {}", token))),
+        }
+    }
+
+    // 解析 Create View 语句，视图的定义只能是查询，不支持 WITH
+    fn parse_ddl_create_view(&mut self) -> Result<Statement> {
+        let name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::As))?;
+        Ok(Statement::CreateView { name, query: Box::new(self.parse_query()?) })
+    }
+
+    // 解析 Truncate Table 语句
+    fn parse_truncate(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Truncate))?;
+        self.next_expect(Token::Keyword(Keyword::Table))?;
+        Ok(Statement::TruncateTable { table_name: self.next_ident()? })
+    }
+
+    // 解析 Analyze 语句
+    fn parse_analyze(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Analyze))?;
+        Ok(Statement::AnalyzeTable { table_name: self.next_ident()? })
+    }
+
+    // 解析 Create Index 语句
+    fn parse_ddl_create_index(&mut self) -> Result<Statement> {
+        let index_name = self.next_ident()?;
+        self.next_expect(Token::Keyword(Keyword::On))?;
+        let table_name = self.next_ident()?;
+        self.next_expect(Token::OpenParen)?;
+        let column_name = self.next_ident()?;
+        self.next_expect(Token::CloseParen)?;
+        Ok(Statement::CreateIndex { index_name, table_name, column_name })
+    }
+
+    // 解析 Select 语句，SELECT 之后可以有 DISTINCT
     fn parse_select(&mut self) -> Result<Statement> {
         self.next_expect(Token::Keyword(Keyword::Select))?;
-        self.next_expect(Token::Asterisk)?;
+        let distinct = self.next_if_token(Token::Keyword(Keyword::Distinct)).is_some();
+        let select = self.parse_select_list()?;
         self.next_expect(Token::Keyword(Keyword::From))?;
-        let table_name = self.next_ident()?;
-        Ok(Statement::Select { table_name })
+        let from = self.parse_from_item()?;
+        let where_clause = if self.next_if_token(Token::Keyword(Keyword::Where)).is_some() {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        let mut group_by = Vec::new();
+        if self.next_if_token(Token::Keyword(Keyword::Group)).is_some() {
+            self.next_expect(Token::Keyword(Keyword::By))?;
+            loop {
+                group_by.push(self.parse_expression()?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+        }
+        let having = if self.next_if_token(Token::Keyword(Keyword::Having)).is_some() {
+            Some(self.parse_expression()?)
+        } else {
+            None
+        };
+        let order_by = self.parse_order_by()?;
+        Ok(Statement::Select { distinct, select, from, where_clause, group_by, having, order_by })
+    }
+
+    // 解析 With 语句，之后必须是查询语句
+    // WITH [RECURSIVE] name AS (query) [, name AS (query)] ... SELECT ...
+    fn parse_with(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::With))?;
+        let recursive = self.next_if_token(Token::Keyword(Keyword::Recursive)).is_some();
+        let mut ctes = Vec::new();
+        loop {
+            let name = self.next_ident()?;
+            self.next_expect(Token::Keyword(Keyword::As))?;
+            self.next_expect(Token::OpenParen)?;
+            let query = self.parse_query()?;
+            self.next_expect(Token::CloseParen)?;
+            ctes.push((name, Box::new(query)));
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        let query = Box::new(self.parse_query()?);
+        Ok(Statement::With { recursive, ctes, query })
+    }
+
+    // 解析查询，多个 Select 语句之间可以用 UNION、INTERSECT、EXCEPT 连接
+    // INTERSECT 的优先级高于 UNION 和 EXCEPT，相同优先级的运算从左往右结合
+    fn parse_query(&mut self) -> Result<Statement> {
+        let depth = self.depth;
+        let result = self.parse_set_operation();
+        self.depth = depth;
+        result
+    }
+
+    fn parse_set_operation(&mut self) -> Result<Statement> {
+        let mut stmt = self.parse_intersect()?;
+        loop {
+            let op = match self.peek()? {
+                Some(Token::Keyword(Keyword::Union)) => SetOperator::Union,
+                Some(Token::Keyword(Keyword::Except)) => SetOperator::Except,
+                _ => return Ok(stmt),
+            };
+            self.next()?;
+            self.descend()?;
+            let all = self.parse_set_quantifier();
+            stmt = Statement::SetOperation { op, left: Box::new(stmt), right: Box::new(self.parse_intersect()?), all };
+        }
+    }
+
+    fn parse_intersect(&mut self) -> Result<Statement> {
+        let mut stmt = self.parse_select()?;
+        while self.next_if_token(Token::Keyword(Keyword::Intersect)).is_some() {
+            self.descend()?;
+            let all = self.parse_set_quantifier();
+            stmt = Statement::SetOperation {
+                op: SetOperator::Intersect,
+                left: Box::new(stmt),
+                right: Box::new(self.parse_select()?),
+                all,
+            };
+        }
+        Ok(stmt)
+    }
+
+    // 集合运算之后的 ALL 或 DISTINCT，省略时为 DISTINCT
+    fn parse_set_quantifier(&mut self) -> bool {
+        if self.next_if_token(Token::Keyword(Keyword::All)).is_some() {
+            return true;
+        }
+        self.next_if_token(Token::Keyword(Keyword::Distinct));
+        false
+    }
+
+    // 解析 SELECT 之后输出的表达式，每个表达式可以用 AS 指定别名，* 表示输出所有的列
+    fn parse_select_list(&mut self) -> Result<Vec<(Expression, Option<String>)>> {
+        let mut select = Vec::new();
+        if self.next_if_token(Token::Asterisk).is_some() {
+            return Ok(select);
+        }
+        loop {
+            let expr = self.parse_expression()?;
+            let alias = match self.next_if_token(Token::Keyword(Keyword::As)) {
+                Some(_) => Some(self.next_ident()?),
+                None => None,
+            };
+            select.push((expr, alias));
+            if self.next_if_token(Token::Comma).is_none() {
+                return Ok(select);
+            }
+        }
+    }
+
+    // 解析 Order By 子句，没有指定方向时为升序
+    fn parse_order_by(&mut self) -> Result<Vec<(Expression, OrderDirection)>> {
+        let mut order_by = Vec::new();
+        if self.next_if_token(Token::Keyword(Keyword::Order)).is_none() {
+            return Ok(order_by);
+        }
+        self.next_expect(Token::Keyword(Keyword::By))?;
+        loop {
+            let expr = self.parse_expression()?;
+            let direction = if self.next_if_token(Token::Keyword(Keyword::Desc)).is_some() {
+                OrderDirection::Desc
+            } else {
+                self.next_if_token(Token::Keyword(Keyword::Asc));
+                OrderDirection::Asc
+            };
+            order_by.push((expr, direction));
+            if self.next_if_token(Token::Comma).is_none() {
+                return Ok(order_by);
+            }
+        }
+    }
+
+    // 解析 Show 语句
+    fn parse_show(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Show))?;
+        if self.next_if_token(Token::Keyword(Keyword::Views)).is_some() {
+            return Ok(Statement::ShowViews);
+        }
+        self.next_expect(Token::Keyword(Keyword::Engine))?;
+        self.next_expect(Token::Keyword(Keyword::Status))?;
+        Ok(Statement::ShowEngineStatus)
+    }
+
+    // 解析 Debug Keys 语句
+    fn parse_debug(&mut self) -> Result<Statement> {
+        self.next_expect(Token::Keyword(Keyword::Debug))?;
+        self.next_expect(Token::Keyword(Keyword::Keys))?;
+        let prefix = match self.next_if(|t| matches!(t, Token::String(_))) {
+            Some(Token::String(prefix)) => Some(prefix),
+            _ => None,
+        };
+        Ok(Statement::DebugKeys { prefix })
+    }
+
+    // 解析 From 子句，多个 Join 从左往右结合
+    fn parse_from_item(&mut self) -> Result<FromItem> {
+        let mut item = self.parse_table()?;
+        while let Some((join_type, right)) = self.parse_join()? {
+            item = FromItem::Join {
+                left: Box::new(item),
+                right: Box::new(right),
+                join_type,
+            };
+        }
+        Ok(item)
+    }
+
+    // 解析一个 Join 子句，返回连接类型和右表，不是 Join 则返回 None
+    // 没有指定连接类型时为 Inner Join，需要在表名之后给出 ON 条件
+    fn parse_join(&mut self) -> Result<Option<(JoinType, FromItem)>> {
+        let join_type = if self.next_if_token(Token::Keyword(Keyword::Cross)).is_some() {
+            Some(JoinType::Cross)
+        } else if self.next_if_token(Token::Keyword(Keyword::Natural)).is_some() {
+            Some(JoinType::Natural)
+        } else if self.next_if_token(Token::Keyword(Keyword::Inner)).is_some()
+            || self.peek()? == Some(Token::Keyword(Keyword::Join))
+        {
+            None
+        } else {
+            return Ok(None);
+        };
+        self.next_expect(Token::Keyword(Keyword::Join))?;
+        let right = self.parse_table()?;
+        let join_type = match join_type {
+            Some(join_type) => join_type,
+            None => {
+                self.next_expect(Token::Keyword(Keyword::On))?;
+                JoinType::Inner(self.parse_expression()?)
+            }
+        };
+        Ok(Some((join_type, right)))
+    }
+
+    // 解析表名和可选的别名，别名之前的 AS 可以省略
+    fn parse_table(&mut self) -> Result<FromItem> {
+        let name = self.next_ident()?;
+        let alias = if self.next_if_token(Token::Keyword(Keyword::As)).is_some() {
+            Some(self.next_ident()?)
+        } else {
+            match self.next_if(|t| matches!(t, Token::Ident(_))) {
+                Some(Token::Ident(alias)) => Some(alias),
+                _ => None,
+            }
+        };
+        Ok(match alias {
+            Some(alias) => FromItem::AliasedTable { name, alias },
+            None => FromItem::Table { name },
+        })
     }
 
 
@@ -74,6 +410,10 @@ impl<'a> Parser<'a> {
         // 表名
         let table_name = self.next_ident()?;
         let columns = if self.next_if_token(Token::OpenParen).is_some() {
+            // 空的列名列表没有意义，不能当作省略列名处理
+            if self.next_if_token(Token::CloseParen).is_some() {
+                return Err(self.error("Empty column list, expected at least one column name"));
+            }
             let mut column = Vec::new();
             loop{
                 column.push(self.next_ident()?);
@@ -81,7 +421,7 @@ impl<'a> Parser<'a> {
                     Token::CloseParen => break,
                     Token::Comma => {},
                     token => {
-                        return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)));
+                        return Err(self.error(format!("Unexpected token {}", token)));
                     }
                 }
             }
@@ -94,14 +434,23 @@ impl<'a> Parser<'a> {
         let mut values = Vec::new();
         loop{
             self.next_expect(Token::OpenParen)?;
+            // 每一行至少有一个值，全部使用默认值时需要写出 DEFAULT
+            if self.next_if_token(Token::CloseParen).is_some() {
+                return Err(self.error("Empty VALUES row, expected at least one value"));
+            }
             let mut value = Vec::new();
             loop {
-                value.push(self.parse_expression()?);
+                // DEFAULT 只能单独出现在 VALUES 中，表示使用列的默认值
+                if self.next_if_token(Token::Keyword(Keyword::Default)).is_some() {
+                    value.push(Expression::Default);
+                } else {
+                    value.push(self.parse_expression()?);
+                }
                 match self.next()? {
                     Token::CloseParen => break,
                     Token::Comma => {},
                     token => {
-                        return Err(Error::Parse(format!("[Parser] Unexpected token {}", token)));
+                        return Err(self.error(format!("Unexpected token {}", token)));
                     }
                 }
             }
@@ -117,43 +466,90 @@ impl<'a> Parser<'a> {
     }
 
     // 解析 Crate 的 ddl 语句
-    fn parse_ddl_create_table(&mut self) -> Result<Statement> {
+    // CREATE [OR REPLACE] TABLE [IF NOT EXISTS] name (column, ... [, PRIMARY KEY (column, ...)])
+    // OR REPLACE 和 IF NOT EXISTS 对已经存在的表的处理相反，不能同时使用
+    fn parse_ddl_create_table(&mut self, or_replace: bool) -> Result<Statement> {
+        let if_not_exists = self.next_if_token(Token::Keyword(Keyword::If)).is_some();
+        if if_not_exists && or_replace {
+            return Err(self.error("OR REPLACE cannot be used with IF NOT EXISTS"));
+        }
+        if if_not_exists {
+            self.next_expect(Token::Keyword(Keyword::Not))?;
+            self.next_expect(Token::Keyword(Keyword::Exists))?;
+        }
         // 期望是表名
         let table_name = self.next_ident()?;
 
         // 表名之后是左括号
         self.next_expect(Token::OpenParen)?;
 
-        // 解析列
+        // 解析列，表级的主键约束只能出现在所有列之后
         let mut columns = Vec::new();
+        let mut primary_key = Vec::new();
         loop{
+            if self.next_if_token(Token::Keyword(Keyword::Primary)).is_some() {
+                primary_key = self.parse_primary_key()?;
+                break;
+            }
             columns.push(self.parse_ddl_column()?);
             if self.next_if_token(Token::Comma).is_none() {
                 break;
             }
         }
+        if columns.is_empty() {
+            return Err(self.error("Expected at least one column before PRIMARY KEY"));
+        }
         // 右括号
         self.next_expect(Token::CloseParen)?;
         Ok(Statement::CreateTable { 
             name: table_name, 
-            columns: columns 
+            columns,
+            if_not_exists,
+            or_replace,
+            primary_key,
         })
     }
 
+    // 解析 PRIMARY 之后的 KEY (column, ...)
+    fn parse_primary_key(&mut self) -> Result<Vec<String>> {
+        self.next_expect(Token::Keyword(Keyword::Key))?;
+        self.next_expect(Token::OpenParen)?;
+        let mut names = Vec::new();
+        loop {
+            names.push(self.next_ident()?);
+            if self.next_if_token(Token::Comma).is_none() {
+                break;
+            }
+        }
+        self.next_expect(Token::CloseParen)?;
+        Ok(names)
+    }
+
     // 解析列
     fn parse_ddl_column(&mut self) -> Result<Column> {
+        let name = self.next_ident()?;
+        let token = self.next()?;
         let mut column = Column{
-            name: self.next_ident()?,
-            datatype: match self.next()? {
-                Token::Keyword(Keyword::Bool) | Token::Keyword(Keyword::Boolean) => DataType::Boolean,
-                Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) => DataType::Integer,
-                Token::Keyword(Keyword::Float) | Token::Keyword(Keyword::Double) => DataType::Float,
-                Token::Keyword(Keyword::String) | Token::Keyword(Keyword::Text) | Token::Keyword(Keyword::Varchar) => DataType::String,
-                token => return Err(Error::Parse(format!("[Parser] Unexpected token {}", token))),
-            },
+            name,
+            datatype: self.datatype(&token)?,
             nullable: None,
             default: None,
+            primary_key: false,
+            auto_increment: token == Token::Keyword(Keyword::Serial),
+            max_len: None,
+            comment: None,
         };
+        // VARCHAR(n) 限制字符串的最大长度
+        if column.datatype == DataType::String && self.next_if_token(Token::OpenParen).is_some() {
+            column.max_len = match self.next()? {
+                Token::Number(n) => match n.parse::<usize>() {
+                    Ok(len) if len > 0 => Some(len),
+                    _ => return Err(self.error(format!("Invalid string length {}", n))),
+                },
+                token => return Err(self.error(format!("Unexpected token {}", token))),
+            };
+            self.next_expect(Token::CloseParen)?;
+        }
         // 判断下一个是否是关键字
         while let Some(Token::Keyword(keyword)) = self.next_if_keyword() {
             match keyword {
@@ -162,8 +558,18 @@ impl<'a> Parser<'a> {
                     self.next_expect(Token::Keyword(Keyword::Null))?;
                     column.nullable = Some(false)
                 }
-                Keyword::Default => column.default = Some(self.parse_expression()?),
-                k => return Err(Error::Parse(format!("[Parser] Unexpected keyword {}", k))),
+                // 默认值中不能出现比较运算，否则会和后面的 NOT NULL 产生歧义
+                Keyword::Default => column.default = Some(self.parse_expression_with(COMPARE_PREC + 1)?),
+                Keyword::Primary => {
+                    self.next_expect(Token::Keyword(Keyword::Key))?;
+                    column.primary_key = true;
+                }
+                Keyword::AutoIncrement => column.auto_increment = true,
+                Keyword::Comment => match self.next()? {
+                    Token::String(comment) => column.comment = Some(comment),
+                    token => return Err(self.error(format!("Expected comment string, got token {}", token))),
+                },
+                k => return Err(self.error(format!("Unexpected keyword {}", k))),
             }
         }
 
@@ -172,40 +578,286 @@ impl<'a> Parser<'a> {
 
     // 解析表达式
     fn parse_expression(&mut self) -> Result<Expression> {
+        self.parse_expression_with(0)
+    }
+
+    // 按照运算符优先级解析表达式，只合并优先级不低于 min_prec 的二元运算
+    // 优先级从低到高为：OR、AND、NOT、比较运算
+    fn parse_expression_with(&mut self, min_prec: u8) -> Result<Expression> {
+        let depth = self.depth;
+        let result = self.parse_binary_expression(min_prec);
+        self.depth = depth;
+        result
+    }
+
+    fn parse_binary_expression(&mut self, min_prec: u8) -> Result<Expression> {
+        self.descend()?;
+        let mut lhs = if self.next_if_token(Token::Keyword(Keyword::Not)).is_some() {
+            Operation::Not(Box::new(self.parse_expression_with(COMPARE_PREC - 1)?)).into()
+        } else {
+            self.parse_expression_atom()?
+        };
+        while let Some(prec) = self.peek()?.as_ref().and_then(Self::binary_prec) {
+            if prec < min_prec {
+                break;
+            }
+            let op = self.next()?;
+            // 连续的 AND 或 OR 先收集所有的操作数，再构造成平衡的语法树，深度只有操作数个数的对数
+            if matches!(op, Token::Keyword(Keyword::And | Keyword::Or)) {
+                let mut operands = vec![lhs, self.parse_expression_with(prec + 1)?];
+                while self.next_if_token(op.clone()).is_some() {
+                    operands.push(self.parse_expression_with(prec + 1)?);
+                }
+                lhs = Self::balanced(op == Token::Keyword(Keyword::And), operands);
+                continue;
+            }
+            // 其他的运算每合并一次，左边的语法树就深一层
+            self.descend()?;
+            // NOT 只能作为 NOT LIKE、NOT BETWEEN、NOT IN 出现在二元运算的位置
+            if op == Token::Keyword(Keyword::Not) {
+                lhs = match self.next()? {
+                    Token::Keyword(Keyword::Like) => {
+                        let rhs = Box::new(self.parse_expression_with(prec + 1)?);
+                        Operation::Not(Box::new(Operation::Like(Box::new(lhs), rhs).into())).into()
+                    }
+                    Token::Keyword(Keyword::Between) => self.parse_between(lhs, true)?,
+                    Token::Keyword(Keyword::In) => self.parse_in_list(lhs, true)?,
+                    t => return Err(self.error(format!("Expected token LIKE, BETWEEN or IN, got {}", t))),
+                };
+                continue;
+            }
+            if op == Token::Keyword(Keyword::Between) {
+                lhs = self.parse_between(lhs, false)?;
+                continue;
+            }
+            if op == Token::Keyword(Keyword::In) {
+                lhs = self.parse_in_list(lhs, false)?;
+                continue;
+            }
+            // IS [NOT] NULL 是后缀运算，没有右操作数
+            if op == Token::Keyword(Keyword::Is) {
+                let not = self.next_if_token(Token::Keyword(Keyword::Not)).is_some();
+                self.next_expect(Token::Keyword(Keyword::Null))?;
+                lhs = Operation::IsNull(Box::new(lhs)).into();
+                if not {
+                    lhs = Operation::Not(Box::new(lhs)).into();
+                }
+                continue;
+            }
+            let rhs = Box::new(self.parse_expression_with(prec + 1)?);
+            let lhs_box = Box::new(lhs);
+            lhs = match op {
+                Token::Equal => Operation::Equal(lhs_box, rhs),
+                Token::NotEqual => Operation::NotEqual(lhs_box, rhs),
+                Token::GreaterThan => Operation::GreaterThan(lhs_box, rhs),
+                Token::GreaterThanOrEqual => Operation::GreaterThanOrEqual(lhs_box, rhs),
+                Token::LessThan => Operation::LessThan(lhs_box, rhs),
+                Token::LessThanOrEqual => Operation::LessThanOrEqual(lhs_box, rhs),
+                Token::Keyword(Keyword::Like) => Operation::Like(lhs_box, rhs),
+                t => return Err(self.error(format!("Unexpected token {}", t))),
+            }
+            .into();
+        }
+        Ok(lhs)
+    }
+
+    // 解析 BETWEEN 之后的 low AND high，两端只解析比较运算之上的表达式，
+    // 因此其中的 AND 属于 BETWEEN，而不是逻辑运算
+    fn parse_between(&mut self, expr: Expression, negated: bool) -> Result<Expression> {
+        let low = self.parse_expression_with(COMPARE_PREC + 1)?;
+        self.next_expect(Token::Keyword(Keyword::And))?;
+        let high = self.parse_expression_with(COMPARE_PREC + 1)?;
+        Ok(Expression::Between { expr: Box::new(expr), low: Box::new(low), high: Box::new(high), negated })
+    }
+
+    // 解析 IN 之后括号中的列表，列表可以为空
+    fn parse_in_list(&mut self, expr: Expression, negated: bool) -> Result<Expression> {
+        self.next_expect(Token::OpenParen)?;
+        let mut list = Vec::new();
+        if self.next_if_token(Token::CloseParen).is_none() {
+            loop {
+                list.push(self.parse_expression()?);
+                if self.next_if_token(Token::Comma).is_none() {
+                    break;
+                }
+            }
+            self.next_expect(Token::CloseParen)?;
+        }
+        Ok(Expression::InList { expr: Box::new(expr), list, negated })
+    }
+
+    // 用 AND 或 OR 连接所有的操作数，左半部分多一个操作数，三个以内的操作数和左结合的结果相同
+    fn balanced(and: bool, mut operands: Vec<Expression>) -> Expression {
+        if operands.len() == 1 {
+            return operands.pop().unwrap();
+        }
+        let right = operands.split_off(operands.len().div_ceil(2));
+        let (l, r) = (Box::new(Self::balanced(and, operands)), Box::new(Self::balanced(and, right)));
+        match and {
+            true => Operation::And(l, r).into(),
+            false => Operation::Or(l, r).into(),
+        }
+    }
+
+    // 二元运算符的优先级，不是二元运算符时返回 None
+    fn binary_prec(token: &Token) -> Option<u8> {
+        Some(match token {
+            Token::Keyword(Keyword::Or) => 1,
+            Token::Keyword(Keyword::And) => 2,
+            Token::Equal
+            | Token::NotEqual
+            | Token::GreaterThan
+            | Token::GreaterThanOrEqual
+            | Token::LessThan
+            | Token::LessThanOrEqual
+            | Token::Keyword(Keyword::Like)
+            | Token::Keyword(Keyword::Not)
+            | Token::Keyword(Keyword::Is)
+            | Token::Keyword(Keyword::Between)
+            | Token::Keyword(Keyword::In) => COMPARE_PREC,
+            _ => return None,
+        })
+    }
+
+    // 解析常量、列名以及括号中的表达式
+    fn parse_expression_atom(&mut self) -> Result<Expression> {
         Ok(match self.next()? {
+            // 表名.列名 作为一个完整的列名，计算时再和 Join 输出的列名匹配
+            Token::Ident(name) => match self.peek()? {
+                Some(Token::Period) => {
+                    self.next()?;
+                    match self.next_if_token(Token::Asterisk) {
+                        Some(_) => Expression::QualifiedWildcard(name),
+                        None => Expression::Field(format!("{}.{}", name, self.next_ident()?)),
+                    }
+                }
+                Some(Token::OpenParen) => self.parse_function(&name)?,
+                _ => Expression::Field(name),
+            },
+            Token::Parameter(i) => {
+                self.parameters = self.parameters.max(i + 1);
+                Expression::Parameter(i)
+            }
+            Token::OpenParen => {
+                let expr = self.parse_expression()?;
+                self.next_expect(Token::CloseParen)?;
+                expr
+            },
+            // 只由数字组成的是整数，带有小数点或指数的是浮点数
             Token::Number(n) => {
                 if n.chars().all(|c| c.is_ascii_digit()) {
                     ast::Consts::Integer(n.parse()?).into()
                 } else {
-                    ast::Consts::Float(n.parse()?).into()
+                    // 位数过多的数字解析后会溢出为无穷大
+                    let f: f64 = n.parse()?;
+                    if !f.is_finite() {
+                        return Err(self.error(format!("Float {} out of range", n)));
+                    }
+                    ast::Consts::Float(f).into()
                 }
             },
             Token::String(s) => ast::Consts::String(s).into(),
+            // 时间戳常量，例如 TIMESTAMP '2024-01-02T03:04:05Z'
+            Token::Keyword(Keyword::Timestamp) => match self.next()? {
+                Token::String(s) => match parse_timestamp(&s) {
+                    Ok(t) => ast::Consts::Timestamp(t).into(),
+                    Err(Error::Parse(msg)) => return Err(self.error(msg)),
+                    Err(err) => return Err(err),
+                },
+                t => return Err(self.error(format!("Expected timestamp string, got {}", t))),
+            },
+            // CAST(expr AS type)
+            Token::Keyword(Keyword::Cast) => {
+                self.next_expect(Token::OpenParen)?;
+                let expr = self.parse_expression()?;
+                self.next_expect(Token::Keyword(Keyword::As))?;
+                let token = self.next()?;
+                let datatype = self.datatype(&token)?;
+                self.next_expect(Token::CloseParen)?;
+                Expression::Cast(Box::new(expr), datatype)
+            }
             Token::Keyword(Keyword::True) => ast::Consts::Boolean(true).into(),
             Token::Keyword(Keyword::False) => ast::Consts::Boolean(false).into(),
             Token::Keyword(Keyword::Null) => ast::Consts::Null.into(),
             t => {
-                return Err(Error::Parse(format!(
-                    "[Parser] Unexpected expression token {}",
+                return Err(self.error(format!(
+                    "Unexpected expression token {}",
                     t
                 )))
             }
         })
     }
 
+    // 解析函数调用，目前只支持聚合函数，COUNT 的参数可以是 *
+    fn parse_function(&mut self, name: &str) -> Result<Expression> {
+        let Some(func) = ast::Aggregate::from_name(name) else {
+            return Err(self.error(format!("Unknown function {}", name)));
+        };
+        self.next_expect(Token::OpenParen)?;
+        let arg = match self.next_if_token(Token::Asterisk) {
+            Some(_) if func == ast::Aggregate::Count => None,
+            Some(token) => return Err(self.error(format!("Unexpected token {}", token))),
+            None => Some(Box::new(self.parse_expression()?)),
+        };
+        self.next_expect(Token::CloseParen)?;
+        Ok(Expression::Aggregate(func, arg))
+    }
+
+    // 类型名对应的数据类型，用于列定义和 CAST
+    fn datatype(&self, token: &Token) -> Result<DataType> {
+        Ok(match token {
+            Token::Keyword(Keyword::Bool) | Token::Keyword(Keyword::Boolean) => DataType::Boolean,
+            Token::Keyword(Keyword::Int) | Token::Keyword(Keyword::Integer) | Token::Keyword(Keyword::Serial) => DataType::Integer,
+            Token::Keyword(Keyword::Float) | Token::Keyword(Keyword::Double) => DataType::Float,
+            Token::Keyword(Keyword::String) | Token::Keyword(Keyword::Text) | Token::Keyword(Keyword::Varchar) => DataType::String,
+            Token::Keyword(Keyword::Timestamp) => DataType::Timestamp,
+            token => return Err(self.error(format!("Unexpected token {}", token))),
+        })
+    }
+
+    // 进入下一层，超过最大深度时返回错误，由 parse_expression_with 和 parse_query 在返回时恢复深度
+    fn descend(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(self.error(format!("Nesting exceeds the maximum depth of {}", MAX_DEPTH)));
+        }
+        Ok(())
+    }
+
+    // 带有最近一个 token 位置的错误
+    fn error(&self, msg: impl Display) -> Error {
+        Error::Parse(format!("[Parser] {}: {}", self.location, msg))
+    }
+
     fn peek(&mut self) -> Result<Option<Token>> {
-        self.lexer.peek().cloned().transpose()
+        Ok(self.lexer.peek().cloned().transpose()?.map(|(token, _)| token))
     }
 
     fn next(&mut self) -> Result<Token> {
-        self.lexer.next().unwrap_or_else(|| Err(Error::Parse(format!("[Parser] Unexpected end of input"))))
+        match self.lexer.next() {
+            Some(Ok((token, location))) => {
+                self.location = location;
+                Ok(token)
+            }
+            Some(Err(err)) => Err(err),
+            None => {
+                self.location = self.end;
+                Err(self.error("Unexpected end of input"))
+            }
+        }
     }
 
     fn next_ident(&mut self) -> Result<String> {
         match self.next()? {
             Token::Ident(ident) => Ok(ident),
-            token => Err(Error::Parse(format!(
-                "[Parser] Expected ident, got token {}",
+            // 关键字需要加上双引号才能作为表名或列名
+            Token::Keyword(keyword) => Err(self.error(format!(
+                "Expected ident, got keyword {}, quote it as \"{}\" to use it as a name",
+                keyword,
+                keyword.to_str().to_lowercase()
+            ))),
+            token => Err(self.error(format!(
+                "Expected ident, got token {}",
                 token
             )))
         }
@@ -214,8 +866,8 @@ impl<'a> Parser<'a> {
     fn next_expect(&mut self, expect: Token) -> Result<()> {
         let token = self.next()?;
         if token != expect {
-            return Err(Error::Parse(format!(
-                "[Parser] Expected token {}, got {}",
+            return Err(self.error(format!(
+                "Expected token {}, got {}",
                 expect, token
             )));
         }
@@ -238,7 +890,11 @@ impl<'a> Parser<'a> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{error::Result, sql::parser::ast};
+    use crate::{
+        error::{Error, Result},
+        sql::{parser::ast::{self, Expression, Operation}, types::DataType},
+        storage::mvcc::IsolationLevel,
+    };
 
     use super::Parser;
 
@@ -276,6 +932,93 @@ mod tests {
 
         let stmt3 = Parser::new(sql3).parse();
         assert!(stmt3.is_err());
+
+        assert!(matches!(
+            Parser::new("create table if not exists t (id int);").parse()?,
+            ast::Statement::CreateTable { if_not_exists: true, .. }
+        ));
+        assert!(Parser::new("create table if t (id int);").parse().is_err());
+        assert!(Parser::new("create table if not t (id int);").parse().is_err());
+        assert!(matches!(
+            Parser::new("create or replace table t (id int);").parse()?,
+            ast::Statement::CreateTable { if_not_exists: false, or_replace: true, .. }
+        ));
+        assert!(Parser::new("create or replace table if not exists t (id int);").parse().is_err());
+        assert!(Parser::new("create or table t (id int);").parse().is_err());
+        assert!(Parser::new("create replace table t (id int);").parse().is_err());
+
+        let stmt4 = Parser::new("create table t (id int primary key auto_increment, name varchar);").parse()?;
+        assert_eq!(
+            stmt4,
+            ast::Statement::CreateTable {
+                name: "t".to_string(),
+                if_not_exists: false,
+                or_replace: false,
+                primary_key: vec![],
+                columns: vec![
+                    ast::Column {
+                        name: "id".to_string(),
+                        datatype: DataType::Integer,
+                        nullable: None,
+                        default: None,
+                        primary_key: true,
+                        auto_increment: true,
+                        max_len: None,
+                        comment: None,
+                    },
+                    ast::Column {
+                        name: "name".to_string(),
+                        datatype: DataType::String,
+                        nullable: None,
+                        default: None,
+                        primary_key: false,
+                        auto_increment: false,
+                        max_len: None,
+                        comment: None,
+                    },
+                ],
+            }
+        );
+        assert!(Parser::new("create table t (id int primary);").parse().is_err());
+
+        // 表级的复合主键
+        let stmt = Parser::new("create table t (a int, b text, c int, primary key (b, a));").parse()?;
+        assert!(matches!(
+            stmt,
+            ast::Statement::CreateTable { columns, primary_key, .. } if columns.len() == 3 && primary_key == vec!["b", "a"]
+        ));
+        assert!(Parser::new("create table t (a int, primary key ());").parse().is_err());
+        assert!(Parser::new("create table t (a int, primary key (a), b int);").parse().is_err());
+        assert!(Parser::new("create table t (primary key (a));").parse().is_err());
+        assert!(Parser::new("create table t (a int, primary (a));").parse().is_err());
+
+        let stmt5 = Parser::new("create table t (name varchar(50) not null, note text(8));").parse()?;
+        match stmt5 {
+            ast::Statement::CreateTable { columns, .. } => {
+                assert_eq!(columns[0].max_len, Some(50));
+                assert_eq!(columns[0].nullable, Some(false));
+                assert_eq!(columns[1].max_len, Some(8));
+            },
+            _ => unreachable!(),
+        }
+        assert!(Parser::new("create table t (name varchar(0));").parse().is_err());
+        assert!(Parser::new("create table t (name varchar(1.5));").parse().is_err());
+        assert!(Parser::new("create table t (name varchar(10);").parse().is_err());
+        assert!(Parser::new("create table t (id int(10));").parse().is_err());
+
+        let stmt6 = Parser::new("create table t (id int primary key comment 'the id', name varchar comment 'a name' not null);").parse()?;
+        match stmt6 {
+            ast::Statement::CreateTable { columns, .. } => {
+                assert_eq!(columns[0].comment, Some("the id".to_string()));
+                assert!(columns[0].primary_key);
+                assert_eq!(columns[1].comment, Some("a name".to_string()));
+                assert_eq!(columns[1].nullable, Some(false));
+            },
+            _ => unreachable!(),
+        }
+        assert!(Parser::new("create table t (id int comment);").parse().is_err());
+        assert!(Parser::new("create table t (id int comment 1);").parse().is_err());
+        assert_eq!(Parser::new("describe t;").parse()?, ast::Statement::Describe { table_name: "t".to_string() });
         Ok(())
     }
 
@@ -320,9 +1063,30 @@ mod tests {
             }
         );
 
+        // 参数占位符按出现的顺序编号
+        let mut parser = Parser::new("insert into tbl1 values (?, 1), (?, ?);");
+        assert_eq!(
+            parser.parse()?,
+            ast::Statement::Insert {
+                table_name: "tbl1".to_string(),
+                columns: None,
+                values: vec![
+                    vec![Expression::Parameter(0), ast::Consts::Integer(1).into()],
+                    vec![Expression::Parameter(1), Expression::Parameter(2)],
+                ],
+            }
+        );
+        assert_eq!(parser.parameters(), 3);
+
         Ok(())
     }
 
+    #[test]
+    fn test_parser_float_out_of_range() {
+        let sql = format!("insert into tbl1 values ({}.0);", "9".repeat(400));
+        assert!(Parser::new(&sql).parse().is_err());
+    }
+
     #[test]
     fn test_parser_select() -> Result<()> {
         let sql = "select * from tbl1;";
@@ -330,9 +1094,721 @@ mod tests {
         assert_eq!(
             stmt,
             ast::Statement::Select {
-                table_name: "tbl1".to_string()
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "tbl1".to_string()
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        let sql = "select * from a natural join b cross join c;";
+        let stmt = Parser::new(sql).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Join {
+                    left: Box::new(ast::FromItem::Join {
+                        left: Box::new(ast::FromItem::Table { name: "a".to_string() }),
+                        right: Box::new(ast::FromItem::Table { name: "b".to_string() }),
+                        join_type: ast::JoinType::Natural,
+                    }),
+                    right: Box::new(ast::FromItem::Table { name: "c".to_string() }),
+                    join_type: ast::JoinType::Cross,
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        assert!(Parser::new("select * from a natural b;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_where() -> Result<()> {
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        let int = |i: i64| Box::new(Expression::Consts(ast::Consts::Integer(i)));
+
+        let stmt = Parser::new("select * from t where a > b and not (c = 1 or d <= 2);").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: Some(
+                    Operation::And(
+                        Box::new(Operation::GreaterThan(field("a"), field("b")).into()),
+                        Box::new(
+                            Operation::Not(Box::new(
+                                Operation::Or(
+                                    Box::new(Operation::Equal(field("c"), int(1)).into()),
+                                    Box::new(Operation::LessThanOrEqual(field("d"), int(2)).into()),
+                                )
+                                .into()
+                            ))
+                            .into()
+                        ),
+                    )
+                    .into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        // AND 的优先级高于 OR
+        let stmt = Parser::new("select * from t where a = 1 or b = 2 and c = 3;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: Some(
+                    Operation::Or(
+                        Box::new(Operation::Equal(field("a"), int(1)).into()),
+                        Box::new(
+                            Operation::And(
+                                Box::new(Operation::Equal(field("b"), int(2)).into()),
+                                Box::new(Operation::Equal(field("c"), int(3)).into()),
+                            )
+                            .into()
+                        ),
+                    )
+                    .into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        let stmt = Parser::new("select * from t where a not like 'x%' and b like '_';").parse()?;
+        let s = |v: &str| Box::new(Expression::Consts(ast::Consts::String(v.to_string())));
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: Some(
+                    Operation::And(
+                        Box::new(
+                            Operation::Not(Box::new(Operation::Like(field("a"), s("x%")).into())).into()
+                        ),
+                        Box::new(Operation::Like(field("b"), s("_")).into()),
+                    )
+                    .into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        let stmt = Parser::new("select * from t where a is null or b is not null;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: Some(
+                    Operation::Or(
+                        Box::new(Operation::IsNull(field("a")).into()),
+                        Box::new(Operation::Not(Box::new(Operation::IsNull(field("b")).into())).into()),
+                    )
+                    .into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        assert!(Parser::new("select * from t where;").parse().is_err());
+        assert!(Parser::new("select * from t where a is 1;").parse().is_err());
+        assert!(Parser::new("select * from t where a is not;").parse().is_err());
+        assert!(Parser::new("select * from t where a not b;").parse().is_err());
+        assert!(Parser::new("select * from t where (a = 1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_transaction() -> Result<()> {
+        let begin = |isolation| ast::Statement::Begin { isolation };
+        assert_eq!(Parser::new("begin;").parse()?, begin(IsolationLevel::Snapshot));
+        assert_eq!(
+            Parser::new("begin isolation level read committed;").parse()?,
+            begin(IsolationLevel::ReadCommitted)
+        );
+        assert_eq!(Parser::new("BEGIN ISOLATION LEVEL SNAPSHOT;").parse()?, begin(IsolationLevel::Snapshot));
+        assert_eq!(Parser::new("commit;").parse()?, ast::Statement::Commit);
+        assert_eq!(Parser::new("rollback;").parse()?, ast::Statement::Rollback);
+
+        assert!(Parser::new("begin isolation level read;").parse().is_err());
+        assert!(Parser::new("begin isolation read committed;").parse().is_err());
+        assert!(Parser::new("begin isolation level serializable;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_cast() -> Result<()> {
+        let stmt = Parser::new("select * from t where cast(a as float) > cast('1' as int);").parse()?;
+        let ast::Statement::Select { where_clause: Some(expr), .. } = stmt else { unreachable!() };
+        assert_eq!(expr.to_string(), "CAST(a AS FLOAT) > CAST('1' AS INTEGER)");
+
+        assert!(Parser::new("select * from t where cast(a float) > 1;").parse().is_err());
+        assert!(Parser::new("select * from t where cast(a as number) > 1;").parse().is_err());
+        assert!(Parser::new("select * from t where cast a as int > 1;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_between_in() -> Result<()> {
+        let where_clause = |sql: &str| -> Result<Expression> {
+            match Parser::new(&format!("select * from t where {};", sql)).parse()? {
+                ast::Statement::Select { where_clause: Some(expr), .. } => Ok(expr),
+                _ => unreachable!(),
+            }
+        };
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        let int = |i: i64| Box::new(Expression::Consts(ast::Consts::Integer(i)));
+
+        // BETWEEN 中的 AND 属于 BETWEEN，之后的 AND 才是逻辑运算
+        assert_eq!(
+            where_clause("a between 1 and 2 and b not between 3 and 4")?,
+            Operation::And(
+                Box::new(Expression::Between { expr: field("a"), low: int(1), high: int(2), negated: false }),
+                Box::new(Expression::Between { expr: field("b"), low: int(3), high: int(4), negated: true }),
+            )
+            .into()
+        );
+        // 两端可以是括号中的任意表达式
+        assert_eq!(
+            where_clause("a between (1 and 2) and 3")?,
+            Expression::Between {
+                expr: field("a"),
+                low: Box::new(Operation::And(int(1), int(2)).into()),
+                high: int(3),
+                negated: false,
+            }
+        );
+        assert_eq!(
+            where_clause("a in (1, b) or a not in () or not a in (3)")?,
+            Operation::Or(
+                Box::new(
+                    Operation::Or(
+                        Box::new(Expression::InList { expr: field("a"), list: vec![*int(1), *field("b")], negated: false }),
+                        Box::new(Expression::InList { expr: field("a"), list: vec![], negated: true }),
+                    )
+                    .into()
+                ),
+                Box::new(
+                    Operation::Not(Box::new(Expression::InList { expr: field("a"), list: vec![*int(3)], negated: false }))
+                        .into()
+                ),
+            )
+            .into()
+        );
+        assert_eq!(where_clause("a between 1 and 2")?.to_string(), "a BETWEEN 1 AND 2");
+        assert_eq!(where_clause("a not in (1, 'x')")?.to_string(), "a NOT IN (1, 'x')");
+
+        assert!(where_clause("a between 1").is_err());
+        assert!(where_clause("a between 1 or 2").is_err());
+        assert!(where_clause("a in 1").is_err());
+        assert!(where_clause("a in (1, 2").is_err());
+        assert!(where_clause("a in (1,)").is_err());
+        assert_eq!(
+            where_clause("a not between").err(),
+            Some(Error::Parse("[Parser] line 1, col 36: Unexpected expression token ;".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_order_by() -> Result<()> {
+        let stmt = Parser::new("select * from t where a > 1 order by a, b desc, c is null asc;").parse()?;
+        let field = |name: &str| Expression::Field(name.to_string());
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: Some(
+                    Operation::GreaterThan(Box::new(field("a")), Box::new(ast::Consts::Integer(1).into())).into()
+                ),
+                group_by: vec![],
+                having: None,
+                order_by: vec![
+                    (field("a"), ast::OrderDirection::Asc),
+                    (field("b"), ast::OrderDirection::Desc),
+                    (Operation::IsNull(Box::new(field("c"))).into(), ast::OrderDirection::Asc),
+                ],
+            }
+        );
+        assert!(Parser::new("select * from t order a;").parse().is_err());
+        assert!(Parser::new("select * from t order by;").parse().is_err());
+        assert!(Parser::new("select * from t order by a,;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_with() -> Result<()> {
+        let stmt = Parser::new("with recursive a as (select * from t union all select * from a), b as (select * from a) select * from b;")
+            .parse()?;
+        let select = |name: &str| {
+            Box::new(ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: name.to_string() },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            })
+        };
+        assert_eq!(
+            stmt,
+            ast::Statement::With {
+                recursive: true,
+                ctes: vec![
+                    (
+                        "a".to_string(),
+                        Box::new(ast::Statement::SetOperation {
+                            op: ast::SetOperator::Union,
+                            left: select("t"),
+                            right: select("a"),
+                            all: true,
+                        }),
+                    ),
+                    ("b".to_string(), select("a")),
+                ],
+                query: select("b"),
+            }
+        );
+        assert!(Parser::new("with a as select * from t select * from a;").parse().is_err());
+        assert!(Parser::new("with a (select * from t) select * from a;").parse().is_err());
+        assert!(Parser::new("with a as (select * from t);").parse().is_err());
+        assert!(Parser::new("with a as (select * from t) insert into a values (1);").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_set_operation() -> Result<()> {
+        let select = |name: &str| {
+            Box::new(ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: name.to_string() },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            })
+        };
+        let set = |op, left, right, all| Box::new(ast::Statement::SetOperation { op, left, right, all });
+        // INTERSECT 的优先级更高
+        let stmt = Parser::new("select * from a except all select * from b intersect select * from c union distinct select * from d;")
+            .parse()?;
+        assert_eq!(
+            stmt,
+            *set(
+                ast::SetOperator::Union,
+                set(
+                    ast::SetOperator::Except,
+                    select("a"),
+                    set(ast::SetOperator::Intersect, select("b"), select("c"), false),
+                    true
+                ),
+                select("d"),
+                false
+            )
+        );
+        assert!(Parser::new("select * from a union;").parse().is_err());
+        assert!(Parser::new("select * from a union all all select * from b;").parse().is_err());
+        assert!(Parser::new("select * from a intersect insert into b values (1);").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_debug_keys() -> Result<()> {
+        assert_eq!(Parser::new("debug keys;").parse()?, ast::Statement::DebugKeys { prefix: None });
+        assert_eq!(
+            Parser::new("DEBUG KEYS 'TxnActive';").parse()?,
+            ast::Statement::DebugKeys { prefix: Some("TxnActive".to_string()) }
+        );
+        assert!(Parser::new("debug;").parse().is_err());
+        assert!(Parser::new("debug keys t;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_view() -> Result<()> {
+        let stmt = Parser::new("create view v as select * from a union select * from b;").parse()?;
+        let ast::Statement::CreateView { name, query } = stmt else {
+            panic!("unexpected statement {:?}", stmt);
+        };
+        assert_eq!(name, "v");
+        assert!(matches!(*query, ast::Statement::SetOperation { op: ast::SetOperator::Union, all: false, .. }));
+        assert_eq!(Parser::new("drop view v;").parse()?, ast::Statement::DropView { name: "v".to_string() });
+        assert_eq!(Parser::new("show views;").parse()?, ast::Statement::ShowViews);
+        assert!(Parser::new("create view v select * from a;").parse().is_err());
+        assert!(Parser::new("create view v as insert into a values (1);").parse().is_err());
+        assert!(Parser::new("drop table v;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_group_by() -> Result<()> {
+        let stmt = Parser::new("select a, count(*) as n, sum(b) from t group by a having count(b) > 1;").parse()?;
+        let field = |name: &str| Expression::Field(name.to_string());
+        let aggregate = |f: ast::Aggregate, e: Option<Expression>| Expression::Aggregate(f, e.map(Box::new));
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![
+                    (field("a"), None),
+                    (aggregate(ast::Aggregate::Count, None), Some("n".to_string())),
+                    (aggregate(ast::Aggregate::Sum, Some(field("b"))), None),
+                ],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: None,
+                group_by: vec![field("a")],
+                having: Some(
+                    Operation::GreaterThan(
+                        Box::new(aggregate(ast::Aggregate::Count, Some(field("b")))),
+                        Box::new(ast::Consts::Integer(1).into())
+                    )
+                    .into()
+                ),
+                order_by: vec![],
             }
         );
+        // 函数名不区分大小写，只有 COUNT 可以使用 *
+        assert!(Parser::new("select MAX(a), Avg(b) from t;").parse().is_ok());
+        assert!(Parser::new("select sum(*) from t;").parse().is_err());
+        assert!(Parser::new("select foo(a) from t;").parse().is_err());
+        assert!(Parser::new("select a from t group by;").parse().is_err());
+        assert!(Parser::new("select a as from t;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_inner_join() -> Result<()> {
+        let table = |name: &str| Box::new(ast::FromItem::Table { name: name.to_string() });
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+        let on = Expression::from(Operation::Equal(field("a.id"), field("b_id")));
+
+        // INNER 可以省略
+        for sql in ["select * from a join b on a.id = b_id;", "select * from a inner join b on a.id = b_id;"] {
+            assert_eq!(
+                Parser::new(sql).parse()?,
+                ast::Statement::Select {
+                    distinct: false,
+                    select: vec![],
+                    from: ast::FromItem::Join { left: table("a"), right: table("b"), join_type: ast::JoinType::Inner(on.clone()) },
+                    where_clause: None,
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                }
+            );
+        }
+        assert!(Parser::new("select * from a join b;").parse().is_err());
+        assert!(Parser::new("select * from a join b on a. = 1;").parse().is_err());
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_parser_table_alias() -> Result<()> {
+        let aliased = |name: &str, alias: &str| ast::FromItem::AliasedTable { name: name.to_string(), alias: alias.to_string() };
+        let field = |name: &str| Expression::Field(name.to_string());
+
+        // AS 可以省略，表名.* 和普通的表达式可以混合使用
+        for sql in [
+            "select u.*, o.amount from users as u join orders o on u.id = o.user_id;",
+            "select u.*, o.amount from users u inner join orders as o on u.id = o.user_id;",
+        ] {
+            assert_eq!(
+                Parser::new(sql).parse()?,
+                ast::Statement::Select {
+                    distinct: false,
+                    select: vec![(Expression::QualifiedWildcard("u".to_string()), None), (field("o.amount"), None)],
+                    from: ast::FromItem::Join {
+                        left: Box::new(aliased("users", "u")),
+                        right: Box::new(aliased("orders", "o")),
+                        join_type: ast::JoinType::Inner(
+                            Operation::Equal(Box::new(field("u.id")), Box::new(field("o.user_id"))).into()
+                        ),
+                    },
+                    where_clause: None,
+                    group_by: vec![],
+                    having: None,
+                    order_by: vec![],
+                }
+            );
+        }
+
+        // 别名之后可以直接跟 WHERE 等子句，关键字不会被当作别名
+        let stmt = Parser::new("select * from users u where u.id = 1;").parse()?;
+        assert!(matches!(stmt, ast::Statement::Select { from, where_clause: Some(_), .. } if from == aliased("users", "u")));
+        let stmt = Parser::new("select * from users where id = 1;").parse()?;
+        assert!(matches!(stmt, ast::Statement::Select { from: ast::FromItem::Table { .. }, .. }));
+        assert_eq!(Expression::QualifiedWildcard("My T".to_string()).to_string(), r#""My T".*"#);
+
+        assert!(Parser::new("select * from users as;").parse().is_err());
+        assert!(Parser::new("select * from users as where;").parse().is_err());
+        assert!(Parser::new("select u.* * from users u;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_index() -> Result<()> {
+        let stmt = Parser::new("create index idx_a on tbl1(a);").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::CreateIndex {
+                index_name: "idx_a".to_string(),
+                table_name: "tbl1".to_string(),
+                column_name: "a".to_string(),
+            }
+        );
+        let stmt = Parser::new("drop index idx_a;").parse()?;
+        assert_eq!(stmt, ast::Statement::DropIndex { index_name: "idx_a".to_string() });
+
+        let stmt = Parser::new("truncate table tbl1;").parse()?;
+        assert_eq!(stmt, ast::Statement::TruncateTable { table_name: "tbl1".to_string() });
+        assert!(Parser::new("truncate tbl1;").parse().is_err());
+
+        let stmt = Parser::new("analyze tbl1;").parse()?;
+        assert_eq!(stmt, ast::Statement::AnalyzeTable { table_name: "tbl1".to_string() });
+
+        assert!(Parser::new("create index idx_a on tbl1(a, b);").parse().is_err());
+        assert!(Parser::new("create index idx_a tbl1(a);").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_explain() -> Result<()> {
+        let stmt = Parser::new("explain select * from t;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Explain(Box::new(ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }))
+        );
+        let stmt = Parser::new("explain analyze select * from t;").parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::ExplainAnalyze(Box::new(ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table { name: "t".to_string() },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }))
+        );
+        assert!(Parser::new("explain;").parse().is_err());
+        assert!(Parser::new("explain analyze explain select * from t;").parse().is_err());
+        assert!(Parser::new("explain explain select * from t;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_show() -> Result<()> {
+        let stmt = Parser::new("show engine status;").parse()?;
+        assert_eq!(stmt, ast::Statement::ShowEngineStatus);
+        assert!(Parser::new("show engine;").parse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_quoted_ident() -> Result<()> {
+        let stmt = Parser::new(r#"create table "table" ("my col" int, "default" text);"#).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::CreateTable {
+                name: "table".to_string(),
+                if_not_exists: false,
+                or_replace: false,
+                primary_key: vec![],
+                columns: vec![
+                    ast::Column {
+                        name: "my col".to_string(),
+                        datatype: DataType::Integer,
+                        nullable: None,
+                        default: None,
+                        primary_key: false,
+                        auto_increment: false,
+                        max_len: None,
+                        comment: None,
+                    },
+                    ast::Column {
+                        name: "default".to_string(),
+                        datatype: DataType::String,
+                        nullable: None,
+                        default: None,
+                        primary_key: false,
+                        auto_increment: false,
+                        max_len: None,
+                        comment: None,
+                    },
+                ],
+            }
+        );
+
+        let stmt = Parser::new(r#"insert into "table" ("my col") values (1);"#).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Insert {
+                table_name: "table".to_string(),
+                columns: Some(vec!["my col".to_string()]),
+                values: vec![vec![ast::Consts::Integer(1).into()]],
+            }
+        );
+
+        let stmt = Parser::new(r#"select * from "select";"#).parse()?;
+        assert_eq!(
+            stmt,
+            ast::Statement::Select {
+                distinct: false,
+                select: vec![],
+                from: ast::FromItem::Table {
+                    name: "select".to_string()
+                },
+                where_clause: None,
+                group_by: vec![],
+                having: None,
+                order_by: vec![],
+            }
+        );
+
+        // 不加引号的关键字不能作为表名
+        assert_eq!(
+            Parser::new("create table order (id int);").parse(),
+            Err(Error::Parse(
+                "[Parser] line 1, col 14: Expected ident, got keyword ORDER, quote it as \"order\" to use it as a name".to_string()
+            ))
+        );
+
+        // 输出的表达式中需要的地方重新加上引号，可以解析回原来的表达式
+        let sql = r#"select * from t where "order"."my col" = 1 and "A""b" like 'x' and c_1 is null;"#;
+        let ast::Statement::Select { where_clause: Some(expr), .. } = Parser::new(sql).parse()? else {
+            unreachable!()
+        };
+        let text = expr.to_string();
+        assert_eq!(text, r#"("order"."my col" = 1 AND "A""b" LIKE 'x') AND c_1 IS NULL"#);
+        let ast::Statement::Select { where_clause: Some(reparsed), .. } =
+            Parser::new(&format!("select * from t where {};", text)).parse()?
+        else {
+            unreachable!()
+        };
+        assert_eq!(reparsed, expr);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parser_error_location() {
+        let sql = "select *\nfrom tbl1\nwhere a = = 1;";
+        assert_eq!(
+            Parser::new(sql).parse(),
+            Err(Error::Parse("[Parser] line 3, col 11: Unexpected expression token =".to_string()))
+        );
+
+        // 空的列名列表和空的一行值
+        assert_eq!(
+            Parser::new("insert into tbl1 () values (1);").parse(),
+            Err(Error::Parse("[Parser] line 1, col 19: Empty column list, expected at least one column name".to_string()))
+        );
+        assert_eq!(
+            Parser::new("insert into tbl1 values ();").parse(),
+            Err(Error::Parse("[Parser] line 1, col 26: Empty VALUES row, expected at least one value".to_string()))
+        );
+        assert_eq!(
+            Parser::new("insert into tbl1 (a) values (1), ();").parse(),
+            Err(Error::Parse("[Parser] line 1, col 35: Empty VALUES row, expected at least one value".to_string()))
+        );
+        assert!(Parser::new("insert into tbl1 values;").parse().is_err());
+
+        let sql = "insert into tbl1\nvalues (1, 2)\n  (3, 4);";
+        assert_eq!(
+            Parser::new(sql).parse(),
+            Err(Error::Parse("[Parser] line 3, col 3: Expected token ;, got (".to_string()))
+        );
+
+        // 缺少结尾时报告输入末尾的位置
+        assert_eq!(
+            Parser::new("select * from\n  tbl1").parse(),
+            Err(Error::Parse("[Parser] line 2, col 7: Unexpected end of input".to_string()))
+        );
+
+        // 时间戳常量的格式错误
+        assert_eq!(
+            Parser::new("select * from tbl1\nwhere\n  ts > TIMESTAMP '2024';").parse(),
+            Err(Error::Parse(
+                "[Parser] line 3, col 18: invalid timestamp '2024', expected format YYYY-MM-DDTHH:MM:SS[.fff]Z".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_parser_malformed_input() {
+        let parse_error = |sql: &str| matches!(Parser::new(sql).parse(), Err(Error::Parse(_)));
+
+        // 过深的嵌套返回错误，不会栈溢出
+        let n = 100_000;
+        assert!(parse_error(&format!("select {}1{} from t;", "(".repeat(n), ")".repeat(n))));
+        assert!(parse_error(&format!("select * from t where {}a;", "not ".repeat(n))));
+        assert!(parse_error(&format!("select 1{} from t;", " + 1".repeat(n))));
+        assert!(parse_error(&format!("select * from t {};", "union select * from t ".repeat(n))));
+        // 没有超过上限的嵌套可以正常解析
+        assert!(Parser::new(&format!("select {}1{} from t;", "(".repeat(100), ")".repeat(100))).parse().is_ok());
+        // 很长的 AND、OR 条件列表不是嵌套，可以正常解析
+        let chain = |op: &str| (0..n).map(|i| format!("id = {}", i)).collect::<Vec<_>>().join(op);
+        assert!(Parser::new(&format!("select * from t where {};", chain(" or "))).parse().is_ok());
+        assert!(Parser::new(&format!("select * from t where {};", chain(" and "))).parse().is_ok());
+        assert!(parse_error(&format!("select * from t where a{};", " = 1".repeat(n))));
+
+        // 很长的标识符
+        let ident = "a".repeat(n);
+        assert!(Parser::new(&format!("select {} from {};", ident, ident)).parse().is_ok());
+
+        // 括号不匹配
+        assert!(parse_error("select (1 from t;"));
+        assert!(parse_error("select 1) from t;"));
+        assert!(parse_error("select ((1) from t;"));
+        assert!(parse_error("insert into t values (1, 2;"));
+
+        // 字符串中的空字节
+        assert!(Parser::new("select 'a\0b' from t;").parse().is_ok());
+        assert!(parse_error("select 'a\0b"));
+        assert!(parse_error("select \0 from t;"));
+
+        // 缺少分号时返回错误
+        assert!(parse_error("select * from t"));
+        assert!(parse_error("select 1 from t"));
+        assert!(parse_error(""));
+    }
+}
@@ -1,37 +1,385 @@
-use planner::Planner;
+use std::{fmt::Display, path::PathBuf};
+
+use planner::{materialize_ctes, Ctes, Planner};
 
 use crate::error::Result;
 
-use super::{engine::Transaction, executor::{Executor, ResultSet}, parser::ast::{Expression, Statement}, schema::Table};
+use super::{engine::Transaction, executor::{ExecutionContext, Executor, ResultSet}, parser::ast::{quote_ident, Expression, OrderDirection, SetOperator, Statement}, schema::{Table, View}, types::Row};
 
 mod planner;
+mod optimizer;
 
 
 #[derive(Debug, PartialEq)]
 pub enum Node {
+    // if_not_exists 为 true 时，表已经存在则跳过；or_replace 为 true 时，先删除已经存在的表
     CreateTable {
         schema: Table,
+        if_not_exists: bool,
+        or_replace: bool,
     },
     Insert {
         table_name: String,
         columns: Vec<String>,
         values: Vec<Vec<Expression>>,
     },
+    // 扫描表，filter 为扫描时对每一行进行过滤的条件，alias 为 FROM 中给表指定的别名
     Scan {
         table_name: String,
+        alias: Option<String>,
+        filter: Option<Expression>,
+    },
+    // 输出 WITH 中已经执行的 CTE 的结果
+    InMemoryScan {
+        name: String,
+        alias: Option<String>,
+        columns: Vec<String>,
+        rows: Vec<Row>,
+    },
+    // 按条件过滤输入的行
+    Filter {
+        source: Box<Node>,
+        predicate: Expression,
+    },
+    // 嵌套循环连接，using 中为需要相等的 (左表列, 右表列) 下标，predicate 为 ON 条件
+    // 两者都为空时是笛卡尔积
+    NestedLoopJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        using: Vec<(usize, usize)>,
+        predicate: Option<Expression>,
+    },
+    // 哈希连接，left_key 在左边的行上计算，right_key 在右边的行上计算，两者相等的行连接在一起
+    HashJoin {
+        left: Box<Node>,
+        right: Box<Node>,
+        left_key: Expression,
+        right_key: Expression,
+    },
+    ShowEngineStatus,
+    CreateView {
+        view: View,
+    },
+    DropView {
+        name: String,
     },
+    ShowViews,
+    DebugKeys {
+        prefix: Option<String>,
+    },
+    CreateIndex {
+        index_name: String,
+        table_name: String,
+        column_name: String,
+    },
+    DropIndex {
+        index_name: String,
+    },
+    TruncateTable {
+        table_name: String,
+    },
+    AnalyzeTable {
+        table_name: String,
+    },
+    Describe {
+        table_name: String,
+    },
+    // 按 order_by 中的表达式依次排序，NULL 在升序时排在最前面
+    Sort {
+        source: Box<Node>,
+        order_by: Vec<(Expression, OrderDirection)>,
+        method: SortMethod,
+    },
+    // 按下标选取输出的列
+    Projection {
+        source: Box<Node>,
+        columns: Vec<usize>,
+    },
+    // 在每一行上计算 SELECT 中的表达式，输出的列名为别名或表达式本身
+    Compute {
+        source: Box<Node>,
+        expressions: Vec<(Expression, String)>,
+    },
+    // 按 group_by 分组并计算每组的聚合函数，输出的列依次为分组的表达式和聚合函数
+    // 列名为表达式的文本，having 在输出的行上计算，引用的是这些列名
+    GroupBy {
+        source: Box<Node>,
+        group_by: Vec<Expression>,
+        aggregates: Vec<Expression>,
+        having: Option<Expression>,
+    },
+    // 集合运算，输出的列名和 left 一致，all 为 false 时输出的行不重复
+    SetOperation {
+        op: SetOperator,
+        left: Box<Node>,
+        right: Box<Node>,
+        all: bool,
+    },
+    // 去掉重复的行，按第一次出现的顺序输出，NULL 和 NULL 视为相同
+    Distinct {
+        source: Box<Node>,
+    },
+    // 输出内部的执行计划，不执行
+    Explain {
+        inner: Box<Node>,
+    },
+    // 执行内部的计划，输出每个节点实际的行数和耗时
+    ExplainAnalyze {
+        inner: Box<Node>,
+    },
+}
+
+// 排序的方式
+#[derive(Debug, PartialEq, Clone)]
+pub enum SortMethod {
+    // 所有的行读入内存之后排序
+    InMemory,
+    // 缓存的行超过 threshold 字节时排序后写入 spill_path 下的临时文件，最后多路归并
+    External { spill_path: PathBuf, threshold: usize },
+}
+
+// 外部排序默认的阈值
+pub const DEFAULT_SORT_THRESHOLD: usize = 64 << 20;
+
+impl Node {
+    // 节点输出的行来自哪张表，Join 时用表名区分两边同名的列，有别名时使用别名
+    pub fn table_alias(&self) -> Option<String> {
+        match self {
+            Node::Scan { table_name, alias, .. } => Some(alias.as_ref().unwrap_or(table_name).clone()),
+            Node::InMemoryScan { name, alias, .. } => Some(alias.as_ref().unwrap_or(name).clone()),
+            Node::Filter { source, .. } => source.table_alias(),
+            _ => None,
+        }
+    }
+
+    // 输出当前节点，子节点增加缩进后依次输出
+    fn format(&self, f: &mut std::fmt::Formatter<'_>, indent: usize) -> std::fmt::Result {
+        let prefix = if indent == 0 { String::new() } else { format!("{}-> ", " ".repeat(indent * 2 - 2)) };
+        write!(f, "{}", prefix)?;
+        let children: Vec<&Node> = match self {
+            Node::CreateTable { schema, if_not_exists, or_replace } => {
+                match (if_not_exists, or_replace) {
+                    (true, _) => writeln!(f, "CreateTable: {} (if not exists)", quote_ident(&schema.name))?,
+                    (_, true) => writeln!(f, "CreateTable: {} (or replace)", quote_ident(&schema.name))?,
+                    _ => writeln!(f, "CreateTable: {}", quote_ident(&schema.name))?,
+                }
+                vec![]
+            },
+            Node::Insert { table_name, values, .. } => {
+                writeln!(f, "Insert: {} (rows: {})", quote_ident(table_name), values.len())?;
+                vec![]
+            },
+            Node::Scan { table_name, alias, filter } => {
+                let name = with_alias(table_name, alias);
+                match filter {
+                    Some(filter) => writeln!(f, "Scan: {} (filter: {})", name, filter)?,
+                    None => writeln!(f, "Scan: {}", name)?,
+                }
+                vec![]
+            },
+            Node::InMemoryScan { name, alias, rows, .. } => {
+                writeln!(f, "InMemoryScan: {} (rows: {})", with_alias(name, alias), rows.len())?;
+                vec![]
+            },
+            Node::Filter { source, predicate } => {
+                writeln!(f, "Filter: {}", predicate)?;
+                vec![source]
+            },
+            Node::NestedLoopJoin { left, right, using, predicate } => {
+                if let Some(predicate) = predicate {
+                    writeln!(f, "NestedLoopJoin: on {}", predicate)?;
+                } else if using.is_empty() {
+                    writeln!(f, "NestedLoopJoin: cross")?;
+                } else {
+                    let using = using.iter().map(|(l, r)| format!("#{} = #{}", l, r)).collect::<Vec<_>>();
+                    writeln!(f, "NestedLoopJoin: using {}", using.join(", "))?;
+                }
+                vec![left, right]
+            },
+            Node::Sort { source, order_by, method } => {
+                let order_by = order_by.iter().map(|(e, d)| format!("{} {}", e, d)).collect::<Vec<_>>();
+                match method {
+                    SortMethod::InMemory => writeln!(f, "Sort: {}", order_by.join(", "))?,
+                    SortMethod::External { .. } => writeln!(f, "Sort: {} (external)", order_by.join(", "))?,
+                }
+                vec![source]
+            },
+            Node::HashJoin { left, right, left_key, right_key } => {
+                writeln!(f, "HashJoin: on {} = {}", left_key, right_key)?;
+                vec![left, right]
+            },
+            Node::ShowEngineStatus => {
+                writeln!(f, "ShowEngineStatus")?;
+                vec![]
+            },
+            Node::CreateView { view } => {
+                writeln!(f, "CreateView: {}", quote_ident(&view.name))?;
+                vec![]
+            },
+            Node::DropView { name } => {
+                writeln!(f, "DropView: {}", quote_ident(name))?;
+                vec![]
+            },
+            Node::ShowViews => {
+                writeln!(f, "ShowViews")?;
+                vec![]
+            },
+            Node::DebugKeys { prefix } => {
+                match prefix {
+                    Some(prefix) => writeln!(f, "DebugKeys: {}", prefix)?,
+                    None => writeln!(f, "DebugKeys")?,
+                }
+                vec![]
+            },
+            Node::CreateIndex { index_name, table_name, column_name } => {
+                writeln!(f, "CreateIndex: {} on {}({})", quote_ident(index_name), quote_ident(table_name), quote_ident(column_name))?;
+                vec![]
+            },
+            Node::DropIndex { index_name } => {
+                writeln!(f, "DropIndex: {}", quote_ident(index_name))?;
+                vec![]
+            },
+            Node::TruncateTable { table_name } => {
+                writeln!(f, "TruncateTable: {}", quote_ident(table_name))?;
+                vec![]
+            },
+            Node::AnalyzeTable { table_name } => {
+                writeln!(f, "AnalyzeTable: {}", quote_ident(table_name))?;
+                vec![]
+            },
+            Node::Describe { table_name } => {
+                writeln!(f, "Describe: {}", quote_ident(table_name))?;
+                vec![]
+            },
+            Node::Projection { source, columns } => {
+                let columns = columns.iter().map(|c| format!("#{}", c)).collect::<Vec<_>>();
+                writeln!(f, "Projection: {}", columns.join(", "))?;
+                vec![source]
+            },
+            Node::Compute { source, expressions } => {
+                let expressions = expressions
+                    .iter()
+                    .map(|(e, name)| match e {
+                        Expression::Field(field) if field == name => e.to_string(),
+                        e if e.to_string() == *name => name.clone(),
+                        e => format!("{} AS {}", e, quote_ident(name)),
+                    })
+                    .collect::<Vec<_>>();
+                writeln!(f, "Compute: {}", expressions.join(", "))?;
+                vec![source]
+            },
+            Node::GroupBy { source, group_by, aggregates, having } => {
+                let list = |exprs: &[Expression]| exprs.iter().map(|e| e.to_string()).collect::<Vec<_>>().join(", ");
+                write!(f, "GroupBy: keys [{}], aggregates [{}]", list(group_by), list(aggregates))?;
+                match having {
+                    Some(having) => writeln!(f, " (having: {})", having)?,
+                    None => writeln!(f)?,
+                }
+                vec![source]
+            },
+            Node::Distinct { source } => {
+                writeln!(f, "Distinct")?;
+                vec![source]
+            },
+            Node::SetOperation { op, left, right, all } => {
+                match all {
+                    true => writeln!(f, "SetOperation: {} ALL", op)?,
+                    false => writeln!(f, "SetOperation: {}", op)?,
+                }
+                vec![left, right]
+            },
+            Node::Explain { inner } => {
+                writeln!(f, "Explain")?;
+                vec![inner]
+            },
+            Node::ExplainAnalyze { inner } => {
+                writeln!(f, "ExplainAnalyze")?;
+                vec![inner]
+            },
+        };
+        for child in children {
+            child.format(f, indent + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// 以缩进表示树形结构，每行一个节点
+// Projection: #1, #0
+// -> NestedLoopJoin: using #1 = #0
+//   -> Scan: a
+//   -> Scan: b (filter: z > 1)
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.format(f, 0)
+    }
+}
+
+// 执行计划中的表名，有别名时输出为 表名 AS 别名
+fn with_alias(name: &str, alias: &Option<String>) -> String {
+    match alias {
+        Some(alias) => format!("{} AS {}", quote_ident(name), quote_ident(alias)),
+        None => quote_ident(name).to_string(),
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Plan(pub Node);
 
 impl Plan {
-    pub fn build(stm: Statement) -> Self {
-        Planner::new().build(stm)
+    pub fn build<T: Transaction>(stm: Statement, txn: &T) -> Result<Self> {
+        Planner::new(txn).build(stm)
     }
 
-    pub fn execute<T: Transaction>(self, txn: &mut T) -> Result<ResultSet> {
-        <dyn Executor<T>>::build(self.0).execute(txn)
+    // 和 build 相同，语句中有 WITH 时先在 ctx 的事务中执行其中的查询
+    pub fn build_in_context<T: Transaction + 'static>(stm: Statement, ctx: &mut ExecutionContext<T>) -> Result<Self> {
+        let mut ctes = Ctes::new();
+        let stm = materialize_ctes(stm, ctx, &mut ctes)?;
+        Planner::new(&ctx.txn).with_ctes(&ctes).build(stm)
+    }
+
+    // 对执行计划进行优化，目前只做常量折叠
+    pub fn optimize(self) -> Plan {
+        Plan(optimizer::fold_constants(self.0))
+    }
+
+    // 将计划中的排序改为外部排序，缓存的行超过 threshold 字节时写入 spill_path 下的临时文件
+    pub fn with_external_sort(self, spill_path: PathBuf, threshold: usize) -> Plan {
+        fn rewrite(node: Node, method: &SortMethod) -> Node {
+            let rewrite = |node: Box<Node>| Box::new(rewrite(*node, method));
+            match node {
+                Node::Sort { source, order_by, .. } => Node::Sort { source: rewrite(source), order_by, method: method.clone() },
+                Node::Filter { source, predicate } => Node::Filter { source: rewrite(source), predicate },
+                Node::Projection { source, columns } => Node::Projection { source: rewrite(source), columns },
+                Node::Compute { source, expressions } => Node::Compute { source: rewrite(source), expressions },
+                Node::Distinct { source } => Node::Distinct { source: rewrite(source) },
+                Node::SetOperation { op, left, right, all } => {
+                    Node::SetOperation { op, left: rewrite(left), right: rewrite(right), all }
+                }
+                Node::GroupBy { source, group_by, aggregates, having } => {
+                    Node::GroupBy { source: rewrite(source), group_by, aggregates, having }
+                }
+                Node::NestedLoopJoin { left, right, using, predicate } => {
+                    Node::NestedLoopJoin { left: rewrite(left), right: rewrite(right), using, predicate }
+                }
+                Node::HashJoin { left, right, left_key, right_key } => {
+                    Node::HashJoin { left: rewrite(left), right: rewrite(right), left_key, right_key }
+                }
+                Node::Explain { inner } => Node::Explain { inner: rewrite(inner) },
+                Node::ExplainAnalyze { inner } => Node::ExplainAnalyze { inner: rewrite(inner) },
+                node => node,
+            }
+        }
+        Plan(rewrite(self.0, &SortMethod::External { spill_path, threshold }))
+    }
+
+    pub fn execute<T: Transaction + 'static>(self, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        self.execute_stream(ctx)?.collect()
+    }
+
+    // 执行计划，查询语句返回 ResultSet::Stream，行在被读取时才产生
+    pub fn execute_stream<T: Transaction + 'static>(self, ctx: &mut ExecutionContext<T>) -> Result<ResultSet> {
+        <dyn Executor<T>>::build(self.0).execute(ctx)
     }
 }
 
@@ -39,18 +387,22 @@ impl Plan {
 #[cfg(test)]
 mod tests {
     use crate::{
-        error::Result,
+        error::{Error, Result},
         sql::{
+            engine::{kv::KVEngine, Engine, Session},
+            executor::ResultSet,
             parser::{
                 ast::{self, Expression},
                 Parser,
             },
             plan::{Node, Plan},
         },
+        storage::memory::MemoryEngine,
     };
 
     #[test]
     fn test_plan_create_table() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new())?.begin()?;
         let sql1 = "
         create table tbl1 (
             a int default 100,
@@ -60,7 +412,7 @@ mod tests {
         );
         ";
         let stmt1 = Parser::new(sql1).parse()?;
-        let p1 = Plan::build(stmt1);
+        let p1 = Plan::build(stmt1, &txn)?;
 
         let sql2 = "
         create            table tbl1 (
@@ -71,7 +423,7 @@ mod tests {
         );
         ";
         let stmt2 = Parser::new(sql2).parse()?;
-        let p2 = Plan::build(stmt2);
+        let p2 = Plan::build(stmt2, &txn)?;
         assert_eq!(p1, p2);
 
         Ok(())
@@ -79,9 +431,10 @@ mod tests {
 
     #[test]
     fn test_plan_insert() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new())?.begin()?;
         let sql1 = "insert into tbl1 values (1, 2, 3, 'a', true);";
         let stmt1 = Parser::new(sql1).parse()?;
-        let p1 = Plan::build(stmt1);
+        let p1 = Plan::build(stmt1, &txn)?;
         assert_eq!(
             p1,
             Plan(Node::Insert {
@@ -99,7 +452,7 @@ mod tests {
 
         let sql2 = "insert into tbl2 (c1, c2, c3) values (3, 'a', true),(4, 'b', false);";
         let stmt2 = Parser::new(sql2).parse()?;
-        let p2 = Plan::build(stmt2);
+        let p2 = Plan::build(stmt2, &txn)?;
         assert_eq!(
             p2,
             Plan(Node::Insert {
@@ -125,16 +478,180 @@ mod tests {
 
     #[test]
     fn test_plan_select() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new())?.begin()?;
         let sql = "select * from tbl1;";
         let stmt = Parser::new(sql).parse()?;
-        let p = Plan::build(stmt);
+        let p = Plan::build(stmt, &txn)?;
         assert_eq!(
             p,
             Plan(Node::Scan {
                 table_name: "tbl1".to_string(),
+                alias: None,
+                filter: None,
+            })
+        );
+
+        // 单表的过滤条件放入扫描节点中
+        let stmt = Parser::new("select * from tbl1 where a > b;").parse()?;
+        assert_eq!(
+            Plan::build(stmt, &txn)?,
+            Plan(Node::Scan {
+                table_name: "tbl1".to_string(),
+                alias: None,
+                filter: Some(
+                    ast::Operation::GreaterThan(
+                        Box::new(Expression::Field("a".to_string())),
+                        Box::new(Expression::Field("b".to_string())),
+                    )
+                    .into()
+                ),
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_natural_join() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table a (x int, id int, y int);")?;
+        s.execute("create table b (id int, z int);")?;
+        s.execute("create table c (w int);")?;
+        let txn = kvengine.begin()?;
+
+        let stmt = Parser::new("select * from a natural join b;").parse()?;
+        assert_eq!(
+            Plan::build(stmt, &txn)?,
+            Plan(Node::Projection {
+                source: Box::new(Node::NestedLoopJoin {
+                    left: Box::new(Node::Scan { table_name: "a".to_string(), alias: None, filter: None }),
+                    right: Box::new(Node::Scan { table_name: "b".to_string(), alias: None, filter: None }),
+                    using: vec![(1, 0)],
+                    predicate: None,
+                }),
+                columns: vec![1, 0, 2, 4],
             })
         );
 
+        // 没有同名列
+        let stmt = Parser::new("select * from a natural join c;").parse()?;
+        assert!(matches!(Plan::build(stmt, &txn), Err(Error::Schema(_))));
+
+        // 表不存在
+        let stmt = Parser::new("select * from a natural join d;").parse()?;
+        assert_eq!(
+            Plan::build(stmt, &txn),
+            Err(Error::TableNotFound("d".to_string()))
+        );
         Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_plan_explain() -> Result<()> {
+        let kvengine = KVEngine::new(MemoryEngine::new())?;
+        let mut s = kvengine.session()?;
+        s.execute("create table a (x int, id int, y int);")?;
+        s.execute("create table b (id int, z int);")?;
+        s.execute("insert into a values (1, 1, 1);")?;
+
+        let explain = |s: &mut Session<KVEngine<MemoryEngine>>, sql: &str| -> Result<String> {
+            match s.execute(sql)?.result {
+                ResultSet::Explain { plan } => Ok(plan),
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(
+            explain(&mut s, "explain select * from a where x > 5 and (y = 'v' or y is null);")?,
+            "Scan: a (filter: x > 5 AND (y = 'v' OR y IS NULL))"
+        );
+        // 只涉及一张表的条件下推到这张表的扫描中
+        assert_eq!(
+            explain(&mut s, "explain select * from a natural join b where z != 1.5;")?,
+            [
+                "Projection: #1, #0, #2, #4",
+                "-> NestedLoopJoin: using #1 = #0",
+                "  -> Scan: a",
+                "  -> Scan: b (filter: z != 1.5)",
+            ]
+            .join("\n")
+        );
+        // 同名列下推到左表，涉及两张表的条件留在 Filter 中
+        assert_eq!(
+            explain(&mut s, "explain select * from a natural join b where id = 1 and x > z and z != 1.5;")?,
+            [
+                "Filter: x > z",
+                "-> Projection: #1, #0, #2, #4",
+                "  -> NestedLoopJoin: using #1 = #0",
+                "    -> Scan: a (filter: id = 1)",
+                "    -> Scan: b (filter: z != 1.5)",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            explain(&mut s, "explain select * from a cross join b where x > 1 or z = 1;")?,
+            [
+                "Filter: x > 1 OR z = 1",
+                "-> NestedLoopJoin: cross",
+                "  -> Scan: a",
+                "  -> Scan: b",
+            ]
+            .join("\n")
+        );
+        assert_eq!(explain(&mut s, "explain insert into a values (2, 2, 2);")?, "Insert: a (rows: 1)");
+
+        // 只输出计划，不会执行
+        match s.execute("select * from a;")?.result {
+            ResultSet::Scan { rows, .. } => assert_eq!(rows.len(), 1),
+            _ => unreachable!(),
+        }
+        // 计划阶段的错误依然会返回
+        assert!(s.execute("explain select * from missing natural join a;").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_plan_optimize() -> Result<()> {
+        let txn = KVEngine::new(MemoryEngine::new())?.begin()?;
+        let filter = |sql: &str| -> Result<Option<Expression>> {
+            let stmt = Parser::new(&format!("select * from t where {};", sql)).parse()?;
+            match Plan::build(stmt, &txn)?.optimize() {
+                Plan(Node::Scan { filter, .. }) => Ok(filter),
+                _ => unreachable!(),
+            }
+        };
+        let consts = |c: ast::Consts| Some(Expression::Consts(c));
+        let field = |name: &str| Box::new(Expression::Field(name.to_string()));
+
+        assert_eq!(filter("1 < 2")?, consts(ast::Consts::Boolean(true)));
+        assert_eq!(filter("'abc' like 'a%' and not 2.5 >= 3")?, consts(ast::Consts::Boolean(true)));
+        assert_eq!(filter("null = 1")?, consts(ast::Consts::Null));
+        assert_eq!(filter("null is null")?, consts(ast::Consts::Boolean(true)));
+        // 一边为常量 FALSE 的 AND、一边为常量 TRUE 的 OR
+        assert_eq!(filter("a > 1 and 1 > 2")?, consts(ast::Consts::Boolean(false)));
+        assert_eq!(filter("a > 1 or 2 = 2")?, consts(ast::Consts::Boolean(true)));
+        // 只折叠常量部分
+        assert_eq!(
+            filter("a > 1 and 1 = 1")?,
+            Some(
+                ast::Operation::And(
+                    Box::new(ast::Operation::GreaterThan(field("a"), Box::new(ast::Consts::Integer(1).into())).into()),
+                    Box::new(ast::Consts::Boolean(true).into()),
+                )
+                .into()
+            )
+        );
+        // 计算出错时保持原样，留到执行时报告
+        assert_eq!(
+            filter("1 > 'a'")?,
+            Some(
+                ast::Operation::GreaterThan(
+                    Box::new(ast::Consts::Integer(1).into()),
+                    Box::new(ast::Consts::String("a".to_string()).into()),
+                )
+                .into()
+            )
+        );
+        Ok(())
+    }
+}
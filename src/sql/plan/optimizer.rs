@@ -0,0 +1,144 @@
+use crate::sql::parser::ast::{Consts, Expression, Operation};
+
+use super::Node;
+
+// 常量折叠，对计划中的所有表达式生效
+pub fn fold_constants(node: Node) -> Node {
+    let fold = |node: Box<Node>| Box::new(fold_constants(*node));
+    match node {
+        Node::Insert { table_name, columns, values } => Node::Insert {
+            table_name,
+            columns,
+            values: values.into_iter().map(|row| row.into_iter().map(fold_expression).collect()).collect(),
+        },
+        Node::Scan { table_name, alias, filter } => Node::Scan { table_name, alias, filter: filter.map(fold_expression) },
+        Node::Filter { source, predicate } => Node::Filter { source: fold(source), predicate: fold_expression(predicate) },
+        Node::NestedLoopJoin { left, right, using, predicate } => Node::NestedLoopJoin {
+            left: fold(left),
+            right: fold(right),
+            using,
+            predicate: predicate.map(fold_expression),
+        },
+        Node::HashJoin { left, right, left_key, right_key } => {
+            Node::HashJoin { left: fold(left), right: fold(right), left_key, right_key }
+        },
+        Node::Projection { source, columns } => Node::Projection { source: fold(source), columns },
+        Node::Compute { source, expressions } => Node::Compute {
+            source: fold(source),
+            expressions: expressions.into_iter().map(|(e, name)| (fold_expression(e), name)).collect(),
+        },
+        // 分组的表达式和聚合函数决定了输出的列名，保持原样
+        Node::GroupBy { source, group_by, aggregates, having } => Node::GroupBy {
+            source: fold(source),
+            group_by,
+            aggregates,
+            having: having.map(fold_expression),
+        },
+        Node::Sort { source, order_by, method } => Node::Sort {
+            source: fold(source),
+            order_by: order_by.into_iter().map(|(e, d)| (fold_expression(e), d)).collect(),
+            method,
+        },
+        Node::Distinct { source } => Node::Distinct { source: fold(source) },
+        Node::SetOperation { op, left, right, all } => Node::SetOperation { op, left: fold(left), right: fold(right), all },
+        Node::Explain { inner } => Node::Explain { inner: fold(inner) },
+        Node::ExplainAnalyze { inner } => Node::ExplainAnalyze { inner: fold(inner) },
+        node @ (Node::CreateTable { .. }
+        | Node::InMemoryScan { .. }
+        | Node::CreateIndex { .. }
+        | Node::DropIndex { .. }
+        | Node::TruncateTable { .. }
+        | Node::AnalyzeTable { .. }
+        | Node::Describe { .. }
+        | Node::ShowEngineStatus
+        | Node::CreateView { .. }
+        | Node::DropView { .. }
+        | Node::ShowViews
+        | Node::DebugKeys { .. }) => node,
+    }
+}
+
+// 自底向上折叠表达式
+// 1. 操作数都是常量时直接计算出结果，例如 1 < 2 -> TRUE
+// 2. AND 的任意一边为 FALSE 时结果为 FALSE，OR 的任意一边为 TRUE 时结果为 TRUE
+// 计算出错（例如比较不同类型的值）时保持原样，错误留到执行时再报告
+pub fn fold_expression(expr: Expression) -> Expression {
+    let fold = |e: Box<Expression>| Box::new(fold_expression(*e));
+    let op = match expr {
+        Expression::Operation(op) => op,
+        Expression::Between { expr, low, high, negated } => {
+            return evaluate_constant(Expression::Between { expr: fold(expr), low: fold(low), high: fold(high), negated });
+        }
+        Expression::Cast(expr, datatype) => return evaluate_constant(Expression::Cast(fold(expr), datatype)),
+        Expression::InList { expr, list, negated } => {
+            let list = list.into_iter().map(fold_expression).collect();
+            return evaluate_constant(Expression::InList { expr: fold(expr), list, negated });
+        }
+        expr => return expr,
+    };
+    let op = match op {
+        Operation::And(l, r) => Operation::And(fold(l), fold(r)),
+        Operation::Or(l, r) => Operation::Or(fold(l), fold(r)),
+        Operation::Not(e) => Operation::Not(fold(e)),
+        Operation::Equal(l, r) => Operation::Equal(fold(l), fold(r)),
+        Operation::NotEqual(l, r) => Operation::NotEqual(fold(l), fold(r)),
+        Operation::GreaterThan(l, r) => Operation::GreaterThan(fold(l), fold(r)),
+        Operation::GreaterThanOrEqual(l, r) => Operation::GreaterThanOrEqual(fold(l), fold(r)),
+        Operation::LessThan(l, r) => Operation::LessThan(fold(l), fold(r)),
+        Operation::LessThanOrEqual(l, r) => Operation::LessThanOrEqual(fold(l), fold(r)),
+        Operation::Like(l, r) => Operation::Like(fold(l), fold(r)),
+        Operation::IsNull(e) => Operation::IsNull(fold(e)),
+    };
+
+    let is_bool = |e: &Expression, b: bool| matches!(e, Expression::Consts(Consts::Boolean(v)) if *v == b);
+    match &op {
+        Operation::And(l, r) if is_bool(l, false) || is_bool(r, false) => {
+            return Consts::Boolean(false).into();
+        }
+        Operation::Or(l, r) if is_bool(l, true) || is_bool(r, true) => {
+            return Consts::Boolean(true).into();
+        }
+        _ => {}
+    }
+
+    evaluate_constant(Expression::Operation(op))
+}
+
+// 操作数都是常量时计算出结果，否则保持原样
+fn evaluate_constant(expr: Expression) -> Expression {
+    if !is_constant(&expr) {
+        return expr;
+    }
+    match expr.evaluate(&[], &Vec::new()) {
+        Ok(value) => Consts::from(value).into(),
+        Err(_) => expr,
+    }
+}
+
+// 直接的操作数是否都是常量，子表达式已经折叠过，不需要递归判断
+fn is_constant(expr: &Expression) -> bool {
+    let c = |e: &Expression| matches!(e, Expression::Consts(_));
+    match expr {
+        Expression::Consts(_) => true,
+        Expression::Field(_)
+        | Expression::Parameter(_)
+        | Expression::Default
+        | Expression::Aggregate(..)
+        | Expression::QualifiedWildcard(_) => false,
+        Expression::Between { expr, low, high, .. } => c(expr) && c(low) && c(high),
+        Expression::InList { expr, list, .. } => c(expr) && list.iter().all(c),
+        Expression::Cast(expr, _) => c(expr),
+        Expression::Operation(op) => match op {
+            Operation::Not(e) | Operation::IsNull(e) => c(e),
+            Operation::And(l, r)
+            | Operation::Or(l, r)
+            | Operation::Equal(l, r)
+            | Operation::NotEqual(l, r)
+            | Operation::GreaterThan(l, r)
+            | Operation::GreaterThanOrEqual(l, r)
+            | Operation::LessThan(l, r)
+            | Operation::LessThanOrEqual(l, r)
+            | Operation::Like(l, r) => c(l) && c(r),
+        },
+    }
+}
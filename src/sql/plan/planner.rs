@@ -1,55 +1,647 @@
-use crate::sql::{parser::ast::Statement, schema::{Column, Table}, types::Value};
+use std::collections::{HashMap, HashSet};
 
-use super::{Node, Plan};
+use crate::{error::{Error, Result}, sql::{engine::Transaction, executor::{join_columns, ExecutionContext, ResultSet}, expression::column_position, parser::ast::{Consts, Expression, FromItem, JoinType, Operation, SetOperator, Statement}, schema::{Column, Table, View}, types::{Row, Value}}};
 
+use super::{Node, Plan, SortMethod};
 
+// 已经执行的 CTE，名字对应输出的列名和所有的行
+pub type Ctes = HashMap<String, (Vec<String>, Vec<Row>)>;
 
-pub struct Planner;
+pub struct Planner<'a, T: Transaction> {
+    // 规划 Join 时需要读取表结构
+    txn: &'a T,
+    // FROM 中的表名和 CTE 的名字相同时，读取 CTE 的结果而不是表
+    ctes: Option<&'a Ctes>,
+}
 
-impl Planner {
-    pub fn new() -> Self {
-        Self
+impl<'a, T: Transaction> Planner<'a, T> {
+    pub fn new(txn: &'a T) -> Self {
+        Self { txn, ctes: None }
     }
 
-    pub fn build(&mut self, stm: Statement) -> Plan {
-        Plan(self.build_statment(stm))
+    pub fn with_ctes(mut self, ctes: &'a Ctes) -> Self {
+        self.ctes = Some(ctes);
+        self
     }
 
-    fn build_statment(&self, stm: Statement) -> Node {
-        match stm {
-            Statement::CreateTable { name, columns } => {
-                Node::CreateTable { schema: Table{
+    pub fn build(&mut self, stm: Statement) -> Result<Plan> {
+        Ok(Plan(self.build_statment(stm)?))
+    }
+
+    fn build_statment(&self, stm: Statement) -> Result<Node> {
+        Ok(match stm {
+            Statement::CreateTable { name, mut columns, if_not_exists, or_replace, primary_key } => {
+                // 表级的 PRIMARY KEY (a, b) 转换为列的下标，并标记这些列为主键
+                let mut key = Vec::new();
+                for col_name in primary_key {
+                    let Some(i) = columns.iter().position(|c| c.name == col_name) else {
+                        return Err(Error::ColumnNotFound { table: name, column: col_name });
+                    };
+                    if key.contains(&i) {
+                        return Err(Error::Schema(format!("column {} appears more than once in the primary key", col_name)));
+                    }
+                    key.push(i);
+                }
+                if !key.is_empty() {
+                    if let Some(col) = columns.iter().enumerate().find(|(i, c)| c.primary_key && !key.contains(i)).map(|(_, c)| c) {
+                        return Err(Error::Schema(format!("column {} is declared as primary key but the table has a PRIMARY KEY constraint", col.name)));
+                    }
+                    for &i in &key {
+                        columns[i].primary_key = true;
+                    }
+                } else {
+                    key.push(columns.iter().position(|c| c.primary_key).unwrap_or(0));
+                }
+                Node::CreateTable { if_not_exists, or_replace, schema: Table{
                     name,
+                    primary_key: key,
                     columns: columns.into_iter().map(|c| {
-                        let nullable = c.nullable.unwrap_or(true);
+                        // 主键不能为空
+                        let nullable = c.nullable.unwrap_or(!c.primary_key);
+                        // 默认值保留为表达式，建表时校验类型，插入时按行计算
                         let default = match c.default {
-                            Some(expr) => {
-                                Some(Value::from_expression(expr))
-                            },
-                            None if nullable => Some(Value::Null),
+                            Some(expr) => Some(expr),
+                            // 自增列省略时先填充 NULL，写入时再分配
+                            None if nullable || c.auto_increment => Some(Consts::Null.into()),
                             None => None,
                         };
-                        Column {
+                        Ok(Column {
                             name: c.name,
                             datatype: c.datatype,
                             nullable,
                             default,
-                        }
-                    }).collect(),
+                            primary_key: c.primary_key,
+                            auto_increment: c.auto_increment,
+                            max_len: c.max_len,
+                            comment: c.comment,
+                        })
+                    }).collect::<Result<_>>()?,
+                    indexes: Vec::new(),
                 } }
             },
             Statement::Insert { table_name, columns, values } => {
+                self.check_updatable(&table_name)?;
                 Node::Insert { 
                     table_name, 
                     columns: columns.unwrap_or_default(), 
                     values 
                 }
             },
-            Statement::Select { table_name } => {
-                Node::Scan { 
-                    table_name 
+            Statement::Select { distinct, select, from, where_clause, group_by, having, order_by } => {
+                let others = where_clause.iter().chain(&group_by).chain(having.iter()).chain(order_by.iter().map(|(e, _)| e));
+                let select = self.resolve_qualified(&from, select, others.collect())?;
+                let mut node = self.build_from_item(from)?;
+                // 过滤条件尽量下推到扫描节点，在扫描的过程中过滤，无法下推的部分留在 Filter 中
+                if let Some(predicate) = where_clause {
+                    if let Some(agg) = predicate.find_aggregate() {
+                        return Err(Error::Parse(format!("aggregate function {} is not allowed in WHERE", agg)));
+                    }
+                    if let Some(predicate) = self.push_down_predicate(&mut node, predicate)? {
+                        node = Node::Filter { source: Box::new(node), predicate };
+                    }
+                }
+                // ORDER BY 中的别名替换为 SELECT 中对应的表达式
+                let mut order_by = order_by
+                    .into_iter()
+                    .map(|(e, d)| match &e {
+                        Expression::Field(name) => match select.iter().find(|(_, alias)| alias.as_ref() == Some(name)) {
+                            Some((expr, _)) => (expr.clone(), d),
+                            None => (e, d),
+                        },
+                        _ => (e, d),
+                    })
+                    .collect::<Vec<_>>();
+                let mut select = select
+                    .into_iter()
+                    .map(|(e, alias)| {
+                        // 没有别名时列名为表达式的文本，列引用保持原来的名字，不加引号
+                        let name = alias.unwrap_or_else(|| match &e {
+                            Expression::Field(name) => name.clone(),
+                            e => e.to_string(),
+                        });
+                        (e, name)
+                    })
+                    .collect::<Vec<_>>();
+
+                // 有 GROUP BY、HAVING 或者聚合函数时先分组，之后的表达式改为引用分组输出的列
+                let mut aggregates = Vec::new();
+                for expr in select.iter().map(|(e, _)| e).chain(having.iter()).chain(order_by.iter().map(|(e, _)| e)) {
+                    collect_aggregates(expr, &mut aggregates)?;
+                }
+                if !group_by.is_empty() || !aggregates.is_empty() || having.is_some() {
+                    if select.is_empty() {
+                        return Err(Error::Parse("SELECT * cannot be used with GROUP BY or aggregate functions".to_string()));
+                    }
+                    if let Some(agg) = group_by.iter().find_map(|e| e.find_aggregate()) {
+                        return Err(Error::Parse(format!("aggregate function {} is not allowed in GROUP BY", agg)));
+                    }
+                    let columns = group_by.iter().chain(&aggregates).map(|e| e.to_string()).collect::<Vec<_>>();
+                    let rewrite = |e: Expression| replace_grouped(e, &group_by, &aggregates, &columns);
+                    select = select.into_iter().map(|(e, name)| Ok((rewrite(e)?, name))).collect::<Result<_>>()?;
+                    order_by = order_by.into_iter().map(|(e, d)| Ok((rewrite(e)?, d))).collect::<Result<_>>()?;
+                    let having = having.map(rewrite).transpose()?;
+                    node = Node::GroupBy { source: Box::new(node), group_by, aggregates, having };
+                }
+
+                // 默认在内存中排序，Plan::with_external_sort 可以改为外部排序
+                // 排序在计算 SELECT 中的表达式之前，因此可以按没有输出的列排序
+                if !order_by.is_empty() {
+                    node = Node::Sort { source: Box::new(node), order_by, method: SortMethod::InMemory };
+                }
+                if !select.is_empty() {
+                    node = Node::Compute { source: Box::new(node), expressions: select };
+                }
+                // 在计算出输出的列之后去重，保留每组重复的行中第一次出现的行，因此排序的结果不受影响
+                if distinct {
+                    node = Node::Distinct { source: Box::new(node) };
+                }
+                node
+            },
+            // WITH 需要先执行其中的查询，见 materialize_ctes
+            Statement::With { .. } => {
+                return Err(Error::Internal("WITH must be materialized before planning".to_string()));
+            },
+            Statement::SetOperation { op, left, right, all } => {
+                let left = self.build_statment(*left)?;
+                let right = self.build_statment(*right)?;
+                check_set_columns(op, &self.output_columns(&left)?, &self.output_columns(&right)?)?;
+                Node::SetOperation { op, left: Box::new(left), right: Box::new(right), all }
+            },
+            Statement::ShowEngineStatus => Node::ShowEngineStatus,
+            // 建立视图时规划一次其中的查询，引用的表不存在或者列数不一致等错误在此时报告
+            // 视图中的表名不受当前语句中 CTE 的影响
+            Statement::CreateView { name, query } => {
+                let node = Planner::new(self.txn).build_statment((*query).clone())?;
+                Planner::new(self.txn).output_columns(&node)?;
+                Node::CreateView { view: View { name, query: *query } }
+            },
+            Statement::DropView { name } => Node::DropView { name },
+            Statement::ShowViews => Node::ShowViews,
+            Statement::DebugKeys { prefix } => Node::DebugKeys { prefix },
+            Statement::CreateIndex { index_name, table_name, column_name } => {
+                self.check_updatable(&table_name)?;
+                Node::CreateIndex { index_name, table_name, column_name }
+            },
+            Statement::DropIndex { index_name } => Node::DropIndex { index_name },
+            Statement::TruncateTable { table_name } => {
+                self.check_updatable(&table_name)?;
+                Node::TruncateTable { table_name }
+            },
+            Statement::AnalyzeTable { table_name } => {
+                self.check_updatable(&table_name)?;
+                Node::AnalyzeTable { table_name }
+            },
+            Statement::Describe { table_name } => Node::Describe { table_name },
+            // 事务控制语句由 Session 直接处理，不会生成执行计划
+            stmt @ (Statement::Begin { .. } | Statement::Commit | Statement::Rollback) => {
+                return Err(Error::Parse(format!("{:?} cannot be planned", stmt)));
+            },
+            Statement::Explain(stmt) => Node::Explain { inner: Box::new(self.build_statment(*stmt)?) },
+            Statement::ExplainAnalyze(stmt) => {
+                Node::ExplainAnalyze { inner: Box::new(self.build_statment(*stmt)?) }
+            },
+        })
+    }
+
+    fn build_from_item(&self, item: FromItem) -> Result<Node> {
+        Ok(match item {
+            FromItem::Table { name } => self.build_table(name, None)?,
+            FromItem::AliasedTable { name, alias } => self.build_table(name, Some(alias))?,
+            FromItem::Join { left, right, join_type } => {
+                let left = self.build_from_item(*left)?;
+                let right = self.build_from_item(*right)?;
+                match join_type {
+                    JoinType::Cross => Node::NestedLoopJoin {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        using: Vec::new(),
+                        predicate: None,
+                    },
+                    JoinType::Inner(predicate) => self.build_inner_join(left, right, predicate)?,
+                    JoinType::Natural => self.build_natural_join(left, right)?,
+                }
+            },
+        })
+    }
+
+    fn build_table(&self, name: String, alias: Option<String>) -> Result<Node> {
+        Ok(match self.ctes.and_then(|ctes| ctes.get(&name)) {
+            Some((columns, rows)) => Node::InMemoryScan { name, alias, columns: columns.clone(), rows: rows.clone() },
+            // 引用视图时替换为视图中查询的执行计划，视图的查询中同样不受 CTE 的影响
+            None => match self.txn.get_view(name.clone())? {
+                Some(view) => Planner::new(self.txn).build_statment(view.query)?,
+                None => Node::Scan { table_name: name, alias, filter: None },
+            },
+        })
+    }
+
+    // 检查查询中 表名.列名 形式的引用，并把 SELECT 中的 表名.* 展开为这张表的所有列
+    // 表有别名时只能通过别名引用，表名或者列不存在时报错，FROM 中同一个名字出现多次时需要用别名区分
+    // 没有带表名的引用时不读取表结构，表不存在的错误留给之后的规划和执行报告
+    fn resolve_qualified(
+        &self,
+        from: &FromItem,
+        select: Vec<(Expression, Option<String>)>,
+        exprs: Vec<&Expression>,
+    ) -> Result<Vec<(Expression, Option<String>)>> {
+        let mut names = Vec::new();
+        from_names(from, &mut names);
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(Error::Schema(format!("table name {} specified more than once, use an alias", name)));
+            }
+        }
+        let mut exprs = exprs;
+        join_predicates(from, &mut exprs);
+        // 之后加入的 SELECT 中的每一项可以是单独的 表名.*
+        let select_start = exprs.len();
+        exprs.extend(select.iter().map(|(e, _)| e));
+        let qualified = |e: &Expression| match e {
+            Expression::Field(name) => name.contains('.'),
+            e => matches!(e, Expression::QualifiedWildcard(_)),
+        };
+        if !exprs.iter().any(|e| contains(e, qualified)) {
+            return Ok(select);
+        }
+
+        let mut scope = Vec::new();
+        self.collect_scope(from, &mut scope)?;
+        let table_columns = |table: &str| -> Result<&Vec<String>> {
+            match scope.iter().find(|(name, _)| name == table) {
+                Some((_, columns)) => Ok(columns),
+                None => Err(Error::Schema(format!("table {} is not in the FROM clause", table))),
+            }
+        };
+        for (i, expr) in exprs.into_iter().enumerate() {
+            let wildcard = |e: &Expression| matches!(e, Expression::QualifiedWildcard(_));
+            if i < select_start || !wildcard(expr) {
+                if let Some(e) = find(expr, wildcard) {
+                    return Err(Error::Parse(format!("{} is only allowed as an item of the SELECT list", e)));
+                }
+            }
+            expr.clone().transform(&mut |e| match e {
+                Expression::Field(name) => {
+                    // 列名本身带有 . 时（例如视图输出的列）按完整的列名匹配
+                    if scope.iter().any(|(_, columns)| columns.contains(name)) {
+                        return Ok(None);
+                    }
+                    if let Some((table, column)) = name.split_once('.') {
+                        if !table_columns(table)?.iter().any(|c| c == column) {
+                            return Err(Error::Schema(format!("column {} not found in table {}", column, table)));
+                        }
+                    }
+                    Ok(None)
+                }
+                Expression::QualifiedWildcard(table) => {
+                    table_columns(table)?;
+                    Ok(None)
+                }
+                _ => Ok(None),
+            })?;
+        }
+
+        let mut expanded = Vec::new();
+        for (expr, alias) in select {
+            match expr {
+                Expression::QualifiedWildcard(table) => {
+                    if alias.is_some() {
+                        return Err(Error::Parse(format!("{} cannot have an alias", Expression::QualifiedWildcard(table))));
+                    }
+                    for column in table_columns(&table)? {
+                        expanded.push((Expression::Field(format!("{}.{}", table, column)), Some(column.clone())));
+                    }
+                }
+                expr => expanded.push((expr, alias)),
+            }
+        }
+        Ok(expanded)
+    }
+
+    // FROM 中每张表在查询中使用的名字（别名或者表名）和它的列名
+    fn collect_scope(&self, item: &FromItem, scope: &mut Vec<(String, Vec<String>)>) -> Result<()> {
+        let (name, alias) = match item {
+            FromItem::Table { name } => (name, None),
+            FromItem::AliasedTable { name, alias } => (name, Some(alias)),
+            FromItem::Join { left, right, .. } => {
+                self.collect_scope(left, scope)?;
+                return self.collect_scope(right, scope);
+            }
+        };
+        let columns = self.output_columns(&self.build_table(name.clone(), None)?)?;
+        scope.push((alias.unwrap_or(name).clone(), columns));
+        Ok(())
+    }
+
+    // 条件为左右两边各一列的等值比较时使用 HashJoin，否则逐对比较
+    fn build_inner_join(&self, left: Node, right: Node, predicate: Expression) -> Result<Node> {
+        if let Expression::Operation(Operation::Equal(lhs, rhs)) = &predicate {
+            if let (Expression::Field(a), Expression::Field(b)) = (lhs.as_ref(), rhs.as_ref()) {
+                // 列名按连接之后的列解析，和执行时计算条件的方式一致
+                let left_cols = self.output_columns(&left)?;
+                let left_len = left_cols.len();
+                let columns = join_columns(
+                    left_cols,
+                    self.output_columns(&right)?,
+                    &[],
+                    (left.table_alias().as_deref(), right.table_alias().as_deref()),
+                );
+                let keys = match (column_position(&columns, a), column_position(&columns, b)) {
+                    (Some(i), Some(j)) if i < left_len && j >= left_len => Some((lhs, rhs)),
+                    (Some(i), Some(j)) if j < left_len && i >= left_len => Some((rhs, lhs)),
+                    _ => None,
+                };
+                if let Some((left_key, right_key)) = keys {
+                    return Ok(Node::HashJoin {
+                        left: Box::new(left),
+                        right: Box::new(right),
+                        left_key: *left_key.clone(),
+                        right_key: *right_key.clone(),
+                    });
+                }
+            }
+        }
+        Ok(Node::NestedLoopJoin { left: Box::new(left), right: Box::new(right), using: Vec::new(), predicate: Some(predicate) })
+    }
+
+    // Natural Join 转换为按同名列等值连接，再通过投影去掉右表中重复的同名列
+    // 输出列的顺序为：同名列、左表其余列、右表其余列
+    // 两边没有同名列时返回错误，而不是退化为笛卡尔积
+    fn build_natural_join(&self, left: Node, right: Node) -> Result<Node> {
+        let left_cols = self.output_columns(&left)?;
+        let right_cols = self.output_columns(&right)?;
+
+        let mut using = Vec::new();
+        for (l, name) in left_cols.iter().enumerate() {
+            if let Some(r) = right_cols.iter().position(|c| c == name) {
+                using.push((l, r));
+            }
+        }
+        if using.is_empty() {
+            return Err(Error::Schema(format!(
+                "natural join has no common columns between ({}) and ({})",
+                left_cols.join(", "),
+                right_cols.join(", ")
+            )));
+        }
+
+        // 连接之后右表的列位于左表的列之后
+        let offset = left_cols.len();
+        let mut columns = using.iter().map(|(l, _)| *l).collect::<Vec<_>>();
+        columns.extend((0..left_cols.len()).filter(|i| !using.iter().any(|(l, _)| l == i)));
+        columns.extend(
+            (0..right_cols.len())
+                .filter(|i| !using.iter().any(|(_, r)| r == i))
+                .map(|i| i + offset),
+        );
+
+        Ok(Node::Projection {
+            source: Box::new(Node::NestedLoopJoin {
+                left: Box::new(left),
+                right: Box::new(right),
+                using,
+                predicate: None,
+            }),
+            columns,
+        })
+    }
+
+    // 把过滤条件下推到 node 下层的扫描节点中，返回无法下推的部分
+    // Join 时条件只涉及一侧的列就下推到这一侧，两侧都涉及的 AND 条件拆开后分别下推
+    // 两侧有同名列时，列名在计算时解析到左侧，因此优先下推到左侧
+    fn push_down_predicate(&self, node: &mut Node, predicate: Expression) -> Result<Option<Expression>> {
+        Ok(match node {
+            Node::Scan { filter, .. } => {
+                *filter = Some(match filter.take() {
+                    Some(f) => Operation::And(Box::new(f), Box::new(predicate)).into(),
+                    None => predicate,
+                });
+                None
+            },
+            // 投影只调整列的顺序，列名保持不变
+            Node::Projection { source, .. } | Node::Filter { source, .. } => {
+                self.push_down_predicate(source, predicate)?
+            },
+            Node::NestedLoopJoin { left, right, .. } | Node::HashJoin { left, right, .. } => {
+                let left_cols = self.output_columns(left)?;
+                let right_cols = self.output_columns(right)?;
+                let fields = predicate.fields();
+                if fields.iter().all(|f| left_cols.iter().any(|c| c == f)) {
+                    self.push_down_predicate(left, predicate)?
+                } else if fields
+                    .iter()
+                    .all(|f| right_cols.iter().any(|c| c == f) && !left_cols.iter().any(|c| c == f))
+                {
+                    self.push_down_predicate(right, predicate)?
+                } else if let Expression::Operation(Operation::And(lhs, rhs)) = predicate {
+                    match (self.push_down_predicate(node, *lhs)?, self.push_down_predicate(node, *rhs)?) {
+                        (Some(lhs), Some(rhs)) => Some(Operation::And(Box::new(lhs), Box::new(rhs)).into()),
+                        (lhs, rhs) => lhs.or(rhs),
+                    }
+                } else {
+                    Some(predicate)
                 }
             },
+            _ => Some(predicate),
+        })
+    }
+
+    // 视图只能用于查询
+    fn check_updatable(&self, name: &str) -> Result<()> {
+        if self.txn.get_view(name.to_string())?.is_some() {
+            return Err(Error::Internal("views are not updatable".to_string()));
+        }
+        Ok(())
+    }
+
+    // 计算节点输出的列名，同时检查下层的节点引用的表是否存在
+    fn output_columns(&self, node: &Node) -> Result<Vec<String>> {
+        Ok(match node {
+            Node::Scan { table_name, .. } => self
+                .txn
+                .must_get_table(table_name.clone())?
+                .columns
+                .into_iter()
+                .map(|c| c.name)
+                .collect(),
+            Node::NestedLoopJoin { left, right, using, .. } => join_columns(
+                self.output_columns(left)?,
+                self.output_columns(right)?,
+                using,
+                (left.table_alias().as_deref(), right.table_alias().as_deref()),
+            ),
+            Node::HashJoin { left, right, .. } => join_columns(
+                self.output_columns(left)?,
+                self.output_columns(right)?,
+                &[],
+                (left.table_alias().as_deref(), right.table_alias().as_deref()),
+            ),
+            Node::InMemoryScan { columns, .. } => columns.clone(),
+            Node::Filter { source, .. } | Node::Sort { source, .. } | Node::Distinct { source } => self.output_columns(source)?,
+            Node::Compute { source, expressions } => {
+                self.output_columns(source)?;
+                expressions.iter().map(|(_, name)| name.clone()).collect()
+            },
+            Node::SetOperation { left, .. } => self.output_columns(left)?,
+            Node::GroupBy { source, group_by, aggregates, .. } => {
+                self.output_columns(source)?;
+                group_by.iter().chain(aggregates).map(|e| e.to_string()).collect()
+            },
+            Node::Projection { source, columns } => {
+                let source = self.output_columns(source)?;
+                columns.iter().map(|i| source[*i].clone()).collect()
+            },
+            _ => return Err(Error::Internal(format!("node {:?} has no output columns", node))),
+        })
+    }
+}
+
+// 执行语句中的 WITH 子句，CTE 的结果按名字放入 ctes，返回 WITH 之后的查询
+// 之后规划查询时，引用 CTE 的地方会改为 InMemoryScan，因此 EXPLAIN 同样会执行 CTE 中的查询
+pub fn materialize_ctes<T: Transaction + 'static>(stmt: Statement, ctx: &mut ExecutionContext<T>, ctes: &mut Ctes) -> Result<Statement> {
+    match stmt {
+        Statement::With { recursive, ctes: queries, query } => {
+            for (name, query) in queries {
+                if ctes.contains_key(&name) {
+                    return Err(Error::Parse(format!("WITH query name {} specified more than once", name)));
+                }
+                let result = match *query {
+                    Statement::SetOperation { op: SetOperator::Union, left, right, all } if recursive => {
+                        materialize_recursive(&name, *left, *right, all, ctx, ctes)?
+                    },
+                    query => query_rows(query, ctx, ctes)?,
+                };
+                ctes.insert(name, result);
+            }
+            Ok(*query)
+        },
+        Statement::Explain(stmt) => Ok(Statement::Explain(Box::new(materialize_ctes(*stmt, ctx, ctes)?))),
+        Statement::ExplainAnalyze(stmt) => Ok(Statement::ExplainAnalyze(Box::new(materialize_ctes(*stmt, ctx, ctes)?))),
+        stmt => Ok(stmt),
+    }
+}
+
+// 递归的 CTE，先执行 UNION 左侧的查询，之后每一轮把上一轮新产生的行作为 CTE 的内容执行右侧的查询，直到不再产生新的行
+// UNION 去重时和之前的行重复的行不算新的行；UNION ALL 的查询不会结束时，只能通过取消或者超时中断
+fn materialize_recursive<T: Transaction + 'static>(
+    name: &str,
+    base: Statement,
+    step: Statement,
+    all: bool,
+    ctx: &mut ExecutionContext<T>,
+    ctes: &mut Ctes,
+) -> Result<(Vec<String>, Vec<Row>)> {
+    let (columns, mut rows) = query_rows(base, ctx, ctes)?;
+    let mut seen = HashSet::new();
+    if !all {
+        rows = distinct(rows, &mut seen);
+    }
+    let mut working = rows.clone();
+    while !working.is_empty() {
+        ctx.check_interrupt()?;
+        ctes.insert(name.to_string(), (columns.clone(), std::mem::take(&mut working)));
+        let (step_columns, new_rows) = query_rows(step.clone(), ctx, ctes)?;
+        check_set_columns(SetOperator::Union, &columns, &step_columns)?;
+        working = if all { new_rows } else { distinct(new_rows, &mut seen) };
+        rows.extend(working.iter().cloned());
+    }
+    ctes.remove(name);
+    Ok((columns, rows))
+}
+
+// 执行查询，返回输出的列名和所有的行
+fn query_rows<T: Transaction + 'static>(stmt: Statement, ctx: &mut ExecutionContext<T>, ctes: &Ctes) -> Result<(Vec<String>, Vec<Row>)> {
+    let plan = Planner::new(&ctx.txn).with_ctes(ctes).build(stmt)?.optimize();
+    match plan.execute(ctx)? {
+        ResultSet::Scan { columns, rows } => Ok((columns, rows)),
+        _ => Err(Error::Internal("WITH query must be a select statement".to_string())),
+    }
+}
+
+fn check_set_columns(op: SetOperator, left: &[String], right: &[String]) -> Result<()> {
+    if left.len() != right.len() {
+        return Err(Error::Schema(format!(
+            "each {} query must have the same number of columns, got {} and {}",
+            op,
+            left.len(),
+            right.len()
+        )));
+    }
+    Ok(())
+}
+
+// FROM 中每张表在查询中使用的名字，有别名时为别名
+fn from_names<'e>(item: &'e FromItem, names: &mut Vec<&'e str>) {
+    match item {
+        FromItem::Table { name } | FromItem::AliasedTable { alias: name, .. } => names.push(name),
+        FromItem::Join { left, right, .. } => {
+            from_names(left, names);
+            from_names(right, names);
         }
     }
-}
\ No newline at end of file
+}
+
+// FROM 中 Join 的 ON 条件
+fn join_predicates<'e>(item: &'e FromItem, predicates: &mut Vec<&'e Expression>) {
+    if let FromItem::Join { left, right, join_type } = item {
+        join_predicates(left, predicates);
+        join_predicates(right, predicates);
+        if let JoinType::Inner(predicate) = join_type {
+            predicates.push(predicate);
+        }
+    }
+}
+
+// 表达式中第一个满足 f 的子表达式
+fn find(expr: &Expression, f: impl Fn(&Expression) -> bool) -> Option<Expression> {
+    let mut found = None;
+    let _ = expr.clone().transform(&mut |e| {
+        if found.is_none() && f(e) {
+            found = Some(e.clone());
+        }
+        Ok(None)
+    });
+    found
+}
+
+fn contains(expr: &Expression, f: impl Fn(&Expression) -> bool) -> bool {
+    find(expr, f).is_some()
+}
+
+// 去掉和 seen 中重复的行，保持原来的顺序，NULL 和 NULL 视为相同
+fn distinct(rows: Vec<Row>, seen: &mut HashSet<Vec<Value>>) -> Vec<Row> {
+    rows.into_iter().filter(|row| seen.insert(row.iter().cloned().map(Value::group_key).collect())).collect()
+}
+
+// 表达式中的聚合函数，按出现的顺序去重之后加入 aggregates，聚合函数不能嵌套
+fn collect_aggregates(expr: &Expression, aggregates: &mut Vec<Expression>) -> Result<()> {
+    expr.clone().transform(&mut |e| {
+        let Expression::Aggregate(_, arg) = e else {
+            return Ok(None);
+        };
+        if let Some(nested) = arg.as_ref().and_then(|arg| arg.find_aggregate()) {
+            return Err(Error::Parse(format!("aggregate function {} cannot be nested in {}", nested, e)));
+        }
+        if !aggregates.contains(e) {
+            aggregates.push(e.clone());
+        }
+        Ok(Some(e.clone()))
+    })?;
+    Ok(())
+}
+
+// 分组之后只能引用分组的表达式和聚合函数的结果，把它们替换为 GroupBy 输出的列
+// 其余的列引用必须是输出的列，否则报错
+fn replace_grouped(expr: Expression, group_by: &[Expression], aggregates: &[Expression], columns: &[String]) -> Result<Expression> {
+    expr.transform(&mut |e| {
+        if group_by.contains(e) || aggregates.contains(e) {
+            return Ok(Some(Expression::Field(e.to_string())));
+        }
+        match e {
+            Expression::Field(name) if column_position(columns, name).is_none() => Err(Error::Schema(format!(
+                "column {} must appear in the GROUP BY clause or be used in an aggregate function",
+                name
+            ))),
+            _ => Ok(None),
+        }
+    })
+}
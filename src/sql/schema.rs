@@ -1,12 +1,119 @@
 use serde::{Serialize, Deserialize};
 
-use super::types::{DataType, Value};
+use crate::error::{Error, Result};
+
+use super::{parser::ast::{Expression, Statement}, types::{DataType, PrimaryKey, Row, Value}};
 
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
+    // 表上的二级索引
+    pub indexes: Vec<Index>,
+    // 主键所在列的下标，复合主键按定义的顺序排列，未声明主键时以第一列作为主键
+    pub primary_key: Vec<usize>,
+}
+
+impl Table {
+    // 校验表定义是否有效
+    pub fn validate(&self) -> Result<()> {
+        if self.columns.is_empty() {
+            return Err(Error::Schema(format!("table {} has no columns", self.name)));
+        }
+        // 列上标记的主键必须和主键的列一致，多个列各自声明 PRIMARY KEY 时报错
+        let flagged = self.columns.iter().enumerate().filter(|(_, c)| c.primary_key).map(|(i, _)| i).collect::<Vec<_>>();
+        if flagged.len() > 1 && flagged.iter().any(|i| !self.primary_key.contains(i)) {
+            return Err(Error::Schema(format!("table {} has multiple primary keys", self.name)));
+        }
+        if self.primary_key.is_empty() {
+            return Err(Error::Schema(format!("table {} has no primary key", self.name)));
+        }
+        for (n, &i) in self.primary_key.iter().enumerate() {
+            let Some(col) = self.columns.get(i) else {
+                return Err(Error::Schema(format!("primary key column {} does not exist in table {}", i, self.name)));
+            };
+            if self.primary_key[..n].contains(&i) {
+                return Err(Error::Schema(format!("column {} appears more than once in the primary key", col.name)));
+            }
+        }
+        for (i, col) in self.columns.iter().enumerate().filter(|(_, c)| c.auto_increment) {
+            // 自增列只能单独作为主键
+            if !col.primary_key || self.primary_key != [i] {
+                return Err(Error::Schema(format!("auto increment column {} must be the primary key", col.name)));
+            }
+            if col.datatype != DataType::Integer {
+                return Err(Error::Schema(format!("auto increment column {} must be an integer", col.name)));
+            }
+        }
+        // 默认值在建表时计算一次，检查类型和长度，插入时再按行重新计算
+        // 类型无法转换为列的类型时 default_value 返回 TypeMismatch
+        for col in &self.columns {
+            if let Some(default) = col.default_value()? {
+                col.check_len(&default)?;
+            }
+        }
+        Ok(())
+    }
+
+    // 一行中主键的值，按主键列的顺序排列
+    pub fn primary_key_of(&self, row: &Row) -> PrimaryKey {
+        self.primary_key.iter().map(|&i| row[i].clone()).collect()
+    }
+
+    // 列所在的下标
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name == name)
+    }
+
+    // 自增列的下标
+    pub fn auto_increment(&self) -> Option<usize> {
+        self.columns.iter().position(|c| c.auto_increment)
+    }
+
+    // 校验一行数据是否符合表的定义
+    // 将一行中的每个值转换为对应列的类型，多出的值保持原样，由 validate_row 报告列数不一致
+    pub fn coerce_row(&self, row: Row) -> Result<Row> {
+        row.into_iter()
+            .enumerate()
+            .map(|(i, value)| match self.columns.get(i) {
+                Some(col) => col.coerce(value),
+                None => Ok(value),
+            })
+            .collect()
+    }
+
+    pub fn validate_row(&self, row: &Row) -> Result<()> {
+        if row.len() != self.columns.len() {
+            return Err(Error::Schema(format!("table {} has {} columns but row has {} values", self.name, self.columns.len(), row.len())));
+        }
+        // 主键的每一列都不能为空，即使列定义允许 NULL
+        if let Some(&i) = self.primary_key.iter().find(|&&i| row[i] == Value::Null) {
+            return Err(Error::ConstraintViolation(format!("primary key column {} cannot be null", self.columns[i].name)));
+        }
+        // 检查类型有效性
+        for (col, value) in self.columns.iter().zip(row.iter()) {
+            match value.datatype() {
+                None if col.nullable => {},
+                None => return Err(Error::ConstraintViolation(format!("column {} cannot be null",col.name))),
+                // NaN 和无穷大没有确定的顺序，不允许写入
+                Some(_) if matches!(value, Value::Float(f) if !f.is_finite()) => {
+                    return Err(Error::ConstraintViolation(format!("column {} cannot store non-finite float {:?}", col.name, value)));
+                }
+                Some(dt) => {
+                    if dt != col.datatype {
+                        return Err(Error::TypeMismatch {
+                            column: col.name.clone(),
+                            expected: col.datatype.clone(),
+                            got: dt,
+                        });
+                    }
+                    col.check_len(value)?;
+                },
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
@@ -14,5 +121,63 @@ pub struct Column {
     pub name: String,
     pub datatype: DataType,
     pub nullable: bool,
-    pub default: Option<Value>,
-}
\ No newline at end of file
+    // 默认值表达式，插入时计算
+    pub default: Option<Expression>,
+    pub primary_key: bool,
+    // 插入时未指定值或者为 NULL，则自动分配下一个整数
+    pub auto_increment: bool,
+    // 字符串的最大字符数，为空时不限制
+    pub max_len: Option<usize>,
+    // 列的注释，只用于展示
+    pub comment: Option<String>,
+}
+
+impl Column {
+    // 字符串按字符数计算长度
+    fn check_len(&self, value: &Value) -> Result<()> {
+        match (value, self.max_len) {
+            (Value::String(s), Some(max_len)) if s.chars().count() > max_len => Err(Error::ConstraintViolation(format!(
+                "value for column {} exceeds maximum length {}",
+                self.name, max_len
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    // 计算默认值，并转换为列的类型
+    pub fn default_value(&self) -> Result<Option<Value>> {
+        self.default.as_ref().map(|expr| self.coerce(expr.evaluate_row(None)?)).transpose()
+    }
+
+    // 将值转换为列的类型，允许的转换见 Value::try_coerce
+    pub fn coerce(&self, value: Value) -> Result<Value> {
+        value.try_coerce(self.datatype.clone()).map_err(|err| match err {
+            Error::TypeMismatch { expected, got, .. } => Error::TypeMismatch { column: self.name.clone(), expected, got },
+            err => err,
+        })
+    }
+}
+
+// 视图，保存定义时的查询，查询视图时替换为这个查询的执行计划
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct View {
+    pub name: String,
+    pub query: Statement,
+}
+
+// 二级索引，索引中保存列值到主键的映射
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Index {
+    pub name: String,
+    pub column: String,
+}
+
+// ANALYZE 时统计的表信息，之后可以供规划器估算代价
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TableStats {
+    pub row_count: usize,
+    // 所有行编码之后的字节数之和，近似表在磁盘上占用的大小
+    pub size_bytes: u64,
+    // 统计的时间，UTC 毫秒数
+    pub analyzed_at: i64,
+}
@@ -1,6 +1,12 @@
+use std::{cmp::Ordering, fmt::Display, hash::{Hash, Hasher}};
+
 use serde::{Serialize,Deserialize};
 
-use super::parser::ast::{Consts, Expression};
+use crate::error::{Error, Result};
+
+use super::parser::ast::Expression;
+
+pub mod timestamp;
 
 // 数据类型，目前只有基本类型
 #[derive(Debug,Clone,Serialize,Deserialize, PartialEq)]
@@ -9,29 +15,187 @@ pub enum DataType {
     Integer,
     Float,
     String,
+    Timestamp,
+}
+
+impl Display for DataType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DataType::Boolean => "BOOLEAN",
+            DataType::Integer => "INTEGER",
+            DataType::Float => "FLOAT",
+            DataType::String => "STRING",
+            DataType::Timestamp => "TIMESTAMP",
+        })
+    }
 }
 
 
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub enum Value {
     Null,
     Boolean(bool),
     Integer(i64),
     Float(f64),
     String(String),
+    // UTC 的毫秒数
+    Timestamp(i64),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Value::Integer(i) => write!(f, "{}", i),
+            // 使用能够精确还原的最短表示，总是带有小数点或指数，例如 1.0、0.1、1e300，重新解析时仍然是浮点数
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Timestamp(t) => write!(f, "{}", timestamp::format_timestamp(*t)),
+        }
+    }
+}
+
+// 按类型和值比较，不同类型的值不相等，例如 1 和 1.0
+// 为了满足 Eq，NaN 和 NaN 相等，0.0 和 -0.0 相等，这样 Value 可以作为 HashMap 和 HashSet 的 key
+// SQL 中比较的语义由 Value::sql_cmp 决定，不受这里的影响
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Null, Value::Null) => true,
+            (Value::Boolean(a), Value::Boolean(b)) => a == b,
+            (Value::Integer(a), Value::Integer(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b || (a.is_nan() && b.is_nan()),
+            (Value::String(a), Value::String(b)) => a == b,
+            (Value::Timestamp(a), Value::Timestamp(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Value {}
+
+impl Hash for Value {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::mem::discriminant(self).hash(state);
+        match self {
+            Value::Null => {}
+            Value::Boolean(b) => b.hash(state),
+            Value::Integer(i) | Value::Timestamp(i) => i.hash(state),
+            // 相等的浮点数的二进制表示必须相同，-0.0 和所有的 NaN 先统一成一个值
+            Value::Float(f) => {
+                let f = if *f == 0.0 { 0.0 } else if f.is_nan() { f64::NAN } else { *f };
+                f.to_bits().hash(state)
+            }
+            Value::String(s) => s.hash(state),
+        }
+    }
+}
+
+// 和 PartialEq 一致：只有同类型的值可以比较，相等的值比较结果为 Equal，NaN 和其他浮点数无法比较
+impl PartialOrd for Value {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, Value::Null) => Some(Ordering::Equal),
+            (Value::Boolean(a), Value::Boolean(b)) => a.partial_cmp(b),
+            (Value::Integer(a), Value::Integer(b)) => a.partial_cmp(b),
+            (Value::Float(a), Value::Float(b)) if a.is_nan() && b.is_nan() => Some(Ordering::Equal),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (Value::String(a), Value::String(b)) => a.partial_cmp(b),
+            (Value::Timestamp(a), Value::Timestamp(b)) => a.partial_cmp(b),
+            _ => None,
+        }
+    }
 }
 
 impl Value {
-    pub fn from_expression(expr: Expression) -> Self {
-        match expr {
-            Expression::Consts(Consts::Null) => Self::Null,
-            Expression::Consts(Consts::Boolean(b)) => Self::Boolean(b),
-            Expression::Consts(Consts::Float(f)) => Self::Float(f),
-            Expression::Consts(Consts::Integer(i)) => Self::Integer(i),
-            Expression::Consts(Consts::String(s)) => Self::String(s),
+    // SQL 中的比较：同类型的值之间可以比较，整数和浮点数按数值比较，其他情况以及 NULL 无法比较
+    // 表达式计算、排序和 MIN、MAX 使用，NULL 由调用方按各自的语义处理
+    pub fn sql_cmp(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Null, _) | (_, Value::Null) => None,
+            (Value::Integer(a), Value::Float(b)) => (*a as f64).partial_cmp(b),
+            (Value::Float(a), Value::Integer(b)) => a.partial_cmp(&(*b as f64)),
+            (Value::Float(a), Value::Float(b)) => a.partial_cmp(b),
+            (a, b) => a.partial_cmp(b),
+        }
+    }
+
+    // 分组、去重和哈希连接时使用的 key，和 = 的比较结果一致：整数和数值相等的浮点数是同一个 key
+    pub fn group_key(self) -> Value {
+        match self {
+            Value::Float(f) if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => Value::Integer(f as i64),
+            value => value,
+        }
+    }
+
+    // 计算不依赖行数据的表达式，表达式中引用了列时报错
+    pub fn from_expression(expr: Expression) -> Result<Self> {
+        expr.evaluate_row(None)
+    }
+
+    // 转换为 JSON 值，非有限的浮点数在 JSON 中无法表示，转换为 null
+    pub fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null => serde_json::Value::Null,
+            Value::Boolean(b) => serde_json::Value::Bool(*b),
+            Value::Integer(i) => serde_json::Value::from(*i),
+            Value::Float(f) => serde_json::Number::from_f64(*f)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            Value::String(s) => serde_json::Value::String(s.clone()),
+            Value::Timestamp(t) => serde_json::Value::String(timestamp::format_timestamp(*t)),
         }
     }
 
+    // 将值转换为 target 类型，允许的转换为：
+    // - NULL 转换后仍然是 NULL，是否允许为空由列的约束检查
+    // - 相同类型的值保持不变
+    // - 整数转换为浮点数，超过 2^53 的整数会损失精度
+    // - ISO-8601 格式的字符串转换为时间戳，格式错误时返回解析错误
+    // 其他的转换返回 TypeMismatch，其中的列名为空，由调用方填入
+    pub fn try_coerce(&self, target: DataType) -> Result<Value> {
+        let Some(got) = self.datatype() else {
+            return Ok(Value::Null);
+        };
+        Ok(match (self, &target) {
+            _ if got == target => self.clone(),
+            (Value::Integer(i), DataType::Float) => Value::Float(*i as f64),
+            (Value::String(s), DataType::Timestamp) => Value::Timestamp(timestamp::parse_timestamp(s)?),
+            _ => return Err(Error::TypeMismatch { column: String::new(), expected: target, got }),
+        })
+    }
+
+    // CAST 的显式转换，除了 try_coerce 允许的转换之外还支持：
+    // - 字符串按内容解析为整数、浮点数、布尔值或时间戳，无法解析时返回类型不匹配的错误
+    // - 浮点数向零取整转换为整数，超出整数范围时报错
+    // - 任意类型的值按输出格式转换为字符串
+    // 其他的转换同样返回 Error::TypeMismatch，转换失败是值的问题，不是语句的语法错误
+    pub fn cast(&self, target: DataType) -> Result<Value> {
+        let invalid = || Error::TypeMismatch { column: String::new(), expected: target.clone(), got: self.datatype().unwrap() };
+        Ok(match (self, &target) {
+            (Value::String(s), DataType::Integer) => Value::Integer(s.trim().parse().map_err(|_| invalid())?),
+            (Value::String(s), DataType::Float) => match s.trim().parse::<f64>() {
+                Ok(f) if f.is_finite() => Value::Float(f),
+                _ => return Err(invalid()),
+            },
+            (Value::String(s), DataType::Boolean) => match s.trim().to_ascii_uppercase().as_str() {
+                "TRUE" => Value::Boolean(true),
+                "FALSE" => Value::Boolean(false),
+                _ => return Err(invalid()),
+            },
+            (Value::String(s), DataType::Timestamp) => Value::Timestamp(timestamp::parse_timestamp(s).map_err(|_| invalid())?),
+            (Value::Float(f), DataType::Integer) => {
+                if !f.is_finite() || *f < i64::MIN as f64 || *f >= i64::MAX as f64 {
+                    return Err(invalid());
+                }
+                Value::Integer(f.trunc() as i64)
+            }
+            (Value::Null, _) | (Value::String(_), DataType::String) => self.clone(),
+            (v, DataType::String) => Value::String(v.to_string()),
+            _ => self.try_coerce(target.clone())?,
+        })
+    }
+
     pub fn datatype(&self) -> Option<DataType>{
         match self {
             Value::Null => None,
@@ -39,8 +203,143 @@ impl Value {
             Value::Integer(_) => Some(DataType::Integer),
             Value::Float(_) => Some(DataType::Float),
             Value::String(_) => Some(DataType::String),
+            Value::Timestamp(_) => Some(DataType::Timestamp),
         }
     }
 }
 
-pub type Row = Vec<Value>;
\ No newline at end of file
+pub type Row = Vec<Value>;
+
+// 一行的主键，复合主键按定义的顺序包含每一列的值
+pub type PrimaryKey = Vec<Value>;
+
+// 单列的主键直接输出值，复合主键输出为 (a, b)
+pub fn format_primary_key(key: &[Value]) -> String {
+    match key {
+        [value] => value.to_string(),
+        key => format!("({})", key.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", ")),
+    }
+}
+
+// 按需读取的行，读取每一行时都可能出错
+pub type Rows = Box<dyn Iterator<Item = Result<Row>>>;
+#[cfg(test)]
+mod tests {
+    use std::{cmp::Ordering, collections::HashSet};
+
+    use crate::error::{Error, Result};
+
+    use super::{DataType, Value};
+
+    #[test]
+    fn test_value_hash() {
+        let values = [
+            Value::Float(1.5),
+            Value::String("a".to_string()),
+            Value::Float(1.5),
+            Value::Float(f64::NAN),
+            Value::Float(-f64::NAN),
+            Value::Float(0.0),
+            Value::Float(-0.0),
+            Value::String("a".to_string()),
+            Value::String("b".to_string()),
+            Value::Null,
+            Value::Null,
+            Value::Integer(1),
+            Value::Float(1.0),
+            Value::Timestamp(1),
+        ];
+        let set = values.iter().cloned().collect::<HashSet<_>>();
+        // NaN 只保留一个，0.0 和 -0.0 相同，不同类型的 1 不同
+        assert_eq!(set.len(), 9);
+        assert_eq!(Value::Float(f64::NAN), Value::Float(f64::NAN));
+        assert_ne!(Value::Integer(1), Value::Float(1.0));
+
+        // group_key 之后整数和数值相等的浮点数相同
+        let keys = values.into_iter().map(Value::group_key).collect::<HashSet<_>>();
+        assert_eq!(keys.len(), 8);
+        assert!(keys.contains(&Value::Integer(0)));
+        assert!(keys.contains(&Value::Float(1.5)));
+    }
+
+    #[test]
+    fn test_value_ordering() {
+        // partial_cmp 和 == 一致，不同类型的值无法比较
+        let (one, one_f) = (Value::Integer(1), Value::Float(1.0));
+        assert_eq!(one.partial_cmp(&one_f), None);
+        assert_eq!(Value::Null.partial_cmp(&Value::Null), Some(Ordering::Equal));
+        assert_eq!(Value::Float(f64::NAN).partial_cmp(&Value::Float(f64::NAN)), Some(Ordering::Equal));
+        assert_eq!(Value::Float(-0.0).partial_cmp(&Value::Float(0.0)), Some(Ordering::Equal));
+
+        // sql_cmp 按数值比较整数和浮点数，NULL 和 NaN 无法比较
+        assert_eq!(one.sql_cmp(&one_f), Some(Ordering::Equal));
+        assert_eq!(Value::Float(0.5).sql_cmp(&one), Some(Ordering::Less));
+        assert_eq!(Value::Null.sql_cmp(&Value::Null), None);
+        assert_eq!(Value::Float(f64::NAN).sql_cmp(&Value::Float(f64::NAN)), None);
+        assert_eq!(one.sql_cmp(&Value::String("1".to_string())), None);
+    }
+
+    #[test]
+    fn test_try_coerce() -> Result<()> {
+        let types = [DataType::Boolean, DataType::Integer, DataType::Float, DataType::String, DataType::Timestamp];
+        let values = [
+            Value::Boolean(true),
+            Value::Integer(3),
+            Value::Float(1.5),
+            Value::String("2024-01-02T03:04:05Z".to_string()),
+            Value::Timestamp(1704164645000),
+        ];
+        for value in &values {
+            for target in &types {
+                let got = value.datatype().unwrap();
+                let expected = match (value, target) {
+                    _ if &got == target => Some(value.clone()),
+                    (Value::Integer(3), DataType::Float) => Some(Value::Float(3.0)),
+                    (Value::String(_), DataType::Timestamp) => Some(Value::Timestamp(1704164645000)),
+                    _ => None,
+                };
+                match expected {
+                    Some(expected) => assert_eq!(value.try_coerce(target.clone())?, expected),
+                    None => assert_eq!(
+                        value.try_coerce(target.clone()),
+                        Err(Error::TypeMismatch { column: String::new(), expected: target.clone(), got })
+                    ),
+                }
+            }
+            // NULL 可以转换为任意类型
+            assert_eq!(Value::Null.try_coerce(value.datatype().unwrap())?, Value::Null);
+        }
+
+        // 不是数字的字符串不会转换为整数，格式错误的时间戳返回解析错误
+        assert!(matches!(Value::String("1".to_string()).try_coerce(DataType::Integer), Err(Error::TypeMismatch { .. })));
+        assert!(matches!(Value::String("2024".to_string()).try_coerce(DataType::Timestamp), Err(Error::Parse(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cast() -> Result<()> {
+        let s = |s: &str| Value::String(s.to_string());
+        assert_eq!(s(" 42 ").cast(DataType::Integer)?, Value::Integer(42));
+        assert_eq!(s("1e3").cast(DataType::Float)?, Value::Float(1000.0));
+        assert_eq!(s("true").cast(DataType::Boolean)?, Value::Boolean(true));
+        assert_eq!(Value::Float(-2.7).cast(DataType::Integer)?, Value::Integer(-2));
+        assert_eq!(Value::Integer(3).cast(DataType::Float)?, Value::Float(3.0));
+        assert_eq!(Value::Float(1.5).cast(DataType::String)?, s("1.5"));
+        assert_eq!(Value::Timestamp(0).cast(DataType::String)?, s("1970-01-01T00:00:00Z"));
+        assert_eq!(Value::Null.cast(DataType::Integer)?, Value::Null);
+
+        assert_eq!(
+            s("abc").cast(DataType::Integer),
+            Err(Error::TypeMismatch { column: String::new(), expected: DataType::Integer, got: DataType::String })
+        );
+        assert!(s("inf").cast(DataType::Float).is_err());
+        assert!(Value::Float(f64::NAN).cast(DataType::Integer).is_err());
+        assert!(matches!(Value::Boolean(true).cast(DataType::Integer), Err(Error::TypeMismatch { .. })));
+        assert_eq!(s("1970-01-01T00:00:01Z").cast(DataType::Timestamp)?, Value::Timestamp(1000));
+        assert_eq!(
+            s("2024").cast(DataType::Timestamp),
+            Err(Error::TypeMismatch { column: String::new(), expected: DataType::Timestamp, got: DataType::String })
+        );
+        Ok(())
+    }
+}
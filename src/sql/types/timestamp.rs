@@ -0,0 +1,122 @@
+use crate::error::{Error, Result};
+
+// 时间戳以 UTC 的毫秒数保存，字符串形式为 ISO-8601，例如 2024-01-02T03:04:05Z、2024-01-02T03:04:05.123Z
+
+const MILLIS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+// 解析 ISO-8601 格式的时间，只支持以 Z 结尾的 UTC 时间，秒之后可以有 1 到 3 位小数
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let invalid = || Error::Parse(format!("invalid timestamp '{}', expected format YYYY-MM-DDTHH:MM:SS[.fff]Z", s));
+    let b = s.as_bytes();
+    if !s.is_ascii() || b.len() < 20 || b[4] != b'-' || b[7] != b'-' || b[10] != b'T' || b[13] != b':' || b[16] != b':' {
+        return Err(invalid());
+    }
+    let num = |range: std::ops::Range<usize>| -> Result<i64> {
+        let part = &s[range];
+        if !part.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(invalid());
+        }
+        part.parse().map_err(|_| invalid())
+    };
+    let (year, month, day) = (num(0..4)?, num(5..7)?, num(8..10)?);
+    let (hour, minute, second) = (num(11..13)?, num(14..16)?, num(17..19)?);
+    let millis = match &s[19..] {
+        "Z" => 0,
+        rest if rest.starts_with('.') && rest.ends_with('Z') && (3..=5).contains(&rest.len()) => {
+            let frac = &rest[1..rest.len() - 1];
+            num(20..20 + frac.len())? * 10i64.pow(3 - frac.len() as u32)
+        }
+        _ => return Err(invalid()),
+    };
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) || hour > 23 || minute > 59 || second > 59 {
+        return Err(invalid());
+    }
+    Ok(days_from_civil(year, month, day) * MILLIS_PER_DAY + ((hour * 60 + minute) * 60 + second) * 1000 + millis)
+}
+
+// 格式化为 ISO-8601，毫秒为 0 时省略小数部分
+pub fn format_timestamp(millis: i64) -> String {
+    let (days, ms) = (millis.div_euclid(MILLIS_PER_DAY), millis.rem_euclid(MILLIS_PER_DAY));
+    let (year, month, day) = civil_from_days(days);
+    let secs = ms / 1000;
+    let time = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    match ms % 1000 {
+        0 => format!("{:04}-{:02}-{:02}T{}Z", year, month, day, time),
+        frac => format!("{:04}-{:02}-{:02}T{}.{:03}Z", year, month, day, time, frac),
+    }
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+// 公历日期到 1970-01-01 的天数，以 3 月为一年的开始，闰日位于一年的最后
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+// days_from_civil 的逆运算
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::Result;
+
+    use super::{format_timestamp, parse_timestamp};
+
+    #[test]
+    fn test_timestamp() -> Result<()> {
+        assert_eq!(parse_timestamp("1970-01-01T00:00:00Z")?, 0);
+        assert_eq!(parse_timestamp("2024-01-02T03:04:05Z")?, 1704164645000);
+        assert_eq!(parse_timestamp("2024-01-02T03:04:05.12Z")?, 1704164645120);
+        assert_eq!(parse_timestamp("1969-12-31T23:59:59.999Z")?, -1);
+        assert_eq!(parse_timestamp("2024-02-29T00:00:00Z")?, 1709164800000);
+
+        for ms in [0, -1, 1704164645000, 1704164645120, 1709164800000, 951782400000] {
+            assert_eq!(parse_timestamp(&format_timestamp(ms))?, ms);
+        }
+        assert_eq!(format_timestamp(1704164645120), "2024-01-02T03:04:05.120Z");
+        assert_eq!(format_timestamp(-1), "1969-12-31T23:59:59.999Z");
+
+        for s in [
+            "2024-01-02",
+            "2024-01-02 03:04:05Z",
+            "2024-01-02T03:04:05",
+            "2024-01-02T03:04:05+08:00",
+            "2024-13-02T03:04:05Z",
+            "2023-02-29T03:04:05Z",
+            "2024-01-02T24:00:00Z",
+            "2024-01-02T03:04:05.1234Z",
+            "2024-0a-02T03:04:05Z",
+            "+024-01-02T03:04:05Z",
+        ] {
+            assert!(parse_timestamp(s).is_err(), "{}", s);
+        }
+        Ok(())
+    }
+}
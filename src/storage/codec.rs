@@ -0,0 +1,242 @@
+use std::io::Write;
+
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::error::{Error, Result};
+
+// 存储中的 value 统一通过这里进行 bincode 编码和解码
+// 解码时限制最多读取的字节数，损坏或者恶意写入的数据中过大的长度前缀只会返回错误，不会尝试分配对应大小的内存
+// 编码格式和 bincode::serialize 相同（定长整数，允许末尾有多余的字节），已有的数据不需要迁移
+
+// 默认的单个 value 的大小上限，16MB，引擎可以通过各自的 set_max_value_size 修改
+pub const DEFAULT_MAX_VALUE_SIZE: u64 = 16 * 1024 * 1024;
+
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+}
+
+// 编码不限制大小，上限只在解码时检查
+pub fn serialize<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    Ok(options().serialize(value)?)
+}
+
+pub fn serialize_into<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+    Ok(options().serialize_into(writer, value)?)
+}
+
+// 按默认的大小上限解码
+pub fn deserialize<T: DeserializeOwned>(data: &[u8]) -> Result<T> {
+    deserialize_with_limit(data, DEFAULT_MAX_VALUE_SIZE)
+}
+
+// bincode 从切片解码时会忽略大小限制，这里按 reader 的方式解码，读取长度前缀之后先检查是否超过上限
+pub fn deserialize_with_limit<T: DeserializeOwned>(data: &[u8], limit: u64) -> Result<T> {
+    options().with_limit(limit).deserialize_from(data).map_err(|err| match err.as_ref() {
+        bincode::ErrorKind::SizeLimit => {
+            Error::Serialization(format!("value exceeds the size limit of {} bytes", limit))
+        }
+        _ => err.into(),
+    })
+}
+
+// 存储引擎对 value 的压缩，key 不压缩
+// 日志格式的引擎在每个压缩过的 value 开头保存一个字节的标记，表示使用的压缩方式，读取时按标记解压，
+// 因此修改引擎的 Codec 之后，之前写入的数据仍然可以读取
+pub trait Codec: Send + Sync {
+    // 写入 value 开头的标记，自定义的 Codec 不能和内置的重复
+    fn id(&self) -> u8;
+
+    fn encode(&self, data: &[u8]) -> Vec<u8>;
+
+    // 解压之后的数据超过 limit 时返回错误
+    fn decode(&self, data: &[u8], limit: u64) -> Result<Vec<u8>>;
+}
+
+pub const NOOP_CODEC: u8 = 0;
+pub const LZ4_CODEC: u8 = 1;
+pub const SNAPPY_CODEC: u8 = 2;
+
+// 不压缩，引擎默认使用
+pub struct NoopCodec;
+
+impl Codec for NoopCodec {
+    fn id(&self) -> u8 {
+        NOOP_CODEC
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        data.to_vec()
+    }
+
+    fn decode(&self, data: &[u8], _limit: u64) -> Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+// LZ4 块压缩，开头的 4 个字节为压缩前的长度
+pub struct Lz4Codec;
+
+impl Codec for Lz4Codec {
+    fn id(&self) -> u8 {
+        LZ4_CODEC
+    }
+
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        lz4_flex::compress_prepend_size(data)
+    }
+
+    fn decode(&self, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        // 解压时按记录的长度分配内存，先检查长度，避免损坏的数据导致过大的分配
+        let size = data.get(..4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+        check_decompressed_size(size.unwrap_or(0) as u64, limit)?;
+        lz4_flex::decompress_size_prepended(data).map_err(|err| Error::Internal(format!("lz4 decompress failed: {}", err)))
+    }
+}
+
+// Snappy 压缩
+pub struct SnappyCodec;
+
+impl Codec for SnappyCodec {
+    fn id(&self) -> u8 {
+        SNAPPY_CODEC
+    }
+
+    // 只有超过 4GB 的数据会压缩失败，这时原样返回，compress 会按不压缩保存
+    fn encode(&self, data: &[u8]) -> Vec<u8> {
+        snap::raw::Encoder::new().compress_vec(data).unwrap_or_else(|_| data.to_vec())
+    }
+
+    fn decode(&self, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+        let snappy_error = |err: snap::Error| Error::Internal(format!("snappy decompress failed: {}", err));
+        check_decompressed_size(snap::raw::decompress_len(data).map_err(snappy_error)? as u64, limit)?;
+        snap::raw::Decoder::new().decompress_vec(data).map_err(snappy_error)
+    }
+}
+
+fn check_decompressed_size(size: u64, limit: u64) -> Result<()> {
+    if size > limit {
+        return Err(Error::Internal(format!(
+            "decompressed size {} exceeds the size limit of {} bytes",
+            size,
+            limit
+        )));
+    }
+    Ok(())
+}
+
+// 用 codec 压缩 value，并在开头加上压缩方式的标记
+// 压缩之后没有变小的数据按不压缩保存，标记为 NOOP_CODEC
+pub fn compress(codec: &dyn Codec, data: &[u8]) -> Vec<u8> {
+    let encoded = match codec.id() {
+        NOOP_CODEC => None,
+        _ => Some(codec.encode(data)).filter(|encoded| encoded.len() < data.len()),
+    };
+    let (id, payload) = match &encoded {
+        Some(encoded) => (codec.id(), encoded.as_slice()),
+        None => (NOOP_CODEC, data),
+    };
+    let mut tagged = Vec::with_capacity(payload.len() + 1);
+    tagged.push(id);
+    tagged.extend_from_slice(payload);
+    tagged
+}
+
+// 按开头的标记解压 compress 的结果，标记和 codec 相同时使用 codec，否则使用内置的实现
+pub fn decompress(codec: &dyn Codec, data: &[u8], limit: u64) -> Result<Vec<u8>> {
+    match data.split_first() {
+        Some((&id, rest)) if id == codec.id() => codec.decode(rest, limit),
+        Some((&NOOP_CODEC, rest)) => Ok(rest.to_vec()),
+        Some((&LZ4_CODEC, rest)) => Lz4Codec.decode(rest, limit),
+        Some((&SNAPPY_CODEC, rest)) => SnappyCodec.decode(rest, limit),
+        Some((id, _)) => Err(Error::Internal(format!("unknown compression codec {}", id))),
+        None => Err(Error::Internal("compressed value has no codec tag".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, Result};
+
+    use super::{compress, decompress, deserialize, deserialize_with_limit, serialize, Codec, Lz4Codec, NoopCodec, SnappyCodec, DEFAULT_MAX_VALUE_SIZE, LZ4_CODEC, NOOP_CODEC, SNAPPY_CODEC};
+
+    #[test]
+    fn test_compatible_with_bincode() -> Result<()> {
+        let value = (1i64, "abc".to_string(), Some(vec![1u8, 2, 3]));
+        let data = serialize(&value)?;
+        assert_eq!(data, bincode::serialize(&value)?);
+        assert_eq!(deserialize::<(i64, String, Option<Vec<u8>>)>(&data)?, value);
+        Ok(())
+    }
+
+    #[test]
+    fn test_length_bomb() -> Result<()> {
+        // 长度前缀声明了 1TB 的数据，实际只有几个字节
+        let mut data = (1u64 << 40).to_le_bytes().to_vec();
+        data.extend_from_slice(b"abc");
+        match deserialize::<String>(&data) {
+            Err(Error::Serialization(msg)) => assert!(msg.contains("size limit"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(deserialize::<Vec<u64>>(&data), Err(Error::Serialization(_))));
+
+        let mut data = vec![1u8];
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(matches!(deserialize::<Option<Vec<u8>>>(&data), Err(Error::Serialization(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_configured_limit() -> Result<()> {
+        // 编码不受上限影响，解码时按传入的上限检查
+        let value = vec![7u8; 1000];
+        let data = serialize(&value)?;
+        assert_eq!(deserialize_with_limit::<Vec<u8>>(&data, 2000)?, value);
+        match deserialize_with_limit::<Vec<u8>>(&data, 100) {
+            Err(Error::Serialization(msg)) => assert!(msg.contains("size limit of 100 bytes"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        let tagged = compress(&Lz4Codec, &value);
+        assert_eq!(tagged[0], LZ4_CODEC);
+        assert_eq!(decompress(&NoopCodec, &tagged, 1000)?, value);
+        assert!(matches!(decompress(&NoopCodec, &tagged, 999), Err(Error::Internal(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_codecs() -> Result<()> {
+        let text = "select * from users where name = 'alice';".repeat(50).into_bytes();
+        let codecs: Vec<Box<dyn Codec>> = vec![Box::new(NoopCodec), Box::new(Lz4Codec), Box::new(SnappyCodec)];
+        for codec in &codecs {
+            let encoded = codec.encode(&text);
+            assert_eq!(codec.decode(&encoded, DEFAULT_MAX_VALUE_SIZE)?, text);
+            assert_eq!(codec.decode(&codec.encode(b""), DEFAULT_MAX_VALUE_SIZE)?, b"");
+
+            // 标记和使用的 codec 一致，任意一个 codec 都可以按标记解压
+            let tagged = compress(codec.as_ref(), &text);
+            assert_eq!(tagged[0], codec.id());
+            assert!(codec.id() == NOOP_CODEC || tagged.len() < text.len() / 5);
+            for other in &codecs {
+                assert_eq!(decompress(other.as_ref(), &tagged, DEFAULT_MAX_VALUE_SIZE)?, text);
+            }
+        }
+
+        // 压缩之后没有变小时不压缩
+        let short = b"abc".to_vec();
+        assert_eq!(compress(&Lz4Codec, &short), vec![NOOP_CODEC, b'a', b'b', b'c']);
+        assert_eq!(compress(&SnappyCodec, &short)[0], NOOP_CODEC);
+        assert_eq!(compress(&Lz4Codec, &text)[0], LZ4_CODEC);
+        assert_eq!(compress(&SnappyCodec, &text)[0], SNAPPY_CODEC);
+
+        assert!(matches!(decompress(&NoopCodec, &[9, 1, 2], DEFAULT_MAX_VALUE_SIZE), Err(Error::Internal(_))));
+        assert!(matches!(decompress(&NoopCodec, &[], DEFAULT_MAX_VALUE_SIZE), Err(Error::Internal(_))));
+        // 损坏的数据返回错误，记录的长度过大时不会按这个长度分配内存
+        assert!(matches!(Lz4Codec.decode(&[0xff, 0xff, 0xff, 0x7f, 1, 2], DEFAULT_MAX_VALUE_SIZE), Err(Error::Internal(_))));
+        assert!(matches!(Lz4Codec.decode(&[1], DEFAULT_MAX_VALUE_SIZE), Err(Error::Internal(_))));
+        assert!(matches!(SnappyCodec.decode(&[0xff, 0xff, 0xff, 0xff, 0x0f, 1], DEFAULT_MAX_VALUE_SIZE), Err(Error::Internal(_))));
+        Ok(())
+    }
+}
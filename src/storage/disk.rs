@@ -1,111 +1,1299 @@
-use std::{collections::BTreeMap, io::{BufWriter, Read, Seek, SeekFrom, Write}};
-use serde::de::value;
+use std::{collections::{btree_map, BTreeMap}, fs::{File, OpenOptions}, io::{BufReader, Read, Seek, SeekFrom, Write}, ops::Bound, path::{Path, PathBuf}, sync::{mpsc, Arc, Mutex, MutexGuard}, thread::JoinHandle};
 
-use crate::error::Result;
+use fs4::FileExt;
+use lru::LruCache;
 
-type KeyDir = BTreeMap<Vec<u8>, (u64,u32)>;
-const LOG_HEAD_SIZE:u32 = 8;
+use crate::error::{Error, Result};
+
+use super::{codec::{self, Codec, NoopCodec, NOOP_CODEC}, engine::WriteOp};
+
+// key -> (段的 id, value 在段中的偏移, value 的长度)
+type KeyDir = BTreeMap<Vec<u8>, (u32,u64,u32)>;
+// 日志头部：key_size(4) + val_size(4) + crc32(4)
+const LOG_HEAD_SIZE:u32 = 12;
+// key_size 的最高位表示 value 经过 codec 编码，开头是一个字节的编码方式标记，见 codec::compress
+// 没有这一位的条目保存的是原始的 value，和之前版本写入的日志兼容
+const CODEC_FLAG: u32 = 1 << 31;
+
+// 磁盘存储引擎配置
+#[derive(Debug, Clone)]
+pub struct DiskEngineConfig {
+    // 自上次压缩之后，日志文件增长超过该字节数时，在后台线程中触发压缩
+    pub compaction_threshold: u64,
+    // 读缓存能够容纳的 value 总字节数，为 0 时不缓存
+    pub cache_size_bytes: usize,
+    // Engine::flush 时把数据同步到磁盘的方式
+    pub sync_policy: SyncPolicy,
+    // 当前写入的日志文件超过该字节数时，转为只读的段，之后的数据写入新的文件
+    pub max_segment_size_bytes: u64,
+    // 解压 value 时允许的最大字节数，超过时读取返回错误，写入不受限制
+    pub max_value_size: u64,
+}
+
+impl Default for DiskEngineConfig {
+    fn default() -> Self {
+        Self {
+            compaction_threshold: 64 * 1024 * 1024,
+            cache_size_bytes: 8 * 1024 * 1024,
+            sync_policy: SyncPolicy::default(),
+            max_segment_size_bytes: 256 * 1024 * 1024,
+            max_value_size: codec::DEFAULT_MAX_VALUE_SIZE,
+        }
+    }
+}
+
+// 数据的同步方式，在 Engine::flush 时生效，MVCC 提交事务时会调用 flush
+// 日志条目直接写入文件，没有用户态的缓冲区，写入返回之后即使进程崩溃数据也不会丢失，
+// 各个策略的区别在于断电或者操作系统崩溃时已经提交的数据是否会丢失
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncPolicy {
+    // 不做任何处理，数据可能还在操作系统的页缓存中，断电时可能丢失
+    None,
+    // 只刷新用户态的缓冲区，不等待数据落盘，断电时同样可能丢失，适合测试等不需要持久性的场景
+    FlushOnly,
+    // fsync，数据和文件的元数据都落盘之后才返回，断电之后已经提交的数据不会丢失
+    #[default]
+    Fsync,
+    // fdatasync，只同步数据和读取数据必需的元数据（例如文件长度），不同步修改时间等元数据，
+    // 对追加写入的日志和 Fsync 有相同的持久性，通常更快
+    Fdatasync,
+}
 
 // 磁盘存储引擎
+// 内存索引和日志文件放在 Inner 中，由前台的读写和后台的压缩线程共享
+// 数据分成多个段，写入总是追加到 file_path 处的当前文件，文件超过 max_segment_size_bytes 时
+// 重命名为 file_path.<段的 id> 转为只读，打开时按 id 的顺序依次读取所有的段，最后读取当前文件
+// 读写模式打开时对 file_path.lock 加排他锁，同一时间只有一个进程可以写入，日志文件只加共享锁，
+// 其他进程可以通过 open_read_only 同时读取
 pub struct DiskEngine{
+    inner: Arc<Mutex<Inner>>,
+    config: DiskEngineConfig,
+    // 通知后台线程检查是否需要压缩
+    compact_tx: Option<mpsc::SyncSender<()>>,
+    compact_handle: Option<JoinHandle<()>>,
+    // 写入 value 时使用的 codec，默认不编码
+    codec: Box<dyn Codec>,
+    // 持有排他锁的 file_path.lock，只读模式下为 None
+    lock_file: Option<File>,
+}
+
+struct Inner {
     keydir: KeyDir,
-    log: Log,
+    // 只读的段，按 id 从小到大排列
+    segments: Vec<Log>,
+    // 当前写入的日志文件，id 比所有的段都大
+    active_log: Log,
+    path: PathBuf,
+    max_segment_size: u64,
+    // 所有日志文件的总大小
+    file_size: u64,
+    // 上一次压缩之后日志文件的总大小
+    compacted_size: u64,
+    // 热点 key 的读缓存
+    cache: ReadCache,
+    // 日志文件中的条目总数，包含过期的数据和删除标记
+    total_entries: u64,
 }
 
-impl super::engine::Engine for DiskEngine {
-    type EngineIterator<'a> = DiskEngineIterator;
+impl DiskEngine {
+    pub fn new(file_path: PathBuf) -> Result<Self> {
+        Self::with_config(file_path, DiskEngineConfig::default())
+    }
 
-    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        // 先写日志
-        let (offset,size) = self.log.write_entry(&key, Some(&value))?;
-        // 更新内存索引
+    pub fn with_config(file_path: PathBuf, config: DiskEngineConfig) -> Result<Self> {
+        // 先获取写锁，再读取日志文件，避免读到另一个写进程正在写入的数据
+        let lock_file = lock_path(&file_path);
+        if let Some(dir) = lock_file.parent() {
+            if !dir.as_os_str().is_empty() && !dir.exists() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let lock_file = OpenOptions::new().create(true).truncate(false).write(true).open(lock_file)?;
+        lock_file.try_lock_exclusive()?;
+        let inner = Arc::new(Mutex::new(Inner::open(file_path, &config, false)?));
+
+        // 启动后台压缩线程，引擎销毁时关闭通道，线程随之退出
+        let (compact_tx, compact_rx) = mpsc::sync_channel::<()>(1);
+        let compact_inner = inner.clone();
+        let threshold = config.compaction_threshold;
+        let compact_handle = std::thread::spawn(move || {
+            while compact_rx.recv().is_ok() {
+                let mut inner = match compact_inner.lock() {
+                    Ok(inner) => inner,
+                    Err(_) => return,
+                };
+                if inner.need_compact(threshold) {
+                    // 压缩失败时原日志文件保持不变，等待下一次触发
+                    let _ = inner.compact();
+                }
+            }
+        });
+
+        Ok(Self {
+            inner,
+            config,
+            compact_tx: Some(compact_tx),
+            compact_handle: Some(compact_handle),
+            codec: Box::new(NoopCodec),
+            lock_file: Some(lock_file),
+        })
+    }
+
+    // 以只读模式打开，日志文件只加共享锁，可以和读写模式打开的引擎同时使用，例如备份或者调试工具
+    // 只能看到打开时已经写入的数据，写入、删除和压缩返回 Error::ReadOnly，不启动后台压缩线程
+    pub fn open_read_only(file_path: PathBuf) -> Result<Self> {
+        let config = DiskEngineConfig::default();
+        Ok(Self {
+            inner: Arc::new(Mutex::new(Inner::open(file_path, &config, true)?)),
+            config,
+            compact_tx: None,
+            compact_handle: None,
+            codec: Box::new(NoopCodec),
+            lock_file: None,
+        })
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.lock_file.is_none()
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.is_read_only() {
+            return Err(Error::ReadOnly(format!("{} is opened read-only", self.inner.lock()?.path.display())));
+        }
+        Ok(())
+    }
+
+    // 修改之后写入的 value 使用的 codec，已经写入的数据按各自的标记读取，不需要重写
+    pub fn set_codec(&mut self, codec: Box<dyn Codec>) {
+        self.codec = codec;
+    }
+
+    // 按 codec 编码 value，不编码时返回 None，直接写入原来的 value
+    fn encode_value(&self, value: &[u8]) -> Option<Vec<u8>> {
+        match self.codec.id() {
+            NOOP_CODEC => None,
+            _ => Some(codec::compress(self.codec.as_ref(), value)),
+        }
+    }
+
+    // 打开日志文件并立即进行一次压缩
+    pub fn new_compact(file_path: PathBuf) -> Result<Self> {
+        let eng = Self::new(file_path)?;
+        eng.compact()?;
+        Ok(eng)
+    }
+
+    // 在当前线程中同步执行压缩
+    pub fn compact(&self) -> Result<()> {
+        self.check_writable()?;
+        self.inner.lock()?.compact()
+    }
+
+    // 写入之后检查日志大小，超过阈值则通知后台线程压缩
+    fn maybe_compact(&self, inner: &Inner) {
+        if inner.need_compact(self.config.compaction_threshold) {
+            if let Some(tx) = &self.compact_tx {
+                // 通道已满说明已经有待处理的压缩请求
+                let _ = tx.try_send(());
+            }
+        }
+    }
+}
+
+impl Drop for DiskEngine {
+    fn drop(&mut self) {
+        // 关闭通道，等待后台线程退出
+        self.compact_tx.take();
+        if let Some(handle) = self.compact_handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Inner {
+    // 按段的顺序从日志文件中恢复内存索引，后面的段覆盖前面的段
+    fn open(file_path: PathBuf, config: &DiskEngineConfig, read_only: bool) -> Result<Self> {
+        let open_log = |path: PathBuf, id: u32| match read_only {
+            true => Log::open_read_only(path, id),
+            false => Log::new(path, id),
+        };
+        let mut keydir = KeyDir::new();
+        let mut total_entries = 0;
+        let mut file_size = 0;
+        let mut segments = Vec::new();
+        for id in segment_ids(&file_path)? {
+            let mut log = open_log(segment_path(&file_path, id), id)?;
+            total_entries += log.build_keydir(&mut keydir)?;
+            file_size += log.file.metadata()?.len();
+            segments.push(log);
+        }
+        let active_id = segments.last().map_or(1, |log| log.id + 1);
+        let mut active_log = open_log(file_path.clone(), active_id)?;
+        total_entries += active_log.build_keydir(&mut keydir)?;
+        file_size += active_log.file.metadata()?.len();
+        Ok(Self {
+            keydir,
+            segments,
+            active_log,
+            path: file_path,
+            max_segment_size: config.max_segment_size_bytes,
+            file_size,
+            compacted_size: file_size,
+            cache: ReadCache::new(config.cache_size_bytes),
+            total_entries,
+        })
+    }
+
+    fn need_compact(&self, threshold: u64) -> bool {
+        self.file_size - self.compacted_size.min(self.file_size) > threshold
+    }
+
+    fn log_mut(&mut self, id: u32) -> Result<&mut Log> {
+        if id == self.active_log.id {
+            return Ok(&mut self.active_log);
+        }
+        match self.segments.binary_search_by_key(&id, |log| log.id) {
+            Ok(i) => Ok(&mut self.segments[i]),
+            Err(_) => Err(Error::Internal(format!("log segment {} does not exist", id))),
+        }
+    }
+
+    // 读取文件中保存的 value，以及它是否经过 codec 编码
+    fn read_value(&mut self, key: &[u8], (id, offset, val_size): (u32, u64, u32)) -> Result<(Vec<u8>, bool)> {
+        self.log_mut(id)?.read_value(key, offset, val_size)
+    }
+
+    // 记录写入当前文件的一个条目，返回它在 keydir 中的位置
+    fn append(&mut self, offset: u64, size: u32, val_size: u32) -> (u32, u64, u32) {
+        self.file_size += size as u64;
+        self.total_entries += 1;
         // 100----------------|-----150
         //                   130
         // val size = 20
-        let val_size = value.len() as u32;
-        // 条目中存入 value 在文件中的偏移以及 value 的长度
-        self.keydir.insert(key, (offset + size as u64 - val_size as u64, val_size));
+        (self.active_log.id, offset + size as u64 - val_size as u64, val_size)
+    }
+
+    // 当前文件超过大小限制时转为只读的段，之后写入新的文件
+    // 一批写入总是位于同一个文件中，因此只在写入之后检查
+    fn maybe_rotate(&mut self) -> Result<()> {
+        if self.active_log.file.metadata()?.len() <= self.max_segment_size {
+            return Ok(());
+        }
+        let id = self.active_log.id;
+        self.active_log.file.sync_all()?;
+        // 重命名之后文件句柄仍然有效，段中的数据在 keydir 中的位置不变
+        // 在这之后崩溃时，重新打开会创建新的当前文件
+        std::fs::rename(&self.path, segment_path(&self.path, id))?;
+        let log = std::mem::replace(&mut self.active_log, Log::new(self.path.clone(), id + 1)?);
+        self.segments.push(log);
+        Ok(())
+    }
+
+    // 将所有段中有效的数据重写到 .compact 临时文件中，再通过 rename 原子地替换原来的文件
+    fn compact(&mut self) -> Result<()> {
+        let mut compact_path = self.path.clone();
+        compact_path.set_extension("compact");
+
+        // 只有一个文件时直接替换当前文件，否则压缩的结果作为一个新的段，id 为当前文件的 id
+        let id = self.active_log.id;
+        let mut new_log = Log::new(compact_path.clone(), id)?;
+        // 清理上一次压缩中断时残留的数据
+        new_log.file.set_len(0)?;
+        let mut new_keydir = KeyDir::new();
+        let positions = self.keydir.iter().map(|(k, pos)| (k.clone(), *pos)).collect::<Vec<_>>();
+        // 编码过的 value 原样写入，不需要解码
+        for (key, pos) in positions {
+            let (value, encoded) = self.read_value(&key, pos)?;
+            let (new_offset, new_size) = new_log.write_entry(&key, Some(&value), encoded)?;
+            let val_size = value.len() as u32;
+            new_keydir.insert(key, (id, new_offset + new_size as u64 - val_size as u64, val_size));
+        }
+        new_log.file.sync_all()?;
+
+        if self.segments.is_empty() {
+            std::fs::rename(&compact_path, &self.path)?;
+            self.active_log = new_log;
+        } else {
+            // 压缩的结果放在所有的段之后、当前文件之前，重新打开时依次读取所有的文件，
+            // 先读到的旧数据会被压缩的结果和当前文件覆盖，已经删除的 key 在当前文件或者旧的段中仍有删除标记
+            // 因此在下面的任何一步崩溃都不会丢失数据，也不会让删除的 key 重新出现
+            // 旧的段需要从最早的开始删除，否则更早的段中已经删除的 key 会失去删除标记
+            std::fs::rename(&compact_path, segment_path(&self.path, id))?;
+            for log in self.segments.drain(..) {
+                std::fs::remove_file(segment_path(&self.path, log.id))?;
+            }
+            self.active_log.file.set_len(0)?;
+            self.active_log.file.sync_all()?;
+            self.active_log.id = id + 1;
+            self.segments.push(new_log);
+        }
+
+        self.file_size = self.segments.iter().chain([&self.active_log]).map(|log| log.file.metadata().map(|m| m.len())).sum::<std::io::Result<u64>>()?;
+        self.compacted_size = self.file_size;
+        self.keydir = new_keydir;
+        self.total_entries = self.keydir.len() as u64;
+        // 压缩之后数据的偏移都变了，清空缓存
+        self.cache.clear();
+        Ok(())
+    }
+}
+
+// 按标记解码文件中读出的 value
+fn decode_value(codec: &dyn Codec, limit: u64, (value, encoded): (Vec<u8>, bool)) -> Result<Vec<u8>> {
+    match encoded {
+        true => codec::decompress(codec, &value, limit),
+        false => Ok(value),
+    }
+}
+
+// 读写模式下加排他锁的文件，例如 sqldb-log.lock
+fn lock_path(path: &Path) -> PathBuf {
+    let mut path = path.to_path_buf().into_os_string();
+    path.push(".lock");
+    PathBuf::from(path)
+}
+
+// 只读的段的文件名，例如 sqldb-log.00000001
+fn segment_path(path: &Path, id: u32) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{:08}", id));
+    path.with_file_name(name)
+}
+
+// 目录中属于 path 的所有段的 id，从小到大排列
+fn segment_ids(path: &Path) -> Result<Vec<u32>> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let prefix = format!("{}.", path.file_name().unwrap_or_default().to_string_lossy());
+    let mut ids = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let name = name.to_string_lossy();
+        if let Some(id) = name.strip_prefix(&prefix) {
+            if id.len() == 8 && id.bytes().all(|b| b.is_ascii_digit()) {
+                ids.push(id.parse()?);
+            }
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+// 按 value 总字节数限制容量的 LRU 缓存
+struct ReadCache {
+    lru: LruCache<Vec<u8>, Vec<u8>>,
+    capacity: usize,
+    current_size: usize,
+}
+
+impl ReadCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            lru: LruCache::unbounded(),
+            capacity,
+            current_size: 0,
+        }
+    }
+
+    fn get(&mut self, key: &[u8]) -> Option<Vec<u8>> {
+        self.lru.get(key).cloned()
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.remove(&key);
+        // 超过整个缓存容量的 value 不缓存
+        if value.len() > self.capacity {
+            return;
+        }
+        self.current_size += value.len();
+        self.lru.put(key, value);
+        // 淘汰最久未使用的条目，直到总大小不超过容量
+        while self.current_size > self.capacity {
+            match self.lru.pop_lru() {
+                Some((_, v)) => self.current_size -= v.len(),
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(v) = self.lru.pop(key) {
+            self.current_size -= v.len();
+        }
+    }
+
+    fn clear(&mut self) {
+        self.lru.clear();
+        self.current_size = 0;
+    }
+}
+
+impl super::engine::Engine for DiskEngine {
+    type EngineIterator<'a> = DiskEngineIterator<'a>;
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        self.check_writable()?;
+        let encoded = self.encode_value(&value);
+        let stored = encoded.as_deref().unwrap_or(&value);
+        let mut inner = self.inner.lock()?;
+        // 先写日志
+        let (offset,size) = inner.active_log.write_entry(key, Some(stored), encoded.is_some())?;
+        // 更新内存索引，条目中存入 value 所在的段、在文件中的偏移以及文件中 value 的长度
+        let pos = inner.append(offset, size, stored.len() as u32);
+        inner.keydir.insert(key.to_vec(), pos);
+        inner.cache.insert(key.to_vec(), value);
+        inner.maybe_rotate()?;
+        self.maybe_compact(&inner);
         Ok(())
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
-        match self.keydir.get(&key) {
-            Some((offset,val_size)) => {
-                let val = self.log.read_value(*offset, *val_size)?;
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut inner = self.inner.lock()?;
+        // 先查缓存
+        if let Some(val) = inner.cache.get(key) {
+            return Ok(Some(val));
+        }
+        match inner.keydir.get(key).copied() {
+            Some(pos) => {
+                let val = decode_value(self.codec.as_ref(), self.config.max_value_size, inner.read_value(key, pos)?)?;
+                // 只有未命中缓存时才需要拷贝 key
+                inner.cache.insert(key.to_vec(), val.clone());
                 Ok(Some(val))
             },
             None => Ok(None)
         }
     }
 
-    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
+    fn contains_key(&mut self, key: &[u8]) -> Result<bool> {
+        // 只查内存索引，不读取文件
+        Ok(self.inner.lock()?.keydir.contains_key(key))
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.check_writable()?;
+        let mut inner = self.inner.lock()?;
         // 删除则写入None 并且从 keydir 中删除key条目
-        self.log.write_entry(&key, None)?;
-        self.keydir.remove(&key);
+        let (offset, size) = inner.active_log.write_entry(key, None, false)?;
+        inner.append(offset, size, 0);
+        inner.keydir.remove(key);
+        inner.cache.remove(key);
+        inner.maybe_rotate()?;
+        self.maybe_compact(&inner);
         Ok(())
     }
 
+    // 所有条目连续地写入日志，只获取一次锁
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        self.check_writable()?;
+        let encoded = ops
+            .iter()
+            .map(|op| match op {
+                WriteOp::Set(_, value) => self.encode_value(value),
+                WriteOp::Delete(_) => None,
+            })
+            .collect::<Vec<_>>();
+        let mut inner = self.inner.lock()?;
+        let positions = inner.active_log.write_batch(&ops, &encoded)?;
+        for ((op, (offset, size)), encoded) in ops.into_iter().zip(positions).zip(encoded) {
+            match op {
+                WriteOp::Set(key, value) => {
+                    let val_size = encoded.map_or(value.len(), |e| e.len());
+                    let pos = inner.append(offset, size, val_size as u32);
+                    inner.keydir.insert(key.clone(), pos);
+                    inner.cache.insert(key, value);
+                }
+                WriteOp::Delete(key) => {
+                    inner.append(offset, size, 0);
+                    inner.keydir.remove(&key);
+                    inner.cache.remove(&key);
+                }
+            }
+        }
+        inner.maybe_rotate()?;
+        self.maybe_compact(&inner);
+        Ok(())
+    }
+
+    // 按照配置的同步方式持久化数据
+    fn flush(&mut self) -> Result<()> {
+        // 只读模式下没有需要持久化的数据，只读事务提交时同样会调用 flush
+        if self.is_read_only() {
+            return Ok(());
+        }
+        // 只读的段在转换时已经落盘，只需要同步当前文件
+        self.inner.lock()?.active_log.sync(self.config.sync_policy)
+    }
+
     fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
-        todo!()
+        // 迭代期间持有锁，避免后台压缩改变数据的偏移
+        // 锁中毒时只读地继续使用其中的数据
+        let inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        DiskEngineIterator {
+            inner,
+            codec: self.codec.as_ref(),
+            max_value_size: self.config.max_value_size,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
+    }
+}
+
+impl super::engine::EngineStats for DiskEngine {
+    fn entry_count(&self) -> Result<usize> {
+        Ok(self.inner.lock()?.keydir.len())
+    }
+
+    fn disk_size_bytes(&self) -> Result<u64> {
+        let inner = self.inner.lock()?;
+        let mut size = inner.active_log.file.metadata()?.len();
+        for log in &inner.segments {
+            size += log.file.metadata()?.len();
+        }
+        Ok(size)
+    }
+
+    // 只根据内存索引计算，不读取文件，编码过的 value 按文件中的长度计算
+    fn live_data_bytes(&self) -> Result<u64> {
+        Ok(self.inner.lock()?.keydir.iter().map(|(k, (_, _, val_size))| k.len() as u64 + *val_size as u64).sum())
+    }
+
+    fn live_entry_ratio(&self) -> Result<f64> {
+        let inner = self.inner.lock()?;
+        if inner.total_entries == 0 {
+            return Ok(1.0);
+        }
+        Ok(inner.keydir.len() as f64 / inner.total_entries as f64)
     }
 }
 
+// 每次迭代时根据剩余的范围在 keydir 中查找下一个 key
+pub struct DiskEngineIterator<'a> {
+    inner: MutexGuard<'a, Inner>,
+    codec: &'a dyn Codec,
+    max_value_size: u64,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
 
-pub struct DiskEngineIterator {
+impl<'a> DiskEngineIterator<'a> {
+    // 剩余的扫描范围，正向和反向迭代相遇之后范围为空
+    // BTreeMap::range 在起点大于终点时会 panic，需要提前判断
+    fn range(&self) -> Option<btree_map::Range<'_, Vec<u8>, (u32, u64, u32)>> {
+        if let (
+            Bound::Included(s) | Bound::Excluded(s),
+            Bound::Included(e) | Bound::Excluded(e),
+        ) = (&self.start, &self.end)
+        {
+            let both_included = matches!((&self.start, &self.end), (Bound::Included(_), Bound::Included(_)));
+            if s > e || (s == e && !both_included) {
+                return None;
+            }
+        }
+        Some(self.inner.keydir.range((self.start.clone(), self.end.clone())))
+    }
 
+    fn read(&mut self, key: Vec<u8>, pos: (u32, u64, u32)) -> <Self as Iterator>::Item {
+        let value = decode_value(self.codec, self.max_value_size, self.inner.read_value(&key, pos)?)?;
+        Ok((key, value))
+    }
 }
 
-impl super::engine::EngineIterator for DiskEngineIterator {
-    
+impl<'a> super::engine::EngineIterator for DiskEngineIterator<'a> {
+
 }
 
-impl Iterator for DiskEngineIterator {
+impl<'a> Iterator for DiskEngineIterator<'a> {
     type Item = Result<(Vec<u8>,Vec<u8>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        todo!()
+        let (key, pos) = self.range()?.next().map(|(k, pos)| (k.clone(), *pos))?;
+        self.start = Bound::Excluded(key.clone());
+        Some(self.read(key, pos))
     }
 }
 
-impl DoubleEndedIterator for DiskEngineIterator {
+impl<'a> DoubleEndedIterator for DiskEngineIterator<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        todo!()
+        let (key, pos) = self.range()?.next_back().map(|(k, pos)| (k.clone(), *pos))?;
+        self.end = Bound::Excluded(key.clone());
+        Some(self.read(key, pos))
     }
 }
 
 
+// 日志条目格式
+// | key_size(u32) | val_size(i32) | crc32(u32) | key | value |
+// val_size 为 -1 表示该条目是删除标记，crc32 覆盖 key 和 value 的内容
+// key_size 的最高位为 CODEC_FLAG，表示 value 经过 codec 编码
 pub struct Log {
-    file: std::fs::File
+    // 段的 id，keydir 中通过 id 找到 value 所在的文件
+    id: u32,
+    file: File,
+    // 从文件中读取 value 的次数
+    reads: u64,
+    // 等待数据落盘的次数
+    syncs: u64,
+    // 只读模式打开，不会修改文件
+    read_only: bool,
 }
 
 impl Log {
-    
-    fn write_entry(&mut self,key: &Vec<u8>, value: Option<&Vec<u8>>) -> Result<(u64,u32)> {
+    fn new(file_path: PathBuf, id: u32) -> Result<Self> {
+        // 如果目录不存在则创建
+        if let Some(dir) = file_path.parent() {
+            if !dir.exists() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(file_path)?;
+        // 写进程之间通过 DiskEngine 的 lock_file 互斥，这里只加共享锁，允许只读模式同时打开
+        FileExt::try_lock_shared(&file)?;
+        Ok(Self { id, file, reads: 0, syncs: 0, read_only: false })
+    }
+
+    // 以只读方式打开已经存在的日志文件
+    fn open_read_only(file_path: PathBuf, id: u32) -> Result<Self> {
+        let file = OpenOptions::new().read(true).open(file_path)?;
+        FileExt::try_lock_shared(&file)?;
+        Ok(Self { id, file, reads: 0, syncs: 0, read_only: true })
+    }
+
+    // 遍历日志文件，把其中的条目依次应用到内存索引中
+    // 返回日志中的条目总数
+    // 写入时崩溃可能只写入了最后一个条目的一部分，这时把文件截断到这个条目的开头，之前完整的条目不受影响
+    fn build_keydir(&mut self, keydir: &mut KeyDir) -> Result<u64> {
+        let mut total_entries = 0;
+        let file_size = self.file.metadata()?.len();
+        let mut reader = BufReader::new(&self.file);
+        let mut offset = 0;
+        while offset < file_size {
+            let Some((key, val_size)) = Self::read_entry(&mut reader, offset, file_size)? else {
+                // 只读模式下末尾不完整的条目可能是写进程正在写入的，忽略即可，不能截断
+                if self.read_only {
+                    break;
+                }
+                eprintln!(
+                    "warning: log entry at offset {} is truncated, discarding the last {} bytes",
+                    offset,
+                    file_size - offset
+                );
+                self.file.set_len(offset)?;
+                self.file.sync_all()?;
+                break;
+            };
+            let key_size = key.len() as u32;
+            total_entries += 1;
+            match val_size {
+                Some(val_size) => {
+                    let val_offset = offset + (LOG_HEAD_SIZE + key_size) as u64;
+                    keydir.insert(key, (self.id, val_offset, val_size));
+                    offset = val_offset + val_size as u64;
+                }
+                None => {
+                    keydir.remove(&key);
+                    offset += (LOG_HEAD_SIZE + key_size) as u64;
+                }
+            }
+        }
+        Ok(total_entries)
+    }
+
+    // 读取 offset 处的一个完整条目并校验，返回 key 以及 value 的长度（删除标记为 None）
+    // 条目超出文件末尾时返回 None
+    fn read_entry(reader: &mut BufReader<&File>, offset: u64, file_size: u64) -> Result<Option<(Vec<u8>, Option<u32>)>> {
+        if offset + LOG_HEAD_SIZE as u64 > file_size {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(offset))?;
+        let mut len_buf = [0;4];
+        reader.read_exact(&mut len_buf)?;
+        let key_size = u32::from_be_bytes(len_buf) & !CODEC_FLAG;
+        reader.read_exact(&mut len_buf)?;
+        let val_size = match i32::from_be_bytes(len_buf) {
+            l if l >= 0 => Some(l as u32),
+            _ => None,
+        };
+        reader.read_exact(&mut len_buf)?;
+        let crc = u32::from_be_bytes(len_buf);
+        let end = offset + LOG_HEAD_SIZE as u64 + key_size as u64 + val_size.unwrap_or(0) as u64;
+        if end > file_size {
+            return Ok(None);
+        }
+
+        let mut key = vec![0;key_size as usize];
+        reader.read_exact(&mut key)?;
+        let mut value = vec![0;val_size.unwrap_or(0) as usize];
+        reader.read_exact(&mut value)?;
+        Self::verify_checksum(crc, &key, &value)?;
+        Ok(Some((key, val_size)))
+    }
+
+    fn write_entry(&mut self,key: &[u8], value: Option<&[u8]>, encoded: bool) -> Result<(u64,u32)> {
         // 定位到文件末尾
         let offset = self.file.seek(SeekFrom::End(0))?;
+        let mut buf = Vec::new();
+        let total_size = Self::encode_entry(&mut buf, key, value, encoded);
+        self.file.write_all(&buf)?;
+        // 返回相对应文件的偏移，和写入的总长度。
+        Ok((offset, total_size))
+    }
+
+    // 将多个条目编码到同一个缓冲区中，一次写入文件末尾，返回每个条目的偏移和长度
+    // encoded 和 ops 一一对应，不为 None 时写入编码之后的 value
+    fn write_batch(&mut self, ops: &[WriteOp], encoded: &[Option<Vec<u8>>]) -> Result<Vec<(u64, u32)>> {
+        let mut offset = self.file.seek(SeekFrom::End(0))?;
+        let mut buf = Vec::new();
+        let mut positions = Vec::with_capacity(ops.len());
+        for (op, encoded) in ops.iter().zip(encoded) {
+            let size = match (op, encoded) {
+                (WriteOp::Set(key, _), Some(encoded)) => Self::encode_entry(&mut buf, key, Some(encoded), true),
+                (WriteOp::Set(key, value), None) => Self::encode_entry(&mut buf, key, Some(value), false),
+                (WriteOp::Delete(key), _) => Self::encode_entry(&mut buf, key, None, false),
+            };
+            positions.push((offset, size));
+            offset += size as u64;
+        }
+        self.file.write_all(&buf)?;
+        Ok(positions)
+    }
+
+    // 按日志格式编码一个条目，返回条目的总长度
+    fn encode_entry(buf: &mut Vec<u8>, key: &[u8], value: Option<&[u8]>, encoded: bool) -> u32 {
         // 计算长度
         let key_size = key.len() as u32;
         let val_size = value.map_or(0, |v| v.len() as u32);
-        let total_size = key_size + val_size + LOG_HEAD_SIZE;
-        // 拿到写入缓存
-        let mut writer = BufWriter::with_capacity(total_size as usize, &self.file);
-        writer.write_all(&key_size.to_be_bytes())?;
-        writer.write_all(&value.map_or(-1, |v| v.len() as i32).to_be_bytes())?;
-        writer.write_all(&key)?;
+        // 计算校验和
+        let crc = Self::checksum(key, value.unwrap_or_default());
+        let flag = if encoded { CODEC_FLAG } else { 0 };
+        buf.extend_from_slice(&(key_size | flag).to_be_bytes());
+        buf.extend_from_slice(&value.map_or(-1, |v| v.len() as i32).to_be_bytes());
+        buf.extend_from_slice(&crc.to_be_bytes());
+        buf.extend_from_slice(key);
         if let Some(v) = value {
-            writer.write_all(&v)?;
+            buf.extend_from_slice(v);
         }
-        writer.flush();
-        // 返回相对应文件的偏移，和写入的总长度。
-        Ok((offset, total_size))
+        key_size + val_size + LOG_HEAD_SIZE
+    }
+
+    fn sync(&mut self, policy: SyncPolicy) -> Result<()> {
+        match policy {
+            SyncPolicy::None => {}
+            SyncPolicy::FlushOnly => self.file.flush()?,
+            SyncPolicy::Fsync => {
+                self.file.sync_all()?;
+                self.syncs += 1;
+            }
+            SyncPolicy::Fdatasync => {
+                self.file.sync_data()?;
+                self.syncs += 1;
+            }
+        }
+        Ok(())
     }
 
-    fn read_value(&mut self,offset: u64, val_size: u32) -> Result<Vec<u8>> {
-        // 定位到 value 所在位置
-        self.file.seek(SeekFrom::Start(offset))?;
+    // 返回文件中保存的 value，以及 value 是否经过 codec 编码
+    fn read_value(&mut self, key: &[u8], offset: u64, val_size: u32) -> Result<(Vec<u8>, bool)> {
+        self.reads += 1;
+        // value 前面紧挨着的是头部和 key，一起读出来用于校验
+        let key_size = key.len() as u64;
+        self.file.seek(SeekFrom::Start(offset - key_size - LOG_HEAD_SIZE as u64))?;
+        let mut head = [0;LOG_HEAD_SIZE as usize];
+        self.file.read_exact(&mut head)?;
+        let encoded = u32::from_be_bytes([head[0], head[1], head[2], head[3]]) & CODEC_FLAG != 0;
+        let crc = u32::from_be_bytes([head[8], head[9], head[10], head[11]]);
+        let mut key_buf = vec![0;key_size as usize];
+        self.file.read_exact(&mut key_buf)?;
         // 定义存储 value 的 buf
         let mut buf = vec![0;val_size as usize];
         self.file.read_exact(&mut buf)?;
-        Ok(buf)
+        Self::verify_checksum(crc, &key_buf, &buf)?;
+        Ok((buf, encoded))
+    }
+
+    fn checksum(key: &[u8], value: &[u8]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(key);
+        hasher.update(value);
+        hasher.finalize()
+    }
+
+    fn verify_checksum(crc: u32, key: &[u8], value: &[u8]) -> Result<()> {
+        if Self::checksum(key, value) != crc {
+            return Err(Error::Internal("checksum mismatch".to_string()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::{Seek, SeekFrom, Write}, time::{Duration, Instant}};
+
+    use crate::{error::{Error, Result}, storage::{codec::{Lz4Codec, SnappyCodec}, engine::{Engine, EngineStats, WriteOp}}};
+
+    use super::{segment_ids, segment_path, DiskEngine, DiskEngineConfig, SyncPolicy};
+
+    #[test]
+    fn test_disk_engine_reopen() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let mut eng = DiskEngine::new(path.clone())?;
+        eng.set(b"aa", b"value1".to_vec())?;
+        eng.set(b"bb", b"value2".to_vec())?;
+        eng.set(b"aa", b"value3".to_vec())?;
+        eng.delete(b"bb")?;
+        drop(eng);
+
+        // 重新打开后能从日志中恢复数据
+        let mut eng = DiskEngine::new(path)?;
+        assert_eq!(eng.get(b"aa")?, Some(b"value3".to_vec()));
+        assert_eq!(eng.get(b"bb")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_checksum() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        // 关闭读缓存，保证读取时访问文件
+        let config = DiskEngineConfig { cache_size_bytes: 0, ..Default::default() };
+        let mut eng = DiskEngine::with_config(path.clone(), config)?;
+        eng.set(b"aa", b"value1".to_vec())?;
+
+        // 篡改 value 的最后一个字节
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::End(-1))?;
+        file.write_all(b"x")?;
+        drop(file);
+
+        assert_eq!(
+            eng.get(b"aa"),
+            Err(Error::Internal("checksum mismatch".to_string()))
+        );
+        drop(eng);
+
+        // 重新打开时同样会校验失败
+        assert!(DiskEngine::new(path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_torn_write() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let mut eng = DiskEngine::new(path.clone())?;
+        eng.set(b"aa", b"value1".to_vec())?;
+        eng.set(b"bb", b"value2".to_vec())?;
+        eng.delete(b"aa")?;
+        drop(eng);
+        let valid_len = std::fs::metadata(&path)?.len();
+
+        // 最后一个条目少写了一个字节
+        let mut eng = DiskEngine::new(path.clone())?;
+        eng.set(b"cc", b"value3".to_vec())?;
+        drop(eng);
+        let file = OpenOptions::new().write(true).open(&path)?;
+        file.set_len(file.metadata()?.len() - 1)?;
+        drop(file);
+
+        // 重新打开时丢弃不完整的条目，之前的条目都可以读取
+        let mut eng = DiskEngine::new(path.clone())?;
+        assert_eq!(std::fs::metadata(&path)?.len(), valid_len);
+        assert_eq!(eng.get(b"aa")?, None);
+        assert_eq!(eng.get(b"bb")?, Some(b"value2".to_vec()));
+        assert_eq!(eng.get(b"cc")?, None);
+
+        // 截断之后可以继续写入，头部不完整的条目同样会被丢弃
+        eng.set(b"cc", b"value4".to_vec())?;
+        drop(eng);
+        let valid_len = std::fs::metadata(&path)?.len();
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&[0, 0, 0])?;
+        drop(file);
+        let mut eng = DiskEngine::new(path.clone())?;
+        assert_eq!(std::fs::metadata(&path)?.len(), valid_len);
+        assert_eq!(eng.get(b"cc")?, Some(b"value4".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_contains_key() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        // 关闭读缓存，保证读取时访问文件
+        let config = DiskEngineConfig { cache_size_bytes: 0, ..Default::default() };
+        let mut eng = DiskEngine::with_config(path.clone(), config)?;
+        eng.set(b"aa", b"value1".to_vec())?;
+
+        // 篡改 value，读取 value 会校验失败
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::End(-1))?;
+        file.write_all(b"x")?;
+        drop(file);
+        assert!(eng.get(b"aa").is_err());
+
+        // contains_key 不读取 value，所以不受影响
+        assert!(eng.contains_key(b"aa")?);
+        assert!(!eng.contains_key(b"bb")?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_cache() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let config = DiskEngineConfig { cache_size_bytes: 12, ..Default::default() };
+        let mut eng = DiskEngine::with_config(path, config)?;
+        let reads = |eng: &DiskEngine| eng.inner.lock().unwrap().active_log.reads;
+
+        // 写入的数据直接进入缓存，读取时不访问文件
+        eng.set(b"aa", b"value1".to_vec())?;
+        eng.set(b"bb", b"value2".to_vec())?;
+        assert_eq!(eng.get(b"aa")?, Some(b"value1".to_vec()));
+        assert_eq!(eng.get(b"bb")?, Some(b"value2".to_vec()));
+        assert_eq!(reads(&eng), 0);
+
+        // 超出容量后淘汰最久未使用的 aa
+        eng.set(b"cc", b"value3".to_vec())?;
+        assert_eq!(eng.get(b"aa")?, Some(b"value1".to_vec()));
+        assert_eq!(reads(&eng), 1);
+        // aa 读取之后重新进入缓存
+        assert_eq!(eng.get(b"aa")?, Some(b"value1".to_vec()));
+        assert_eq!(reads(&eng), 1);
+
+        // 删除之后缓存同样失效
+        eng.delete(b"aa")?;
+        assert_eq!(eng.get(b"aa")?, None);
+
+        // 压缩之后清空缓存
+        eng.compact()?;
+        let before = reads(&eng);
+        assert_eq!(eng.get(b"cc")?, Some(b"value3".to_vec()));
+        assert_eq!(reads(&eng), before + 1);
+
+        // 超过缓存容量的 value 不缓存
+        eng.set(b"dd", vec![0; 13])?;
+        let before = reads(&eng);
+        assert_eq!(eng.get(b"dd")?, Some(vec![0; 13]));
+        assert_eq!(reads(&eng), before + 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_stats() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let mut eng = DiskEngine::new(path.clone())?;
+        eng.set(b"aa", b"value1".to_vec())?;
+        eng.set(b"aa", b"value2".to_vec())?;
+        eng.set(b"bb", b"value3".to_vec())?;
+        eng.delete(b"bb")?;
+        assert_eq!(eng.entry_count()?, 1);
+        assert_eq!(eng.live_entry_ratio()?, 0.25);
+        drop(eng);
+
+        // 重新打开之后统计信息保持一致
+        let eng = DiskEngine::new(path)?;
+        assert_eq!(eng.live_entry_ratio()?, 0.25);
+        let size = eng.disk_size_bytes()?;
+
+        // 压缩之后只剩下有效的数据
+        eng.compact()?;
+        assert_eq!(eng.entry_count()?, 1);
+        assert_eq!(eng.live_entry_ratio()?, 1.0);
+        assert!(eng.disk_size_bytes()? < size);
+        assert_eq!(eng.live_data_bytes()?, 8);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_compact() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let mut eng = DiskEngine::new(path.clone())?;
+        for i in 0..100 {
+            eng.set(b"key1", format!("value{}", i).into_bytes())?;
+        }
+        eng.set(b"key2", b"value".to_vec())?;
+        eng.set(b"key3", b"value".to_vec())?;
+        eng.delete(b"key3")?;
+
+        let size = std::fs::metadata(&path)?.len();
+        eng.compact()?;
+        assert!(std::fs::metadata(&path)?.len() < size);
+        assert!(!path.with_extension("compact").exists());
+        assert_eq!(eng.get(b"key1")?, Some(b"value99".to_vec()));
+
+        // 压缩之后继续写入，并重新打开
+        eng.set(b"key4", b"value".to_vec())?;
+        drop(eng);
+        let mut eng = DiskEngine::new_compact(path)?;
+        assert_eq!(eng.get(b"key1")?, Some(b"value99".to_vec()));
+        assert_eq!(eng.get(b"key3")?, None);
+        let keys = eng.scan(..).map(|r| r.map(|(k, _)| k)).collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, vec![b"key1".to_vec(), b"key2".to_vec(), b"key4".to_vec()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_background_compact() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let config = DiskEngineConfig { compaction_threshold: 1024, ..Default::default() };
+        let mut eng = DiskEngine::with_config(path.clone(), config)?;
+        // 反复覆盖同一个 key，产生大量的无效数据
+        for i in 0..200 {
+            eng.set(b"key", format!("value{:03}", i).into_bytes())?;
+        }
+
+        // 等待后台线程完成压缩
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while std::fs::metadata(&path)?.len() > 1024 {
+            assert!(Instant::now() < deadline, "background compaction did not run");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(eng.get(b"key")?, Some(b"value199".to_vec()));
+        drop(eng);
+
+        let mut eng = DiskEngine::new(path)?;
+        assert_eq!(eng.get(b"key")?, Some(b"value199".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_scan_both_ends() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut eng = DiskEngine::new(dir.path().join("sqldb-log"))?;
+        eng.set(b"a", b"1".to_vec())?;
+        eng.set(b"b", b"2".to_vec())?;
+        eng.set(b"c", b"3".to_vec())?;
+
+        // 正向和反向迭代相遇之后结束
+        let mut iter = eng.scan(..);
+        assert_eq!(iter.next().transpose()?.map(|(k, _)| k), Some(b"a".to_vec()));
+        assert_eq!(iter.next_back().transpose()?.map(|(k, _)| k), Some(b"c".to_vec()));
+        assert_eq!(iter.next().transpose()?.map(|(k, _)| k), Some(b"b".to_vec()));
+        assert!(iter.next_back().is_none());
+        assert!(iter.next().is_none());
+        Ok(())
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_disk_engine_sync_policy() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let syncs = |eng: &DiskEngine| eng.inner.lock().unwrap().active_log.syncs;
+        // 只有 Fsync 和 Fdatasync 在 flush 时等待数据落盘
+        for (i, (policy, synced)) in [
+            (SyncPolicy::None, false),
+            (SyncPolicy::FlushOnly, false),
+            (SyncPolicy::Fsync, true),
+            (SyncPolicy::Fdatasync, true),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let path = dir.path().join(format!("sqldb-log-{}", i));
+            let config = DiskEngineConfig { sync_policy: policy, ..Default::default() };
+            let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+            eng.set(b"aa", b"value1".to_vec())?;
+            eng.apply_batch(vec![WriteOp::Set(b"bb".to_vec(), b"value2".to_vec()), WriteOp::Delete(b"aa".to_vec())])?;
+            assert_eq!(syncs(&eng), 0);
+            eng.flush()?;
+            assert_eq!(syncs(&eng), synced as u64);
+            drop(eng);
+
+            let mut eng = DiskEngine::with_config(path, config)?;
+            assert_eq!(eng.get(b"aa")?, None);
+            assert_eq!(eng.get(b"bb")?, Some(b"value2".to_vec()));
+        }
+        assert_eq!(DiskEngineConfig::default().sync_policy, SyncPolicy::Fsync);
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_segments() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let config = DiskEngineConfig { max_segment_size_bytes: 70, cache_size_bytes: 0, ..Default::default() };
+        let expected = vec![
+            (b"key00".to_vec(), b"value20".to_vec()),
+            (b"key03".to_vec(), b"value03".to_vec()),
+            (b"key05".to_vec(), b"value05".to_vec()),
+        ];
+        let check = |eng: &mut DiskEngine| -> Result<()> {
+            assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+            assert_eq!(eng.get(b"key01")?, None);
+            assert_eq!(eng.get(b"key02")?, None);
+            assert_eq!(eng.get(b"key03")?, Some(b"value03".to_vec()));
+            Ok(())
+        };
+
+        // 每个条目 24 字节，删除标记 17 字节，文件超过 70 字节之后切换到新的文件
+        // 第一个段：key00 key01 key02，第二个段：key03 删除 key02 key04 key05
+        // 当前文件：key00 删除 key01 删除 key04
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        let set = |eng: &mut DiskEngine, i: usize| eng.set(format!("key{:02}", i).as_bytes(), format!("value{:02}", i).into_bytes());
+        for i in 0..4 {
+            set(&mut eng, i)?;
+        }
+        eng.delete(b"key02")?;
+        set(&mut eng, 4)?;
+        set(&mut eng, 5)?;
+        eng.set(b"key00", b"value20".to_vec())?;
+        eng.delete(b"key01")?;
+        eng.delete(b"key04")?;
+        assert_eq!(segment_ids(&path)?, vec![1, 2]);
+        assert_eq!(std::fs::metadata(&path)?.len(), 58);
+        check(&mut eng)?;
+        drop(eng);
+
+        // 重新打开时依次读取所有的段，保存一份压缩之前的文件
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        check(&mut eng)?;
+        let backup = dir.path().join("backup");
+        std::fs::create_dir(&backup)?;
+        for id in [1, 2] {
+            std::fs::copy(segment_path(&path, id), backup.join(id.to_string()))?;
+        }
+        std::fs::copy(&path, backup.join("active"))?;
+
+        // 压缩之后只剩下一个段，当前文件为空
+        eng.compact()?;
+        assert_eq!(segment_ids(&path)?, vec![3]);
+        assert_eq!(std::fs::metadata(&path)?.len(), 0);
+        assert_eq!(eng.live_entry_ratio()?, 1.0);
+        check(&mut eng)?;
+        drop(eng);
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        check(&mut eng)?;
+        drop(eng);
+
+        // 模拟压缩的结果写入之后、旧的文件删除之前崩溃，以及只删除了最早的段时崩溃
+        for ids in [vec![1, 2], vec![2]] {
+            for id in &ids {
+                std::fs::copy(backup.join(id.to_string()), segment_path(&path, *id))?;
+            }
+            std::fs::copy(backup.join("active"), &path)?;
+            let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+            check(&mut eng)?;
+            eng.compact()?;
+            assert_eq!(segment_ids(&path)?, vec![4]);
+            check(&mut eng)?;
+            drop(eng);
+            std::fs::rename(segment_path(&path, 4), segment_path(&path, 3))?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_codec() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let config = DiskEngineConfig { cache_size_bytes: 0, ..Default::default() };
+        let value = |i: usize| format!("row {} of the users table, ", i).repeat(20).into_bytes();
+
+        // 先用 LZ4 写入一部分，再切换到 Snappy，短的 value 编码之后没有变小，按原样保存
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        eng.set_codec(Box::new(Lz4Codec));
+        for i in 0..50 {
+            eng.set(format!("key{:02}", i).as_bytes(), value(i))?;
+        }
+        eng.set(b"short", b"abc".to_vec())?;
+        eng.set_codec(Box::new(SnappyCodec));
+        eng.apply_batch((50..100).map(|i| WriteOp::Set(format!("key{:02}", i).into_bytes(), value(i))).collect())?;
+        eng.delete(b"key00")?;
+        assert_eq!(eng.get(b"key01")?, Some(value(1)));
+        assert_eq!(eng.get(b"key99")?, Some(value(99)));
+        let raw_size = (1..100).map(|i| value(i).len() as u64).sum::<u64>();
+        assert!(eng.live_data_bytes()? < raw_size / 5);
+        assert!(std::fs::metadata(&path)?.len() < raw_size / 5);
+        drop(eng);
+
+        // 重新打开时使用默认的 codec，按每个条目的标记解码
+        let check = |eng: &mut DiskEngine| -> Result<()> {
+            let expected = (1..100)
+                .map(|i| (format!("key{:02}", i).into_bytes(), value(i)))
+                .chain(std::iter::once((b"short".to_vec(), b"abc".to_vec())))
+                .collect::<Vec<_>>();
+            assert_eq!(eng.scan(..).collect::<Result<Vec<_>>>()?, expected);
+            assert_eq!(eng.get(b"key00")?, None);
+            assert_eq!(eng.get(b"key50")?, Some(value(50)));
+            assert_eq!(eng.get(b"short")?, Some(b"abc".to_vec()));
+            Ok(())
+        };
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        check(&mut eng)?;
+
+        // 压缩时原样复制编码过的 value
+        eng.compact()?;
+        check(&mut eng)?;
+        assert!(std::fs::metadata(&path)?.len() < raw_size / 5);
+        drop(eng);
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        check(&mut eng)?;
+        drop(eng);
+
+        // 解压之后超过配置的上限时读取返回错误
+        let mut eng = DiskEngine::with_config(path, DiskEngineConfig { max_value_size: 100, ..config })?;
+        assert!(matches!(eng.get(b"key01"), Err(Error::Internal(_))));
+        assert!(eng.scan(..).any(|r| matches!(r, Err(Error::Internal(_)))));
+        assert_eq!(eng.get(b"short")?, Some(b"abc".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk_engine_read_only() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        let config = DiskEngineConfig { max_segment_size_bytes: 50, ..Default::default() };
+        let mut eng = DiskEngine::with_config(path.clone(), config.clone())?;
+        eng.set(b"key1", b"value1".to_vec())?;
+        eng.set(b"key2", b"value2".to_vec())?;
+        eng.set(b"key3", b"value3".to_vec())?;
+        eng.delete(b"key2")?;
+        assert_eq!(segment_ids(&path)?, vec![1]);
+
+        // 读写模式只允许打开一个，只读模式可以同时打开多个
+        assert!(DiskEngine::with_config(path.clone(), config.clone()).is_err());
+        let mut reader = DiskEngine::open_read_only(path.clone())?;
+        let mut other = DiskEngine::open_read_only(path.clone())?;
+        assert!(reader.is_read_only() && !eng.is_read_only());
+        let expected = vec![(b"key1".to_vec(), b"value1".to_vec()), (b"key3".to_vec(), b"value3".to_vec())];
+        assert_eq!(reader.scan(..).collect::<Result<Vec<_>>>()?, expected);
+        assert_eq!(other.get(b"key3")?, Some(b"value3".to_vec()));
+
+        // 写入、删除和压缩都返回错误，文件保持不变
+        let size = eng.disk_size_bytes()?;
+        assert!(matches!(reader.set(b"key4", b"value4".to_vec()), Err(Error::ReadOnly(_))));
+        assert!(matches!(reader.delete(b"key1"), Err(Error::ReadOnly(_))));
+        assert!(matches!(reader.apply_batch(vec![WriteOp::Delete(b"key1".to_vec())]), Err(Error::ReadOnly(_))));
+        assert!(matches!(reader.compact(), Err(Error::ReadOnly(_))));
+        reader.flush()?;
+        assert_eq!(eng.disk_size_bytes()?, size);
+        assert_eq!(reader.get(b"key1")?, Some(b"value1".to_vec()));
+
+        // 写进程继续写入，只读模式看到的是打开时的数据
+        eng.set(b"key4", b"value4".to_vec())?;
+        eng.flush()?;
+        assert_eq!(reader.get(b"key4")?, None);
+        assert_eq!(DiskEngine::open_read_only(path.clone())?.get(b"key4")?, Some(b"value4".to_vec()));
+
+        // 只读模式不能打开不存在的数据库
+        assert!(DiskEngine::open_read_only(dir.path().join("missing")).is_err());
+        drop(eng);
+        let mut eng = DiskEngine::with_config(path, config)?;
+        assert_eq!(eng.get(b"key4")?, Some(b"value4".to_vec()));
+        Ok(())
+    }
+}
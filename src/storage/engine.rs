@@ -9,78 +9,226 @@ pub trait Engine {
     type EngineIterator<'a> : EngineIterator where Self: 'a;
 
     // 设置 key/value
-    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()>;
+    // 点操作的 key 都是借用的，调用方不需要为了一次读取拷贝 key，需要保存 key 的引擎自己拷贝
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()>;
 
     // 获取 key 对应的 value
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>>;
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>>;
+
+    // 判断 key 是否存在，只需要判断存在性时可以避免读取和拷贝 value
+    fn contains_key(&mut self, key: &[u8]) -> Result<bool> {
+        Ok(self.get(key)?.is_some())
+    }
 
     // 删除 key 对应数据，如果 key 不存在则忽略
-    fn delete(&mut self, key: Vec<u8>) -> Result<()>;
+    fn delete(&mut self, key: &[u8]) -> Result<()>;
+
+    // 按顺序执行一批写操作，一次逻辑上的修改（例如 MVCC 的提交）作为一个整体写入
+    // 默认逐个执行，引擎可以实现为连续的一次写入
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        for op in ops {
+            match op {
+                WriteOp::Set(key, value) => self.set(&key, value)?,
+                WriteOp::Delete(key) => self.delete(&key)?,
+            }
+        }
+        Ok(())
+    }
+
+    // 将写入的数据持久化，不需要持久化的引擎可以不实现
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
 
     // 扫描
+    // 范围仍然使用 Vec<u8>，迭代器需要在整个扫描期间保存边界，调用方一般也是刚编码出来的 key
     fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_>;
 
     // 前缀扫描
-    fn scan_prefix(&mut self, prefix: Vec<u8>) -> Self::EngineIterator<'_>{
-        let start = Bound::Included(prefix.clone());
-        let mut bound_prefix = prefix.clone();
-        if let Some(last) = bound_prefix.iter_mut().last() {
-            *last += 1;
-        }
-        let end = Bound::Excluded(bound_prefix);
-        self.scan((start,end))
+    fn scan_prefix(&mut self, prefix: &[u8]) -> Self::EngineIterator<'_>{
+        self.scan((Bound::Included(prefix.to_vec()), prefix_end_bound(prefix)))
     }
 }
 
 
+// 批量写入中的一个操作
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteOp {
+    Set(Vec<u8>, Vec<u8>),
+    Delete(Vec<u8>),
+}
+
+// 引擎的可变引用同样可以作为引擎使用，这样把引用交给 Mvcc 之后仍然可以检查引擎中的数据
+impl<E: Engine> Engine for &mut E {
+    type EngineIterator<'a> = E::EngineIterator<'a> where Self: 'a;
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        (**self).set(key, value)
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        (**self).get(key)
+    }
+
+    fn contains_key(&mut self, key: &[u8]) -> Result<bool> {
+        (**self).contains_key(key)
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        (**self).delete(key)
+    }
+
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        (**self).apply_batch(ops)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        (**self).flush()
+    }
+
+    fn scan(&mut self, range: impl RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        (**self).scan(range)
+    }
+
+    fn scan_prefix(&mut self, prefix: &[u8]) -> Self::EngineIterator<'_> {
+        (**self).scan_prefix(prefix)
+    }
+}
+
 pub trait EngineIterator: DoubleEndedIterator<Item = Result<(Vec<u8>,Vec<u8>)>> {
     
 }
 
+// 以 prefix 开头的 key 的上界，为前缀最后一个非 255 的字节加一，例如 [1, 2, 255] -> [1, 3]
+// 如果全部是 255 则没有上界
+pub fn prefix_end_bound(prefix: &[u8]) -> Bound<Vec<u8>> {
+    let mut bound_prefix = prefix.to_vec();
+    while bound_prefix.last() == Some(&u8::MAX) {
+        bound_prefix.pop();
+    }
+    match bound_prefix.last_mut() {
+        Some(last) => {
+            *last += 1;
+            Bound::Excluded(bound_prefix)
+        }
+        None => Bound::Unbounded,
+    }
+}
+
+// 存储引擎的统计信息，用于观察引擎状态和调优
+pub trait EngineStats {
+    // 有效的 key 的数量
+    fn entry_count(&self) -> Result<usize>;
+
+    // 数据占用的字节数
+    fn disk_size_bytes(&self) -> Result<u64>;
+
+    // 有效的 key 和 value 的总字节数，不包含过期的数据、删除标记和日志头等额外的开销
+    fn live_data_bytes(&self) -> Result<u64>;
+
+    // 有效条目占全部条目的比例，比例越低说明可以回收的空间越多
+    fn live_entry_ratio(&self) -> Result<f64>;
+
+    fn stats(&self) -> Result<StorageStats> {
+        Ok(StorageStats {
+            entry_count: self.entry_count()?,
+            disk_size_bytes: self.disk_size_bytes()?,
+            live_data_bytes: self.live_data_bytes()?,
+            live_entry_ratio: self.live_entry_ratio()?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StorageStats {
+    pub entry_count: usize,
+    pub disk_size_bytes: u64,
+    pub live_data_bytes: u64,
+    pub live_entry_ratio: f64,
+}
+
 
 #[cfg(test)]
 mod tests {
-    use super::Engine;
+    use super::{Engine, EngineStats, WriteOp};
     use crate::{
         error::Result,
-        storage::{ memory::MemoryEngine},
+        storage::{codec::{Codec, Lz4Codec, SnappyCodec}, disk::DiskEngine, memory::MemoryEngine, page::PageEngine},
     };
-    use std::{ops::Bound, path::PathBuf};
+    use std::ops::Bound;
 
     // 测试点读的情况
     fn test_point_opt(mut eng: impl Engine) -> Result<()> {
         // 测试获取一个不存在的 key
-        assert_eq!(eng.get(b"not exist".to_vec())?, None);
+        assert_eq!(eng.get(b"not exist")?, None);
 
         // 获取一个存在的 key
-        eng.set(b"aa".to_vec(), vec![1, 2, 3, 4])?;
-        assert_eq!(eng.get(b"aa".to_vec())?, Some(vec![1, 2, 3, 4]));
+        eng.set(b"aa", vec![1, 2, 3, 4])?;
+        assert_eq!(eng.get(b"aa")?, Some(vec![1, 2, 3, 4]));
 
         // 重复 put，将会覆盖前一个值
-        eng.set(b"aa".to_vec(), vec![5, 6, 7, 8])?;
-        assert_eq!(eng.get(b"aa".to_vec())?, Some(vec![5, 6, 7, 8]));
+        eng.set(b"aa", vec![5, 6, 7, 8])?;
+        assert_eq!(eng.get(b"aa")?, Some(vec![5, 6, 7, 8]));
 
         // 删除之后再读取
-        eng.delete(b"aa".to_vec())?;
-        assert_eq!(eng.get(b"aa".to_vec())?, None);
+        eng.delete(b"aa")?;
+        assert_eq!(eng.get(b"aa")?, None);
 
         // key、value 为空的情况
-        assert_eq!(eng.get(b"".to_vec())?, None);
-        eng.set(b"".to_vec(), vec![])?;
-        assert_eq!(eng.get(b"".to_vec())?, Some(vec![]));
+        assert_eq!(eng.get(b"")?, None);
+        eng.set(b"", vec![])?;
+        assert_eq!(eng.get(b"")?, Some(vec![]));
+
+        eng.set(b"cc", vec![5, 6, 7, 8])?;
+        assert_eq!(eng.get(b"cc")?, Some(vec![5, 6, 7, 8]));
+        Ok(())
+    }
+
+    // 测试 key 是否存在
+    fn test_contains_key(mut eng: impl Engine) -> Result<()> {
+        assert!(!eng.contains_key(b"aa")?);
+
+        eng.set(b"aa", vec![1, 2, 3])?;
+        eng.set(b"", vec![])?;
+        assert!(eng.contains_key(b"aa")?);
+        // value 为空也是存在的
+        assert!(eng.contains_key(b"")?);
+        assert!(!eng.contains_key(b"a")?);
+
+        eng.delete(b"aa")?;
+        assert!(!eng.contains_key(b"aa")?);
+        Ok(())
+    }
 
-        eng.set(b"cc".to_vec(), vec![5, 6, 7, 8])?;
-        assert_eq!(eng.get(b"cc".to_vec())?, Some(vec![5, 6, 7, 8]));
+    // 测试统计信息
+    fn test_stats(mut eng: impl Engine + EngineStats) -> Result<()> {
+        assert_eq!(eng.entry_count()?, 0);
+        assert_eq!(eng.live_entry_ratio()?, 1.0);
+
+        eng.set(b"aa", vec![1, 2, 3])?;
+        eng.set(b"bb", vec![4, 5, 6])?;
+        assert_eq!(eng.entry_count()?, 2);
+        assert_eq!(eng.live_data_bytes()?, 10);
+        assert!(eng.disk_size_bytes()? >= eng.live_data_bytes()?);
+
+        // 覆盖写入按新的 value 计算
+        eng.set(b"aa", vec![1])?;
+        assert_eq!(eng.live_data_bytes()?, 8);
+
+        eng.delete(b"bb")?;
+        assert_eq!(eng.entry_count()?, 1);
+        assert_eq!(eng.live_data_bytes()?, 3);
+        assert_eq!(eng.stats()?.live_data_bytes, 3);
         Ok(())
     }
 
     // 测试扫描
     fn test_scan(mut eng: impl Engine) -> Result<()> {
-        eng.set(b"nnaes".to_vec(), b"value1".to_vec())?;
-        eng.set(b"amhue".to_vec(), b"value2".to_vec())?;
-        eng.set(b"meeae".to_vec(), b"value3".to_vec())?;
-        eng.set(b"uujeh".to_vec(), b"value4".to_vec())?;
-        eng.set(b"anehe".to_vec(), b"value5".to_vec())?;
+        eng.set(b"nnaes", b"value1".to_vec())?;
+        eng.set(b"amhue", b"value2".to_vec())?;
+        eng.set(b"meeae", b"value3".to_vec())?;
+        eng.set(b"uujeh", b"value4".to_vec())?;
+        eng.set(b"anehe", b"value5".to_vec())?;
 
         let start = Bound::Included(b"a".to_vec());
         let end = Bound::Excluded(b"e".to_vec());
@@ -111,28 +259,125 @@ mod tests {
 
     // 测试前缀扫描
     fn test_scan_prefix(mut eng: impl Engine) -> Result<()> {
-        eng.set(b"ccnaes".to_vec(), b"value1".to_vec())?;
-        eng.set(b"camhue".to_vec(), b"value2".to_vec())?;
-        eng.set(b"deeae".to_vec(), b"value3".to_vec())?;
-        eng.set(b"eeujeh".to_vec(), b"value4".to_vec())?;
-        eng.set(b"canehe".to_vec(), b"value5".to_vec())?;
-        eng.set(b"aanehe".to_vec(), b"value6".to_vec())?;
-
-        let prefix = b"ca".to_vec();
+        eng.set(b"ccnaes", b"value1".to_vec())?;
+        eng.set(b"camhue", b"value2".to_vec())?;
+        eng.set(b"deeae", b"value3".to_vec())?;
+        eng.set(b"eeujeh", b"value4".to_vec())?;
+        eng.set(b"canehe", b"value5".to_vec())?;
+        eng.set(b"aanehe", b"value6".to_vec())?;
+
+        let prefix = b"ca";
         let mut iter = eng.scan_prefix(prefix);
         let (key1, _) = iter.next().transpose()?.unwrap();
         assert_eq!(key1, b"camhue".to_vec());
         let (key2, _) = iter.next().transpose()?.unwrap();
         assert_eq!(key2, b"canehe".to_vec());
+        assert!(iter.next().is_none());
+        drop(iter);
+
+        // 前缀以 255 结尾
+        eng.set(&[1, 255], b"value7".to_vec())?;
+        eng.set(&[1, 255, 255, 3], b"value8".to_vec())?;
+        eng.set(&[2], b"value9".to_vec())?;
+        let keys = eng
+            .scan_prefix(&[1, 255])
+            .map(|r| r.map(|(k, _)| k))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(keys, vec![vec![1, 255], vec![1, 255, 255, 3]]);
+
+        Ok(())
+    }
 
+    // 批量写入按顺序执行，同一个 key 以最后一次操作为准
+    fn test_apply_batch(mut eng: impl Engine) -> Result<()> {
+        eng.set(b"aa", b"value1".to_vec())?;
+        eng.apply_batch(vec![
+            WriteOp::Set(b"bb".to_vec(), b"value2".to_vec()),
+            WriteOp::Delete(b"aa".to_vec()),
+            WriteOp::Set(b"cc".to_vec(), b"value3".to_vec()),
+            WriteOp::Delete(b"cc".to_vec()),
+            WriteOp::Set(b"dd".to_vec(), Vec::new()),
+            WriteOp::Set(b"bb".to_vec(), b"value4".to_vec()),
+        ])?;
+        eng.apply_batch(Vec::new())?;
+        let items = eng.scan(..).collect::<Result<Vec<_>>>()?;
+        assert_eq!(items, vec![(b"bb".to_vec(), b"value4".to_vec()), (b"dd".to_vec(), Vec::new())]);
+        assert_eq!(eng.get(b"aa")?, None);
         Ok(())
     }
 
     #[test]
     fn test_memory() -> Result<()> {
         test_point_opt(MemoryEngine::new())?;
+        // 通过引用使用引擎，之后检查剩下的数据
+        let mut eng = MemoryEngine::new();
+        test_point_opt(&mut eng)?;
+        assert_eq!(eng.len(), 2);
+        assert_eq!(eng.dump()?, vec![(b"".to_vec(), vec![]), (b"cc".to_vec(), vec![5, 6, 7, 8])]);
+        test_contains_key(MemoryEngine::new())?;
+        test_stats(MemoryEngine::new())?;
         test_scan(MemoryEngine::new())?;
         test_scan_prefix(MemoryEngine::new())?;
+        test_apply_batch(MemoryEngine::new())?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_disk() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        test_point_opt(DiskEngine::new(dir.path().join("point-log"))?)?;
+        test_contains_key(DiskEngine::new(dir.path().join("contains-log"))?)?;
+        test_stats(DiskEngine::new(dir.path().join("stats-log"))?)?;
+        test_scan(DiskEngine::new(dir.path().join("scan-log"))?)?;
+        test_scan_prefix(DiskEngine::new(dir.path().join("scan-prefix-log"))?)?;
+        test_apply_batch(DiskEngine::new(dir.path().join("batch-log"))?)?;
+
+        // 批量写入的条目重新打开之后同样可以恢复
+        let mut eng = DiskEngine::new(dir.path().join("batch-log"))?;
+        assert_eq!(eng.get(b"bb")?, Some(b"value4".to_vec()));
+        assert_eq!(eng.get(b"dd")?, Some(Vec::new()));
+        assert_eq!(eng.get(b"aa")?, None);
+        assert_eq!(eng.get(b"cc")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        test_point_opt(PageEngine::new(dir.path().join("point-pages"))?)?;
+        test_contains_key(PageEngine::new(dir.path().join("contains-pages"))?)?;
+        test_stats(PageEngine::new(dir.path().join("stats-pages"))?)?;
+        test_scan(PageEngine::new(dir.path().join("scan-pages"))?)?;
+        test_scan_prefix(PageEngine::new(dir.path().join("scan-prefix-pages"))?)?;
+        test_apply_batch(PageEngine::new(dir.path().join("batch-pages"))?)?;
+
+        let mut eng = PageEngine::new(dir.path().join("batch-pages"))?;
+        assert_eq!(eng.get(b"bb")?, Some(b"value4".to_vec()));
+        assert_eq!(eng.get(b"dd")?, Some(Vec::new()));
+        assert_eq!(eng.get(b"aa")?, None);
+        Ok(())
+    }
+
+    // 使用压缩的引擎对外的行为不变，value 的大小和分配次数与压缩方式有关，不检查
+    #[test]
+    fn test_compressed() -> Result<()> {
+        test_point_opt(MemoryEngine::with_codec(Box::new(Lz4Codec)))?;
+        test_contains_key(MemoryEngine::with_codec(Box::new(SnappyCodec)))?;
+        test_scan(MemoryEngine::with_codec(Box::new(Lz4Codec)))?;
+        test_scan_prefix(MemoryEngine::with_codec(Box::new(SnappyCodec)))?;
+        test_apply_batch(MemoryEngine::with_codec(Box::new(Lz4Codec)))?;
+
+        let dir = tempfile::tempdir()?;
+        let disk = |name: &str, codec: Box<dyn Codec>| -> Result<DiskEngine> {
+            let mut eng = DiskEngine::new(dir.path().join(name))?;
+            eng.set_codec(codec);
+            Ok(eng)
+        };
+        test_point_opt(disk("point-log", Box::new(Lz4Codec))?)?;
+        test_contains_key(disk("contains-log", Box::new(SnappyCodec))?)?;
+        test_scan(disk("scan-log", Box::new(Lz4Codec))?)?;
+        test_scan_prefix(disk("scan-prefix-log", Box::new(SnappyCodec))?)?;
+        test_apply_batch(disk("batch-log", Box::new(Lz4Codec))?)?;
         Ok(())
     }
 
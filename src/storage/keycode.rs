@@ -0,0 +1,741 @@
+use serde::{de::{self, IntoDeserializer}, ser, Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+// key 的编码方式，编码之后的字节序和原始值的顺序保持一致，便于范围扫描
+// 1. bool：false 为 0，true 为 1
+// 2. u64：大端序
+// 3. i64：大端序，并翻转符号位，保证负数排在正数前面
+// 4. f64：正数翻转符号位，负数翻转所有位
+// 5. 字节数组：0 转义为 0 255，并以 0 0 结尾
+// 6. 枚举：使用变体的下标（u8）作为前缀
+// 7. 较短的整数和 f32 的编码方式和对应的 64 位类型相同，只是长度不同，char 按 u32 编码
+// 8. Option：None 为 0，Some 为 1 加上值的编码
+// map 和 struct 不支持作为 key
+pub fn serialize_key<T: Serialize>(key: &T) -> Result<Vec<u8>> {
+    let mut ser = Serializer { output: Vec::new() };
+    key.serialize(&mut ser)?;
+    Ok(ser.output)
+}
+
+pub fn deserialize_key<'a, T: Deserialize<'a>>(input: &'a [u8]) -> Result<T> {
+    let mut der = Deserializer { input };
+    T::deserialize(&mut der)
+}
+
+pub struct Serializer {
+    output: Vec<u8>,
+}
+
+impl ser::Serializer for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    type SerializeSeq = Self;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = ser::Impossible<(), Error>;
+    type SerializeStruct = ser::Impossible<(), Error>;
+    type SerializeStructVariant = ser::Impossible<(), Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.output.push(v as u8 ^ 1 << 7);
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        let mut bytes = v.to_be_bytes();
+        bytes[0] ^= 1 << 7;
+        self.output.extend(bytes);
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        let mut bytes = v.to_be_bytes();
+        bytes[0] ^= 1 << 7;
+        self.output.extend(bytes);
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        let mut bytes = v.to_be_bytes();
+        bytes[0] ^= 1 << 7;
+        self.output.extend(bytes);
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.output.extend(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.output.extend(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.output.extend(v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        let mut bytes = v.to_be_bytes();
+        if v.is_sign_negative() {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        } else {
+            bytes[0] ^= 1 << 7;
+        }
+        self.output.extend(bytes);
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        let mut bytes = v.to_be_bytes();
+        if v.is_sign_negative() {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        } else {
+            bytes[0] ^= 1 << 7;
+        }
+        self.output.extend(bytes);
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_u32(v as u32)
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    // 原始值           编码后
+    // 97 98 99     -> 97 98 99 0 0
+    // 97 98 0 99   -> 97 98 0 255 99 0 0
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        let mut res = Vec::new();
+        for e in v.iter() {
+            match e {
+                0 => res.extend([0, 255]),
+                b => res.push(*b),
+            }
+        }
+        // 放 0 0 表示结尾
+        res.extend([0, 0]);
+
+        self.output.extend(res);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<()> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        Ok(())
+    }
+
+    // 类似 MvccKey::NextVersion
+    fn serialize_unit_variant(self, _name: &'static str, variant_index: u32, _variant: &'static str) -> Result<()> {
+        self.output.push(variant_index as u8);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _name: &'static str, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    // 类似 MvccKey::TxnActive(Version)
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Ok(self)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeTupleStruct> {
+        Ok(self)
+    }
+
+    // 类似 MvccKey::TxnWrite(Version, Vec<u8>)
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        self.serialize_unit_variant(name, variant_index, variant)?;
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(unsupported("map"))
+    }
+
+    fn serialize_struct(self, name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(unsupported(name))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(unsupported(&format!("{}::{}", name, variant)))
+    }
+}
+
+fn unsupported(kind: &str) -> Error {
+    Error::Serialization(format!("{} is not supported in keys", kind))
+}
+
+impl ser::SerializeSeq for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTuple for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut Serializer {
+    type Ok = ();
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<()> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    fn take_bytes(&mut self, len: usize) -> Result<&'de [u8]> {
+        if self.input.len() < len {
+            return Err(Error::Serialization(format!(
+                "insufficient bytes, expected {} bytes for {:?}",
+                len, self.input
+            )));
+        }
+        let bytes = &self.input[..len];
+        self.input = &self.input[len..];
+        Ok(bytes)
+    }
+
+    // 解析转义之后的字节数组
+    // 如果是 0 0 表示结尾
+    // 如果是 0 255 则原始值为 0
+    fn next_bytes(&mut self) -> Result<Vec<u8>> {
+        let mut res = Vec::new();
+        let mut iter = self.input.iter().enumerate();
+        let i = loop {
+            match iter.next() {
+                Some((_, 0)) => match iter.next() {
+                    Some((i, 0)) => break i + 1,
+                    Some((_, 255)) => res.push(0),
+                    _ => return Err(Error::Serialization("unexpected input".to_string())),
+                },
+                Some((_, b)) => res.push(*b),
+                _ => return Err(Error::Serialization("unexpected input".to_string())),
+            }
+        };
+        self.input = &self.input[i..];
+        Ok(res)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    // 编码中没有类型信息，只能按目标类型解码
+    fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("self-describing type"))
+    }
+
+    fn deserialize_bool<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = match self.take_bytes(1)?[0] {
+            0 => false,
+            1 => true,
+            b => return Err(Error::Serialization(format!("invalid boolean value {}", b))),
+        };
+        visitor.visit_bool(v)
+    }
+
+    fn deserialize_i8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_i8((self.take_bytes(1)?[0] ^ 1 << 7) as i8)
+    }
+
+    fn deserialize_i16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bytes: [u8; 2] = self.take_bytes(2)?.try_into()?;
+        bytes[0] ^= 1 << 7;
+        visitor.visit_i16(i16::from_be_bytes(bytes))
+    }
+
+    fn deserialize_i32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bytes: [u8; 4] = self.take_bytes(4)?.try_into()?;
+        bytes[0] ^= 1 << 7;
+        visitor.visit_i32(i32::from_be_bytes(bytes))
+    }
+
+    // 编码时翻转了符号位，这里再翻转回来
+    fn deserialize_i64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bytes: [u8; 8] = self.take_bytes(8)?.try_into()?;
+        bytes[0] ^= 1 << 7;
+        visitor.visit_i64(i64::from_be_bytes(bytes))
+    }
+
+    fn deserialize_u8<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u8(self.take_bytes(1)?[0])
+    }
+
+    fn deserialize_u16<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u16(u16::from_be_bytes(self.take_bytes(2)?.try_into()?))
+    }
+
+    fn deserialize_u32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_u32(u32::from_be_bytes(self.take_bytes(4)?.try_into()?))
+    }
+
+    fn deserialize_u64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bytes = self.take_bytes(8)?;
+        let v = u64::from_be_bytes(bytes.try_into()?);
+        visitor.visit_u64(v)
+    }
+
+    // 编码时正数只翻转了符号位，最高位为 1；负数翻转了所有位，最高位为 0
+    fn deserialize_f32<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bytes: [u8; 4] = self.take_bytes(4)?.try_into()?;
+        if bytes[0] >> 7 == 1 {
+            bytes[0] ^= 1 << 7;
+        } else {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        }
+        visitor.visit_f32(f32::from_be_bytes(bytes))
+    }
+
+    fn deserialize_f64<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let mut bytes: [u8; 8] = self.take_bytes(8)?.try_into()?;
+        if bytes[0] >> 7 == 1 {
+            bytes[0] ^= 1 << 7;
+        } else {
+            bytes.iter_mut().for_each(|b| *b = !*b);
+        }
+        visitor.visit_f64(f64::from_be_bytes(bytes))
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let v = u32::from_be_bytes(self.take_bytes(4)?.try_into()?);
+        let c = char::from_u32(v).ok_or_else(|| Error::Serialization(format!("invalid char value {}", v)))?;
+        visitor.visit_char(c)
+    }
+
+    // 转义之后的字符串无法直接借用输入，统一返回 String
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        let bytes = self.next_bytes()?;
+        visitor.visit_string(String::from_utf8(bytes).map_err(|e| Error::Serialization(e.to_string()))?)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_bytes(&self.next_bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_byte_buf(self.next_bytes()?)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        match self.take_bytes(1)?[0] {
+            0 => visitor.visit_none(),
+            1 => visitor.visit_some(self),
+            b => Err(Error::Serialization(format!("invalid option tag {}", b))),
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // 序列没有长度前缀，只能作为 key 的最后一部分，一直读取到输入结束
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(Remaining(self))
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_seq(self)
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("map"))
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value> {
+        Err(unsupported(name))
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("identifier"))
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value> {
+        Err(unsupported("ignored value"))
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for Deserializer<'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        seed.deserialize(self).map(Some)
+    }
+}
+
+// 读取剩余的全部输入作为序列的元素
+struct Remaining<'a, 'de>(&'a mut Deserializer<'de>);
+
+impl<'de> de::SeqAccess<'de> for Remaining<'_, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>> {
+        if self.0.input.is_empty() {
+            return Ok(None);
+        }
+        seed.deserialize(&mut *self.0).map(Some)
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self::Variant)> {
+        let index = self.take_bytes(1)?[0] as u32;
+        let variant_index: Result<_> = seed.deserialize(index.into_deserializer());
+        Ok((variant_index?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Deserializer<'de> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<()> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value> {
+        visitor.visit_seq(self)
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value> {
+        Err(unsupported("struct variant"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{collection::vec, prelude::*};
+
+    use crate::{error::Result, storage::mvcc::{MvccKey, MvccKeyPrefix}};
+
+    use super::{deserialize_key, serialize_key};
+
+    #[test]
+    fn test_encode() -> Result<()> {
+        let ser_cmp = |k: MvccKey, v: Vec<u8>| {
+            let res = serialize_key(&k).unwrap();
+            assert_eq!(res, v);
+        };
+
+        ser_cmp(MvccKey::NextVersion, vec![0]);
+        ser_cmp(MvccKey::TxnActive(1), vec![1, 0, 0, 0, 0, 0, 0, 0, 1]);
+        ser_cmp(
+            MvccKey::TxnWrite(1, vec![1, 2, 3]),
+            vec![2, 0, 0, 0, 0, 0, 0, 0, 1, 1, 2, 3, 0, 0],
+        );
+        ser_cmp(
+            MvccKey::Version(b"abc".to_vec(), 11),
+            vec![3, 97, 98, 99, 0, 0, 0, 0, 0, 0, 0, 0, 0, 11],
+        );
+        ser_cmp(
+            MvccKey::Version(vec![97, 0, 98], 1),
+            vec![3, 97, 0, 255, 98, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1],
+        );
+
+        let prefix = serialize_key(&MvccKeyPrefix::TxnWrite(1))?;
+        assert_eq!(prefix, vec![2, 0, 0, 0, 0, 0, 0, 0, 1]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_order() -> Result<()> {
+        // 编码后的字节序和原始值的顺序一致
+        let keys = [
+            MvccKey::Version(vec![], 100),
+            MvccKey::Version(vec![0], 1),
+            MvccKey::Version(vec![0, 0], 1),
+            MvccKey::Version(vec![1], 1),
+            MvccKey::Version(vec![1], 2),
+            MvccKey::Version(vec![1, 0], 1),
+            MvccKey::Version(vec![255], 1),
+        ];
+        let encoded = keys.iter().map(serialize_key).collect::<Result<Vec<_>>>()?;
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(encoded, sorted);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode() -> Result<()> {
+        let der_cmp = |k: MvccKey| {
+            let encoded = k.encode().unwrap();
+            assert_eq!(MvccKey::decode(encoded).unwrap(), k);
+        };
+
+        der_cmp(MvccKey::NextVersion);
+        der_cmp(MvccKey::TxnActive(1));
+        der_cmp(MvccKey::TxnWrite(1, vec![1, 2, 3]));
+        der_cmp(MvccKey::Version(b"abc".to_vec(), 11));
+        der_cmp(MvccKey::Version(vec![0, 0, 255, 0], u64::MAX));
+        Ok(())
+    }
+
+    #[test]
+    fn test_roundtrip_mixed() -> Result<()> {
+        let ints = [i64::MIN, i64::MIN + 1, -256, -1, 0, 1, 255, i64::MAX - 1, i64::MAX];
+        let strs = ["", "a", "a\0b", "\0", "ab", "b", "你好", "\u{ff}"];
+        let mut keys = Vec::new();
+        for (i, n) in ints.iter().enumerate() {
+            for (j, s) in strs.iter().enumerate() {
+                keys.push(((i + j) % 2 == 0, *n, s.to_string(), (i * j) as u64));
+            }
+        }
+
+        for key in &keys {
+            let encoded = serialize_key(key)?;
+            let decoded: (bool, i64, String, u64) = deserialize_key(&encoded)?;
+            assert_eq!(&decoded, key);
+        }
+
+        // 按 (i64, String) 排序后，编码的字节序保持一致
+        let mut pairs = keys.iter().map(|(_, n, s, _)| (*n, s.clone())).collect::<Vec<_>>();
+        pairs.sort();
+        let encoded = pairs.iter().map(serialize_key).collect::<Result<Vec<_>>>()?;
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(encoded, sorted);
+
+        assert!(deserialize_key::<bool>(&[2]).is_err());
+        assert!(deserialize_key::<i64>(&[0, 0, 0]).is_err());
+        assert!(deserialize_key::<String>(&[0xff, 0xfe, 0, 0]).is_err());
+        assert!(deserialize_key::<String>(&[97, 98]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trailing_seq() -> Result<()> {
+        // 序列作为最后一部分时可以解码，并且前缀相同的序列排在一起
+        let keys = [
+            ("t".to_string(), vec![1i64]),
+            ("t".to_string(), vec![1, -5]),
+            ("t".to_string(), vec![1, 2]),
+            ("t".to_string(), vec![2]),
+            ("u".to_string(), vec![]),
+        ];
+        let encoded = keys.iter().map(serialize_key).collect::<Result<Vec<_>>>()?;
+        let mut sorted = encoded.clone();
+        sorted.sort();
+        assert_eq!(encoded, sorted);
+        for (key, data) in keys.iter().zip(&encoded) {
+            assert_eq!(&deserialize_key::<(String, Vec<i64>)>(data)?, key);
+        }
+        assert!(encoded[1].starts_with(&encoded[0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_scalar_types() -> Result<()> {
+        let key = (-3i8, i16::MIN, -70000i32, 7u16, u32::MAX, -1.5f32, 2.5f64, 'é', Some(3i64), None::<String>, ());
+        let encoded = serialize_key(&key)?;
+        assert_eq!(&encoded[..3], &[0x7d, 0x00, 0x00]);
+        assert_eq!(deserialize_key::<(i8, i16, i32, u16, u32, f32, f64, char, Option<i64>, Option<String>, ())>(&encoded)?, key);
+
+        assert!(serialize_key(&std::collections::HashMap::<u8, u8>::new()).is_err());
+        assert!(deserialize_key::<char>(&[0, 0x11, 0, 0]).is_err());
+        assert!(deserialize_key::<Option<u8>>(&[2, 1]).is_err());
+        Ok(())
+    }
+
+    // 字节数组中经常出现需要转义的 0 和 255，单独生成全 0、全 255 以及混合的数据
+    fn bytes() -> impl Strategy<Value = Vec<u8>> {
+        prop_oneof![
+            vec(any::<u8>(), 0..16),
+            (0..16usize).prop_map(|n| vec![0; n]),
+            (0..16usize).prop_map(|n| vec![0xff; n]),
+            vec(prop_oneof![Just(0u8), Just(0xff), Just(1), any::<u8>()], 0..16),
+        ]
+    }
+
+    fn version() -> impl Strategy<Value = u64> {
+        prop_oneof![Just(0), Just(u64::MAX), 0..1000u64, any::<u64>()]
+    }
+
+    fn mvcc_key() -> impl Strategy<Value = MvccKey> {
+        prop_oneof![
+            Just(()).prop_map(|_| MvccKey::NextVersion),
+            version().prop_map(MvccKey::TxnActive),
+            (version(), bytes()).prop_map(|(v, k)| MvccKey::TxnWrite(v, k)),
+            (bytes(), version()).prop_map(|(k, v)| MvccKey::Version(k, v)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn prop_mvcc_key_roundtrip(key in mvcc_key()) {
+            prop_assert_eq!(MvccKey::decode(key.encode().unwrap()).unwrap(), key);
+        }
+
+        #[test]
+        fn prop_version_order(key in bytes(), v1 in version(), v2 in version()) {
+            prop_assume!(v1 != v2);
+            let (v1, v2) = (v1.min(v2), v1.max(v2));
+            prop_assert!(MvccKey::Version(key.clone(), v1).encode().unwrap() < MvccKey::Version(key, v2).encode().unwrap());
+        }
+
+        // 不同的 key 按 (key, version) 排序，编码之后的字节序一致
+        #[test]
+        fn prop_key_order(a in (bytes(), version()), b in (bytes(), version())) {
+            let encode = |(k, v): &(Vec<u8>, u64)| MvccKey::Version(k.clone(), *v).encode().unwrap();
+            prop_assert_eq!(a.cmp(&b), encode(&a).cmp(&encode(&b)));
+        }
+
+        #[test]
+        fn prop_scalar_roundtrip(key in (any::<i8>(), any::<i16>(), any::<i32>(), any::<i64>(), any::<u16>(), any::<u32>(), any::<Option<u8>>(), any::<char>(), ".*")) {
+            let decoded: (i8, i16, i32, i64, u16, u32, Option<u8>, char, String) = deserialize_key(&serialize_key(&key).unwrap()).unwrap();
+            prop_assert_eq!(decoded, key);
+        }
+
+        #[test]
+        fn prop_float_order(a in any::<f64>(), b in any::<f64>()) {
+            let decoded: f64 = deserialize_key(&serialize_key(&a).unwrap()).unwrap();
+            prop_assert_eq!(decoded.to_bits(), a.to_bits());
+            if a < b {
+                prop_assert!(serialize_key(&a).unwrap() < serialize_key(&b).unwrap());
+            }
+        }
+    }
+}
@@ -2,54 +2,139 @@ use std::collections::{btree_map, BTreeMap};
 
 use crate::error::Result;
 
+use super::{codec::{Codec, NoopCodec, DEFAULT_MAX_VALUE_SIZE, NOOP_CODEC}, engine::WriteOp};
+
 // 这里直接采用 BTreeMap 的结构来实现内存的引擎
+// value 按 codec 压缩之后保存，引擎的生命周期内 codec 不变，因此不需要标记压缩方式
 pub struct MemoryEngine {
     data: BTreeMap<Vec<u8>,Vec<u8>>,
+    codec: Box<dyn Codec>,
+    // 解压 value 时允许的最大字节数
+    max_value_size: u64,
 }
 
 
 impl MemoryEngine {
     pub fn new() -> Self {
+        Self::with_codec(Box::new(NoopCodec))
+    }
+
+    pub fn with_codec(codec: Box<dyn Codec>) -> Self {
         MemoryEngine {
-            data: BTreeMap::new()
+            data: BTreeMap::new(),
+            codec,
+            max_value_size: DEFAULT_MAX_VALUE_SIZE,
+        }
+    }
+
+    // 修改读取时解压 value 的大小上限，写入不受限制
+    pub fn set_max_value_size(&mut self, limit: u64) {
+        self.max_value_size = limit;
+    }
+
+    // 不压缩时直接保存原来的 value，避免拷贝
+    fn encode(&self, value: Vec<u8>) -> Vec<u8> {
+        match self.codec.id() {
+            NOOP_CODEC => value,
+            _ => self.codec.encode(&value),
         }
     }
+
+    // 保存的 key 的数量
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    // 按照 key 的顺序导出所有解压之后的数据，用于在测试中检查底层保存的 key
+    pub fn dump(&self) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        self.data.iter().map(|(k, v)| Ok((k.clone(), self.codec.decode(v, self.max_value_size)?))).collect()
+    }
+
+    // 复制当前所有的数据，之后的修改不影响快照
+    // 快照中保存解压之后的数据和 value 的大小上限，不依赖引擎的 codec
+    pub fn snapshot(&self) -> Result<MemoryEngineSnapshot> {
+        Ok(MemoryEngineSnapshot { data: self.dump()?.into_iter().collect(), max_value_size: self.max_value_size })
+    }
+
+    // 从快照创建新的引擎，相当于进程在快照时退出之后重新打开
+    // 同一个快照可以多次恢复，得到互不影响的引擎，恢复的引擎使用 codec 重新压缩快照中的数据
+    pub fn restore(snapshot: MemoryEngineSnapshot, codec: Box<dyn Codec>) -> Self {
+        let mut engine = Self::with_codec(codec);
+        engine.max_value_size = snapshot.max_value_size;
+        engine.data = snapshot.data.into_iter().map(|(k, v)| (k, engine.encode(v))).collect();
+        engine
+    }
+}
+
+// 内存引擎在某一时刻的全部数据，只能通过 MemoryEngine::restore 使用
+#[derive(Debug, Clone)]
+pub struct MemoryEngineSnapshot {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+    max_value_size: u64,
 }
 
 
+impl Default for MemoryEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl super::engine::Engine for MemoryEngine {
     type EngineIterator<'a> = MemoryEngineIterator<'a>;
 
-    fn set(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        self.data.insert(key, value);
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        let value = self.encode(value);
+        self.data.insert(key.to_vec(), value);
         Ok(())
     }
 
-    fn get(&mut self, key: Vec<u8>) -> Result<Option<Vec<u8>>> {
-        let val = self.data.get(&key).cloned();
-        Ok(val)
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.data.get(key).map(|v| self.codec.decode(v, self.max_value_size)).transpose()
+    }
+
+    fn contains_key(&mut self, key: &[u8]) -> Result<bool> {
+        Ok(self.data.contains_key(key))
     }
 
-    fn delete(&mut self, key: Vec<u8>) -> Result<()> {
-        self.data.remove(&key);
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        self.data.remove(key);
+        Ok(())
+    }
+
+    fn apply_batch(&mut self, ops: Vec<WriteOp>) -> Result<()> {
+        for op in ops {
+            match op {
+                WriteOp::Set(key, value) => self.data.insert(key, self.encode(value)),
+                WriteOp::Delete(key) => self.data.remove(&key),
+            };
+        }
         Ok(())
     }
 
     fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
         MemoryEngineIterator {
-            inner: self.data.range(range)
+            inner: self.data.range(range),
+            codec: self.codec.as_ref(),
+            max_value_size: self.max_value_size,
         }
     }
 }
 
 pub struct MemoryEngineIterator<'a> {
     inner: btree_map::Range<'a, Vec<u8>,Vec<u8>>,
+    codec: &'a dyn Codec,
+    max_value_size: u64,
 }
 
 impl<'a> MemoryEngineIterator<'a> {
-    fn map(item: (&Vec<u8>,&Vec<u8>)) -> <Self as Iterator>::Item{
+    fn decode_item(&self, item: (&Vec<u8>,&Vec<u8>)) -> <Self as Iterator>::Item{
         let (key,val) = item;
-        Ok((key.clone(),val.clone()))
+        Ok((key.clone(),self.codec.decode(val, self.max_value_size)?))
     }
 }
 
@@ -57,13 +142,34 @@ impl<'a> Iterator for MemoryEngineIterator<'a> {
     type Item = Result<(Vec<u8>,Vec<u8>)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.inner.next().map(Self::map)
+        let item = self.inner.next()?;
+        Some(self.decode_item(item))
     }
 }
 
 impl<'a> DoubleEndedIterator for MemoryEngineIterator<'a> {
     fn next_back(&mut self) -> Option<Self::Item> {
-        self.inner.next_back().map(Self::map)
+        let item = self.inner.next_back()?;
+        Some(self.decode_item(item))
+    }
+}
+
+// 内存引擎中没有过期的数据，占用的空间按照 key 和压缩之后的 value 的大小估算
+impl super::engine::EngineStats for MemoryEngine {
+    fn entry_count(&self) -> Result<usize> {
+        Ok(self.data.len())
+    }
+
+    fn disk_size_bytes(&self) -> Result<u64> {
+        self.live_data_bytes()
+    }
+
+    fn live_data_bytes(&self) -> Result<u64> {
+        Ok(self.data.iter().map(|(k, v)| (k.len() + v.len()) as u64).sum())
+    }
+
+    fn live_entry_ratio(&self) -> Result<f64> {
+        Ok(1.0)
     }
 }
 
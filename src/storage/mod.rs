@@ -1,4 +1,7 @@
 pub mod engine;
 pub mod memory;
 pub mod mvcc;
-pub mod disk;
\ No newline at end of file
+pub mod disk;
+pub mod page;
+pub mod keycode;
+pub mod codec;
\ No newline at end of file
@@ -1,73 +1,1653 @@
-use std::sync::{Arc, Mutex};
+use std::{borrow::Cow, collections::{HashSet, VecDeque}, fmt::Display, ops::{Bound, RangeBounds}, sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, MutexGuard}, time::{Duration, SystemTime, UNIX_EPOCH}};
 
-use crate::error::Result;
+use serde::{Deserialize, Serialize};
 
-use super::engine::Engine;
+use crate::error::{Error, Result};
+
+use super::{engine::{prefix_end_bound, Engine, EngineStats, StorageStats, WriteOp}, codec, keycode::{deserialize_key, serialize_key}};
+
+pub type Version = u64;
 
 pub struct Mvcc<E : Engine>{
     engine: Arc<Mutex<E>>,
+    // 只读模式下开启的事务都是只读事务，见 Mvcc::new_read_only
+    read_only: bool,
+    // 判断数据是否过期时使用的时钟
+    clock: Arc<dyn Clock>,
+    // 解码 value 时允许的最大字节数，见 Mvcc::set_max_value_size
+    max_value_size: u64,
 }
 
 impl<E : Engine> Clone for Mvcc<E> {
     fn clone(&self) -> Self {
-        Self { engine: self.engine.clone() }
+        Self { engine: self.engine.clone(), read_only: self.read_only, clock: self.clock.clone(), max_value_size: self.max_value_size }
     }
 }
 
 impl<E : Engine> Mvcc<E> {
-    pub fn new(eng: E) -> Self {
-        Self { engine:Arc::new(Mutex::new(eng)) }
+    // 打开时回滚上一次进程退出时没有完成的事务
+    pub fn new(eng: E) -> Result<Self> {
+        Self::with_clock(eng, Arc::new(SystemClock))
+    }
+
+    // 使用指定的时钟判断数据是否过期，测试中可以使用 ManualClock 推进时间
+    pub fn with_clock(mut eng: E, clock: Arc<dyn Clock>) -> Result<Self> {
+        Self::recover(&mut eng)?;
+        Ok(Self { engine:Arc::new(Mutex::new(eng)), read_only: false, clock, max_value_size: codec::DEFAULT_MAX_VALUE_SIZE })
+    }
+
+    // 以只读模式使用存储引擎，例如另一个进程正在写入的数据库
+    // 不回滚残留的事务，它们可能仍然活跃；开启事务时不写入 NextVersion 和 TxnActive，
+    // 事务读取开启时最新的已提交数据，写入返回 Error::ReadOnly
+    pub fn new_read_only(eng: E) -> Self {
+        Self { engine: Arc::new(Mutex::new(eng)), read_only: true, clock: Arc::new(SystemClock), max_value_size: codec::DEFAULT_MAX_VALUE_SIZE }
+    }
+
+    // 修改之后开启的事务解码 value 时允许的最大字节数，超过时读取返回错误，写入不受限制
+    // 只影响当前的 Mvcc 和之后从它复制的 Mvcc
+    pub fn set_max_value_size(&mut self, limit: u64) {
+        self.max_value_size = limit;
+    }
+
+    // 刚打开时不可能有存活的事务，残留的 TxnActive 都属于崩溃时没有提交或回滚的事务，
+    // 它们写入的版本对之后的事务永远不可见，直接回滚掉
+    fn recover(engine: &mut E) -> Result<()> {
+        let versions = MvccTransaction::scan_active(engine)?;
+        for version in &versions {
+            MvccTransaction::rollback_version(engine, *version)?;
+        }
+        if !versions.is_empty() {
+            engine.flush()?;
+        }
+        Ok(())
     }
 
     pub fn begin(&self) -> Result<MvccTransaction<E>> {
-        Ok(MvccTransaction::begin(self.engine.clone()))
+        self.begin_with_isolation(IsolationLevel::Snapshot)
+    }
+
+    // 以指定的隔离级别开启事务，只读模式下忽略隔离级别，开启只读事务
+    pub fn begin_with_isolation(&self, isolation: IsolationLevel) -> Result<MvccTransaction<E>> {
+        let txn = match self.read_only {
+            true => MvccTransaction::begin_read_only(self.engine.clone())?,
+            false => MvccTransaction::begin_with_isolation(self.engine.clone(), isolation)?,
+        };
+        Ok(txn.with_clock(self.clock.clone()).with_max_value_size(self.max_value_size))
+    }
+
+    // 开启只读事务，读取 version 时的数据，见 MvccTransaction::begin_as_of
+    pub fn begin_as_of(&self, version: Version) -> Result<MvccTransaction<E>> {
+        let txn = MvccTransaction::begin_as_of(self.engine.clone(), version)?;
+        Ok(txn.with_clock(self.clock.clone()).with_max_value_size(self.max_value_size))
+    }
+
+    // 物理删除已经过期的 key，返回删除的 key 的数量
+    // 只处理最新版本已经提交并且过期的 key，连同它更早的版本一起删除；
+    // 最新版本之前开启的事务仍然活跃时跳过，这些事务还能看到更早的版本
+    // begin_as_of 开启的事务不在活跃事务列表中，删除之后读取历史数据时看不到这些 key
+    pub fn purge_expired(&self) -> Result<usize> {
+        let mut engine = self.engine.lock()?;
+        let min_active = MvccTransaction::scan_active(&mut *engine)?.into_iter().min().unwrap_or(Version::MAX);
+        let now = self.clock.now_millis();
+        let mut ops = Vec::new();
+        let mut purged = 0;
+        // 同一个 key 的所有版本连续存放，版本号从小到大，遇到下一个 key 时判断上一个 key 的最新版本
+        let mut versions = Vec::new();
+        let mut latest: Option<(Vec<u8>, Version, Vec<u8>)> = None;
+        let mut iter = engine.scan_prefix(&MvccTransaction::<E>::version_prefix()?);
+        loop {
+            let entry = match iter.next().transpose()? {
+                Some((key, value)) => match MvccKey::decode(key.clone())? {
+                    MvccKey::Version(raw_key, version) => Some((key, raw_key, version, value)),
+                    _ => return Err(Error::Internal(format!("unexpected key: {:?}", String::from_utf8(key)))),
+                },
+                None => None,
+            };
+            if let Some((raw_key, version, value)) = &latest {
+                if entry.as_ref().is_none_or(|(_, next, _, _)| next != raw_key) {
+                    let stored = StoredValue::decode(value, self.max_value_size).map_err(|err| version_error(err, raw_key))?;
+                    if *version < min_active && stored.is_expired(now) {
+                        ops.extend(versions.drain(..).map(WriteOp::Delete));
+                        purged += 1;
+                    }
+                    versions.clear();
+                }
+            }
+            let Some((key, raw_key, version, value)) = entry else {
+                break;
+            };
+            versions.push(key);
+            latest = Some((raw_key, version, value));
+        }
+        drop(iter);
+        if !ops.is_empty() {
+            engine.apply_batch(ops)?;
+            engine.flush()?;
+        }
+        Ok(purged)
+    }
+
+    pub fn stats(&self) -> Result<MvccStats> {
+        MvccStats::collect(&mut *self.engine.lock()?)
+    }
+}
+
+// MVCC 的统计信息
+#[derive(Debug, PartialEq)]
+pub struct MvccStats {
+    // 活跃的事务数量
+    pub active_transactions: usize,
+    // 所有 key 的版本总数，包含删除标记
+    pub total_versions: u64,
+}
+
+impl MvccStats {
+    fn collect<E: Engine>(engine: &mut E) -> Result<Self> {
+        let active_transactions = engine
+            .scan_prefix(&MvccKeyPrefix::TxnActive.encode()?)
+            .try_fold(0, |n, r| r.map(|_| n + 1))?;
+        // 去掉空 key 编码后的结束符，得到所有 Version 的公共前缀
+        let mut prefix = MvccKeyPrefix::Version(Vec::new()).encode()?;
+        prefix.truncate(prefix.len() - 2);
+        let total_versions = engine
+            .scan_prefix(&prefix)
+            .try_fold(0, |n, r| r.map(|_| n + 1))?;
+        Ok(Self { active_transactions, total_versions })
+    }
+}
+
+// 判断数据是否过期时使用的时钟，返回 UNIX 时间戳（毫秒）
+pub trait Clock: Send + Sync {
+    fn now_millis(&self) -> u64;
+}
+
+// 系统时间，默认使用
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64)
+    }
+}
+
+// 手动推进的时钟，克隆之后共享同一个时间，用于测试数据的过期
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    now: Arc<AtomicU64>,
+}
+
+impl ManualClock {
+    pub fn new(now_millis: u64) -> Self {
+        Self { now: Arc::new(AtomicU64::new(now_millis)) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.fetch_add(duration.as_millis() as u64, Ordering::SeqCst);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> u64 {
+        self.now.load(Ordering::SeqCst)
+    }
+}
+
+// MvccKey::Version 中保存的 value，data 为 None 表示删除
+// 没有过期时间的 value 编码为 Option<Vec<u8>>，和之前的格式相同，第一个字节为 0 或 1；
+// 设置了过期时间的 value 第一个字节为 STORED_VALUE_FORMAT，之后是 StoredValue 的编码
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StoredValue {
+    // 过期的 UNIX 时间戳（毫秒），到达之后视为已删除
+    pub expires_at: Option<u64>,
+    pub data: Option<Vec<u8>>,
+}
+
+const STORED_VALUE_FORMAT: u8 = 2;
+
+impl StoredValue {
+    fn encode(&self) -> Result<Vec<u8>> {
+        if self.expires_at.is_none() {
+            return codec::serialize(&self.data);
+        }
+        let mut buf = vec![STORED_VALUE_FORMAT];
+        codec::serialize_into(&mut buf, self)?;
+        Ok(buf)
+    }
+
+    // 解码 MvccKey::Version 中保存的原始 value，两种格式都可以解码
+    pub fn decode(value: &[u8], limit: u64) -> Result<Self> {
+        match value.split_first() {
+            Some((&STORED_VALUE_FORMAT, rest)) => codec::deserialize_with_limit(rest, limit),
+            _ => Ok(Self { expires_at: None, data: codec::deserialize_with_limit(value, limit)? }),
+        }
+    }
+
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|t| t <= now)
+    }
+
+    // 当前时间可见的数据，过期之后返回 None
+    fn into_live(self, now: u64) -> Option<Vec<u8>> {
+        match self.is_expired(now) {
+            true => None,
+            false => self.data,
+        }
     }
 }
 
+// 事务的隔离级别
+// Snapshot：开启事务时记录活跃事务列表，之后的读取都基于这个快照，同一个 key 重复读取的结果一致（可重复读）
+// ReadCommitted：每次读写时重新获取活跃事务列表，可以读到其他事务在本事务开启之后提交的修改，
+// 因此同一个 key 两次读取的结果可能不同（不可重复读）
+// 两种隔离级别下写冲突的判断相同
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum IsolationLevel {
+    #[default]
+    Snapshot,
+    ReadCommitted,
+}
+
+impl Display for IsolationLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            IsolationLevel::Snapshot => "SNAPSHOT",
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+        })
+    }
+}
+
+// 事务的状态，用来判断数据的可见性
+#[derive(Debug, Clone)]
+pub struct TransactionState {
+    // 当前事务的版本号
+    pub version: Version,
+    // 开启事务时，其他活跃的事务的版本号，ReadCommitted 下每次操作时重新获取
+    pub active_versions: HashSet<Version>,
+    pub isolation: IsolationLevel,
+}
+
+impl TransactionState {
+    // 活跃事务的修改不可见
+    // Snapshot 下版本号比自己大的事务的修改也不可见，ReadCommitted 下已提交的修改都可见
+    fn is_visible(&self, version: Version) -> bool {
+        if self.active_versions.contains(&version) {
+            false
+        } else {
+            version <= self.max_visible_version()
+        }
+    }
+
+    // 可能可见的最大版本号
+    fn max_visible_version(&self) -> Version {
+        match self.isolation {
+            IsolationLevel::Snapshot => self.version,
+            IsolationLevel::ReadCommitted => Version::MAX,
+        }
+    }
+}
+
+// 存储在底层引擎中的 key 的类型
+// NextVersion                  下一个可用的版本号
+// TxnActive(version)           活跃的事务
+// TxnWrite(version, key)       事务写入过的 key，用于回滚
+// Version(key, version)        key 在某个版本下的值
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum MvccKey {
+    NextVersion,
+    TxnActive(Version),
+    TxnWrite(
+        Version,
+        #[serde(with = "serde_bytes")]
+        Vec<u8>,
+    ),
+    Version(
+        #[serde(with = "serde_bytes")]
+        Vec<u8>,
+        Version,
+    ),
+}
+
+impl MvccKey {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        serialize_key(self)
+    }
+
+    pub fn decode(data: Vec<u8>) -> Result<Self> {
+        deserialize_key(&data)
+    }
+}
+
+// 前缀扫描时使用的 key，变体的顺序需要和 MvccKey 保持一致
+#[derive(Debug, Serialize, Deserialize)]
+pub enum MvccKeyPrefix {
+    NextVersion,
+    TxnActive,
+    TxnWrite(Version),
+    Version(
+        #[serde(with = "serde_bytes")]
+        Vec<u8>,
+    ),
+}
+
+impl MvccKeyPrefix {
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        serialize_key(self)
+    }
+}
+
+#[deprecated(note = "renamed to MvccKeyPrefix")]
+pub type MvccKeyPerfix = MvccKeyPrefix;
 
 pub struct MvccTransaction<E : Engine> {
-    engine: Arc<Mutex<E>>
+    engine: Arc<Mutex<E>>,
+    state: TransactionState,
+    // begin_as_of 和 begin_read_only 开启的事务只能读取，不占用版本号，也不在活跃事务列表中
+    read_only: bool,
+    clock: Arc<dyn Clock>,
+    max_value_size: u64,
 }
 
 impl<E : Engine> MvccTransaction<E> {
-    pub fn begin(eng: Arc<Mutex<E>>) -> Self{
-        Self { engine: eng }
+    // 开启事务，默认使用快照隔离
+    pub fn begin(eng: Arc<Mutex<E>>) -> Result<Self> {
+        Self::begin_with_isolation(eng, IsolationLevel::Snapshot)
     }
 
-    pub fn commit(&self) -> Result<()> {
-        Ok(())
+    pub fn begin_with_isolation(eng: Arc<Mutex<E>>, isolation: IsolationLevel) -> Result<Self> {
+        // 获取存储引擎
+        let mut engine = eng.lock()?;
+        // 获取最新的版本号
+        let next_version = Self::next_version(&mut engine)?;
+        // 获取当前活跃的事务列表
+        let active_versions = Self::scan_active(&mut engine)?;
+
+        // 保存下一个版本号，并将当前事务加入到活跃事务列表中
+        engine.apply_batch(vec![
+            WriteOp::Set(MvccKey::NextVersion.encode()?, codec::serialize(&(next_version + 1))?),
+            WriteOp::Set(MvccKey::TxnActive(next_version).encode()?, vec![]),
+        ])?;
+
+        Ok(Self {
+            engine: eng.clone(),
+            state: TransactionState {
+                version: next_version,
+                active_versions,
+                isolation,
+            },
+            read_only: false,
+            clock: Arc::new(SystemClock),
+            max_value_size: codec::DEFAULT_MAX_VALUE_SIZE,
+        })
+    }
+
+    // 开启只读事务，看到的数据和 version 版本的事务开启时一致，用于查询历史数据
+    // 历史上的活跃事务列表没有保存，这里做了简化：当前仍然活跃的事务的修改不可见，
+    // 其余版本号不超过 version 的修改都视为已提交，因此 version 开启时活跃、之后才提交的事务的修改同样可见
+    pub fn begin_as_of(eng: Arc<Mutex<E>>, version: Version) -> Result<Self> {
+        let mut engine = eng.lock()?;
+        let next_version = Self::next_version(&mut engine)?;
+        if version >= next_version {
+            return Err(Error::Internal(format!("version {} has not been allocated yet", version)));
+        }
+        let active_versions = Self::scan_active(&mut engine)?;
+        drop(engine);
+        Ok(Self {
+            engine: eng,
+            state: TransactionState { version, active_versions, isolation: IsolationLevel::Snapshot },
+            read_only: true,
+            clock: Arc::new(SystemClock),
+            max_value_size: codec::DEFAULT_MAX_VALUE_SIZE,
+        })
+    }
+
+    fn with_clock(self, clock: Arc<dyn Clock>) -> Self {
+        Self { clock, ..self }
     }
 
+    fn with_max_value_size(self, max_value_size: u64) -> Self {
+        Self { max_value_size, ..self }
+    }
+
+    // 解码 value 时允许的最大字节数，上层解码保存在 value 中的数据时使用同样的上限
+    pub fn max_value_size(&self) -> u64 {
+        self.max_value_size
+    }
+
+    // 开启只读事务，读取最新的已提交数据，不占用版本号
+    pub fn begin_read_only(eng: Arc<Mutex<E>>) -> Result<Self> {
+        let next_version = Self::next_version(&mut *eng.lock()?)?;
+        Self::begin_as_of(eng, next_version - 1)
+    }
+
+    fn next_version(engine: &mut E) -> Result<Version> {
+        Ok(match engine.get(&MvccKey::NextVersion.encode()?)? {
+            Some(value) => codec::deserialize(&value)?,
+            None => 1,
+        })
+    }
+
+    // 当前事务的版本号
+    pub fn version(&self) -> Version {
+        self.state.version
+    }
+
+    pub fn stats(&self) -> Result<MvccStats> {
+        MvccStats::collect(&mut *self.engine.lock()?)
+    }
+
+    // 底层存储引擎的统计信息
+    pub fn engine_stats(&self) -> Result<StorageStats>
+    where
+        E: EngineStats,
+    {
+        self.engine.lock()?.stats()
+    }
+
+    // 提交事务，返回事务提交时的版本号
+    pub fn commit(&self) -> Result<Version> {
+        if self.read_only {
+            return Ok(self.state.version);
+        }
+        let mut engine = self.engine.lock()?;
+        // 先从活跃事务列表中删除，再删除 TxnWrite 信息
+        // 这样即使只写入了一部分，启动时也不会把已经提交的事务回滚
+        let mut ops = vec![WriteOp::Delete(MvccKey::TxnActive(self.state.version).encode()?)];
+        let mut iter = engine.scan_prefix(&MvccKeyPrefix::TxnWrite(self.state.version).encode()?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            ops.push(WriteOp::Delete(key));
+        }
+        drop(iter);
+        engine.apply_batch(ops)?;
+        // 数据持久化之后才算提交成功
+        engine.flush()?;
+        Ok(self.state.version)
+    }
+
+    // 回滚事务
     pub fn rollback(&self) -> Result<()> {
-        Ok(())
+        if self.read_only {
+            return Ok(());
+        }
+        let mut engine = self.engine.lock()?;
+        Self::rollback_version(&mut engine, self.state.version)
+    }
+
+    // 删除事务写入的数据和 TxnWrite 信息，最后从活跃事务列表中删除
+    fn rollback_version(engine: &mut E, version: Version) -> Result<()> {
+        let mut ops = Vec::new();
+        let mut iter = engine.scan_prefix(&MvccKeyPrefix::TxnWrite(version).encode()?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            match MvccKey::decode(key.clone())? {
+                MvccKey::TxnWrite(_, raw_key) => {
+                    ops.push(WriteOp::Delete(MvccKey::Version(raw_key, version).encode()?));
+                }
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            }
+            ops.push(WriteOp::Delete(key));
+        }
+        drop(iter);
+        ops.push(WriteOp::Delete(MvccKey::TxnActive(version).encode()?));
+        engine.apply_batch(ops)
     }
 
     // 插入数据
     pub fn set(&self,key:Vec<u8>,value:Vec<u8>) -> Result<()> {
-        let mut eng = self.engine.lock()?;
-        eng.set(key, value)
+        self.write_inner(key, StoredValue { expires_at: None, data: Some(value) })
+    }
+
+    // 插入数据，经过 ttl 之后过期，读取时视为不存在，之后由 Mvcc::purge_expired 删除
+    pub fn set_with_ttl(&self, key: Vec<u8>, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.write_inner(key, StoredValue { expires_at: Some(self.expires_at(ttl)), data: Some(value) })
+    }
+
+    fn expires_at(&self, ttl: Duration) -> u64 {
+        self.clock.now_millis().saturating_add(ttl.as_millis() as u64)
+    }
+
+    // 批量插入数据，只获取一次引擎的锁
+    // 先对所有的 key 做冲突检测，都通过之后再写入，任何一个 key 冲突则整批都不写入
+    pub fn set_batch(&self, items: Vec<(Vec<u8>, Vec<u8>)>) -> Result<()> {
+        self.write_batch(items, None)
+    }
+
+    // 批量插入数据，所有的 key 经过 ttl 之后过期
+    pub fn set_batch_with_ttl(&self, items: Vec<(Vec<u8>, Vec<u8>)>, ttl: Duration) -> Result<()> {
+        self.write_batch(items, Some(self.expires_at(ttl)))
+    }
+
+    fn write_batch(&self, items: Vec<(Vec<u8>, Vec<u8>)>, expires_at: Option<u64>) -> Result<()> {
+        self.check_writable()?;
+        let mut engine = self.engine.lock()?;
+        for (key, _) in items.iter() {
+            self.check_conflict(&mut engine, key)?;
+        }
+        let mut ops = Vec::with_capacity(items.len() * 2);
+        for (key, value) in items.into_iter() {
+            self.write_version(&mut ops, key, StoredValue { expires_at, data: Some(value) })?;
+        }
+        engine.apply_batch(ops)
+    }
+
+    // 删除数据
+    pub fn delete(&self, key: Vec<u8>) -> Result<()> {
+        self.write_inner(key, StoredValue { expires_at: None, data: None })
     }
 
     // 获取数据
     pub fn get(&self,key:Vec<u8>) -> Result<Option<Vec<u8>>> {
-        let mut eng = self.engine.lock()?;
-        eng.get(key)
+        let mut engine = self.engine.lock()?;
+        self.get_visible(&mut engine, &key)
     }
 
+    // 读取 key 对当前事务可见的最新版本，最新版本已经过期时返回 None
+    fn get_visible(&self, engine: &mut MutexGuard<E>, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let state = self.read_state(engine)?;
+        // version: 9
+        // 扫描的 version 的范围应该是 0-9
+        let from = MvccKey::Version(key.to_vec(), 0).encode()?;
+        let to = MvccKey::Version(key.to_vec(), state.max_visible_version()).encode()?;
+        let mut iter = engine.scan(from..=to).rev();
+        // 从最新的版本开始读取，找到一个最新的可见的版本
+        while let Some((key, value)) = iter.next().transpose()? {
+            match MvccKey::decode(key.clone())? {
+                MvccKey::Version(raw_key, version) => {
+                    if state.is_visible(version) {
+                        let stored = StoredValue::decode(&value, self.max_value_size).map_err(|err| version_error(err, &raw_key))?;
+                        return Ok(stored.into_live(self.clock.now_millis()));
+                    }
+                }
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            }
+        }
+        Ok(None)
+    }
 
+    // 前缀扫描，返回每个 key 对当前事务可见的最新版本
     pub fn scan_prefix(&self,prefix: Vec<u8>) -> Result<Vec<ScanResult>> {
-        let mut eng = self.engine.lock()?;
-        let mut iter = eng.scan_prefix(prefix);
-        let mut v = Vec::new();
-        while let Some((key,value)) = iter.next().transpose()? {
-            v.push(ScanResult{key,value});
+        self.scan_prefix_iter(prefix)?.collect()
+    }
+
+    // 和 scan_prefix 相同，按批读取，遍历时才读取存储引擎，见 MvccScan
+    pub fn scan_prefix_iter(&self, prefix: Vec<u8>) -> Result<MvccScan<E>> {
+        let mut enc_prefix = MvccKeyPrefix::Version(prefix).encode()?;
+        // 原始值           编码后
+        // 97 98 99     -> 97 98 99 0 0
+        // 前缀原始值        前缀编码后
+        // 97 98        -> 97 98 0 0         -> 97 98
+        // 去掉最后的 [0, 0] 后缀
+        enc_prefix.truncate(enc_prefix.len() - 2);
+        let end = prefix_end_bound(&enc_prefix);
+        self.scan_versions(Bound::Included(enc_prefix), end)
+    }
+
+    // 直接扫描存储引擎中以 prefix 开头的所有 key，返回编码后的 key 和 value
+    // 不做可见性判断，会读到其他事务未提交的数据以及 MVCC 自身的元数据，只用于排查问题
+    pub fn raw_scan_prefix(&self, prefix: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut engine = self.engine.lock()?;
+        let iter = engine.scan_prefix(prefix);
+        iter.collect()
+    }
+
+    // 范围扫描，范围是原始 key 的范围，返回每个 key 对当前事务可见的最新版本
+    pub fn scan(&self, range: impl RangeBounds<Vec<u8>>) -> Result<Vec<ScanResult>> {
+        self.scan_iter(range)?.collect()
+    }
+
+    // 和 scan 相同，按批读取，遍历时才读取存储引擎，见 MvccScan
+    pub fn scan_iter(&self, range: impl RangeBounds<Vec<u8>>) -> Result<MvccScan<E>> {
+        // 同一个 key 的所有版本连续存放，包含某个 key 时从版本 0 开始，排除时从最大的版本之后开始
+        let start = match range.start_bound() {
+            Bound::Included(k) => Bound::Included(MvccKey::Version(k.clone(), 0).encode()?),
+            Bound::Excluded(k) => Bound::Excluded(MvccKey::Version(k.clone(), u64::MAX).encode()?),
+            Bound::Unbounded => Bound::Included(Self::version_prefix()?),
+        };
+        let end = match range.end_bound() {
+            Bound::Included(k) => Bound::Included(MvccKey::Version(k.clone(), u64::MAX).encode()?),
+            Bound::Excluded(k) => Bound::Excluded(MvccKey::Version(k.clone(), 0).encode()?),
+            Bound::Unbounded => prefix_end_bound(&Self::version_prefix()?),
+        };
+        self.scan_versions(start, end)
+    }
+
+    // 扫描编码之后的范围内的所有版本，可见性按调用时的事务状态判断
+    fn scan_versions(&self, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> Result<MvccScan<E>> {
+        let state = self.read_state(&mut self.engine.lock()?)?.into_owned();
+        // 起点在终点之后时范围为空，存储引擎不接受这样的范围
+        let empty = match (&start, &end) {
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => {
+                s > e || (s == e && !matches!((&start, &end), (Bound::Included(_), Bound::Included(_))))
+            }
+            _ => false,
+        };
+        Ok(MvccScan {
+            engine: self.engine.clone(),
+            state,
+            now: self.clock.now_millis(),
+            max_value_size: self.max_value_size,
+            start,
+            end,
+            buffer: VecDeque::new(),
+            done: empty,
+        })
+    }
+
+    // 所有 MvccKey::Version 共同的前缀
+    fn version_prefix() -> Result<Vec<u8>> {
+        let mut prefix = MvccKeyPrefix::Version(Vec::new()).encode()?;
+        prefix.truncate(prefix.len() - 2);
+        Ok(prefix)
+    }
+
+    // 更新/删除数据
+    // 删除时如果 key 没有可见的版本（从未写入或者已经删除），不写入墓碑，冲突检测仍然照常进行
+    fn write_inner(&self, key: Vec<u8>, value: StoredValue) -> Result<()> {
+        self.check_writable()?;
+        // 获取存储引擎
+        let mut engine = self.engine.lock()?;
+        self.check_conflict(&mut engine, &key)?;
+        if value.data.is_none() && self.get_visible(&mut engine, &key)?.is_none() {
+            return Ok(());
+        }
+        let mut ops = Vec::with_capacity(2);
+        self.write_version(&mut ops, key, value)?;
+        engine.apply_batch(ops)
+    }
+
+    fn check_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly(format!("transaction as of version {} is read-only", self.state.version)));
+        }
+        Ok(())
+    }
+
+    // 检测冲突
+    //  3 4 5
+    //  6
+    //  key1-3 key2-4 key3-5
+    // 如果 key 存在对当前事务不可见的版本（活跃事务或者之后开启的事务写入的），则认为冲突
+    fn check_conflict(&self, engine: &mut MutexGuard<E>, key: &[u8]) -> Result<()> {
+        let state = self.read_state(engine)?;
+        let from = MvccKey::Version(
+            key.to_vec(),
+            state
+                .active_versions
+                .iter()
+                .min()
+                .copied()
+                .unwrap_or(self.state.version + 1),
+        )
+        .encode()?;
+        let to = MvccKey::Version(key.to_vec(), u64::MAX).encode()?;
+
+        // 当前活跃事务列表 3 4 5
+        // 当前事务 6
+        // 只需要判断最后一个版本号
+        // 1. key 按照顺序排列，扫描出的结果是从小到大的
+        // 2. 假如有新的的事务修改了这个 key，比如 10，修改之后 10 提交了，那么 6 再修改这个 key 就是冲突的
+        // 3. 如果是当前活跃事务修改了这个 key，比如 4，那么事务 5 就不可能修改这个 key，
+        if let Some((k, _)) = engine.scan(from..=to).next_back().transpose()? {
+            match MvccKey::decode(k.clone())? {
+                MvccKey::Version(_, version) => {
+                    // 活跃事务或者之后开启的事务写入的版本都是冲突的
+                    // ReadCommitted 下之后开启的事务提交的版本虽然可见，但新写入的版本号更小，同样视为冲突
+                    if state.active_versions.contains(&version) || version > state.version {
+                        return Err(Error::TransactionConflict);
+                    }
+                }
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(k)
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 记录事务写入的 key，并写入新版本的数据，两者在同一批中写入
+    fn write_version(&self, ops: &mut Vec<WriteOp>, key: Vec<u8>, value: StoredValue) -> Result<()> {
+        // 记录这个 version 写入了哪些 key，用于回滚事务
+        ops.push(WriteOp::Set(MvccKey::TxnWrite(self.state.version, key.clone()).encode()?, vec![]));
+        // 写入实际的 key value 数据
+        ops.push(WriteOp::Set(MvccKey::Version(key, self.state.version).encode()?, value.encode()?));
+        Ok(())
+    }
+
+    // 判断可见性时使用的事务状态，ReadCommitted 下重新获取活跃事务列表
+    fn read_state(&self, engine: &mut MutexGuard<E>) -> Result<Cow<'_, TransactionState>> {
+        Ok(match self.state.isolation {
+            IsolationLevel::Snapshot => Cow::Borrowed(&self.state),
+            IsolationLevel::ReadCommitted => {
+                let mut active_versions = Self::scan_active(engine)?;
+                active_versions.remove(&self.state.version);
+                Cow::Owned(TransactionState { active_versions, ..self.state.clone() })
+            }
+        })
+    }
+
+    // 扫描获取当前活跃事务列表
+    fn scan_active(engine: &mut E) -> Result<HashSet<Version>> {
+        let mut active_versions = HashSet::new();
+        let mut iter = engine.scan_prefix(&MvccKeyPrefix::TxnActive.encode()?);
+        while let Some((key, _)) = iter.next().transpose()? {
+            match MvccKey::decode(key.clone())? {
+                MvccKey::TxnActive(version) => {
+                    active_versions.insert(version);
+                }
+                _ => {
+                    return Err(Error::Internal(format!(
+                        "unexpected key: {:?}",
+                        String::from_utf8(key)
+                    )))
+                }
+            }
         }
-        Ok(v)
+        Ok(active_versions)
     }
 }
 
-#[derive(Debug)]
+// 数据的 value 无法解码时，在错误中带上对应的 key
+fn version_error(err: Error, key: &[u8]) -> Error {
+    match err {
+        Error::Serialization(msg) => Error::Serialization(format!("{} at key {:?}", msg, key.escape_ascii().to_string())),
+        err => err,
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub struct ScanResult {
     pub key: Vec<u8>,
     pub value: Vec<u8>,
-}
\ No newline at end of file
+}
+
+// 每批读取的 key 的数量，一个 key 的所有版本总是在同一批中读取
+const SCAN_BATCH_SIZE: usize = 256;
+
+// 事务的扫描结果，按 key 的顺序返回每个 key 对事务可见的最新版本，已删除和已过期的 key 不返回
+// 每次从存储引擎中读取一批 key，读取期间持有存储引擎的锁，批与批之间释放，内存中只保存一批数据
+// 可见性使用创建时的事务状态判断，事务提交之后仍然可以继续读取；
+// 但 Mvcc::purge_expired 删除的版本在之后的批中读不到
+pub struct MvccScan<E: Engine> {
+    engine: Arc<Mutex<E>>,
+    state: TransactionState,
+    now: u64,
+    max_value_size: u64,
+    // 剩余的扫描范围，为编码之后的 key
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+    buffer: VecDeque<ScanResult>,
+    done: bool,
+}
+
+impl<E: Engine> MvccScan<E> {
+    // 读取下一批 key，读到范围的末尾时 done 为 true
+    fn fill(&mut self) -> Result<()> {
+        let mut engine = self.engine.lock()?;
+        let mut iter = engine.scan((self.start.clone(), self.end.clone()));
+        // 正在读取的 key 以及它目前可见的最新版本
+        let mut current: Option<(Vec<u8>, Option<Vec<u8>>)> = None;
+        let mut keys = 0;
+        loop {
+            let Some((key, value)) = iter.next().transpose()? else {
+                self.done = true;
+                break;
+            };
+            let MvccKey::Version(raw_key, version) = MvccKey::decode(key.clone())? else {
+                return Err(Error::Internal(format!("Unexepected key {:?}", String::from_utf8(key))));
+            };
+            if current.as_ref().is_some_and(|(k, _)| *k != raw_key) {
+                let (k, v) = current.take().unwrap();
+                if let Some(v) = v {
+                    self.buffer.push_back(ScanResult { key: k, value: v });
+                }
+                keys += 1;
+                // 下一批从这个 key 的第一个版本开始
+                if keys >= SCAN_BATCH_SIZE {
+                    self.start = Bound::Included(MvccKey::Version(raw_key, 0).encode()?);
+                    return Ok(());
+                }
+            }
+            let latest = match current.take() {
+                Some((_, latest)) => latest,
+                None => None,
+            };
+            let latest = match self.state.is_visible(version) {
+                true => StoredValue::decode(&value, self.max_value_size)
+                    .map_err(|err| version_error(err, &raw_key))?
+                    .into_live(self.now),
+                false => latest,
+            };
+            current = Some((raw_key, latest));
+        }
+        if let Some((k, Some(v))) = current {
+            self.buffer.push_back(ScanResult { key: k, value: v });
+        }
+        Ok(())
+    }
+}
+
+impl<E: Engine> Iterator for MvccScan<E> {
+    type Item = Result<ScanResult>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.buffer.is_empty() && !self.done {
+            if let Err(err) = self.fill() {
+                self.done = true;
+                return Some(Err(err));
+            }
+        }
+        self.buffer.pop_front().map(Ok)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::Bound, sync::Arc, time::Duration};
+
+    use crate::{
+        error::{Error, Result},
+        storage::{
+            codec::{Lz4Codec, NoopCodec},
+            disk::{DiskEngine, DiskEngineConfig, SyncPolicy},
+            engine::{Engine, EngineStats},
+            memory::MemoryEngine,
+        },
+    };
+
+    use super::{IsolationLevel, ManualClock, Mvcc, MvccKey, MvccKeyPrefix, MvccStats, ScanResult, SCAN_BATCH_SIZE};
+
+    // 分别对内存引擎和磁盘引擎执行同一个测试
+    fn for_each_engine(f: fn(Mvcc<MemoryEngine>) -> Result<()>, g: fn(Mvcc<DiskEngine>) -> Result<()>) -> Result<()> {
+        f(Mvcc::new(MemoryEngine::new())?)?;
+        let dir = tempfile::tempdir()?;
+        // 提交时不等待数据落盘，持久性由 DiskEngine 自己的测试覆盖
+        let config = DiskEngineConfig { sync_policy: SyncPolicy::FlushOnly, ..Default::default() };
+        g(Mvcc::new(DiskEngine::with_config(dir.path().join("sqldb-log"), config)?)?)?;
+        Ok(())
+    }
+
+    // 1. Get
+    fn get<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val3".to_vec())?;
+        tx.set(b"key3".to_vec(), b"val4".to_vec())?;
+        tx.delete(b"key3".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx1.get(b"key2".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(tx1.get(b"key3".to_vec())?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get() -> Result<()> {
+        for_each_engine(get, get)
+    }
+
+    // 2. Get Isolation
+    fn get_isolation<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val3".to_vec())?;
+        tx.set(b"key3".to_vec(), b"val4".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        tx1.set(b"key1".to_vec(), b"val2".to_vec())?;
+
+        let tx2 = eng.begin()?;
+
+        let tx3 = eng.begin()?;
+        tx3.set(b"key2".to_vec(), b"val4".to_vec())?;
+        tx3.delete(b"key3".to_vec())?;
+        tx3.commit()?;
+
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx2.get(b"key2".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(tx2.get(b"key3".to_vec())?, Some(b"val4".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_isolation() -> Result<()> {
+        for_each_engine(get_isolation, get_isolation)
+    }
+
+    // 3. scan prefix
+    fn scan_prefix<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"aabb".to_vec(), b"val1".to_vec())?;
+        tx.set(b"abcc".to_vec(), b"val2".to_vec())?;
+        tx.set(b"bbaa".to_vec(), b"val3".to_vec())?;
+        tx.set(b"acca".to_vec(), b"val4".to_vec())?;
+        tx.set(b"aaca".to_vec(), b"val5".to_vec())?;
+        tx.set(b"bcca".to_vec(), b"val6".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        let iter1 = tx1.scan_prefix(b"aa".to_vec())?;
+        assert_eq!(
+            iter1,
+            vec![
+                ScanResult {
+                    key: b"aabb".to_vec(),
+                    value: b"val1".to_vec()
+                },
+                ScanResult {
+                    key: b"aaca".to_vec(),
+                    value: b"val5".to_vec()
+                },
+            ]
+        );
+
+        let iter2 = tx1.scan_prefix(b"a".to_vec())?;
+        assert_eq!(iter2.len(), 4);
+
+        let iter3 = tx1.scan_prefix(b"bcca".to_vec())?;
+        assert_eq!(
+            iter3,
+            vec![ScanResult {
+                key: b"bcca".to_vec(),
+                value: b"val6".to_vec()
+            },]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_prefix() -> Result<()> {
+        for_each_engine(scan_prefix, scan_prefix)
+    }
+
+    // 3.1 scan
+    fn scan<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"a".to_vec(), b"val1".to_vec())?;
+        tx.set(b"b".to_vec(), b"val2".to_vec())?;
+        tx.set(b"b\x00".to_vec(), b"val3".to_vec())?;
+        tx.set(b"c".to_vec(), b"val4".to_vec())?;
+        tx.set(b"d".to_vec(), b"val5".to_vec())?;
+        tx.commit()?;
+
+        // 其他事务的修改和删除
+        let tx1 = eng.begin()?;
+        tx1.set(b"c".to_vec(), b"val6".to_vec())?;
+        tx1.delete(b"d".to_vec())?;
+
+        let keys = |results: Vec<ScanResult>| results.into_iter().map(|r| r.key).collect::<Vec<_>>();
+        let tx2 = eng.begin()?;
+        assert_eq!(keys(tx2.scan(b"b".to_vec()..=b"c".to_vec())?), vec![b"b".to_vec(), b"b\x00".to_vec(), b"c".to_vec()]);
+        assert_eq!(keys(tx2.scan(b"b".to_vec()..b"c".to_vec())?), vec![b"b".to_vec(), b"b\x00".to_vec()]);
+        assert_eq!(
+            keys(tx2.scan((Bound::Excluded(b"b".to_vec()), Bound::Unbounded))?),
+            vec![b"b\x00".to_vec(), b"c".to_vec(), b"d".to_vec()]
+        );
+        assert_eq!(keys(tx2.scan(..b"b".to_vec())?), vec![b"a".to_vec()]);
+        assert_eq!(keys(tx2.scan(b"c".to_vec()..=b"b".to_vec())?), Vec::<Vec<u8>>::new());
+        assert_eq!(keys(tx2.scan(b"b".to_vec()..b"b".to_vec())?), Vec::<Vec<u8>>::new());
+        tx1.commit()?;
+
+        let tx3 = eng.begin()?;
+        let results = tx3.scan(b"c".to_vec()..)?;
+        assert_eq!(results, vec![ScanResult { key: b"c".to_vec(), value: b"val6".to_vec() }]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan() -> Result<()> {
+        for_each_engine(scan, scan)
+    }
+
+    // 3.2 按批读取的扫描
+    fn scan_iter<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        // 超过一批的 key，每个 key 有多个版本，其中一部分被删除
+        let n = SCAN_BATCH_SIZE * 2 + 10;
+        let key = |i: usize| format!("key{:05}", i).into_bytes();
+        for round in 0..2 {
+            let tx = eng.begin()?;
+            for i in 0..n {
+                tx.set(key(i), vec![round])?;
+            }
+            tx.commit()?;
+        }
+        let tx = eng.begin()?;
+        for i in (0..n).step_by(3) {
+            tx.delete(key(i))?;
+        }
+        tx.commit()?;
+        let expected = (0..n).filter(|i| i % 3 != 0).map(|i| ScanResult { key: key(i), value: vec![1] }).collect::<Vec<_>>();
+
+        // 遍历期间不持有存储引擎的锁，其他事务可以写入，写入对扫描的事务不可见
+        let tx1 = eng.begin()?;
+        let mut iter = tx1.scan_prefix_iter(b"key".to_vec())?;
+        let first = iter.next().transpose()?;
+        let tx2 = eng.begin()?;
+        tx2.set(key(n - 1), vec![9])?;
+        tx2.set(key(n), vec![9])?;
+        tx2.commit()?;
+        let results = first.into_iter().map(Ok).chain(iter).collect::<Result<Vec<_>>>()?;
+        assert_eq!(results, expected);
+        assert_eq!(tx1.scan_iter(key(0)..key(n))?.collect::<Result<Vec<_>>>()?, expected);
+
+        // 提交之后仍然可以继续读取
+        let iter = tx1.scan_iter(key(1)..)?;
+        tx1.commit()?;
+        assert_eq!(iter.count(), expected.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_scan_iter() -> Result<()> {
+        for_each_engine(scan_iter, scan_iter)
+    }
+
+    // 4. set conflict
+    fn set_conflict<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        let tx2 = eng.begin()?;
+
+        tx1.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx1.set(b"key2".to_vec(), b"val3".to_vec())?;
+
+        // 活跃事务修改过的 key 不能再修改
+        assert_eq!(tx2.set(b"key1".to_vec(), b"val4".to_vec()), Err(Error::TransactionConflict));
+        tx1.commit()?;
+
+        // 之后开启的事务提交的修改，之前的事务也不能修改
+        let tx3 = eng.begin()?;
+        tx3.set(b"key3".to_vec(), b"val5".to_vec())?;
+        tx3.commit()?;
+        assert_eq!(tx2.set(b"key3".to_vec(), b"val6".to_vec()), Err(Error::TransactionConflict));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_conflict() -> Result<()> {
+        for_each_engine(set_conflict, set_conflict)
+    }
+
+    // 5. dirty read
+    fn dirty_read<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        let tx2 = eng.begin()?;
+
+        tx2.set(b"key1".to_vec(), b"val2".to_vec())?;
+        // 读不到未提交的数据
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_dirty_read() -> Result<()> {
+        for_each_engine(dirty_read, dirty_read)
+    }
+
+    // 6. unrepeatable read
+    fn unrepeatable_read<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        let tx2 = eng.begin()?;
+
+        tx2.set(b"key1".to_vec(), b"val2".to_vec())?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        tx2.commit()?;
+        // 其他事务提交之后，再次读取的结果和之前一致
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_unrepeatable_read() -> Result<()> {
+        for_each_engine(unrepeatable_read, unrepeatable_read)
+    }
+
+    // 6.1 read committed
+    fn read_committed<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin_with_isolation(IsolationLevel::ReadCommitted)?;
+        let tx2 = eng.begin()?;
+
+        // 未提交的修改不可见
+        tx2.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx2.set(b"key2".to_vec(), b"val3".to_vec())?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx1.scan_prefix(b"key".to_vec())?.len(), 1);
+        // 活跃事务修改过的 key 不能再修改
+        assert_eq!(tx1.set(b"key1".to_vec(), b"val4".to_vec()), Err(Error::TransactionConflict));
+        tx2.commit()?;
+
+        // 其他事务提交之后，再次读取可以读到新的值
+        assert_eq!(tx1.get(b"key1".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(tx1.scan_prefix(b"key".to_vec())?.len(), 2);
+
+        // 之后开启的事务提交的修改同样可见，但不能再修改这些 key
+        let tx3 = eng.begin()?;
+        tx3.delete(b"key2".to_vec())?;
+        tx3.commit()?;
+        assert_eq!(tx1.get(b"key2".to_vec())?, None);
+        assert_eq!(tx1.set(b"key1".to_vec(), b"val5".to_vec()), Err(Error::TransactionConflict));
+        tx1.set(b"key3".to_vec(), b"val6".to_vec())?;
+        assert_eq!(tx1.get(b"key3".to_vec())?, Some(b"val6".to_vec()));
+        tx1.commit()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_committed() -> Result<()> {
+        for_each_engine(read_committed, read_committed)
+    }
+
+    // 7. phantom read
+    fn phantom_read<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        let tx2 = eng.begin()?;
+
+        assert_eq!(tx1.scan_prefix(b"key".to_vec())?.len(), 2);
+
+        tx2.set(b"key3".to_vec(), b"val3".to_vec())?;
+        tx2.commit()?;
+
+        // 其他事务新插入的数据不可见
+        assert_eq!(tx1.scan_prefix(b"key".to_vec())?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_phantom_read() -> Result<()> {
+        for_each_engine(phantom_read, phantom_read)
+    }
+
+    // 8. rollback
+    fn rollback<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        tx1.set(b"key1".to_vec(), b"val3".to_vec())?;
+        tx1.delete(b"key2".to_vec())?;
+        tx1.set(b"key3".to_vec(), b"val4".to_vec())?;
+        tx1.rollback()?;
+
+        let tx2 = eng.begin()?;
+        assert_eq!(tx2.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx2.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(tx2.get(b"key3".to_vec())?, None);
+        // 回滚之后再修改不会冲突
+        tx2.set(b"key1".to_vec(), b"val5".to_vec())?;
+
+        Ok(())
+    }
+
+    // 底层引擎中保存的所有 key
+    fn engine_keys(engine: &MemoryEngine) -> Result<Vec<MvccKey>> {
+        engine.dump()?.into_iter().map(|(k, _)| MvccKey::decode(k)).collect()
+    }
+
+    fn as_of<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        let v1 = tx.commit()?;
+
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val3".to_vec())?;
+        tx.delete(b"key2".to_vec())?;
+        let v2 = tx.commit()?;
+        // 未提交的事务的修改在之后的版本中也不可见
+        let active = eng.begin()?;
+        active.set(b"key3".to_vec(), b"val4".to_vec())?;
+
+        let old = eng.begin_as_of(v1)?;
+        assert_eq!(old.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(old.get(b"key2".to_vec())?, Some(b"val2".to_vec()));
+        assert_eq!(old.scan_prefix(b"key".to_vec())?.len(), 2);
+        let new = eng.begin_as_of(v2)?;
+        assert_eq!(new.get(b"key1".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(new.get(b"key2".to_vec())?, None);
+        let latest = eng.begin_as_of(active.version())?;
+        assert_eq!(latest.get(b"key3".to_vec())?, None);
+        assert!(eng.begin_as_of(active.version() + 1).is_err());
+
+        // 只读，提交和回滚都不会影响其他事务
+        assert!(old.set(b"key1".to_vec(), b"val5".to_vec()).is_err());
+        assert!(old.delete(b"key1".to_vec()).is_err());
+        old.rollback()?;
+        latest.commit()?;
+        active.commit()?;
+        assert_eq!(eng.stats()?.active_transactions, 0);
+        assert_eq!(eng.begin_as_of(active.version())?.get(b"key3".to_vec())?, Some(b"val4".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_as_of() -> Result<()> {
+        for_each_engine(as_of, as_of)
+    }
+
+    fn delete_absent<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        // 删除不存在的 key 和已经删除的 key 不会报错
+        let tx1 = eng.begin()?;
+        tx1.delete(b"key2".to_vec())?;
+        tx1.delete(b"key1".to_vec())?;
+        tx1.delete(b"key1".to_vec())?;
+        assert_eq!(tx1.get(b"key1".to_vec())?, None);
+        tx1.commit()?;
+
+        // 其他活跃事务写入的 key 仍然冲突，即使对当前事务不可见
+        let tx2 = eng.begin()?;
+        let tx3 = eng.begin()?;
+        tx2.set(b"key3".to_vec(), b"val3".to_vec())?;
+        assert_eq!(tx3.delete(b"key3".to_vec()), Err(Error::TransactionConflict));
+        tx2.commit()?;
+        assert_eq!(tx3.delete(b"key3".to_vec()), Err(Error::TransactionConflict));
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_absent() -> Result<()> {
+        for_each_engine(delete_absent, delete_absent)?;
+
+        let mut engine = MemoryEngine::new();
+        let mvcc = Mvcc::new(&mut engine)?;
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+        let tx = mvcc.begin()?;
+        for i in 0..100u32 {
+            tx.delete(i.to_be_bytes().to_vec())?;
+        }
+        tx.commit()?;
+        drop(mvcc);
+        assert_eq!(
+            engine_keys(&engine)?,
+            vec![MvccKey::NextVersion, MvccKey::Version(b"key1".to_vec(), 1)]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_corrupted_value() -> Result<()> {
+        let mut engine = MemoryEngine::new();
+        let mvcc = Mvcc::new(&mut engine)?;
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+        drop(mvcc);
+
+        // Some 之后的长度前缀声明了 1TB 的数据
+        let mut bomb = vec![1u8];
+        bomb.extend_from_slice(&(1u64 << 40).to_le_bytes());
+        engine.set(&MvccKey::Version(b"key1".to_vec(), 1).encode()?, bomb)?;
+
+        let mvcc = Mvcc::new(&mut engine)?;
+        let tx = mvcc.begin()?;
+        match tx.get(b"key1".to_vec()) {
+            Err(Error::Serialization(msg)) => assert!(msg.contains("key1"), "{}", msg),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(matches!(tx.scan_prefix(b"key".to_vec()), Err(Error::Serialization(_))));
+        tx.rollback()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_rollback() -> Result<()> {
+        for_each_engine(rollback, rollback)?;
+
+        // 回滚的事务写入的数据和 TxnWrite 都被删除，未提交的 tx2 仍然保留
+        let mut engine = MemoryEngine::new();
+        rollback(Mvcc::new(&mut engine)?)?;
+        assert_eq!(
+            engine_keys(&engine)?,
+            vec![
+                MvccKey::NextVersion,
+                MvccKey::TxnActive(3),
+                MvccKey::TxnWrite(3, b"key1".to_vec()),
+                MvccKey::Version(b"key1".to_vec(), 1),
+                MvccKey::Version(b"key1".to_vec(), 3),
+                MvccKey::Version(b"key2".to_vec(), 1),
+            ]
+        );
+        Ok(())
+    }
+
+    // 9. set batch
+    fn set_batch<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set_batch(vec![
+            (b"key1".to_vec(), b"val1".to_vec()),
+            (b"key2".to_vec(), b"val2".to_vec()),
+        ])?;
+        tx.commit()?;
+
+        let tx1 = eng.begin()?;
+        let tx2 = eng.begin()?;
+        tx1.set(b"key3".to_vec(), b"val3".to_vec())?;
+        // 其中一个 key 冲突，整批都不写入
+        assert_eq!(
+            tx2.set_batch(vec![
+                (b"key4".to_vec(), b"val4".to_vec()),
+                (b"key3".to_vec(), b"val5".to_vec()),
+            ]),
+            Err(Error::TransactionConflict)
+        );
+        tx1.commit()?;
+        tx2.rollback()?;
+
+        let tx3 = eng.begin()?;
+        assert_eq!(tx3.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx3.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(tx3.get(b"key4".to_vec())?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_set_batch() -> Result<()> {
+        for_each_engine(set_batch, set_batch)
+    }
+
+    // 10. commit version
+    fn commit_version<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        let version = tx.version();
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        let v1 = tx.commit()?;
+        assert_eq!(v1, version);
+
+        let tx = eng.begin()?;
+        let v2 = tx.commit()?;
+        assert!(v2 > v1);
+
+        // 事务开启的先后决定版本号，而不是提交的先后
+        let tx1 = eng.begin()?;
+        let tx2 = eng.begin()?;
+        let v4 = tx2.commit()?;
+        let v3 = tx1.commit()?;
+        assert!(v2 < v3 && v3 < v4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_commit_version() -> Result<()> {
+        for_each_engine(commit_version, commit_version)
+    }
+
+    // 11. flush on commit
+    fn flush_on_commit<E: Engine>(eng: Mvcc<E>) -> Result<()> {
+        let tx = eng.begin()?;
+        tx.set_batch(vec![
+            (b"key1".to_vec(), b"val1".to_vec()),
+            (b"key2".to_vec(), b"val2".to_vec()),
+        ])?;
+        tx.delete(b"key2".to_vec())?;
+        tx.commit()?;
+
+        // 只读事务提交时同样会调用 flush
+        let tx = eng.begin()?;
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx.get(b"key2".to_vec())?, None);
+        tx.commit()?;
+        assert_eq!(eng.stats()?.active_transactions, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush_on_commit() -> Result<()> {
+        for_each_engine(flush_on_commit, flush_on_commit)?;
+
+        // 提交之后只保留数据，不再有 TxnActive 和 TxnWrite
+        let mut engine = MemoryEngine::new();
+        flush_on_commit(Mvcc::new(&mut engine)?)?;
+        assert_eq!(
+            engine_keys(&engine)?,
+            vec![
+                MvccKey::NextVersion,
+                MvccKey::Version(b"key1".to_vec(), 1),
+                MvccKey::Version(b"key2".to_vec(), 1),
+            ]
+        );
+        Ok(())
+    }
+
+    // 变体的顺序决定了 key 的编码，修改变体时要保证已有数据仍然能够读取
+    #[test]
+    fn test_key_encoding_stable() -> Result<()> {
+        let v = vec![0, 0, 0, 0, 0, 0, 0, 7];
+        assert_eq!(MvccKey::NextVersion.encode()?, vec![0]);
+        assert_eq!(MvccKey::TxnActive(7).encode()?, [vec![1], v.clone()].concat());
+        assert_eq!(
+            MvccKey::TxnWrite(7, b"ab".to_vec()).encode()?,
+            [vec![2], v.clone(), vec![b'a', b'b', 0, 0]].concat()
+        );
+        assert_eq!(
+            MvccKey::Version(vec![b'a', 0], 7).encode()?,
+            [vec![3, b'a', 0, 255, 0, 0], v.clone()].concat()
+        );
+
+        assert_eq!(MvccKeyPrefix::NextVersion.encode()?, vec![0]);
+        assert_eq!(MvccKeyPrefix::TxnActive.encode()?, vec![1]);
+        assert_eq!(MvccKeyPrefix::TxnWrite(7).encode()?, [vec![2], v].concat());
+        assert_eq!(
+            MvccKeyPrefix::Version(b"ab".to_vec()).encode()?,
+            vec![3, b'a', b'b', 0, 0]
+        );
+        Ok(())
+    }
+
+    fn stats(mvcc: Mvcc<impl Engine>) -> Result<()> {
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val3".to_vec())?;
+        let _tx2 = mvcc.begin()?;
+        assert_eq!(
+            mvcc.stats()?,
+            MvccStats { active_transactions: 2, total_versions: 3 }
+        );
+
+        tx1.rollback()?;
+        assert_eq!(
+            mvcc.stats()?,
+            MvccStats { active_transactions: 1, total_versions: 2 }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        for_each_engine(stats, stats)
+    }
+
+    // 开启事务写入数据之后直接丢弃，模拟进程在提交之前崩溃
+    fn crash(mvcc: Mvcc<impl Engine>) -> Result<()> {
+        let tx = mvcc.begin()?;
+        tx.set(b"key1".to_vec(), b"val1".to_vec())?;
+        tx.commit()?;
+
+        let tx1 = mvcc.begin()?;
+        tx1.set(b"key1".to_vec(), b"val2".to_vec())?;
+        tx1.set(b"key2".to_vec(), b"val3".to_vec())?;
+        let _tx2 = mvcc.begin()?;
+        Ok(())
+    }
+
+    // 重新打开之后未完成的事务已经回滚，它写过的 key 可以继续写入
+    fn recovered(mvcc: Mvcc<impl Engine>) -> Result<()> {
+        assert_eq!(mvcc.stats()?, MvccStats { active_transactions: 0, total_versions: 1 });
+        let tx = mvcc.begin()?;
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(tx.get(b"key2".to_vec())?, None);
+        tx.set(b"key1".to_vec(), b"val4".to_vec())?;
+        tx.set(b"key2".to_vec(), b"val5".to_vec())?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_recover() -> Result<()> {
+        let mut engine = MemoryEngine::new();
+        crash(Mvcc::new(&mut engine)?)?;
+        assert!(engine_keys(&engine)?.contains(&MvccKey::TxnWrite(2, b"key2".to_vec())));
+        recovered(Mvcc::new(&mut engine)?)?;
+        assert!(engine_keys(&engine)?
+            .iter()
+            .all(|k| !matches!(k, MvccKey::TxnActive(_) | MvccKey::TxnWrite(..))));
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-log");
+        crash(Mvcc::new(DiskEngine::new(path.clone())?)?)?;
+        recovered(Mvcc::new(DiskEngine::new(path)?)?)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_snapshot() -> Result<()> {
+        let mut engine = MemoryEngine::new();
+        crash(Mvcc::new(&mut engine)?)?;
+        let snapshot = engine.snapshot()?;
+
+        // 快照之后的修改不会出现在恢复的引擎中
+        engine.set(b"other", b"x".to_vec())?;
+        let mut restored = MemoryEngine::restore(snapshot.clone(), Box::new(NoopCodec));
+        assert_eq!(restored.len() + 1, engine.len());
+        assert_eq!(restored.get(b"other")?, None);
+
+        // 从同一个快照恢复的引擎分别回滚未完成的事务，之后的写入互不影响
+        recovered(Mvcc::new(&mut restored)?)?;
+        let fork = Mvcc::new(MemoryEngine::restore(snapshot, Box::new(NoopCodec)))?;
+        let tx = fork.begin()?;
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        tx.set(b"key2".to_vec(), b"val6".to_vec())?;
+        tx.commit()?;
+
+        let tx = Mvcc::new(&mut restored)?.begin()?;
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val4".to_vec()));
+        assert_eq!(tx.get(b"key2".to_vec())?, Some(b"val5".to_vec()));
+        tx.commit()?;
+
+        // 恢复时使用传入的 codec 重新压缩，value 的大小上限和快照时一致
+        let mut engine = MemoryEngine::with_codec(Box::new(Lz4Codec));
+        engine.set_max_value_size(2048);
+        engine.set(b"key", vec![b'a'; 1024])?;
+        let snapshot = engine.snapshot()?;
+        let mut compressed = MemoryEngine::restore(snapshot.clone(), Box::new(Lz4Codec));
+        let plain = MemoryEngine::restore(snapshot, Box::new(NoopCodec));
+        assert_eq!(compressed.get(b"key")?, Some(vec![b'a'; 1024]));
+        assert_eq!(compressed.live_data_bytes()?, engine.live_data_bytes()?);
+        assert!(compressed.live_data_bytes()? < plain.live_data_bytes()?);
+        compressed.set(b"big", vec![0; 4096])?;
+        assert!(compressed.get(b"big").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ttl() -> Result<()> {
+        let clock = ManualClock::new(1_000);
+        let mut engine = MemoryEngine::new();
+        let mvcc = Mvcc::with_clock(&mut engine, Arc::new(clock.clone()))?;
+        let keys = |tx: &super::MvccTransaction<&mut MemoryEngine>| -> Result<Vec<Vec<u8>>> {
+            Ok(tx.scan_prefix(b"key".to_vec())?.into_iter().map(|r| r.key).collect())
+        };
+        let tx = mvcc.begin()?;
+        tx.set_with_ttl(b"key1".to_vec(), b"val1".to_vec(), Duration::from_secs(10))?;
+        tx.set(b"key2".to_vec(), b"val2".to_vec())?;
+        tx.set_with_ttl(b"key3".to_vec(), b"val3".to_vec(), Duration::from_secs(5))?;
+        tx.set_batch_with_ttl(vec![(b"key4".to_vec(), b"val4".to_vec())], Duration::from_secs(20))?;
+        tx.commit()?;
+
+        // 过期之前正常可见，到达过期时间之后 get 和扫描都视为不存在
+        let tx = mvcc.begin()?;
+        assert_eq!(tx.get(b"key3".to_vec())?, Some(b"val3".to_vec()));
+        assert_eq!(keys(&tx)?.len(), 4);
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(tx.get(b"key3".to_vec())?, None);
+        assert_eq!(tx.get(b"key1".to_vec())?, Some(b"val1".to_vec()));
+        assert_eq!(keys(&tx)?, vec![b"key1".to_vec(), b"key2".to_vec(), b"key4".to_vec()]);
+        // 过期的 key 可以重新写入，key2 的新版本过期之后不会露出旧的版本
+        tx.set(b"key3".to_vec(), b"val5".to_vec())?;
+        tx.set_with_ttl(b"key2".to_vec(), b"val6".to_vec(), Duration::from_secs(1))?;
+        tx.commit()?;
+        clock.advance(Duration::from_secs(5));
+        let tx = mvcc.begin()?;
+        assert_eq!(keys(&tx)?, vec![b"key3".to_vec(), b"key4".to_vec()]);
+        assert_eq!(tx.scan(b"key1".to_vec()..=b"key2".to_vec())?, vec![]);
+        tx.commit()?;
+
+        // 最新版本之前开启的事务仍然活跃时，这些版本不能删除
+        let old = mvcc.begin()?;
+        let tx = mvcc.begin()?;
+        tx.set_with_ttl(b"key5".to_vec(), b"val7".to_vec(), Duration::from_secs(1))?;
+        tx.commit()?;
+        clock.advance(Duration::from_secs(1));
+        let versions = mvcc.stats()?.total_versions;
+        assert_eq!(mvcc.purge_expired()?, 2);
+        assert_eq!(mvcc.stats()?.total_versions, versions - 3);
+        old.commit()?;
+        assert_eq!(mvcc.purge_expired()?, 1);
+        assert_eq!(mvcc.purge_expired()?, 0);
+        let tx = mvcc.begin()?;
+        assert_eq!(keys(&tx)?, vec![b"key3".to_vec(), b"key4".to_vec()]);
+        tx.commit()?;
+        drop(mvcc);
+
+        // 没有过期时间的 value 和之前的编码相同，过期的 key 的所有版本都已经删除
+        assert_eq!(engine.get(&MvccKey::Version(b"key3".to_vec(), 2).encode()?)?, Some(vec![1, 4, 0, 0, 0, 0, 0, 0, 0, b'v', b'a', b'l', b'5']));
+        assert_eq!(engine.get(&MvccKey::Version(b"key2".to_vec(), 1).encode()?)?, None);
+        assert_eq!(engine.get(&MvccKey::Version(b"key5".to_vec(), 4).encode()?)?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,487 @@
+use std::{collections::{btree_map, BTreeMap}, fs::{File, OpenOptions}, io::{Read, Seek, SeekFrom, Write}, ops::Bound, path::PathBuf};
+
+use fs4::FileExt;
+
+use crate::error::{Error, Result};
+
+// 页的大小，页号乘以 PAGE_SIZE 即为页在文件中的偏移
+pub const PAGE_SIZE: usize = 4096;
+// 页头：page_type(1) + 保留(1) + slot_count(2) + crc32(4)
+const PAGE_HEADER_SIZE: usize = 8;
+// 槽：entry 在页中的偏移(2) + entry 的长度(2)，偏移为 0 表示空槽
+const SLOT_SIZE: usize = 4;
+// entry：key_size(2) + key + value
+const ENTRY_HEADER_SIZE: usize = 2;
+// 一个 entry 最多占用的字节数，包含 entry 的头部，需要和一个槽一起放入空页
+pub const MAX_ENTRY_SIZE: usize = PAGE_SIZE - PAGE_HEADER_SIZE - SLOT_SIZE;
+const DATA_PAGE: u8 = 1;
+
+// key -> (页号, 槽号)
+type PageDir = BTreeMap<Vec<u8>, (u32, u16)>;
+
+// 基于页的存储引擎
+// 数据保存在一个文件中，文件由固定大小的页组成，每个页由页头、槽数组和 entry 组成：
+// | page_type | 保留 | slot_count | crc32 | slot 0 | slot 1 | ... | 空闲空间 | ... | entry 1 | entry 0 |
+// 槽数组从页头之后向后增长，entry 从页尾向前增长，crc32 覆盖页中除自身之外的所有字节
+// 修改直接写回所在的页，删除的 entry 留下的空槽和空间由之后写入的 entry 复用，文件不会缩小
+// 点读只需要读取一个页，适合随机读较多的场景；页的写入不是原子的，写入时崩溃可能导致页校验失败
+// 一个 key/value 需要放入一个页中，超过 MAX_ENTRY_SIZE 时写入失败
+pub struct PageEngine {
+    file: File,
+    // 页目录，打开时扫描所有的页重建
+    index: PageDir,
+    // 每个页剩余的空闲字节数，下标为页号
+    free: Vec<usize>,
+    // 有效的 key 和 value 的总字节数
+    live_bytes: u64,
+}
+
+impl PageEngine {
+    pub fn new(file_path: PathBuf) -> Result<Self> {
+        // 如果目录不存在则创建
+        if let Some(dir) = file_path.parent() {
+            if !dir.exists() {
+                std::fs::create_dir_all(dir)?;
+            }
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(file_path)?;
+        // 加文件锁，保证同一时间只有一个进程使用
+        file.try_lock_exclusive()?;
+
+        let file_size = file.metadata()?.len();
+        if file_size % PAGE_SIZE as u64 != 0 {
+            return Err(Error::Internal(format!("page file size {} is not a multiple of the page size", file_size)));
+        }
+        let mut engine = Self { file, index: PageDir::new(), free: Vec::new(), live_bytes: 0 };
+        for id in 0..(file_size / PAGE_SIZE as u64) as u32 {
+            let page = engine.load(id)?;
+            for (slot, entry) in page.slots.iter().enumerate() {
+                if let Some((key, value)) = entry {
+                    engine.live_bytes += (key.len() + value.len()) as u64;
+                    engine.index.insert(key.clone(), (id, slot as u16));
+                }
+            }
+            engine.free.push(page.free_space());
+        }
+        Ok(engine)
+    }
+
+    // 页的数量
+    pub fn page_count(&self) -> usize {
+        self.free.len()
+    }
+
+    // 读取一个页并校验
+    fn read_page(&self, id: u32, buf: &mut [u8; PAGE_SIZE]) -> Result<()> {
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+        file.read_exact(buf)?;
+        if u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]) != Page::checksum(buf) {
+            return Err(Error::Internal(format!("checksum mismatch in page {}", id)));
+        }
+        if buf[0] != DATA_PAGE {
+            return Err(Error::Internal(format!("unknown type {} of page {}", buf[0], id)));
+        }
+        Ok(())
+    }
+
+    fn load(&self, id: u32) -> Result<Page> {
+        let mut buf = [0; PAGE_SIZE];
+        self.read_page(id, &mut buf)?;
+        Page::decode(&buf)
+    }
+
+    fn write_page(&mut self, id: u32, page: &Page) -> Result<()> {
+        self.file.seek(SeekFrom::Start(id as u64 * PAGE_SIZE as u64))?;
+        self.file.write_all(&page.encode())?;
+        match self.free.get_mut(id as usize) {
+            Some(free) => *free = page.free_space(),
+            None => self.free.push(page.free_space()),
+        }
+        Ok(())
+    }
+
+    // 读取槽中的 value，不解码整个页
+    fn read_value(&self, (id, slot): (u32, u16)) -> Result<Vec<u8>> {
+        let mut buf = [0; PAGE_SIZE];
+        self.read_page(id, &mut buf)?;
+        match Page::entry(&buf, slot as usize)? {
+            Some((_, value)) => Ok(value.to_vec()),
+            None => Err(Error::Internal(format!("slot {} of page {} is empty", slot, id))),
+        }
+    }
+}
+
+impl super::engine::Engine for PageEngine {
+    type EngineIterator<'a> = PageEngineIterator<'a>;
+
+    fn set(&mut self, key: &[u8], value: Vec<u8>) -> Result<()> {
+        let size = ENTRY_HEADER_SIZE + key.len() + value.len();
+        if size > MAX_ENTRY_SIZE {
+            return Err(Error::Internal(format!(
+                "entry of {} bytes does not fit in a page of {} bytes",
+                size, PAGE_SIZE
+            )));
+        }
+        // 覆盖已有的 key 时优先写回原来的页，放不下时再寻找其他的页
+        // 删除旧值时末尾的空槽会被去掉，新值放入第一个空槽，槽号可能改变
+        if let Some((id, slot)) = self.index.get(key).copied() {
+            let mut page = self.load(id)?;
+            if let Some((old_key, old_value)) = page.remove(slot as usize) {
+                self.live_bytes -= (old_key.len() + old_value.len()) as u64;
+            }
+            if page.fits(size) {
+                let slot = page.insert(key.to_vec(), value);
+                self.write_page(id, &page)?;
+                self.index.insert(key.to_vec(), (id, slot as u16));
+                self.live_bytes += (size - ENTRY_HEADER_SIZE) as u64;
+                return Ok(());
+            }
+            self.write_page(id, &page)?;
+            self.index.remove(key);
+        }
+        // 按页号顺序找到第一个放得下的页，都放不下时在文件末尾追加一个页
+        // 空闲空间按需要新的槽计算，复用空槽时可能略微保守
+        let (id, mut page) = match self.free.iter().position(|free| *free >= size + SLOT_SIZE) {
+            Some(id) => (id as u32, self.load(id as u32)?),
+            None => (self.free.len() as u32, Page::default()),
+        };
+        let slot = page.insert(key.to_vec(), value);
+        self.write_page(id, &page)?;
+        self.index.insert(key.to_vec(), (id, slot as u16));
+        self.live_bytes += (size - ENTRY_HEADER_SIZE) as u64;
+        Ok(())
+    }
+
+    fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.index.get(key).copied() {
+            Some(pos) => Ok(Some(self.read_value(pos)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn contains_key(&mut self, key: &[u8]) -> Result<bool> {
+        // 只查页目录，不读取文件
+        Ok(self.index.contains_key(key))
+    }
+
+    fn delete(&mut self, key: &[u8]) -> Result<()> {
+        let Some((id, slot)) = self.index.remove(key) else {
+            return Ok(());
+        };
+        let mut page = self.load(id)?;
+        if let Some((key, value)) = page.remove(slot as usize) {
+            self.live_bytes -= (key.len() + value.len()) as u64;
+        }
+        self.write_page(id, &page)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
+    fn scan(&mut self, range: impl std::ops::RangeBounds<Vec<u8>>) -> Self::EngineIterator<'_> {
+        PageEngineIterator {
+            engine: self,
+            start: range.start_bound().cloned(),
+            end: range.end_bound().cloned(),
+        }
+    }
+}
+
+// 页中没有过期的数据，删除的空间会被复用
+impl super::engine::EngineStats for PageEngine {
+    fn entry_count(&self) -> Result<usize> {
+        Ok(self.index.len())
+    }
+
+    fn disk_size_bytes(&self) -> Result<u64> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    fn live_data_bytes(&self) -> Result<u64> {
+        Ok(self.live_bytes)
+    }
+
+    fn live_entry_ratio(&self) -> Result<f64> {
+        Ok(1.0)
+    }
+}
+
+// 解码之后的页，槽号就是 slots 中的下标，None 为空槽
+#[derive(Debug, Default)]
+struct Page {
+    slots: Vec<Option<(Vec<u8>, Vec<u8>)>>,
+}
+
+impl Page {
+    fn entry_size((key, value): &(Vec<u8>, Vec<u8>)) -> usize {
+        ENTRY_HEADER_SIZE + key.len() + value.len()
+    }
+
+    // 页中剩余的空闲字节数，entry 之间没有碎片，编码时会重新排列
+    fn free_space(&self) -> usize {
+        let used: usize = self.slots.iter().flatten().map(Self::entry_size).sum();
+        PAGE_SIZE - PAGE_HEADER_SIZE - self.slots.len() * SLOT_SIZE - used
+    }
+
+    // 大小为 size 的 entry 能否放入一个空槽，没有空槽时需要额外的一个槽
+    fn fits(&self, size: usize) -> bool {
+        let slot = if self.slots.contains(&None) { 0 } else { SLOT_SIZE };
+        self.free_space() >= size + slot
+    }
+
+    // 放入第一个空槽，没有空槽时追加一个槽，返回槽号
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) -> usize {
+        match self.slots.iter().position(Option::is_none) {
+            Some(slot) => {
+                self.slots[slot] = Some((key, value));
+                slot
+            }
+            None => {
+                self.slots.push(Some((key, value)));
+                self.slots.len() - 1
+            }
+        }
+    }
+
+    // 清空一个槽，末尾的空槽直接去掉，回收槽占用的空间
+    fn remove(&mut self, slot: usize) -> Option<(Vec<u8>, Vec<u8>)> {
+        let entry = self.slots.get_mut(slot)?.take();
+        while self.slots.last() == Some(&None) {
+            self.slots.pop();
+        }
+        entry
+    }
+
+    fn encode(&self) -> [u8; PAGE_SIZE] {
+        let mut buf = [0; PAGE_SIZE];
+        buf[0] = DATA_PAGE;
+        buf[2..4].copy_from_slice(&(self.slots.len() as u16).to_be_bytes());
+        let mut end = PAGE_SIZE;
+        for (i, entry) in self.slots.iter().enumerate() {
+            let Some(kv @ (key, value)) = entry else {
+                continue;
+            };
+            let size = Self::entry_size(kv);
+            end -= size;
+            buf[end..end + 2].copy_from_slice(&(key.len() as u16).to_be_bytes());
+            buf[end + 2..end + 2 + key.len()].copy_from_slice(key);
+            buf[end + 2 + key.len()..end + size].copy_from_slice(value);
+            let pos = PAGE_HEADER_SIZE + i * SLOT_SIZE;
+            buf[pos..pos + 2].copy_from_slice(&(end as u16).to_be_bytes());
+            buf[pos + 2..pos + 4].copy_from_slice(&(size as u16).to_be_bytes());
+        }
+        let crc = Self::checksum(&buf);
+        buf[4..8].copy_from_slice(&crc.to_be_bytes());
+        buf
+    }
+
+    fn decode(buf: &[u8; PAGE_SIZE]) -> Result<Self> {
+        let slot_count = u16::from_be_bytes([buf[2], buf[3]]) as usize;
+        let slots = (0..slot_count)
+            .map(|slot| Ok(Self::entry(buf, slot)?.map(|(key, value)| (key.to_vec(), value.to_vec()))))
+            .collect::<Result<_>>()?;
+        Ok(Self { slots })
+    }
+
+    // 从页中读取一个槽中的 key 和 value，越界时说明页已经损坏
+    fn entry(buf: &[u8; PAGE_SIZE], slot: usize) -> Result<Option<(&[u8], &[u8])>> {
+        let corrupted = || Error::Internal(format!("corrupted slot {} in page", slot));
+        let pos = PAGE_HEADER_SIZE + slot * SLOT_SIZE;
+        let header = buf.get(pos..pos + SLOT_SIZE).ok_or_else(corrupted)?;
+        let offset = u16::from_be_bytes([header[0], header[1]]) as usize;
+        let size = u16::from_be_bytes([header[2], header[3]]) as usize;
+        if offset == 0 {
+            return Ok(None);
+        }
+        let entry = buf.get(offset..offset + size).ok_or_else(corrupted)?;
+        if entry.len() < ENTRY_HEADER_SIZE {
+            return Err(corrupted());
+        }
+        let key_size = u16::from_be_bytes([entry[0], entry[1]]) as usize;
+        let key = entry.get(ENTRY_HEADER_SIZE..ENTRY_HEADER_SIZE + key_size).ok_or_else(corrupted)?;
+        Ok(Some((key, &entry[ENTRY_HEADER_SIZE + key_size..])))
+    }
+
+    fn checksum(buf: &[u8; PAGE_SIZE]) -> u32 {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&buf[..4]);
+        hasher.update(&buf[PAGE_HEADER_SIZE..]);
+        hasher.finalize()
+    }
+}
+
+// 和 DiskEngineIterator 相同，每次迭代时根据剩余的范围在页目录中查找下一个 key
+pub struct PageEngineIterator<'a> {
+    engine: &'a PageEngine,
+    start: Bound<Vec<u8>>,
+    end: Bound<Vec<u8>>,
+}
+
+impl<'a> PageEngineIterator<'a> {
+    // 剩余的扫描范围，BTreeMap::range 在起点大于终点时会 panic，需要提前判断
+    fn range(&self) -> Option<btree_map::Range<'a, Vec<u8>, (u32, u16)>> {
+        if let (
+            Bound::Included(s) | Bound::Excluded(s),
+            Bound::Included(e) | Bound::Excluded(e),
+        ) = (&self.start, &self.end)
+        {
+            let both_included = matches!((&self.start, &self.end), (Bound::Included(_), Bound::Included(_)));
+            if s > e || (s == e && !both_included) {
+                return None;
+            }
+        }
+        Some(self.engine.index.range((self.start.clone(), self.end.clone())))
+    }
+
+    fn read(&self, key: Vec<u8>, pos: (u32, u16)) -> <Self as Iterator>::Item {
+        Ok((key, self.engine.read_value(pos)?))
+    }
+}
+
+impl<'a> super::engine::EngineIterator for PageEngineIterator<'a> {
+
+}
+
+impl<'a> Iterator for PageEngineIterator<'a> {
+    type Item = Result<(Vec<u8>, Vec<u8>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, pos) = self.range()?.next().map(|(k, pos)| (k.clone(), *pos))?;
+        self.start = Bound::Excluded(key.clone());
+        Some(self.read(key, pos))
+    }
+}
+
+impl<'a> DoubleEndedIterator for PageEngineIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let (key, pos) = self.range()?.next_back().map(|(k, pos)| (k.clone(), *pos))?;
+        self.end = Bound::Excluded(key.clone());
+        Some(self.read(key, pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::{Seek, SeekFrom, Write}};
+
+    use crate::{error::{Error, Result}, storage::engine::{Engine, EngineStats}};
+
+    use super::{PageEngine, MAX_ENTRY_SIZE, PAGE_SIZE};
+
+    #[test]
+    fn test_page_engine_reopen() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-pages");
+        let mut eng = PageEngine::new(path.clone())?;
+        for i in 0..500u32 {
+            eng.set(&i.to_be_bytes(), vec![i as u8; 100])?;
+        }
+        eng.delete(&7u32.to_be_bytes())?;
+        eng.set(&8u32.to_be_bytes(), b"value".to_vec())?;
+        // 每个 entry 大约 110 字节，需要多个页
+        let pages = eng.page_count();
+        assert!(pages > 10);
+        assert_eq!(eng.disk_size_bytes()?, (pages * PAGE_SIZE) as u64);
+        drop(eng);
+
+        // 重新打开后从页中恢复页目录，扫描结果按 key 排序
+        let mut eng = PageEngine::new(path)?;
+        assert_eq!(eng.page_count(), pages);
+        assert_eq!(eng.entry_count()?, 499);
+        assert_eq!(eng.get(&7u32.to_be_bytes())?, None);
+        assert_eq!(eng.get(&8u32.to_be_bytes())?, Some(b"value".to_vec()));
+        assert_eq!(eng.get(&499u32.to_be_bytes())?, Some(vec![243; 100]));
+        let keys = eng.scan(..).map(|r| r.map(|(k, _)| k)).collect::<Result<Vec<_>>>()?;
+        let expected = (0..500u32).filter(|i| *i != 7).map(|i| i.to_be_bytes().to_vec()).collect::<Vec<_>>();
+        assert_eq!(keys, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_engine_reuse_space() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut eng = PageEngine::new(dir.path().join("sqldb-pages"))?;
+        for i in 0..200u32 {
+            eng.set(&i.to_be_bytes(), vec![1; 200])?;
+        }
+        let pages = eng.page_count();
+
+        // 删除之后再写入，复用已有的页，文件不再增长
+        for i in 0..200u32 {
+            eng.delete(&i.to_be_bytes())?;
+        }
+        assert_eq!(eng.entry_count()?, 0);
+        assert_eq!(eng.live_data_bytes()?, 0);
+        for i in 200..400u32 {
+            eng.set(&i.to_be_bytes(), vec![2; 200])?;
+        }
+        assert_eq!(eng.page_count(), pages);
+
+        // 原来的页放不下更大的 value 时移到其他的页
+        eng.set(&200u32.to_be_bytes(), vec![3; 3000])?;
+        assert_eq!(eng.get(&200u32.to_be_bytes())?, Some(vec![3; 3000]));
+        assert_eq!(eng.get(&201u32.to_be_bytes())?, Some(vec![2; 200]));
+        assert_eq!(eng.live_data_bytes()?, 199 * 204 + 3004);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_engine_entry_too_large() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let mut eng = PageEngine::new(dir.path().join("sqldb-pages"))?;
+        // key_size 占 2 个字节
+        eng.set(b"a", vec![0; MAX_ENTRY_SIZE - 3])?;
+        assert_eq!(eng.get(b"a")?, Some(vec![0; MAX_ENTRY_SIZE - 3]));
+        assert!(matches!(eng.set(b"b", vec![0; MAX_ENTRY_SIZE - 2]), Err(Error::Internal(_))));
+        assert_eq!(eng.get(b"b")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_engine_overwrite_after_empty_slot() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-pages");
+        let mut eng = PageEngine::new(path.clone())?;
+        // b 在 a 之后的槽中，删除 a 之后覆盖 b，新值需要的空间按实际的槽数计算
+        eng.set(b"a", vec![1])?;
+        eng.set(b"b", vec![2])?;
+        eng.delete(b"a")?;
+        eng.set(b"b", vec![3; MAX_ENTRY_SIZE - 3])?;
+        assert_eq!(eng.get(b"b")?, Some(vec![3; MAX_ENTRY_SIZE - 3]));
+        drop(eng);
+
+        let mut eng = PageEngine::new(path)?;
+        assert_eq!(eng.get(b"b")?, Some(vec![3; MAX_ENTRY_SIZE - 3]));
+        assert_eq!(eng.get(b"a")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_page_engine_checksum() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sqldb-pages");
+        let mut eng = PageEngine::new(path.clone())?;
+        eng.set(b"aa", b"value1".to_vec())?;
+
+        // 篡改页中最后一个字节，即 value 的最后一个字节
+        let mut file = OpenOptions::new().write(true).open(&path)?;
+        file.seek(SeekFrom::Start(PAGE_SIZE as u64 - 1))?;
+        file.write_all(b"x")?;
+        drop(file);
+
+        assert_eq!(eng.get(b"aa"), Err(Error::Internal("checksum mismatch in page 0".to_string())));
+        drop(eng);
+
+        // 重新打开时同样会校验失败
+        assert!(PageEngine::new(path).is_err());
+        Ok(())
+    }
+}
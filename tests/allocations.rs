@@ -0,0 +1,78 @@
+// 检查存储引擎点读时的内存分配次数
+// 计数的分配器会替换整个测试程序的全局分配器，因此放在单独的集成测试中，不影响其他测试
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    cell::Cell,
+};
+
+use sql_rs::{
+    error::Result,
+    storage::{disk::DiskEngine, engine::Engine, memory::MemoryEngine, page::PageEngine},
+};
+
+// 统计当前线程的内存分配次数，测试并行执行，因此按线程计数
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// 执行 f 期间当前线程分配内存的次数
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|n| n.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|n| n.get()) - before)
+}
+
+// 点读时 key 是借用的，只有返回的 value 需要分配内存
+fn test_get_allocations(mut eng: impl Engine) -> Result<()> {
+    eng.set(b"aa", vec![1, 2, 3])?;
+    // 磁盘引擎第一次读取时会把 value 放入缓存，页引擎每次读取整个页到栈上的缓冲区
+    eng.get(b"aa")?;
+
+    let key: &[u8] = b"aa";
+    let (value, allocations) = count_allocations(|| eng.get(key));
+    assert_eq!(value?, Some(vec![1, 2, 3]));
+    assert_eq!(allocations, 1);
+
+    let (value, allocations) = count_allocations(|| eng.get(b"bb"));
+    assert_eq!(value?, None);
+    assert_eq!(allocations, 0);
+
+    let (exists, allocations) = count_allocations(|| eng.contains_key(key));
+    assert!(exists?);
+    assert_eq!(allocations, 0);
+    Ok(())
+}
+
+#[test]
+fn test_memory() -> Result<()> {
+    test_get_allocations(MemoryEngine::new())
+}
+
+#[test]
+fn test_disk() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    test_get_allocations(DiskEngine::new(dir.path().join("alloc-log"))?)
+}
+
+#[test]
+fn test_page() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    test_get_allocations(PageEngine::new(dir.path().join("alloc-pages"))?)
+}